@@ -0,0 +1,232 @@
+//! Scenarios run one after the other without a window, read from a RON
+//! file given with `run --batch`.
+//!
+//! A batch file looks like
+//!
+//! ```ron
+//! (
+//!     output: "results",
+//!     scenarios: [
+//!         (name: "base", seed: Some(1), ticks: 36000, args: ["--demand-profile", "base.ron"]),
+//!         (name: "rush", seed: Some(1), ticks: 36000, args: ["--demand-profile", "rush.ron"]),
+//!     ],
+//! )
+//! ```
+//!
+//! The arguments of a scenario are the command line arguments of a single
+//! `run`. Each scenario writes its fundamental diagram to `<name>.csv` and
+//! its summary to `<name>-stats.csv` in the output directory, and the
+//! summaries of all scenarios go to `summary.csv` at the end.
+
+use crate::{controller::Controller, model::stateful, Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Batch {
+    /// Directory of the outputs of the scenarios, the working directory by
+    /// default.
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    pub scenarios: Vec<Scenario>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Scenario {
+    /// Name of the scenario, the outputs of the scenario are named after it.
+    pub name: String,
+    /// Seed of the update and of the generated model, instead of the ones
+    /// of the arguments.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Number of updates to run.
+    pub ticks: usize,
+    /// Seconds of each update, 1/60 by default like the window.
+    #[serde(default = "default_dt")]
+    pub dt: f64,
+    /// Command line arguments of the scenario.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from(".")
+}
+
+fn default_dt() -> f64 {
+    1.0 / 60.0
+}
+
+impl Batch {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for scenario in self.scenarios.iter() {
+            if scenario.name.is_empty() || scenario.name.contains(std::path::is_separator) {
+                return Err(format!(
+                    "scenario name {:?} is not a file name",
+                    scenario.name
+                ));
+            }
+            if !names.insert(&scenario.name) {
+                return Err(format!("scenario {:?} appears twice", scenario.name));
+            }
+            if !scenario.dt.is_finite() || scenario.dt <= 0.0 {
+                return Err(format!(
+                    "scenario {:?} has a non positive dt {}",
+                    scenario.name, scenario.dt
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// File of the fundamental diagram of a scenario.
+    pub fn diagram_path(&self, scenario: &Scenario) -> PathBuf {
+        self.output.join(format!("{}.csv", scenario.name))
+    }
+
+    /// File of the summary of a scenario.
+    pub fn stats_path(&self, scenario: &Scenario) -> PathBuf {
+        self.output.join(format!("{}-stats.csv", scenario.name))
+    }
+
+    /// File of the summaries of all scenarios.
+    pub fn summary_path(&self) -> PathBuf {
+        self.output.join("summary.csv")
+    }
+}
+
+/// Measurements of a scenario at the end of its run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub name: String,
+    /// Seconds simulated.
+    pub time: f64,
+    /// Cars in the city at the end.
+    pub cars: usize,
+    /// Cars entered intersections since the end of the warm-up.
+    pub entered: usize,
+    /// Average seconds of control delay of the cars entered.
+    pub control_delay: f64,
+    /// Network density in cars per kilometer at the end.
+    pub density: f64,
+    /// Average flow in cars per hour at the end.
+    pub flow: f64,
+    pub refused_car_outs: usize,
+}
+
+impl Summary {
+    pub fn of(name: &str, controller: &Controller, stateful: &stateful::Model) -> Self {
+        let stats = &controller.stats;
+        let (entered, total_delay) = stats.board.intersections.iter().fold(
+            (0, 0.0),
+            |(entered, total_delay), intersection| {
+                (
+                    entered + intersection.entered,
+                    total_delay + intersection.total_delay,
+                )
+            },
+        );
+        Self {
+            name: name.to_string(),
+            time: controller.time,
+            cars: stateful.cars.iter().filter(|car| car.is_some()).count(),
+            entered,
+            control_delay: if entered == 0 {
+                0.0
+            } else {
+                total_delay / entered as f64
+            },
+            density: stats.density(),
+            flow: stats.flow(),
+            refused_car_outs: stats.refused_car_outs,
+        }
+    }
+}
+
+/// Write `summaries` as a CSV table with a row per scenario.
+pub fn write_summaries<W: Write>(writer: &mut W, summaries: &[Summary]) -> io::Result<()> {
+    writeln!(
+        writer,
+        "scenario,time,cars,entered,control_delay,density,flow,refused_car_outs"
+    )?;
+    for summary in summaries.iter() {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{}",
+            summary.name,
+            summary.time,
+            summary.cars,
+            summary.entered,
+            summary.control_delay,
+            summary.density,
+            summary.flow,
+            summary.refused_car_outs
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenario(name: &str) -> Scenario {
+        Scenario {
+            name: name.to_string(),
+            seed: Some(1),
+            ticks: 10,
+            dt: default_dt(),
+            args: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn batch_outputs_and_summaries() {
+        let mut batch = Batch {
+            output: PathBuf::from("results"),
+            scenarios: vec![scenario("base"), scenario("rush")],
+        };
+        assert_eq!(batch.validate(), Ok(()));
+        assert_eq!(
+            batch.diagram_path(&batch.scenarios[1]),
+            Path::new("results").join("rush.csv")
+        );
+        assert_eq!(
+            batch.stats_path(&batch.scenarios[0]),
+            Path::new("results").join("base-stats.csv")
+        );
+        batch.scenarios[1].name = "base".to_string();
+        assert!(batch.validate().is_err());
+        batch.scenarios[1].name = "a/b".to_string();
+        assert!(batch.validate().is_err());
+
+        let summary = Summary {
+            name: "base".to_string(),
+            time: 60.0,
+            cars: 12,
+            entered: 30,
+            control_delay: 4.5,
+            density: 8.0,
+            flow: 600.0,
+            refused_car_outs: 2,
+        };
+        let mut written = Vec::new();
+        write_summaries(&mut written, &[summary]).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "scenario,time,cars,entered,control_delay,density,flow,refused_car_outs\n\
+             base,60,12,30,4.5,8,600,2\n"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Default command line options read from a RON file given with `--config`
+//! or the `MPI_TRAFFIC_CONFIG` environment variable.
+//!
+//! A config file maps long option names to their values
+//!
+//! ```ron
+//! {
+//!     "seed": 1,
+//!     "turn-policy": "route",
+//!     "reaction-time": 0.8,
+//!     "view-show-grid": true,
+//! }
+//! ```
+//!
+//! The options of the file go before the options of the command line, so
+//! an option given on the command line overrides the one of the file. Only
+//! the `run` command reads a config file.
+
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+/// Environment variable naming the config file when `--config` is not
+/// given.
+pub const CONFIG_ENV: &str = "MPI_TRAFFIC_CONFIG";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum OptionValue {
+    /// A flag, given when true.
+    Flag(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// Values of options by their long names, without the leading dashes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Config {
+    pub options: BTreeMap<String, OptionValue>,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for name in self.options.keys() {
+            if name.is_empty() || name.starts_with('-') {
+                return Err(format!(
+                    "option {:?} is not a long name without dashes",
+                    name
+                ));
+            }
+            if name == "config" {
+                return Err("a config file can not name another one".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Command line arguments of the options, in the order of their names.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, value) in self.options.iter() {
+            let value = match value {
+                OptionValue::Flag(true) => None,
+                // Flags are off unless given
+                OptionValue::Flag(false) => continue,
+                OptionValue::Integer(value) => Some(value.to_string()),
+                OptionValue::Float(value) => Some(value.to_string()),
+                OptionValue::Text(value) => Some(value.clone()),
+            };
+            args.push(format!("--{}", name));
+            args.extend(value);
+        }
+        args
+    }
+
+    /// The command line `args`, starting with the name of the program and
+    /// the subcommand, with the options of the config before its own.
+    pub fn under(&self, mut args: Vec<String>) -> Vec<String> {
+        let rest = args.split_off(args.len().min(2));
+        args.extend(self.args());
+        args.extend(rest);
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn config_options_before_command_line() {
+        let mut config = Config::default();
+        config
+            .options
+            .insert("seed".to_string(), OptionValue::Integer(1));
+        config
+            .options
+            .insert("reaction-time".to_string(), OptionValue::Float(0.8));
+        config
+            .options
+            .insert("view-show-grid".to_string(), OptionValue::Flag(true));
+        config
+            .options
+            .insert("view-show-legend".to_string(), OptionValue::Flag(false));
+        config.options.insert(
+            "turn-policy".to_string(),
+            OptionValue::Text("route".to_string()),
+        );
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(
+            config.under(strings(&["mpi-traffic", "run", "--seed", "2"])),
+            strings(&[
+                "mpi-traffic",
+                "run",
+                "--reaction-time",
+                "0.8",
+                "--seed",
+                "1",
+                "--turn-policy",
+                "route",
+                "--view-show-grid",
+                "--seed",
+                "2",
+            ])
+        );
+        config
+            .options
+            .insert("config".to_string(), OptionValue::Text("a.ron".to_string()));
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Consequences of cars running into each other on a lane.
+
+use crate::model::{
+    common::CarIndex,
+    stateful::{car::Location, Car, LaneKey},
+    stateless,
+};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// What happens to two cars on a lane closer than half the sum of their
+/// lengths.
+///
+/// Only cars on lanes are checked, cars changing lanes or in intersections
+/// are not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// The cars overlap, only the violation checks report it.
+    #[default]
+    Ignore,
+    /// The back car is put right behind the front car, at the velocity of
+    /// the front car if it is slower.
+    Prevent,
+    /// Both cars crash, stand still and block the lane until they are
+    /// removed after the clearance time.
+    Crash,
+}
+
+impl CollisionPolicy {
+    /// Apply the policy to the updated `cars`, crashed cars are removed
+    /// after `clearance_time` seconds.
+    pub fn apply(
+        self,
+        cars: &mut [Option<Car>],
+        stateless: &stateless::Model,
+        clearance_time: f64,
+    ) {
+        if self == CollisionPolicy::Ignore {
+            return;
+        }
+        for lane in lanes(cars).values_mut() {
+            // From the front car back, so a moved car pushes the cars behind
+            for k in (1..lane.len()).rev() {
+                let ((back_position, back), (front_position, front)) = (lane[k - 1], lane[k]);
+                let min_distance =
+                    (stateless.cars[back].length + stateless.cars[front].length) / 2.0;
+                if front_position - back_position >= min_distance {
+                    continue;
+                }
+                match self {
+                    CollisionPolicy::Ignore => (),
+                    CollisionPolicy::Prevent => {
+                        let front_velocity = cars[front].as_ref().unwrap().velocity;
+                        let car = cars[back].as_mut().unwrap();
+                        let position = front_position - min_distance;
+                        if let Location::OnLane { position: p, .. } = &mut car.location {
+                            *p = position;
+                        }
+                        car.velocity = car.velocity.min(front_velocity);
+                        lane[k - 1].0 = position;
+                    }
+                    CollisionPolicy::Crash => {
+                        for index in [back, front].iter() {
+                            let car = cars[*index].as_mut().unwrap();
+                            car.crashed.get_or_insert(clearance_time);
+                            car.velocity = 0.0;
+                            car.acceleration = 0.0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(CollisionPolicy::Ignore),
+            "prevent" => Ok(CollisionPolicy::Prevent),
+            "crash" => Ok(CollisionPolicy::Crash),
+            _ => Err(format!("unknown collision policy: {}", s)),
+        }
+    }
+}
+
+/// Positions and indices of the cars on each lane, by position then index.
+fn lanes(cars: &[Option<Car>]) -> BTreeMap<LaneKey, Vec<(f64, CarIndex)>> {
+    let mut lanes: BTreeMap<LaneKey, Vec<(f64, CarIndex)>> = BTreeMap::new();
+    for (car_index, car) in cars.iter().enumerate() {
+        if let Some(Car {
+            location:
+                Location::OnLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    lane_index,
+                    position,
+                    ..
+                },
+            ..
+        }) = car
+        {
+            lanes
+                .entry((*road_direction, *road_index, *lane_direction, *lane_index))
+                .or_default()
+                .push((*position, car_index));
+        }
+    }
+    for lane in lanes.values_mut() {
+        lane.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    lanes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        stateful::testing::{car_at, on_lane},
+        stateless::car::DrivingModel,
+    };
+
+    fn position(car: &Option<Car>) -> f64 {
+        match car.as_ref().unwrap().location {
+            Location::OnLane { position, .. } => position,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Cars 4.5 meters long.
+    fn model(car_number: usize) -> stateless::Model {
+        let car = stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity: 30.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        };
+        stateless::Model {
+            cars: vec![car; car_number],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prevent_pushes_cars_back() {
+        let stateless = model(3);
+        let mut cars = vec![
+            car_at(on_lane(0, 10.0), 8.0),
+            car_at(on_lane(0, 12.0), 5.0),
+            car_at(on_lane(0, 30.0), 10.0),
+        ];
+        CollisionPolicy::Prevent.apply(&mut cars, &stateless, 60.0);
+        assert_eq!(position(&cars[0]), 7.5);
+        assert_eq!(cars[0].as_ref().unwrap().velocity, 5.0);
+        assert_eq!(position(&cars[1]), 12.0);
+        assert_eq!(position(&cars[2]), 30.0);
+        assert!(cars
+            .iter()
+            .all(|car| car.as_ref().unwrap().crashed.is_none()));
+    }
+
+    #[test]
+    fn crash_stops_both_cars() {
+        let stateless = model(3);
+        let mut cars = vec![
+            car_at(on_lane(0, 10.0), 8.0),
+            car_at(on_lane(0, 12.0), 5.0),
+            car_at(on_lane(0, 30.0), 10.0),
+        ];
+        CollisionPolicy::Ignore.apply(&mut cars, &stateless, 60.0);
+        assert!(cars
+            .iter()
+            .all(|car| car.as_ref().unwrap().crashed.is_none()));
+        CollisionPolicy::Crash.apply(&mut cars, &stateless, 60.0);
+        let crashed = cars
+            .iter()
+            .map(|car| {
+                let car = car.as_ref().unwrap();
+                (car.crashed, car.velocity)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            crashed,
+            vec![(Some(60.0), 0.0), (Some(60.0), 0.0), (None, 10.0)]
+        );
+        assert_eq!(position(&cars[0]), 10.0);
+    }
+}
@@ -0,0 +1,168 @@
+//! Count and occupancy of the detectors of a model, written as CSV.
+
+use crate::model::{
+    stateful::{self, car::Location, CarHandle},
+    stateless::Detector,
+};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+#[derive(Clone, Debug, Default)]
+struct DetectorState {
+    /// Cars on the detector in the last update.
+    cars: HashSet<CarHandle>,
+    /// Cars entered the detector in current interval.
+    count: usize,
+    /// Seconds the detector is occupied in current interval.
+    occupied_time: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct DetectorRecorder {
+    pub detectors: Vec<Detector>,
+    /// Seconds aggregated in a record.
+    pub interval: f64,
+    states: Vec<DetectorState>,
+    interval_start: f64,
+}
+
+impl DetectorRecorder {
+    pub fn new(detectors: Vec<Detector>, interval: f64) -> Self {
+        let states = vec![Default::default(); detectors.len()];
+        Self {
+            detectors,
+            interval,
+            states,
+            interval_start: 0.0,
+        }
+    }
+
+    pub fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "time,detector,count,occupancy")
+    }
+
+    /// Detect cars after an update which ends at `time`, and write records of
+    /// all detectors when an interval is over.
+    pub fn update<W: Write>(
+        &mut self,
+        time: f64,
+        dt: f64,
+        stateful: &stateful::Model,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        for (detector, state) in self.detectors.iter().zip(self.states.iter_mut()) {
+            let cars = stateful
+                .cars
+                .iter()
+                .enumerate()
+                .filter_map(|(index, car)| Some((index, car.as_ref()?)))
+                .filter(|(_, car)| Self::on_detector(detector, car))
+                .filter_map(|(index, _)| stateful.handle(index))
+                .collect::<HashSet<_>>();
+            state.count += cars.difference(&state.cars).count();
+            if !cars.is_empty() {
+                state.occupied_time += dt;
+            }
+            state.cars = cars;
+        }
+        if time - self.interval_start >= self.interval {
+            let duration = time - self.interval_start;
+            for (index, state) in self.states.iter_mut().enumerate() {
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    time,
+                    index,
+                    state.count,
+                    state.occupied_time / duration
+                )?;
+                state.count = 0;
+                state.occupied_time = 0.0;
+            }
+            self.interval_start = time;
+        }
+        Ok(())
+    }
+
+    fn on_detector(detector: &Detector, car: &stateful::Car) -> bool {
+        match car.location {
+            Location::OnLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                lane_index,
+                position,
+                ..
+            } => {
+                (road_direction, road_index, lane_direction, lane_index)
+                    == (
+                        detector.road_direction,
+                        detector.road_index,
+                        detector.lane_direction,
+                        detector.lane_index,
+                    )
+                    && detector.covers(position)
+            }
+            Location::ChangingLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                from_lane_index,
+                to_lane_index,
+                position,
+                ..
+            } => {
+                (road_direction, road_index, lane_direction)
+                    == (
+                        detector.road_direction,
+                        detector.road_index,
+                        detector.lane_direction,
+                    )
+                    && (from_lane_index == detector.lane_index
+                        || to_lane_index == detector.lane_index)
+                    && detector.covers(position)
+            }
+            Location::InIntersection { .. } => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        common::{AxisDirection, LaneDirection},
+        stateful::testing::{car_at, on_road},
+    };
+
+    #[test]
+    fn count_and_occupancy() {
+        let detector = Detector {
+            road_direction: AxisDirection::Vertical,
+            road_index: (0, 1),
+            lane_direction: LaneDirection::HighToLow,
+            lane_index: 0,
+            position: 50.0,
+            length: 2.0,
+        };
+        let mut recorder = DetectorRecorder::new(vec![detector], 1.0);
+        let mut output = Vec::new();
+        let mut model = stateful::Model::default();
+        for (time, position) in [(0.25, 49.0), (0.5, 51.0), (0.75, 51.5), (1.0, 60.0)].iter() {
+            model.replace_cars(vec![car_at(
+                on_road(
+                    AxisDirection::Vertical,
+                    (0, 1),
+                    LaneDirection::HighToLow,
+                    0,
+                    *position,
+                ),
+                10.0,
+            )]);
+            recorder.update(*time, 0.25, &model, &mut output).unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), "1,0,1,0.5\n");
+    }
+}
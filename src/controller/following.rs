@@ -0,0 +1,312 @@
+//! Longitudinal car following models.
+//!
+//! Every model computes the acceleration a driver chooses given the gap to
+//! the object in front of it. The object may be a car or a virtual one, such
+//! as the stop line of an intersection.
+
+use crate::{
+    controller::params::SimParams,
+    model::stateless::{self, car::DrivingModel},
+};
+use std::{fmt, str::FromStr};
+
+pub trait CarFollowingModel: fmt::Debug + Send {
+    /// Acceleration of a car with `velocity`, `gap` meters behind an object
+    /// moving at `leader_velocity`.
+    fn acceleration(
+        &self,
+        gap: f64,
+        velocity: f64,
+        leader_velocity: f64,
+        parameters: &stateless::Car,
+    ) -> f64;
+
+    fn box_clone(&self) -> Box<dyn CarFollowingModel>;
+}
+
+impl Clone for Box<dyn CarFollowingModel> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Cushion model, driven by the `DrivingModel` of the car.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cushion;
+
+impl CarFollowingModel for Cushion {
+    fn acceleration(
+        &self,
+        gap: f64,
+        velocity: f64,
+        leader_velocity: f64,
+        parameters: &stateless::Car,
+    ) -> f64 {
+        match &parameters.driving_model {
+            DrivingModel::Normal {
+                min_cushion,
+                cushion_velocity_factor,
+                prediction_time,
+            } => {
+                let aim_cushion = min_cushion + cushion_velocity_factor * leader_velocity;
+                let dx = gap - aim_cushion; // if dx is greater than 0, the car should go faster than leader_velocity
+                let aim_average_velocity = dx / *prediction_time;
+                (aim_average_velocity + leader_velocity - velocity) * 2.0 / *prediction_time
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn CarFollowingModel> {
+        Box::new(*self)
+    }
+}
+
+/// Intelligent driver model.
+#[derive(Clone, Copy, Debug)]
+pub struct Idm {
+    pub time_headway: f64,
+    pub min_gap: f64,
+    pub exponent: f64,
+}
+
+impl Default for Idm {
+    fn default() -> Self {
+        Self {
+            time_headway: 1.5,
+            min_gap: 2.0,
+            exponent: 4.0,
+        }
+    }
+}
+
+impl CarFollowingModel for Idm {
+    fn acceleration(
+        &self,
+        gap: f64,
+        velocity: f64,
+        leader_velocity: f64,
+        parameters: &stateless::Car,
+    ) -> f64 {
+        let a = parameters.max_acceleration;
+        let b = parameters.max_break_acceleration;
+        let desired_gap = self.min_gap
+            + (velocity * self.time_headway
+                + velocity * (velocity - leader_velocity) / (2.0 * (a * b).sqrt()))
+            .max(0.0);
+        let free = (velocity / parameters.max_velocity).powf(self.exponent);
+        let interaction = (desired_gap / gap.max(f64::EPSILON)).powi(2);
+        a * (1.0 - free - interaction)
+    }
+
+    fn box_clone(&self) -> Box<dyn CarFollowingModel> {
+        Box::new(*self)
+    }
+}
+
+/// Gipps model, the acceleration reaches the velocity chosen by Gipps'
+/// formula within one reaction time.
+#[derive(Clone, Copy, Debug)]
+pub struct Gipps {
+    pub reaction_time: f64,
+    pub min_gap: f64,
+}
+
+impl Default for Gipps {
+    fn default() -> Self {
+        Self {
+            reaction_time: 1.0,
+            min_gap: 2.0,
+        }
+    }
+}
+
+impl CarFollowingModel for Gipps {
+    fn acceleration(
+        &self,
+        gap: f64,
+        velocity: f64,
+        leader_velocity: f64,
+        parameters: &stateless::Car,
+    ) -> f64 {
+        let tau = self.reaction_time;
+        let a = parameters.max_acceleration;
+        let b = parameters.max_break_acceleration;
+        let max_velocity = parameters.max_velocity;
+        let ratio = (velocity / max_velocity).min(1.0);
+        let free = velocity + 2.5 * a * tau * (1.0 - ratio) * (0.025 + ratio).sqrt();
+        let discriminant = b * b * tau * tau
+            + b * (2.0 * (gap - self.min_gap) - velocity * tau
+                + leader_velocity * leader_velocity / b);
+        let safe = if discriminant > 0.0 {
+            -b * tau + discriminant.sqrt()
+        } else {
+            0.0
+        };
+        (free.min(safe).max(0.0) - velocity) / tau
+    }
+
+    fn box_clone(&self) -> Box<dyn CarFollowingModel> {
+        Box::new(*self)
+    }
+}
+
+/// Safe distance model after Krauss, the car never drives faster than the
+/// velocity which still allows stopping behind a braking leader.
+#[derive(Clone, Copy, Debug)]
+pub struct SafeDistance {
+    pub reaction_time: f64,
+    pub min_gap: f64,
+}
+
+impl Default for SafeDistance {
+    fn default() -> Self {
+        Self {
+            reaction_time: 1.0,
+            min_gap: 2.0,
+        }
+    }
+}
+
+impl CarFollowingModel for SafeDistance {
+    fn acceleration(
+        &self,
+        gap: f64,
+        velocity: f64,
+        leader_velocity: f64,
+        parameters: &stateless::Car,
+    ) -> f64 {
+        let tau = self.reaction_time;
+        let b = parameters.max_break_acceleration;
+        let safe = leader_velocity
+            + (gap - self.min_gap - leader_velocity * tau)
+                / ((velocity + leader_velocity) / (2.0 * b) + tau);
+        let desired = safe
+            .min(velocity + parameters.max_acceleration * tau)
+            .min(parameters.max_velocity)
+            .max(0.0);
+        (desired - velocity) / tau
+    }
+
+    fn box_clone(&self) -> Box<dyn CarFollowingModel> {
+        Box::new(*self)
+    }
+}
+
+/// Selects a `CarFollowingModel` from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CarFollowingModelKind {
+    Cushion,
+    Idm,
+    Gipps,
+    SafeDistance,
+}
+
+impl CarFollowingModelKind {
+    /// Build the model with the gap and headway of `params`.
+    ///
+    /// The cushion model keeps the cushion of the `DrivingModel` of each car.
+    pub fn build(self, params: &SimParams) -> Box<dyn CarFollowingModel> {
+        match self {
+            CarFollowingModelKind::Cushion => Box::new(Cushion),
+            CarFollowingModelKind::Idm => Box::new(Idm {
+                time_headway: params.time_headway,
+                min_gap: params.min_gap,
+                ..Default::default()
+            }),
+            CarFollowingModelKind::Gipps => Box::new(Gipps {
+                min_gap: params.min_gap,
+                ..Default::default()
+            }),
+            CarFollowingModelKind::SafeDistance => Box::new(SafeDistance {
+                min_gap: params.min_gap,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl FromStr for CarFollowingModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cushion" => Ok(CarFollowingModelKind::Cushion),
+            "idm" => Ok(CarFollowingModelKind::Idm),
+            "gipps" => Ok(CarFollowingModelKind::Gipps),
+            "safe-distance" => Ok(CarFollowingModelKind::SafeDistance),
+            _ => Err(format!("unknown car following model: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_car() -> stateless::Car {
+        stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity: 30.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        }
+    }
+
+    fn all_models() -> Vec<Box<dyn CarFollowingModel>> {
+        ["cushion", "idm", "gipps", "safe-distance"]
+            .iter()
+            .map(|s| {
+                s.parse::<CarFollowingModelKind>()
+                    .unwrap()
+                    .build(&SimParams::default())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accelerate_on_free_road() {
+        let car = example_car();
+        for model in all_models() {
+            let acceleration = model.acceleration(1000.0, 0.0, 0.0, &car);
+            assert!(acceleration > 0.0, "{:?}: {}", model, acceleration);
+        }
+    }
+
+    #[test]
+    fn break_behind_stopped_leader() {
+        let car = example_car();
+        for model in all_models() {
+            let acceleration = model.acceleration(3.0, 20.0, 0.0, &car);
+            assert!(acceleration < 0.0, "{:?}: {}", model, acceleration);
+        }
+    }
+
+    #[test]
+    fn longer_headway_keeps_longer_gap() {
+        let car = example_car();
+        let params = SimParams {
+            time_headway: 3.0,
+            ..Default::default()
+        };
+        let idm = CarFollowingModelKind::Idm.build(&SimParams::default());
+        let patient_idm = CarFollowingModelKind::Idm.build(&params);
+        // Following at 20 m/s, 40 meters is more than enough for a headway of
+        // 1.5 seconds but too short for 3 seconds
+        assert!(idm.acceleration(40.0, 20.0, 20.0, &car) > 0.0);
+        assert!(patient_idm.acceleration(40.0, 20.0, 20.0, &car) < 0.0);
+    }
+
+    #[test]
+    fn parse_unknown_kind() {
+        assert!("unknown".parse::<CarFollowingModelKind>().is_err());
+    }
+}
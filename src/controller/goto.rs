@@ -0,0 +1,95 @@
+//! Moving the view to an intersection by typing its coordinates.
+
+use super::Controller;
+use crate::{
+    info::Info,
+    model::{board::IntersectionIndex, stateless},
+};
+use piston_window::{Button, ButtonArgs, ButtonState, Input, Key};
+
+impl Controller {
+    /// Read the coordinates of an intersection after the G key, and return
+    /// whether `input` was used.
+    ///
+    /// While the coordinates are typed, the keyboard only edits them:
+    /// Backspace deletes a character, Enter moves the view to the
+    /// intersection and Escape cancels.
+    pub fn goto_input(
+        &mut self,
+        info: &mut Info,
+        stateless: &stateless::Model,
+        input: &Input,
+    ) -> bool {
+        let text = match info.goto_input.as_mut() {
+            Some(text) => text,
+            None => {
+                if let Input::Button(ButtonArgs {
+                    state: ButtonState::Press,
+                    button: Button::Keyboard(Key::G),
+                    ..
+                }) = input
+                {
+                    info.goto_input = Some(String::new());
+                    return true;
+                }
+                return false;
+            }
+        };
+        match input {
+            // The G key is also typed as text, keep only what makes
+            // coordinates
+            Input::Text(typed) => text.extend(
+                typed
+                    .chars()
+                    .filter(|c| c.is_ascii_digit() || ", ()".contains(*c)),
+            ),
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(key),
+                ..
+            }) => match key {
+                Key::Backspace => {
+                    text.pop();
+                }
+                Key::Return | Key::NumPadEnter => {
+                    match parse_intersection(text, &stateless.city) {
+                        Ok(index) => info.goto_intersection = Some(index),
+                        Err(e) => log::warn!("cannot go to intersection: {}", e),
+                    }
+                    info.goto_input = None;
+                }
+                Key::Escape => info.goto_input = None,
+                _ => (),
+            },
+            Input::Button(ButtonArgs {
+                button: Button::Keyboard(_),
+                ..
+            }) => (),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Parse the index of an intersection of `city` written as `i, j`, with or
+/// without parentheses.
+pub fn parse_intersection(s: &str, city: &stateless::City) -> Result<IntersectionIndex, String> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(s);
+    let coordinates = s
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<usize>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let index = match coordinates[..] {
+        [i, j] => (i, j),
+        _ => return Err(format!("expected two coordinates, got {:?}", s)),
+    };
+    match city.board.intersections.get(index) {
+        Some(Some(_)) => Ok(index),
+        _ => Err(format!("no intersection at {:?}", index)),
+    }
+}
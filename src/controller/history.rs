@@ -0,0 +1,100 @@
+use crate::model::stateful;
+use std::collections::VecDeque;
+
+/// Network-wide metrics sampled once per update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub time: f64,
+    pub average_speed: f64,
+    pub car_number: usize,
+}
+
+impl Sample {
+    pub fn of_model(time: f64, stateful: &stateful::Model) -> Self {
+        let (car_number, total_speed) = stateful
+            .cars
+            .iter()
+            .filter_map(|car| car.as_ref())
+            .fold((0, 0.0), |(number, total), car| {
+                (number + 1, total + car.velocity)
+            });
+        let average_speed = if car_number == 0 {
+            0.0
+        } else {
+            total_speed / car_number as f64
+        };
+        Self {
+            time,
+            average_speed,
+            car_number,
+        }
+    }
+}
+
+/// Ring buffer keeping samples of the last `window` seconds.
+#[derive(Clone, Debug)]
+pub struct History {
+    pub window: f64,
+    samples: VecDeque<Sample>,
+}
+
+impl History {
+    pub fn new(window: f64) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        let oldest = sample.time - self.window;
+        while let Some(front) = self.samples.front() {
+            if front.time < oldest {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> + Clone {
+        self.samples.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn latest(&self) -> Option<&Sample> {
+        self.samples.back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: f64) -> Sample {
+        Sample {
+            time,
+            average_speed: time,
+            car_number: 0,
+        }
+    }
+
+    #[test]
+    fn push_drops_old_samples() {
+        let mut history = History::new(1.0);
+        for i in 0..10 {
+            history.push(sample(i as f64 * 0.25));
+        }
+        assert_eq!(history.len(), 5);
+        assert_eq!(history.samples().next().unwrap().time, 1.25);
+        assert_eq!(history.latest().unwrap().time, 2.25);
+    }
+}
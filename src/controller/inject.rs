@@ -0,0 +1,144 @@
+use super::{Controller, UpdateController};
+use crate::model::{
+    common::{CarIndex, LaneIndex, RelativeDirection},
+    routing::{Route, Segment},
+    stateful, stateless,
+};
+use quick_error::quick_error;
+
+quick_error! {
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum InjectError {
+        NoLane(segment: Segment, lane: LaneIndex) {
+            display("lane {} of {:?} is out of range", lane, segment)
+        }
+        PositionOutOfRange(position: f64, length: f64) {
+            display("position {} is out of the road of {} meters", position, length)
+        }
+        Occupied(car_index: CarIndex) {
+            display("the spot is occupied by car {}", car_index)
+        }
+        NoFreeSlot {
+            display("all car slots are taken")
+        }
+        InvalidRoute {
+            display("the route can not be followed from the lane")
+        }
+    }
+}
+
+impl Controller {
+    /// Put a car on `lane` of `road` at `position`, see
+    /// `UpdateController::inject_car`.
+    pub fn inject_car(
+        &mut self,
+        stateful: &mut stateful::Model,
+        stateless: &stateless::Model,
+        road: Segment,
+        lane: LaneIndex,
+        position: f64,
+        route: Route,
+    ) -> Result<CarIndex, InjectError> {
+        self.clear_preview();
+        self.update_controller
+            .inject_car(stateful, stateless, road, lane, position, route)
+    }
+}
+
+impl UpdateController {
+    /// Put a stopped car on `lane` of `road` at `position` meters from the
+    /// start of the road, in the first empty slot.
+    ///
+    /// The car drives through the segments of `route` after `road`, and
+    /// turns to the first turn its lane allows without a route. The car
+    /// keeps the min gap to the cars on the lane. Every process updates its
+    /// own copy of the model, so inject the same car on every process.
+    pub fn inject_car(
+        &self,
+        stateful: &mut stateful::Model,
+        stateless: &stateless::Model,
+        road: Segment,
+        lane: LaneIndex,
+        position: f64,
+        mut route: Route,
+    ) -> Result<CarIndex, InjectError> {
+        let turn_rule = stateless
+            .city
+            .board
+            .get_road(road.road_direction, road.road_index)
+            .and_then(Option::as_ref)
+            .and_then(|stateless_road| {
+                stateless_road
+                    .lanes_to_direction(road.lane_direction)
+                    .get(lane)
+            })
+            .ok_or(InjectError::NoLane(road, lane))?
+            .direction_rule;
+        let length = stateless
+            .city
+            .road_length(road.road_direction, road.road_index);
+        if !(0.0..=length).contains(&position) {
+            return Err(InjectError::PositionOutOfRange(position, length));
+        }
+        let car_index = (0..stateless.cars.len())
+            .find(|index| !matches!(stateful.cars.get(*index), Some(Some(_))))
+            .ok_or(InjectError::NoFreeSlot)?;
+        let car_length = stateless.cars[car_index].length;
+        let lane_key = (
+            road.road_direction,
+            road.road_index,
+            road.lane_direction,
+            lane,
+        );
+        for (other_position, other_index) in stateful.lanes.cars(lane_key) {
+            let clearance =
+                (car_length + stateless.cars[*other_index].length) / 2.0 + self.params.min_gap;
+            if (other_position - position).abs() < clearance {
+                return Err(InjectError::Occupied(*other_index));
+            }
+        }
+        if route.first() == Some(&road) {
+            route.remove(0);
+        }
+        let about_to_turn = if route.is_empty() {
+            [
+                RelativeDirection::Front,
+                RelativeDirection::Right,
+                RelativeDirection::Left,
+                RelativeDirection::Back,
+            ]
+            .iter()
+            .copied()
+            .find(|turn| turn_rule.contains(turn.to_turn_rule()))
+        } else {
+            self.turn_to_follow(road, &route, turn_rule)
+        }
+        .ok_or(InjectError::InvalidRoute)?;
+        let destination = route
+            .last()
+            .map(|segment| segment.to_intersection(&stateless.city));
+        let mut cars = stateful.cars.clone();
+        if cars.len() <= car_index {
+            cars.resize(car_index + 1, None);
+        }
+        cars[car_index] = Some(stateful::Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: road.road_direction,
+                road_index: road.road_index,
+                lane_direction: road.lane_direction,
+                lane_index: lane,
+                about_to_turn,
+                position,
+            },
+            velocity: 0.0,
+            acceleration: 0.0,
+            perception: Default::default(),
+            crashed: None,
+            id: stateful.next_car_id,
+            destination,
+            route,
+        });
+        stateful.replace_cars(cars);
+        Ok(car_index)
+    }
+}
@@ -0,0 +1,311 @@
+//! Expensive checks of the consistency of a whole model, run after every
+//! update while developing to stop at the first inconsistent update.
+
+use super::violation::{self, Violation};
+use crate::model::{
+    board::IntersectionIndex,
+    common::{AbsoluteDirection, CarIndex, InOutDirection, LaneDirection, LaneIndex},
+    stateful::{self, car::Location, Car, LaneCars, LaneKey},
+    stateless::{self, Movement},
+};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inconsistency {
+    /// Cars overlap or move invalidly.
+    Violation(Violation),
+    /// The location of a car refers to a road, lane or intersection which
+    /// does not exist.
+    InvalidLocation {
+        car_index: CarIndex,
+        location: Location,
+    },
+    /// The cars of a lane are not in driving order.
+    UnsortedLane { key: LaneKey },
+    /// The lists of cars on lanes differ from the locations of the cars.
+    LanesOutOfSync,
+    /// The movements occupying an intersection differ from the cars in it
+    /// when it was updated.
+    Occupancy {
+        intersection_index: IntersectionIndex,
+        expected: Vec<Movement>,
+        actual: Vec<Movement>,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inconsistency::Violation(violation) => write!(f, "{}", violation),
+            Inconsistency::InvalidLocation {
+                car_index,
+                location,
+            } => write!(
+                f,
+                "car {} is at an invalid location {:?}",
+                car_index, location
+            ),
+            Inconsistency::UnsortedLane { key } => {
+                write!(f, "cars of lane {:?} are not in driving order", key)
+            }
+            Inconsistency::LanesOutOfSync => {
+                write!(f, "cars of the lanes differ from the locations of the cars")
+            }
+            Inconsistency::Occupancy {
+                intersection_index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "intersection {:?} is occupied by {:?} instead of {:?}",
+                intersection_index, actual, expected
+            ),
+        }
+    }
+}
+
+/// Find the inconsistencies of `stateful`, with cars closer than
+/// `min_distance` on a lane overlapping.
+///
+/// The occupancy of intersections is checked against `occupants`, the cars
+/// when the intersections were last updated, if given.
+pub fn find_inconsistencies(
+    stateful: &stateful::Model,
+    stateless: &stateless::Model,
+    occupants: Option<&[Option<Car>]>,
+    min_distance: f64,
+) -> Vec<Inconsistency> {
+    let mut inconsistencies = violation::find_violations(stateful, min_distance)
+        .into_iter()
+        .map(Inconsistency::Violation)
+        .collect::<Vec<_>>();
+    for (car_index, car) in stateful.cars.iter().enumerate() {
+        if let Some(car) = car {
+            if !valid_location(&car.location, &stateless.city) {
+                inconsistencies.push(Inconsistency::InvalidLocation {
+                    car_index,
+                    location: car.location.clone(),
+                });
+            }
+        }
+    }
+    for (key, cars) in stateful.lanes.iter() {
+        let unsorted = cars.windows(2).any(|pair| {
+            let ((back_position, back), (front_position, front)) = (pair[0], pair[1]);
+            back_position
+                .total_cmp(&front_position)
+                .then(back.cmp(&front))
+                .is_ge()
+        });
+        if unsorted {
+            inconsistencies.push(Inconsistency::UnsortedLane { key });
+        }
+    }
+    if stateful.lanes != LaneCars::from_cars(&stateful.cars) {
+        inconsistencies.push(Inconsistency::LanesOutOfSync);
+    }
+    if let Some(occupants) = occupants {
+        let (m, n) = stateful.city.board.shape();
+        for intersection_index in (0..m).flat_map(|i| (0..n).map(move |j| (i, j))) {
+            let intersection = match &stateful.city.board.intersections[intersection_index] {
+                Some(intersection) => intersection,
+                None => continue,
+            };
+            let mut expected = movements_in(intersection_index, occupants);
+            let mut actual = intersection.occupancy().clone();
+            expected.sort_by_key(movement_key);
+            actual.sort_by_key(movement_key);
+            if expected != actual {
+                inconsistencies.push(Inconsistency::Occupancy {
+                    intersection_index,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+    inconsistencies
+}
+
+/// Whether the road, lanes and intersection of `location` exist.
+fn valid_location(location: &Location, city: &stateless::City) -> bool {
+    let lane_exists = |road_direction, road_index, lane_direction, lane_index: LaneIndex| {
+        city.board
+            .get_road(road_direction, road_index)
+            .and_then(Option::as_ref)
+            .map_or(false, |road: &stateless::Road| {
+                lane_index < road.lanes_to_direction(lane_direction).len()
+            })
+    };
+    match *location {
+        Location::OnLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            lane_index,
+            ..
+        } => lane_exists(road_direction, road_index, lane_direction, lane_index),
+        Location::ChangingLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            from_lane_index,
+            to_lane_index,
+            ..
+        } => {
+            lane_exists(road_direction, road_index, lane_direction, from_lane_index)
+                && lane_exists(road_direction, road_index, lane_direction, to_lane_index)
+        }
+        Location::InIntersection {
+            intersection_index,
+            from_direction,
+            from_lane_index,
+            to_direction,
+            to_lane_index,
+            ..
+        } => {
+            if !matches!(
+                city.board.intersections.get(intersection_index),
+                Some(Some(_))
+            ) {
+                return false;
+            }
+            let context = city.board.context_of_intersection(intersection_index);
+            let approach_lane_exists =
+                |direction: AbsoluteDirection, in_out: InOutDirection, lane_index| {
+                    (*context.get(direction)).map_or(false, |road_index| {
+                        lane_exists(
+                            direction.axis_direction(),
+                            road_index,
+                            LaneDirection::absolute_in_out_to_lane(direction, in_out),
+                            lane_index,
+                        )
+                    })
+                };
+            approach_lane_exists(from_direction, InOutDirection::In, from_lane_index)
+                && approach_lane_exists(to_direction, InOutDirection::Out, to_lane_index)
+        }
+    }
+}
+
+/// Movements of the cars in the intersection at `intersection_index`, each
+/// once.
+fn movements_in(intersection_index: IntersectionIndex, cars: &[Option<Car>]) -> Vec<Movement> {
+    let mut movements = Vec::new();
+    for car in cars.iter().filter_map(|car| car.as_ref()) {
+        if let Location::InIntersection {
+            intersection_index: index,
+            from_direction,
+            from_lane_index,
+            to_direction,
+            ..
+        } = car.location
+        {
+            let movement = Movement {
+                from_direction,
+                from_lane_index,
+                to_direction,
+            };
+            if index == intersection_index && !movements.contains(&movement) {
+                movements.push(movement);
+            }
+        }
+    }
+    movements
+}
+
+fn movement_key(movement: &Movement) -> (usize, LaneIndex, usize) {
+    (
+        movement.from_direction as usize,
+        movement.from_lane_index,
+        movement.to_direction as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        common::AxisDirection,
+        generate::stateful::generate_from_stateless,
+        stateful::testing::{car_at, on_lane},
+        stateless::CityBuilder,
+    };
+
+    #[test]
+    fn find_inconsistencies_of_a_model() {
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road_length(AxisDirection::Horizontal, 0, 100.0)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let entering = Location::InIntersection {
+            intersection_index: (0, 1),
+            from_direction: AbsoluteDirection::West,
+            from_lane_index: 0,
+            to_direction: AbsoluteDirection::West,
+            to_lane_index: 0,
+            total_length: 10.0,
+            position: 1.0,
+        };
+        stateful.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 5.0),
+            car_at(on_lane(0, 30.0), 5.0),
+            car_at(entering.clone(), 5.0),
+        ]);
+        let occupants = stateful.cars.clone();
+        stateful.city.board.intersections[(0, 1)]
+            .as_mut()
+            .unwrap()
+            .occupancy_mut()
+            .push(Movement {
+                from_direction: AbsoluteDirection::West,
+                from_lane_index: 0,
+                to_direction: AbsoluteDirection::West,
+            });
+        let check = |stateful: &stateful::Model| {
+            find_inconsistencies(stateful, &stateless, Some(&occupants), 1.0)
+        };
+        assert_eq!(check(&stateful), Vec::new());
+
+        let mut overlapping = stateful.clone();
+        overlapping.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 5.0),
+            car_at(on_lane(0, 10.5), 5.0),
+            car_at(entering, 5.0),
+        ]);
+        assert!(matches!(
+            check(&overlapping)[..],
+            [Inconsistency::Violation(Violation::Overlap { .. })]
+        ));
+
+        let mut off_road = stateful.clone();
+        off_road.cars[1] = car_at(on_lane(1, 30.0), 5.0);
+        let inconsistencies = check(&off_road);
+        assert!(inconsistencies.contains(&Inconsistency::InvalidLocation {
+            car_index: 1,
+            location: on_lane(1, 30.0),
+        }));
+        // The car was moved without updating the lanes
+        assert!(inconsistencies.contains(&Inconsistency::LanesOutOfSync));
+
+        let mut vacated = stateful.clone();
+        vacated.city.board.intersections[(0, 1)]
+            .as_mut()
+            .unwrap()
+            .occupancy_mut()
+            .clear();
+        assert!(matches!(
+            check(&vacated)[..],
+            [Inconsistency::Occupancy {
+                intersection_index: (0, 1),
+                ..
+            }]
+        ));
+    }
+}
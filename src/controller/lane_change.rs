@@ -0,0 +1,344 @@
+//! Discretionary lane changes by the MOBIL rule, and mandatory ones.
+//!
+//! A driver changes to an adjacent lane when the car behind it there does
+//! not have to brake harder than the safe deceleration, and when its own
+//! gain in acceleration, plus the politeness times the gains of the cars
+//! behind it on both lanes, exceeds the lane change threshold. A driver on a
+//! lane which does not allow the next turn of its route changes towards a
+//! lane which does whatever the gain, as soon as it is safe.
+
+use super::{bumper_gap, params::SimParams, UpdateController};
+use crate::model::{
+    common::{CarIndex, LaneIndex, RelativeDirection},
+    stateful::{self, car::Location, LaneKey},
+    stateless,
+};
+use std::cmp::Ordering;
+
+/// Accelerations of the drivers a lane change concerns, before and after
+/// the change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LaneChangeAccelerations {
+    /// Of the changing car.
+    pub own: (f64, f64),
+    /// Of the car it gets in front of on the new lane, if any.
+    pub new_follower: Option<(f64, f64)>,
+    /// Of the car it leaves behind on its lane, if any.
+    pub old_follower: Option<(f64, f64)>,
+}
+
+impl LaneChangeAccelerations {
+    /// Whether the change is safe and worth it by the MOBIL rule with the
+    /// politeness and thresholds of `params`.
+    pub fn change(&self, params: &SimParams) -> bool {
+        let gain = |(before, after): (f64, f64)| after - before;
+        let safe = self
+            .new_follower
+            .map_or(true, |(_, after)| after >= -params.safe_deceleration);
+        let others = self.new_follower.map_or(0.0, gain) + self.old_follower.map_or(0.0, gain);
+        safe && gain(self.own) + params.politeness * others > params.lane_change_threshold
+    }
+}
+
+/// Position of a car on a lane, none in intersections.
+fn lane_position(car: &stateful::Car) -> Option<f64> {
+    match car.location {
+        Location::OnLane { position, .. } | Location::ChangingLane { position, .. } => {
+            Some(position)
+        }
+        Location::InIntersection { .. } => None,
+    }
+}
+
+/// Whether the car at `car_index`, at `position`, fits between `follower`
+/// and `leader` on a new lane.
+fn fits_between(
+    car_index: CarIndex,
+    position: f64,
+    follower: Option<CarIndex>,
+    leader: Option<CarIndex>,
+    stateful: &stateful::Model,
+    stateless: &stateless::Model,
+) -> bool {
+    let clearance = |other: CarIndex| {
+        let other_position = lane_position(stateful.cars[other].as_ref()?)?;
+        let length = (stateless.cars[car_index].length + stateless.cars[other].length) / 2.0;
+        Some((other_position - position).abs() - length)
+    };
+    follower
+        .iter()
+        .chain(leader.iter())
+        .all(|other| clearance(*other).map_or(false, |gap| gap > 0.0))
+}
+
+impl UpdateController {
+    /// Adjacent lane the car at `car_index`, at `position` on the lane of
+    /// `lane_key` and about to turn to `about_to_turn`, changes to, if any.
+    ///
+    /// Only lanes allowing the class of the car and its turn count, and the
+    /// one with the larger incentive is chosen if both sides qualify. Cars
+    /// in intersections and the stop line are left out of the
+    /// accelerations, they are the same on both lanes.
+    pub fn discretionary_lane_change(
+        &self,
+        car_index: CarIndex,
+        lane_key: LaneKey,
+        position: f64,
+        about_to_turn: RelativeDirection,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> Option<LaneIndex> {
+        let (road_direction, road_index, lane_direction, lane_index) = lane_key;
+        let lanes = stateless.city.board.get_roads(road_direction)[road_index]
+            .as_ref()?
+            .lanes_to_direction(lane_direction);
+        let class = stateless.cars[car_index].class;
+        let (old_follower, old_leader) = stateful.lanes.neighbours(lane_key, position, car_index);
+        let own_before = self.following_acceleration(
+            car_index,
+            position,
+            old_leader,
+            &lanes[lane_index],
+            stateful,
+            stateless,
+        );
+        let old_follower = old_follower.and_then(|follower| {
+            let follower_position = lane_position(stateful.cars[follower].as_ref()?)?;
+            let lane = &lanes[lane_index];
+            Some((
+                self.following_acceleration(
+                    follower,
+                    follower_position,
+                    Some(car_index),
+                    lane,
+                    stateful,
+                    stateless,
+                ),
+                self.following_acceleration(
+                    follower,
+                    follower_position,
+                    old_leader,
+                    lane,
+                    stateful,
+                    stateless,
+                ),
+            ))
+        });
+        let candidates = [lane_index.checked_sub(1), Some(lane_index + 1)];
+        candidates
+            .iter()
+            .filter_map(|target| {
+                let target = (*target)?;
+                let lane = lanes.get(target)?;
+                if !lane.allows(class)
+                    || !lane.direction_rule.contains(about_to_turn.to_turn_rule())
+                {
+                    return None;
+                }
+                let target_key = (road_direction, road_index, lane_direction, target);
+                let (new_follower, new_leader) =
+                    stateful.lanes.neighbours(target_key, position, car_index);
+                if !fits_between(
+                    car_index,
+                    position,
+                    new_follower,
+                    new_leader,
+                    stateful,
+                    stateless,
+                ) {
+                    return None;
+                }
+                let own_after = self.following_acceleration(
+                    car_index, position, new_leader, lane, stateful, stateless,
+                );
+                let new_follower = new_follower.and_then(|follower| {
+                    let follower_position = lane_position(stateful.cars[follower].as_ref()?)?;
+                    Some((
+                        self.following_acceleration(
+                            follower,
+                            follower_position,
+                            new_leader,
+                            lane,
+                            stateful,
+                            stateless,
+                        ),
+                        self.following_acceleration(
+                            follower,
+                            follower_position,
+                            Some(car_index),
+                            lane,
+                            stateful,
+                            stateless,
+                        ),
+                    ))
+                });
+                let accelerations = LaneChangeAccelerations {
+                    own: (own_before, own_after),
+                    new_follower,
+                    old_follower,
+                };
+                if accelerations.change(&self.params) {
+                    Some((target, own_after - own_before))
+                } else {
+                    None
+                }
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(target, _)| target)
+    }
+
+    /// Adjacent lane towards the nearest lane allowing `turn`, which the car
+    /// at `car_index`, at `position` on the lane of `lane_key`, changes to
+    /// because its lane does not allow the turn.
+    ///
+    /// The change is made whatever the gain, once the car fits between the
+    /// cars on the new lane and the car behind it there does not have to
+    /// brake harder than the safe deceleration. Lanes the class of the car
+    /// may not drive on are neither targets nor passed through.
+    pub fn mandatory_lane_change(
+        &self,
+        car_index: CarIndex,
+        lane_key: LaneKey,
+        position: f64,
+        turn: RelativeDirection,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> Option<LaneIndex> {
+        let (road_direction, road_index, lane_direction, lane_index) = lane_key;
+        let lanes = stateless.city.board.get_roads(road_direction)[road_index]
+            .as_ref()?
+            .lanes_to_direction(lane_direction);
+        let class = stateless.cars[car_index].class;
+        let target = (0..lanes.len())
+            .filter(|index| {
+                lanes[*index].allows(class)
+                    && lanes[*index].direction_rule.contains(turn.to_turn_rule())
+            })
+            .min_by_key(|index| (*index as isize - lane_index as isize).abs())?;
+        let next = match target.cmp(&lane_index) {
+            Ordering::Less => lane_index - 1,
+            Ordering::Greater => lane_index + 1,
+            Ordering::Equal => return None,
+        };
+        let lane = &lanes[next];
+        if !lane.allows(class) {
+            return None;
+        }
+        let next_key = (road_direction, road_index, lane_direction, next);
+        let (new_follower, new_leader) = stateful.lanes.neighbours(next_key, position, car_index);
+        if !fits_between(
+            car_index,
+            position,
+            new_follower,
+            new_leader,
+            stateful,
+            stateless,
+        ) {
+            return None;
+        }
+        let safe = new_follower.map_or(true, |follower| {
+            let follower_position = stateful.cars[follower].as_ref().and_then(lane_position);
+            follower_position.map_or(true, |follower_position| {
+                self.following_acceleration(
+                    follower,
+                    follower_position,
+                    Some(car_index),
+                    lane,
+                    stateful,
+                    stateless,
+                ) >= -self.params.safe_deceleration
+            })
+        });
+        if safe {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Acceleration of the car at `car_index`, at `position` on `lane`,
+    /// following the car at `leader`, or a free road without one.
+    fn following_acceleration(
+        &self,
+        car_index: CarIndex,
+        position: f64,
+        leader: Option<CarIndex>,
+        lane: &stateless::Lane,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> f64 {
+        let car = match stateful.cars[car_index].as_ref() {
+            Some(car) => car,
+            None => return 0.0,
+        };
+        let driver = self.params.driver(
+            &stateless.cars[car_index],
+            self.params.lane_speed_limit(lane),
+        );
+        // An object infinitely far away leaves the car to accelerate freely
+        let (gap, leader_velocity) = leader
+            .and_then(|leader_index| {
+                let leader = stateful.cars[leader_index].as_ref()?;
+                let distance = lane_position(leader)? - position;
+                Some((
+                    bumper_gap(stateless, car_index, leader_index, distance),
+                    leader.velocity,
+                ))
+            })
+            .unwrap_or((f64::INFINITY, car.velocity));
+        self.following
+            .acceleration(gap, car.velocity, leader_velocity, &driver)
+            .min(driver.max_acceleration)
+            .max(-driver.max_break_acceleration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_when_safe_and_worth_it() {
+        let params = SimParams {
+            politeness: 0.5,
+            lane_change_threshold: 0.1,
+            safe_deceleration: 4.0,
+            ..Default::default()
+        };
+        let accelerations = LaneChangeAccelerations {
+            own: (-1.0, 1.0),
+            new_follower: None,
+            old_follower: None,
+        };
+        assert!(accelerations.change(&params));
+        // Not worth the gain of the threshold
+        let small_gain = LaneChangeAccelerations {
+            own: (1.0, 1.05),
+            ..accelerations
+        };
+        assert!(!small_gain.change(&params));
+        // The new follower would brake too hard
+        let unsafe_change = LaneChangeAccelerations {
+            new_follower: Some((0.0, -5.0)),
+            ..accelerations
+        };
+        assert!(!unsafe_change.change(&params));
+        // Polite drivers weigh the loss of the new follower
+        let impolite = LaneChangeAccelerations {
+            new_follower: Some((1.0, -3.0)),
+            ..accelerations
+        };
+        assert!(!impolite.change(&params));
+        assert!(impolite.change(&SimParams {
+            politeness: 0.0,
+            ..params.clone()
+        }));
+        // and the gain of the old one
+        let freeing = LaneChangeAccelerations {
+            own: (0.0, 0.0),
+            old_follower: Some((-2.0, 1.0)),
+            ..accelerations
+        };
+        assert!(freeing.change(&params));
+    }
+}
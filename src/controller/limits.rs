@@ -0,0 +1,141 @@
+//! Clamps of the velocity and acceleration of updated cars to the limits of
+//! the cars, so a misconfigured model or a blown-up step can not send cars
+//! off with invalid motion.
+
+use crate::model::{stateful, stateless};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clamp {
+    /// The velocity was not finite, negative or above the max velocity of
+    /// the car.
+    Velocity { computed: f64, clamped: f64 },
+    /// The acceleration was not finite or beyond the max acceleration or
+    /// braking of the car.
+    Acceleration { computed: f64, clamped: f64 },
+}
+
+impl fmt::Display for Clamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Clamp::Velocity { computed, clamped } => write!(
+                f,
+                "velocity {:.3} m/s clamped to {:.3} m/s",
+                computed, clamped
+            ),
+            Clamp::Acceleration { computed, clamped } => write!(
+                f,
+                "acceleration {:.3} m/s² clamped to {:.3} m/s²",
+                computed, clamped
+            ),
+        }
+    }
+}
+
+/// `value` limited to `min..=max`, unlike `f64::clamp` a NaN is kept to be
+/// reported by `enforce_limits` and crossed limits do not panic.
+pub fn within(value: f64, min: f64, max: f64) -> f64 {
+    if value > max {
+        max
+    } else if value < min {
+        min
+    } else {
+        value
+    }
+}
+
+/// Clamp the velocity of `car` to 0 to the max velocity of `limits` and its
+/// acceleration to the max braking to the max acceleration, values which are
+/// not finite to 0, and return what was clamped.
+pub fn enforce_limits(car: &mut stateful::Car, limits: &stateless::Car) -> Vec<Clamp> {
+    let mut clamps = Vec::new();
+    let velocity = clamped(car.velocity, 0.0, limits.max_velocity);
+    if velocity != car.velocity {
+        clamps.push(Clamp::Velocity {
+            computed: car.velocity,
+            clamped: velocity,
+        });
+        car.velocity = velocity;
+    }
+    let acceleration = clamped(
+        car.acceleration,
+        -limits.max_break_acceleration,
+        limits.max_acceleration,
+    );
+    if acceleration != car.acceleration {
+        clamps.push(Clamp::Acceleration {
+            computed: car.acceleration,
+            clamped: acceleration,
+        });
+        car.acceleration = acceleration;
+    }
+    clamps
+}
+
+fn clamped(value: f64, min: f64, max: f64) -> f64 {
+    if value.is_finite() {
+        within(value, min, max)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        stateful::testing::{car_at, on_lane},
+        stateless::car::DrivingModel,
+    };
+
+    #[test]
+    fn clamp_invalid_motion() {
+        let limits = stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity: 20.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 2.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 2.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 1.0,
+            },
+        };
+        let car = |velocity, acceleration| stateful::Car {
+            acceleration,
+            id: 7,
+            ..car_at(on_lane(0, 10.0), velocity).unwrap()
+        };
+
+        let mut valid = car(20.0, -8.0);
+        assert!(enforce_limits(&mut valid, &limits).is_empty());
+
+        let mut rocketing = car(300.0, 50.0);
+        assert_eq!(
+            enforce_limits(&mut rocketing, &limits),
+            vec![
+                Clamp::Velocity {
+                    computed: 300.0,
+                    clamped: 20.0
+                },
+                Clamp::Acceleration {
+                    computed: 50.0,
+                    clamped: 3.0
+                },
+            ]
+        );
+        assert_eq!((rocketing.velocity, rocketing.acceleration), (20.0, 3.0));
+
+        let mut blown_up = car(f64::NAN, f64::NEG_INFINITY);
+        let clamps = enforce_limits(&mut blown_up, &limits);
+        assert_eq!(clamps.len(), 2);
+        assert_eq!((blown_up.velocity, blown_up.acceleration), (0.0, 0.0));
+
+        assert!(within(f64::NAN, -8.0, 3.0).is_nan());
+        assert_eq!(within(-20.0, -8.0, 3.0), -8.0);
+    }
+}
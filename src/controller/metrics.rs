@@ -0,0 +1,167 @@
+//! Live metrics in the Prometheus text format, served over HTTP.
+//!
+//! The simulation renders a snapshot after each update, a background thread
+//! answers every request with the latest snapshot.
+
+use crate::{
+    controller::{history::Sample, stats::Stats},
+    model::stateful,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Debug)]
+pub struct MetricsServer {
+    snapshot: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    /// Listen on `port` of all interfaces in a background thread.
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let snapshot = Arc::new(Mutex::new(String::new()));
+        let shared = Arc::clone(&snapshot);
+        thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| Self::respond(stream, &shared));
+                    if let Err(e) = result {
+                        log::warn!("failed to serve metrics: {}", e);
+                    }
+                }
+            })?;
+        Ok(Self { snapshot })
+    }
+
+    /// Replace the metrics served to the next requests.
+    pub fn publish(&self, time: f64, stats: &Stats, stateful: &stateful::Model) {
+        let text = render(time, stats, stateful);
+        *self.snapshot.lock().unwrap() = text;
+    }
+
+    fn respond(mut stream: TcpStream, snapshot: &Mutex<String>) -> io::Result<()> {
+        // Every request gets the metrics, the request itself is not parsed
+        let mut buffer = [0; 1024];
+        let _ = stream.read(&mut buffer)?;
+        let body = snapshot.lock().unwrap().clone();
+        write!(
+            stream,
+            "HTTP/1.0 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             \r\n\
+             {}",
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
+
+/// Render car count, average speed and intersection throughput.
+pub fn render(time: f64, stats: &Stats, stateful: &stateful::Model) -> String {
+    let sample = Sample::of_model(time, stateful);
+    let mut text = String::new();
+    header(
+        &mut text,
+        "mpi_traffic_time_seconds",
+        "gauge",
+        "Simulated time since the start.",
+    );
+    writeln!(text, "mpi_traffic_time_seconds {}", time).unwrap();
+    header(&mut text, "mpi_traffic_cars", "gauge", "Cars in the city.");
+    writeln!(text, "mpi_traffic_cars {}", sample.car_number).unwrap();
+    header(
+        &mut text,
+        "mpi_traffic_average_speed_meters_per_second",
+        "gauge",
+        "Average speed of the cars in the city.",
+    );
+    writeln!(
+        text,
+        "mpi_traffic_average_speed_meters_per_second {}",
+        sample.average_speed
+    )
+    .unwrap();
+    header(
+        &mut text,
+        "mpi_traffic_intersection_entered_total",
+        "counter",
+        "Cars entered the intersection.",
+    );
+    for ((row, column), intersection) in stats.board.intersections.enumerate() {
+        writeln!(
+            text,
+            "mpi_traffic_intersection_entered_total{{row=\"{}\",column=\"{}\"}} {}",
+            row, column, intersection.entered
+        )
+        .unwrap();
+    }
+    header(
+        &mut text,
+        "mpi_traffic_intersection_control_delay_seconds",
+        "gauge",
+        "Average control delay of the cars entered the intersection.",
+    );
+    for ((row, column), intersection) in stats.board.intersections.enumerate() {
+        writeln!(
+            text,
+            "mpi_traffic_intersection_control_delay_seconds{{row=\"{}\",column=\"{}\"}} {}",
+            row,
+            column,
+            intersection.control_delay()
+        )
+        .unwrap();
+    }
+    text
+}
+
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::board::Board;
+
+    #[test]
+    fn render_prometheus_text() {
+        let mut stats = Stats {
+            flow_window: 60.0,
+            board: Board::with_shape(Default::default(), None, (1, 2)),
+            car_out_queue: 0,
+            exit_queue: 0,
+            refused_car_outs: 0,
+            pending_delays: Vec::new(),
+            fundamental_diagram: Vec::new(),
+            cordon: Vec::new(),
+        };
+        stats.board.intersections[(0, 1)].entered = 3;
+        stats.board.intersections[(0, 1)].total_delay = 4.5;
+        let text = render(1.5, &stats, &Default::default());
+        let samples = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            vec![
+                "mpi_traffic_time_seconds 1.5",
+                "mpi_traffic_cars 0",
+                "mpi_traffic_average_speed_meters_per_second 0",
+                "mpi_traffic_intersection_entered_total{row=\"0\",column=\"0\"} 0",
+                "mpi_traffic_intersection_entered_total{row=\"0\",column=\"1\"} 3",
+                "mpi_traffic_intersection_control_delay_seconds{row=\"0\",column=\"0\"} 0",
+                "mpi_traffic_intersection_control_delay_seconds{row=\"0\",column=\"1\"} 1.5",
+            ]
+        );
+    }
+}
@@ -2,47 +2,258 @@ use crate::{
     communication::{self, Division},
     info::Info,
     model::{
-        board::RoadIndex,
+        board::{IntersectionIndex, RoadIndex},
         common::{
-            AbsoluteDirection, AxisDirection, CarIndex,
+            AbsoluteDirection, Around, AxisDirection, CarIndex,
             InOutDirection::{self, Out},
             LaneDirection, LaneIndex, RelativeDirection, TurnRule,
         },
-        stateful::{self, Car},
-        stateless::{self, car::DrivingModel},
+        routing::{Route, RoutingAlgorithm, Segment},
+        stateful::{self, car::Perception, Car, LaneKey},
+        stateless::{self, car::VehicleClass, Movement},
     },
 };
+use collision::CollisionPolicy;
+use detector::DetectorRecorder;
+use following::{CarFollowingModel, CarFollowingModelKind};
+use history::{History, Sample};
 use mpi::{collective::CommunicatorCollectives, topology::Rank};
-use piston_window::{Button, ButtonArgs, ButtonState, Input, Motion, MouseButton, UpdateArgs};
+use params::SimParams;
+use piston_window::{Button, ButtonArgs, ButtonState, Input, Key, Motion, MouseButton, UpdateArgs};
 use process_local_state::ProcessLocalState;
-use rand::{self, Rng};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use stats::Stats;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+    ops::Range,
+    path::PathBuf,
+};
 use structopt::StructOpt;
+use turn::{TurnPolicy, TurnWeights};
 
+pub mod collision;
+pub mod detector;
+pub mod following;
+pub mod goto;
+pub mod history;
+pub mod inject;
+pub mod invariants;
+pub mod lane_change;
+pub mod limits;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod params;
+pub mod preview;
 pub mod process_local_state;
+#[cfg(feature = "binary-state")]
+pub mod replay;
+pub mod signal_edit;
+pub mod stats;
+pub mod transition;
+pub mod turn;
+pub mod violation;
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Controller {
     pub mouse_left_button_down: bool,
     pub mouse_left_button_down_location: Option<(f64, f64)>,
     pub start_drag_location: Option<(f64, f64)>,
+    /// Whether a shift key is held, dragging then selects cars instead of
+    /// moving the view.
+    shift_down: bool,
+    /// Logical window coordinates where a drag selecting cars started.
+    pub selection_start: Option<[f64; 2]>,
     pub settings: ControllerSettings,
     pub update_controller: UpdateController,
+    pub time: f64,
+    pub history: History,
+    pub stats: Stats,
+    pub detector_recorder: DetectorRecorder,
+    detector_output: Option<BufWriter<File>>,
+    /// Time when the current interval of the fundamental diagram started.
+    diagram_interval_start: f64,
+    #[cfg(feature = "binary-state")]
+    replay_output: Option<crate::model::file::ReplayWriter<BufWriter<File>>>,
+    /// Replay played in the window instead of the simulation.
+    #[cfg(feature = "binary-state")]
+    pub replay: Option<replay::Replay>,
+    /// Whether the simulation is stopped, toggled with the space key.
+    pub paused: bool,
+    /// Seconds of the previewed update and the locations of the cars after
+    /// it, kept until the model is edited or updated.
+    preview: Option<(f64, Vec<(CarIndex, stateful::car::Location)>)>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<metrics::MetricsServer>,
 }
 
 #[derive(StructOpt, Clone, Debug)]
 pub struct ControllerSettings {
     #[structopt(name = "zoom-step", long = "zoom-step", default_value = "0.1")]
     pub zoom_step: f64,
+    /// Seconds of metrics kept for the live graph.
+    #[structopt(
+        name = "history-window",
+        long = "history-window",
+        default_value = "60.0"
+    )]
+    pub history_window: f64,
+    /// Seconds of passing cars counted in the flow of roads.
+    #[structopt(name = "flow-window", long = "flow-window", default_value = "60.0")]
+    pub flow_window: f64,
+    /// Write detector records of the model to this CSV file.
+    #[structopt(name = "detector-output", long = "detector-output", parse(from_os_str))]
+    pub detector_output: Option<PathBuf>,
+    /// Seconds aggregated in a detector record.
+    #[structopt(
+        name = "detector-interval",
+        long = "detector-interval",
+        default_value = "60.0"
+    )]
+    pub detector_interval: f64,
+    /// Seconds between two points of the network fundamental diagram.
+    #[structopt(
+        name = "diagram-interval",
+        long = "diagram-interval",
+        default_value = "60.0"
+    )]
+    pub diagram_interval: f64,
+    /// Write the network fundamental diagram to this CSV file at the end.
+    #[structopt(name = "diagram-output", long = "diagram-output", parse(from_os_str))]
+    pub diagram_output: Option<PathBuf>,
+    /// Write a summary of the run to this CSV file at the end, as a scenario
+    /// of a batch.
+    #[structopt(name = "summary-output", long = "summary-output", parse(from_os_str))]
+    pub summary_output: Option<PathBuf>,
+    /// Seconds of an interval of the counts of cars entering and leaving
+    /// the city across each side.
+    #[structopt(
+        name = "cordon-interval",
+        long = "cordon-interval",
+        default_value = "300.0"
+    )]
+    pub cordon_interval: f64,
+    /// Write the counts of the cordon around the city to this CSV file at
+    /// the end.
+    #[structopt(name = "cordon-output", long = "cordon-output", parse(from_os_str))]
+    pub cordon_output: Option<PathBuf>,
+    /// Pause and select the car when a collision or an invalid state of a
+    /// car is detected.
+    #[structopt(name = "break-on-violation", long = "break-on-violation")]
+    pub break_on_violation: bool,
+    /// Check the whole model is consistent after every update and panic at
+    /// the first inconsistency, slow.
+    #[structopt(name = "check-invariants", long = "check-invariants")]
+    pub check_invariants: bool,
+    /// Open the window with the simulation paused, resumed with the space
+    /// key.
+    #[structopt(name = "start-paused", long = "start-paused")]
+    pub start_paused: bool,
+    /// Meters between two cars on a lane below which they collide.
+    #[structopt(
+        name = "collision-distance",
+        long = "collision-distance",
+        default_value = "4.5"
+    )]
+    pub collision_distance: f64,
+    /// Seconds from the start before statistics are collected, counters of
+    /// the statistics restart when it ends.
+    #[structopt(name = "warmup", long = "warmup", default_value = "0.0")]
+    pub warmup: f64,
+    /// Fraction of the speed limit of its lane below which a car is slowed
+    /// down, slowdowns and recoveries are logged to the
+    /// mpi_traffic::controller::transition::slowdown target.
+    #[structopt(
+        name = "slowdown-fraction",
+        long = "slowdown-fraction",
+        default_value = "0.5"
+    )]
+    pub slowdown_fraction: f64,
+    /// Save the final state of the simulation to this RON file.
+    #[structopt(name = "state-output", long = "state-output", parse(from_os_str))]
+    pub state_output: Option<PathBuf>,
+    /// Compare the final state of the simulation with the state in this RON
+    /// file and report the differences.
+    #[structopt(name = "state-baseline", long = "state-baseline", parse(from_os_str))]
+    pub state_baseline: Option<PathBuf>,
+    /// Record the state after every update to this bincode replay file.
+    #[cfg(feature = "binary-state")]
+    #[structopt(name = "replay-output", long = "replay-output", parse(from_os_str))]
+    pub replay_output: Option<PathBuf>,
+    /// Replay file played in the window instead of simulating, set by the
+    /// `replay` command.
+    #[cfg(feature = "binary-state")]
+    #[structopt(skip)]
+    pub replay: Option<PathBuf>,
+    /// Frames skipped backward or forward in a replay by the left and right
+    /// arrow keys.
+    #[cfg(feature = "binary-state")]
+    #[structopt(skip = 60usize)]
+    pub replay_seek_frames: usize,
+    /// Serve metrics in the Prometheus text format on this port.
+    #[cfg(feature = "metrics")]
+    #[structopt(name = "metrics-port", long = "metrics-port")]
+    pub metrics_port: Option<u16>,
 }
 
 impl Controller {
-    pub fn new(update_controller: UpdateController, settings: ControllerSettings) -> Self {
+    pub fn new(
+        update_controller: UpdateController,
+        settings: ControllerSettings,
+        stateless: &stateless::Model,
+    ) -> Self {
+        let detector_recorder =
+            DetectorRecorder::new(stateless.detectors.clone(), settings.detector_interval);
+        let detector_output = settings.detector_output.as_ref().map(|path| {
+            let file =
+                File::create(path).unwrap_or_else(|e| panic!("failed to create {:?}: {}", path, e));
+            let mut writer = BufWriter::new(file);
+            detector_recorder
+                .write_header(&mut writer)
+                .unwrap_or_else(|e| panic!("failed to write {:?}: {}", path, e));
+            writer
+        });
+        #[cfg(feature = "binary-state")]
+        let replay_output = settings.replay_output.as_ref().map(|path| {
+            crate::model::file::ReplayWriter::create(path, stateless)
+                .unwrap_or_else(|e| panic!("failed to create {:?}: {}", path, e))
+        });
+        #[cfg(feature = "binary-state")]
+        let replay = settings.replay.as_ref().map(|path| {
+            let replay = replay::Replay::open(path, stateless)
+                .unwrap_or_else(|e| panic!("failed to open replay {:?}: {}", path, e));
+            log::info!("replay {:?} has {} frames", path, replay.len());
+            replay
+        });
+        #[cfg(feature = "metrics")]
+        let metrics = settings.metrics_port.map(|port| {
+            metrics::MetricsServer::start(port)
+                .unwrap_or_else(|e| panic!("failed to serve metrics on port {}: {}", port, e))
+        });
+        let paused = settings.start_paused;
         Self {
+            stats: Stats::new(&stateless.city, settings.flow_window),
+            detector_recorder,
+            detector_output,
+            diagram_interval_start: 0.0,
+            #[cfg(feature = "binary-state")]
+            replay_output,
+            #[cfg(feature = "binary-state")]
+            replay,
             mouse_left_button_down: false,
             mouse_left_button_down_location: None,
             start_drag_location: None,
+            shift_down: false,
+            selection_start: None,
+            history: History::new(settings.history_window),
             settings,
             update_controller,
+            time: 0.0,
+            paused,
+            preview: None,
+            #[cfg(feature = "metrics")]
+            metrics,
         }
     }
 }
@@ -51,10 +262,19 @@ impl Controller {
     pub fn input(
         &mut self,
         info: &mut Info,
-        _stateful: &mut stateful::Model,
-        _stateless: &stateless::Model,
+        stateful: &mut stateful::Model,
+        stateless: &stateless::Model,
         input: Input,
     ) {
+        if self.goto_input(info, stateless, &input) {
+            return;
+        }
+        #[cfg(feature = "binary-state")]
+        {
+            if self.replay_input(stateful, &input) {
+                return;
+            }
+        }
         match input {
             Input::Button(ButtonArgs {
                 state,
@@ -62,23 +282,32 @@ impl Controller {
                 ..
             }) => {
                 match state {
+                    ButtonState::Press if self.shift_down => {
+                        self.selection_start = Some(info.cursor);
+                    }
                     ButtonState::Press => {
                         self.mouse_left_button_down = true;
-                        self.start_drag_location = Some((info.x, info.y));
+                        info.follow_selected = false;
+                        self.start_drag_location = Some((info.camera.x, info.camera.y));
                     }
                     ButtonState::Release => {
+                        if let Some(start) = self.selection_start.take() {
+                            info.selection_box = Some((start, info.cursor));
+                        }
                         self.mouse_left_button_down = false;
                         self.start_drag_location = None;
                     }
                 };
                 self.mouse_left_button_down_location = None;
             }
-            Input::Move(Motion::MouseCursor([x, y])) => {
+            Input::Move(Motion::MouseCursor(position)) => {
+                let [x, y] = info.logical_position(position);
+                info.cursor = [x, y];
                 if self.mouse_left_button_down {
                     if let Some((origin_x, origin_y)) = self.mouse_left_button_down_location {
                         if let Some((start_drag_x, start_drag_y)) = self.start_drag_location {
-                            info.x = start_drag_x + x - origin_x;
-                            info.y = start_drag_y + y - origin_y;
+                            info.camera.x = start_drag_x + x - origin_x;
+                            info.camera.y = start_drag_y + y - origin_y;
                         }
                     } else {
                         self.mouse_left_button_down_location = Some((x, y));
@@ -86,7 +315,36 @@ impl Controller {
                 }
             }
             Input::Move(Motion::MouseScroll([_x, y])) => {
-                info.zoom += y * self.settings.zoom_step;
+                let zoom = info.camera.zoom + y * self.settings.zoom_step;
+                info.camera.zoom_at(info.cursor, zoom);
+            }
+            Input::Button(ButtonArgs {
+                state,
+                button: Button::Keyboard(Key::LShift | Key::RShift),
+                ..
+            }) => {
+                self.shift_down = state == ButtonState::Press;
+            }
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::Space),
+                ..
+            }) => {
+                self.paused = !self.paused;
+            }
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::O),
+                ..
+            }) => {
+                self.toggle_signal_failure(info);
+            }
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(key),
+                ..
+            }) if info.selected_intersection.is_some() => {
+                self.edit_signal(info, stateful, key);
             }
             _ => (),
         }
@@ -94,32 +352,409 @@ impl Controller {
 }
 
 impl Controller {
+    /// Flush the records written during the run, for the end of the run.
+    pub fn flush_outputs(&mut self) {
+        if let Some(writer) = self.detector_output.as_mut() {
+            if let Err(e) = writer.flush() {
+                log::warn!("failed to flush detector records: {}", e);
+            }
+        }
+        #[cfg(feature = "binary-state")]
+        {
+            if let Some(replay) = self.replay_output.as_mut() {
+                if let Err(e) = replay.flush() {
+                    log::warn!("failed to flush replay: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Update the model in the order of `UpdateController::update`, then
+    /// the statistics.
     pub fn update<Comm>(
         &mut self,
         root: Rank,
         communicator: Comm,
-        _info: &mut Info,
+        info: &mut Info,
         stateful: &mut stateful::Model,
         stateless: &stateless::Model,
         args: UpdateArgs,
     ) where
         Comm: CommunicatorCollectives + Clone,
     {
+        self.clear_preview();
+        let road_positions = Stats::road_positions(stateful);
+        let cordon_positions = Stats::cordon_positions(stateful, &stateless.city);
+        let before = if transition::log_enabled() {
+            Some((stateful.cars.clone(), stateful.generations.clone()))
+        } else {
+            None
+        };
+        // Intersections are occupied by the cars before the update, unless
+        // the update takes several physics steps
+        let occupants = if self.settings.check_invariants
+            && self.update_controller.physics_steps(args).0 == 1
+        {
+            Some(stateful.cars.clone())
+        } else {
+            None
+        };
         self.update_controller
             .update(root, communicator, stateful, stateless, args);
+        let warming_up = self.time < self.settings.warmup;
+        self.time += args.dt;
+        self.history.push(Sample::of_model(self.time, stateful));
+        if let Some((cars, generations)) = before {
+            let slowdowns = transition::Slowdowns {
+                city: &stateless.city,
+                params: self.update_controller.params(),
+                fraction: self.settings.slowdown_fraction,
+            };
+            transition::log_transitions(self.time, &cars, &generations, stateful, &slowdowns);
+        }
+        if warming_up && self.time >= self.settings.warmup {
+            log::info!("warm-up ended at time {:.3}", self.time);
+            self.stats.reset();
+            self.update_controller.reset_refused_car_outs();
+            self.diagram_interval_start = self.time;
+        }
+        self.stats.update(self.time, &road_positions, stateful);
+        if !warming_up {
+            self.stats.update_cordon(
+                self.time,
+                self.settings.cordon_interval,
+                &cordon_positions,
+                stateful,
+                &stateless.city,
+            );
+        }
+        self.stats.update_delays(
+            args.dt,
+            &road_positions,
+            stateful,
+            stateless,
+            self.update_controller.params(),
+        );
+        self.stats.car_out_queue = self.update_controller.car_out_queue();
+        self.stats.exit_queue = self.update_controller.exit_queue(stateful, stateless);
+        self.stats.refused_car_outs = self.update_controller.refused_car_outs();
+        if !warming_up && self.time - self.diagram_interval_start >= self.settings.diagram_interval
+        {
+            self.stats.record_fundamental_diagram();
+            self.diagram_interval_start = self.time;
+        }
+        if let Some(writer) = self.detector_output.as_mut() {
+            if let Err(e) = self
+                .detector_recorder
+                .update(self.time, args.dt, stateful, writer)
+                .and_then(|_| writer.flush())
+            {
+                log::warn!("failed to write detector records: {}", e);
+            }
+        }
+        #[cfg(feature = "binary-state")]
+        {
+            if let Some(replay) = self.replay_output.as_mut() {
+                if let Err(e) = replay.write_frame(self.time, stateful) {
+                    log::warn!("failed to write replay frame: {}", e);
+                }
+            }
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.publish(self.time, &self.stats, stateful);
+            }
+        }
+        if self.settings.check_invariants {
+            let inconsistencies = invariants::find_inconsistencies(
+                stateful,
+                stateless,
+                occupants.as_deref(),
+                self.settings.collision_distance,
+            );
+            if !inconsistencies.is_empty() {
+                let messages = inconsistencies
+                    .iter()
+                    .map(|inconsistency| inconsistency.to_string())
+                    .collect::<Vec<_>>();
+                panic!(
+                    "inconsistent model at time {:.3}:\n{}",
+                    self.time,
+                    messages.join("\n")
+                );
+            }
+        }
+        if self.settings.break_on_violation {
+            let violations = violation::find_violations(stateful, self.settings.collision_distance);
+            for violation in violations.iter() {
+                log::warn!("at time {:.3}: {}", self.time, violation);
+            }
+            if let Some(violation) = violations.first() {
+                let car_index = violation.car_index();
+                log::warn!(
+                    "paused on car {}: {:?}",
+                    car_index,
+                    stateful.cars[car_index]
+                );
+                self.paused = true;
+                info.selected_car = stateful.handle(car_index);
+                info.follow_selected = true;
+            }
+        }
+    }
+}
+
+#[derive(StructOpt, Clone, Debug)]
+pub struct UpdateSettings {
+    /// One of "cushion", "idm", "gipps" and "safe-distance".
+    #[structopt(
+        name = "car-following-model",
+        long = "car-following-model",
+        default_value = "cushion"
+    )]
+    pub car_following_model: CarFollowingModelKind,
+    /// Seconds between a driver perceiving the front objects and reacting.
+    #[structopt(name = "reaction-time", long = "reaction-time", default_value = "0.0")]
+    pub reaction_time: f64,
+    /// One of "dijkstra" and "a-star".
+    #[structopt(
+        name = "routing-algorithm",
+        long = "routing-algorithm",
+        default_value = "dijkstra"
+    )]
+    pub routing_algorithm: RoutingAlgorithm,
+    /// Give new cars random destinations and drive them along the fastest
+    /// routes, the same as the "route" turn policy.
+    #[structopt(name = "route-cars", long = "route-cars")]
+    pub route_cars: bool,
+    /// One of "route", "random" and "random:<left>,<front>,<right>,<back>"
+    /// with the weights of the turns of cars without routes.
+    #[structopt(name = "turn-policy", long = "turn-policy", default_value = "random")]
+    pub turn_policy: TurnPolicy,
+    /// Seconds between reroutes of routed cars, 0 to disable rerouting.
+    #[structopt(
+        name = "reroute-interval",
+        long = "reroute-interval",
+        default_value = "10.0"
+    )]
+    pub reroute_interval: f64,
+    /// Proportion of routed cars rerouted each interval.
+    #[structopt(
+        name = "reroute-fraction",
+        long = "reroute-fraction",
+        default_value = "0.1",
+        parse(try_from_str = parse_fraction)
+    )]
+    pub reroute_fraction: f64,
+    /// Max change of acceleration per second, unlimited if not given.
+    #[structopt(name = "max-jerk", long = "max-jerk")]
+    pub max_jerk: Option<f64>,
+    /// Let cars change to an adjacent lane to overtake by the MOBIL rule,
+    /// with the politeness and thresholds of the parameters.
+    #[structopt(name = "lane-changes", long = "lane-changes")]
+    pub lane_changes: bool,
+    /// Max seconds of a physics step, each update is divided into steps no
+    /// longer than it.
+    #[structopt(
+        name = "max-physics-dt",
+        long = "max-physics-dt",
+        parse(try_from_str = parse_positive_seconds)
+    )]
+    pub max_physics_dt: Option<f64>,
+    /// What happens to cars running into each other on a lane, one of
+    /// "ignore", "prevent" and "crash".
+    #[structopt(
+        name = "collision-policy",
+        long = "collision-policy",
+        default_value = "ignore"
+    )]
+    pub collision_policy: CollisionPolicy,
+    /// Seconds crashed cars block their lane before they are removed.
+    #[structopt(
+        name = "crash-clearance-time",
+        long = "crash-clearance-time",
+        default_value = "120.0"
+    )]
+    pub crash_clearance_time: f64,
+    /// Seconds between two cars leaving the city through the same exit lane,
+    /// cars queue at the end of the lane until the exit takes them. 0 to let
+    /// cars leave as soon as they reach the end.
+    #[structopt(name = "exit-headway", long = "exit-headway", default_value = "0.0")]
+    pub exit_headway: f64,
+    /// Seconds a car waits at the end of a lane with no way to leave it
+    /// before a warning names the car and its lane.
+    #[structopt(name = "stuck-timeout", long = "stuck-timeout", default_value = "60.0")]
+    pub stuck_timeout: f64,
+    /// Warn about every car whose velocity or acceleration is clamped to
+    /// the limits of the car, with the car id and the computed value.
+    #[structopt(name = "log-clamps", long = "log-clamps")]
+    pub log_clamps: bool,
+    /// Seed of the random choices of the update, runs with the same seed
+    /// and number of processes have the same results. Random without it.
+    #[structopt(name = "seed", long = "seed")]
+    pub seed: Option<u64>,
+    /// Load the gap, headway and speed of drivers from a RON file of
+    /// `SimParams`.
+    #[structopt(name = "sim-params", long = "sim-params", parse(from_os_str))]
+    pub sim_params: Option<PathBuf>,
+}
+
+/// Lower bound of the speed used to estimate travel time, so jammed roads
+/// are expensive but still passable.
+const MIN_ROUTING_SPEED: f64 = 1.0;
+
+/// A fraction in [0, 1], such as the proportion of cars rerouted.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(format!("{} is not in [0, 1]", s))
     }
 }
 
-#[derive(Clone, Debug, Default)]
+/// Seconds which are finite and greater than 0, such as the length of a step.
+fn parse_positive_seconds(s: &str) -> Result<f64, String> {
+    let seconds = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if seconds.is_finite() && seconds > 0.0 {
+        Ok(seconds)
+    } else {
+        Err(format!("{} is not a positive number of seconds", s))
+    }
+}
+
+/// Meters per second below which a car counts as stopped at a failed signal.
+const FAILED_SIGNAL_STOP_SPEED: f64 = 0.1;
+
+/// Meters before the stop line within which the front car of a lane stopping
+/// counts as stopped at a failed signal, beyond the standstill gap the
+/// following models keep to the line.
+const FAILED_SIGNAL_STOP_DISTANCE: f64 = 20.0;
+
+#[derive(Clone, Debug)]
 pub struct UpdateController {
     car_out_rank: Rank,
+    following: Box<dyn CarFollowingModel>,
+    reaction_time: f64,
+    routing_algorithm: RoutingAlgorithm,
+    route_cars: bool,
+    turn_weights: TurnWeights,
+    reroute_interval: f64,
+    reroute_fraction: f64,
+    max_jerk: Option<f64>,
+    lane_changes: bool,
+    max_physics_dt: Option<f64>,
+    collision_policy: CollisionPolicy,
+    crash_clearance_time: f64,
+    exit_headway: f64,
+    /// Time each exit lane last took a car, the next car it takes waits
+    /// `exit_headway` after it.
+    last_exits: BTreeMap<LaneKey, f64>,
+    stuck_timeout: f64,
+    log_clamps: bool,
+    params: SimParams,
+    time: f64,
+    refused_car_outs: usize,
+    demand_due: f64,
+    /// Source of the random choices of the update, other than the noise of
+    /// the cars.
+    rng: StdRng,
+    /// Seed of the noise of the cars, drawn at random without one in the
+    /// settings.
+    noise_seed: u64,
+    /// Physics steps taken, which with the noise seed and the id of a car
+    /// pick the noise of the car.
+    physics_step: u64,
+    /// Signals set by hand, kept instead of the rules of the schedule.
+    signal_overrides: HashMap<IntersectionIndex, Around<TurnRule>>,
+    /// Time since which cars have waited at the ends of lanes with no way
+    /// to leave them, and whether they have been warned about.
+    stuck_since: HashMap<CarIndex, (f64, bool)>,
+    /// Whether all signals have failed, every signalized intersection is an
+    /// all-way stop instead. Set on the root, and passed on to the other
+    /// processes in the city by `update_city`.
+    signals_failed: bool,
+    /// Cars which have come to a stop before an intersection with failed
+    /// signals and may enter it once it is clear.
+    stopped_at_failed_signal: HashSet<CarIndex>,
 }
 
 impl UpdateController {
-    pub fn new() -> Self {
-        Self { car_out_rank: 0 }
+    pub fn new(settings: UpdateSettings, params: SimParams) -> Self {
+        Self {
+            car_out_rank: 0,
+            following: settings.car_following_model.build(&params),
+            reaction_time: settings.reaction_time,
+            routing_algorithm: settings.routing_algorithm,
+            route_cars: settings.route_cars || settings.turn_policy == TurnPolicy::Route,
+            turn_weights: match settings.turn_policy {
+                TurnPolicy::Random(weights) => weights,
+                TurnPolicy::Route => Default::default(),
+            },
+            reroute_interval: settings.reroute_interval,
+            reroute_fraction: settings.reroute_fraction,
+            max_jerk: settings.max_jerk,
+            lane_changes: settings.lane_changes,
+            max_physics_dt: settings.max_physics_dt,
+            collision_policy: settings.collision_policy,
+            crash_clearance_time: settings.crash_clearance_time,
+            exit_headway: settings.exit_headway,
+            last_exits: BTreeMap::new(),
+            stuck_timeout: settings.stuck_timeout,
+            log_clamps: settings.log_clamps,
+            params,
+            time: 0.0,
+            refused_car_outs: 0,
+            demand_due: 0.0,
+            rng: match settings.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            noise_seed: settings.seed.unwrap_or_else(rand::random),
+            physics_step: 0,
+            signal_overrides: HashMap::new(),
+            stuck_since: HashMap::new(),
+            signals_failed: false,
+            stopped_at_failed_signal: HashSet::new(),
+        }
+    }
+
+    pub fn routing_algorithm(&self) -> RoutingAlgorithm {
+        self.routing_algorithm
+    }
+
+    pub fn params(&self) -> &SimParams {
+        &self.params
+    }
+
+    /// Cars due by the demand profile and waiting to enter the city because
+    /// the entry lanes are full.
+    ///
+    /// The queue is not capped, it grows for as long as the demand is above
+    /// what the entry lanes take. Without a demand profile cars enter
+    /// whenever there is room, and none wait.
+    pub fn car_out_queue(&self) -> usize {
+        self.demand_due.max(0.0).floor() as usize
+    }
+
+    /// Times a car due by the demand profile was refused to enter the city.
+    pub fn refused_car_outs(&self) -> usize {
+        self.refused_car_outs
     }
 
+    pub fn reset_refused_car_outs(&mut self) {
+        self.refused_car_outs = 0;
+    }
+
+    /// Update the model on all processes.
+    ///
+    /// The order of updates is fixed, so runs with the same random choices
+    /// have the same results whatever the number of processes:
+    /// intersections in row-major order, then cars by index. Each process
+    /// updates a contiguous range of indices and the ranges are gathered in
+    /// rank order. Cars see the model before the update, and the leader of
+    /// a car is the next car on its lane by position, then by index.
     pub fn update<Comm>(
         &mut self,
         root: Rank,
@@ -130,19 +765,88 @@ impl UpdateController {
     ) where
         Comm: CommunicatorCollectives + Clone,
     {
-        self.update_city(
-            root,
-            communicator.clone(),
-            &mut stateful.city,
-            &stateless.city,
-            args,
-        );
-        self.update_cars(root, communicator.clone(), stateful, stateless, args);
+        // Cars only come out in the first physics step, when due
+        let (steps, step_args) = self.physics_steps(args);
+        let car_due = self.car_due(stateless, args.dt);
+        for step in 0..steps {
+            self.update_city(
+                root,
+                communicator.clone(),
+                &mut stateful.city,
+                &stateful.cars,
+                &stateless.city,
+                step_args,
+            );
+            self.update_cars(
+                root,
+                communicator.clone(),
+                stateful,
+                stateless,
+                step_args,
+                step == 0 && car_due,
+            );
+            self.time += step_args.dt;
+            self.physics_step += 1;
+        }
 
         self.car_out_rank += 1;
         self.car_out_rank %= communicator.size();
     }
 
+    /// Update the model in a single process without communication, in the
+    /// same order as `update`.
+    pub fn step(
+        &mut self,
+        stateful: &mut stateful::Model,
+        stateless: &stateless::Model,
+        args: UpdateArgs,
+    ) {
+        let (steps, step_args) = self.physics_steps(args);
+        let car_due = self.car_due(stateless, args.dt);
+        for step in 0..steps {
+            let allow_car_out = step == 0 && car_due;
+            self.update_intersections(
+                &mut stateful.city,
+                &stateful.cars,
+                &stateless.city,
+                step_args,
+            );
+            let local_state = ProcessLocalState::generate(
+                &stateless.city,
+                &stateful.cars[..],
+                &stateless.cars[..],
+            );
+            let mut cars = self.update_car_range(
+                0..stateful.cars.len(),
+                0,
+                &local_state,
+                stateful,
+                stateless,
+                step_args,
+                allow_car_out,
+            );
+            self.collision_policy
+                .apply(&mut cars, stateless, self.crash_clearance_time);
+            self.serve_demand(stateful, stateless, &cars, allow_car_out);
+            self.record_exits(stateful, stateless, &cars, step_args.dt);
+            stateful.replace_cars(cars);
+            self.time += step_args.dt;
+            self.physics_step += 1;
+        }
+    }
+
+    /// Divide an update into physics steps no longer than `max_physics_dt`.
+    fn physics_steps(&self, args: UpdateArgs) -> (usize, UpdateArgs) {
+        let steps = match self.max_physics_dt {
+            Some(max_physics_dt) => (args.dt / max_physics_dt).ceil().max(1.0) as usize,
+            None => 1,
+        };
+        let step_args = UpdateArgs {
+            dt: args.dt / steps as f64,
+        };
+        (steps, step_args)
+    }
+
     pub fn update_cars<Comm>(
         &mut self,
         _root: Rank,
@@ -150,38 +854,81 @@ impl UpdateController {
         stateful: &mut stateful::Model,
         stateless: &stateless::Model,
         args: UpdateArgs,
+        allow_car_out: bool,
     ) where
         Comm: CommunicatorCollectives,
     {
         let local_state =
             ProcessLocalState::generate(&stateless.city, &stateful.cars[..], &stateless.cars[..]);
-
         let car_number = stateful.cars.len();
         let rank = communicator.rank();
         let size = communicator.size();
         let division = Division::new(car_number, rank, size);
-        let mut local_cars = Vec::new();
-        let mut outed = false;
-        for car_index in division.range() {
-            local_cars.push(self.update_car(
-                &mut outed,
-                communicator.rank(),
-                car_index,
-                &local_state,
-                &*stateful,
-                stateless,
-                args,
-            ));
-        }
+        let local_cars = self.update_car_range(
+            division.range(),
+            rank,
+            &local_state,
+            &*stateful,
+            stateless,
+            args,
+            allow_car_out,
+        );
         let gathered =
             communication::bincode_all_gather_varcount(communicator, &local_cars).unwrap();
-        stateful.cars = gathered.into_iter().flatten().collect();
+        let mut cars = gathered.into_iter().flatten().collect::<Vec<_>>();
+        self.collision_policy
+            .apply(&mut cars, stateless, self.crash_clearance_time);
+        self.serve_demand(stateful, stateless, &cars, allow_car_out);
+        self.record_exits(stateful, stateless, &cars, args.dt);
+        stateful.replace_cars(cars);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_car_range(
+        &mut self,
+        range: Range<CarIndex>,
+        rank: Rank,
+        local_state: &ProcessLocalState,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        args: UpdateArgs,
+        allow_car_out: bool,
+    ) -> Vec<Option<Car>> {
+        let mut outed = !allow_car_out;
+        range
+            .map(|car_index| {
+                let mut car = self.update_car(
+                    &mut outed,
+                    rank,
+                    car_index,
+                    local_state,
+                    stateful,
+                    stateless,
+                    args,
+                );
+                if let Some(car) = &mut car {
+                    let clamps = limits::enforce_limits(car, &stateless.cars[car_index]);
+                    if self.log_clamps {
+                        for clamp in clamps {
+                            log::warn!(
+                                "at time {:.3}: car {} (id {}): {}",
+                                self.time,
+                                car_index,
+                                car.id,
+                                clamp
+                            );
+                        }
+                    }
+                }
+                car
+            })
+            .collect()
     }
 
     // TODO: fix this
     #[allow(clippy::too_many_arguments)]
     pub fn update_car(
-        &self,
+        &mut self,
         outed: &mut bool,
         rank: Rank,
         car_index: CarIndex,
@@ -191,8 +938,22 @@ impl UpdateController {
         args: UpdateArgs,
     ) -> Option<stateful::Car> {
         use crate::model::stateful::car::Location::*;
-        let stateless_car = &stateless.cars[car_index];
+        // Only cars still stuck at the end of their lane put it back
+        let stuck_since = self.stuck_since.remove(&car_index);
+        // and only cars still before the failed signal they stopped at
+        let stopped_at_failed_signal = self.stopped_at_failed_signal.remove(&car_index);
         if let Some(car) = &stateful.cars[car_index] {
+            if let Some(remaining) = car.crashed {
+                // Crashed cars stand still until they are cleared
+                return if remaining > args.dt {
+                    Some(Car {
+                        crashed: Some(remaining - args.dt),
+                        ..car.clone()
+                    })
+                } else {
+                    None
+                };
+            }
             match &car.location {
                 OnLane {
                     road_direction,
@@ -207,28 +968,192 @@ impl UpdateController {
                         .as_ref()
                         .unwrap();
                     let lane = &road.lanes_to_direction(*lane_direction)[*lane_index];
-                    // let max_velocity = stateless_car.max_velocity.min(lane.max_speed);
+                    let stateless_car = &self.params.driver(
+                        &stateless.cars[car_index],
+                        self.params.lane_speed_limit(lane),
+                    );
                     let max_velocity = stateless_car.max_velocity;
                     let mut velocity =
                         (car.velocity + car.acceleration * args.dt).min(max_velocity);
                     if velocity < 0.0 {
                         velocity = 0.0;
                     }
+                    let lane_key = (*road_direction, *road_index, *lane_direction, *lane_index);
+                    let front_car_index = stateful.lanes.leader(lane_key, *position, car_index);
+                    // Lane changes are decided on the positions before the
+                    // update
+                    let last_position = *position;
                     let position = position + car.velocity * args.dt;
 
-                    if position >= road_length {
+                    let intersection_index = stateless.city.board.lane_to_intersection_index(
+                        *road_direction,
+                        *road_index,
+                        *lane_direction,
+                    );
+                    let stops_at_failed_signal = stateful.city.signals_failed
+                        && stateful.city.board.intersections[intersection_index]
+                            .as_ref()
+                            .and_then(|intersection| intersection.current())
+                            .is_some();
+                    // As at an all-way stop, the front car of the lane comes
+                    // to a stop once before it enters
+                    let stopped_at_failed_signal = stops_at_failed_signal
+                        && (stopped_at_failed_signal
+                            || (front_car_index.is_none()
+                                && car.velocity < FAILED_SIGNAL_STOP_SPEED
+                                && road_length - position <= FAILED_SIGNAL_STOP_DISTANCE));
+                    if stopped_at_failed_signal {
+                        self.stopped_at_failed_signal.insert(car_index);
+                    }
+                    let waits_at_failed_signal =
+                        stops_at_failed_signal && !stopped_at_failed_signal;
+                    let driver_direction =
+                        AbsoluteDirection::of_lane(*road_direction, *lane_direction);
+                    // A turn onto no road is chosen again among the turns
+                    // the lane allows, a car with none of them is stuck
+                    let reachable = reachable_turns(
+                        &stateless.city,
+                        intersection_index,
+                        driver_direction,
+                        lane.direction_rule,
+                        stateless.cars[car_index].class,
+                    );
+                    let mut stuck = false;
+                    let about_to_turn = &if lane.direction_rule.is_empty()
+                        || reachable.contains(about_to_turn.to_turn_rule())
+                    {
+                        *about_to_turn
+                    } else {
+                        let segment = Segment {
+                            road_direction: *road_direction,
+                            road_index: *road_index,
+                            lane_direction: *lane_direction,
+                        };
+                        match self
+                            .turn_to_follow(segment, &car.route, reachable)
+                            .or_else(|| self.random_choose_relative_direction(reachable))
+                        {
+                            Some(turn) => turn,
+                            None => {
+                                stuck = true;
+                                *about_to_turn
+                            }
+                        }
+                    };
+                    let movement = Movement {
+                        from_direction: driver_direction.turn_back(),
+                        from_lane_index: *lane_index,
+                        to_direction: driver_direction.turn(*about_to_turn),
+                    };
+                    let occupied = self.conflicts_with_occupancy(
+                        intersection_index,
+                        &movement,
+                        stateful,
+                        stateless,
+                    );
+
+                    // Destinations of the OD matrix are sinks
+                    let arrived = stateless.od_matrix.is_some()
+                        && car.destination == Some(intersection_index);
+                    if position >= road_length && arrived {
+                        // the car leaves the city at its destination
+                        None
+                    } else if position >= road_length && lane.direction_rule.is_empty() {
+                        if self.exit_open(lane_key, args.dt) {
+                            // the exit takes the car at the end of the lane
+                            None
+                        } else {
+                            // wait at the end of the lane for the exit
+                            Some(Car {
+                                location: OnLane {
+                                    road_direction: *road_direction,
+                                    road_index: *road_index,
+                                    lane_direction: *lane_direction,
+                                    lane_index: *lane_index,
+                                    about_to_turn: *about_to_turn,
+                                    position: road_length,
+                                },
+                                velocity: 0.0,
+                                acceleration: 0.0,
+                                perception: car.perception.clone(),
+                                crashed: None,
+                                id: car.id,
+                                destination: car.destination,
+                                route: car.route.clone(),
+                            })
+                        }
+                    } else if position >= road_length && stuck {
+                        // wait at the stop line, there is no way to leave
+                        // the lane
+                        self.wait_stuck(car_index, lane_key, stuck_since);
+                        Some(Car {
+                            location: OnLane {
+                                road_direction: *road_direction,
+                                road_index: *road_index,
+                                lane_direction: *lane_direction,
+                                lane_index: *lane_index,
+                                about_to_turn: *about_to_turn,
+                                position: road_length,
+                            },
+                            velocity: 0.0,
+                            acceleration: 0.0,
+                            perception: car.perception.clone(),
+                            crashed: None,
+                            id: car.id,
+                            destination: car.destination,
+                            route: car.route.clone(),
+                        })
+                    } else if position >= road_length
+                        && (occupied
+                            || waits_at_failed_signal
+                            || self.stopped_by_signal(
+                                intersection_index,
+                                &movement,
+                                *about_to_turn,
+                                stateful,
+                            )
+                            || self.yields_to_entrant(
+                                car_index,
+                                intersection_index,
+                                &movement,
+                                stateful,
+                                stateless,
+                                args.dt,
+                            )
+                            || self.waits_for_gap(
+                                intersection_index,
+                                &movement,
+                                *about_to_turn,
+                                stateful,
+                                stateless,
+                            ))
+                    {
+                        // wait at the stop line until the intersection is clear
+                        Some(Car {
+                            location: OnLane {
+                                road_direction: *road_direction,
+                                road_index: *road_index,
+                                lane_direction: *lane_direction,
+                                lane_index: *lane_index,
+                                about_to_turn: *about_to_turn,
+                                position: road_length,
+                            },
+                            velocity: 0.0,
+                            acceleration: 0.0,
+                            perception: car.perception.clone(),
+                            crashed: None,
+                            id: car.id,
+                            destination: car.destination,
+                            route: car.route.clone(),
+                        })
+                    } else if position >= road_length {
                         // switch to InIntersection
-                        let intersection_index = stateless.city.board.lane_to_intersection_index(
-                            *road_direction,
-                            *road_index,
-                            *lane_direction,
-                        );
-                        let driver_direction =
-                            AbsoluteDirection::of_lane(*road_direction, *lane_direction);
-                        let to_direction = driver_direction.turn(*about_to_turn);
-                        let from_direction = driver_direction.turn_back();
+                        let Movement {
+                            from_direction,
+                            to_direction,
+                            ..
+                        } = movement;
                         let to_lane_index = {
-                            let mut rng = rand::thread_rng();
                             let road_index = *stateless
                                 .city
                                 .board
@@ -247,8 +1172,17 @@ impl UpdateController {
                                 .unwrap()
                                 .as_ref()
                                 .unwrap();
-                            let size = road.lanes_to_direction(lane_direction).len();
-                            rng.gen_range(0..size)
+                            let segment = Segment {
+                                road_direction: to_direction.axis_direction(),
+                                road_index,
+                                lane_direction,
+                            };
+                            lane_for_turn(
+                                road.lanes_to_direction(lane_direction),
+                                planned_turn(segment, &car.route),
+                                stateless.cars[car_index].class,
+                                &mut self.rng,
+                            )
                         };
                         let total_length = stateless
                             .city
@@ -273,124 +1207,305 @@ impl UpdateController {
                             location,
                             velocity: car.velocity,
                             acceleration: 0.0,
+                            perception: Default::default(),
+                            crashed: None,
+                            id: car.id,
+                            destination: car.destination,
+                            route: car.route.clone(),
                         })
                     } else {
                         let mut front_objects = Vec::new();
                         {
-                            let front_car_index = self.get_front_car(
-                                car_index,
-                                local_state,
-                                *road_direction,
-                                *road_index,
-                                *lane_direction,
-                                *lane_index,
-                            );
                             if let Some(front_car_index) = front_car_index {
                                 let front_car = stateful.cars[front_car_index].as_ref().unwrap();
                                 let front_position = match &front_car.location {
-                                    OnLane { position, .. } => *position,
+                                    OnLane { position, .. } | ChangingLane { position, .. } => {
+                                        *position
+                                    }
                                     _ => unreachable!(),
                                 };
                                 let velocity = front_car.velocity;
-                                front_objects.push((front_position - position, velocity));
+                                let gap = bumper_gap(
+                                    stateless,
+                                    car_index,
+                                    front_car_index,
+                                    front_position - position,
+                                );
+                                front_objects.push((gap, velocity));
                             }
                         }
+                        if occupied {
+                            front_objects.push((road_length - position, 0.0));
+                        }
+                        // The stop line is a virtual leader, standing still
+                        // while the signal is red for the car
+                        let stop_line_velocity = match stateless.city.board.intersections
+                            [intersection_index]
+                            .as_ref()
+                            .unwrap()
                         {
-                            let intersection_index =
-                                stateless.city.board.lane_to_intersection_index(
-                                    *road_direction,
-                                    *road_index,
-                                    *lane_direction,
-                                );
-                            let stateful_intersection = stateful.city.board.intersections
-                                [intersection_index]
-                                .as_ref()
-                                .unwrap();
-                            let stateless_intersection = stateless.city.board.intersections
-                                [intersection_index]
-                                .as_ref()
-                                .unwrap();
-                            match stateless_intersection {
-                                stateless::Intersection::Crossroad { max_speed, .. } => {
-                                    match stateful_intersection {
-                                        stateful::Intersection::Crossroad { current, .. } => {
-                                            let from_direction = AbsoluteDirection::of_lane(
-                                                *road_direction,
-                                                *lane_direction,
-                                            )
-                                            .turn_back();
-                                            let turn_rule = *current.get(from_direction);
-                                            if about_to_turn.to_turn_rule().intersects(turn_rule) {
-                                                front_objects
-                                                    .push((road_length - position, *max_speed))
-                                            } else {
-                                                front_objects.push((road_length - position, 0.0))
-                                            }
-                                        }
-                                        _ => unreachable!(),
-                                    }
-                                }
-                                stateless::Intersection::TJunction { max_speed, .. } => {
-                                    match stateful_intersection {
-                                        stateful::Intersection::TJunction { current, .. } => {
-                                            // TODO: Fix redundant code
-                                            let from_direction = AbsoluteDirection::of_lane(
-                                                *road_direction,
-                                                *lane_direction,
-                                            )
-                                            .turn_back();
-                                            let turn_rule = *current.get(from_direction);
-                                            if about_to_turn.to_turn_rule().intersects(turn_rule) {
-                                                front_objects
-                                                    .push((road_length - position, *max_speed))
-                                            } else {
-                                                front_objects.push((road_length - position, 0.0))
-                                            }
-                                        }
-                                        _ => unreachable!(),
-                                    }
-                                }
-                                stateless::Intersection::Turn { max_speed } => {
-                                    front_objects.push((road_length - position, *max_speed))
+                            stateless::Intersection::Crossroad { max_speed, .. }
+                            | stateless::Intersection::TJunction { max_speed, .. } => {
+                                if waits_at_failed_signal
+                                    || self.stopped_by_signal(
+                                        intersection_index,
+                                        &movement,
+                                        *about_to_turn,
+                                        stateful,
+                                    )
+                                {
+                                    0.0
+                                } else {
+                                    *max_speed
                                 }
-                                stateless::Intersection::Straight => {
-                                    front_objects.push((road_length - position, lane.max_speed))
-                                }
-                                stateless::Intersection::End { max_speed } => {
-                                    front_objects.push((road_length - position, *max_speed))
+                            }
+                            stateless::Intersection::Turn { max_speed }
+                            | stateless::Intersection::End { max_speed } => *max_speed,
+                            stateless::Intersection::Straight => self.params.lane_speed_limit(lane),
+                        };
+                        front_objects.push((road_length - position, stop_line_velocity));
+                        let mut perception = car.perception.clone();
+                        let front_objects = self.perceive(&mut perception, front_objects);
+                        let mut about_to_turn = *about_to_turn;
+                        let mut route = car.route.clone();
+                        if let Some(destination) = car.destination {
+                            if self.reroute_now(args.dt) && self.rng.gen_bool(self.reroute_fraction)
+                            {
+                                let segment = Segment {
+                                    road_direction: *road_direction,
+                                    road_index: *road_index,
+                                    lane_direction: *lane_direction,
+                                };
+                                let rerouted = self
+                                    .find_route(
+                                        segment.to_intersection(&stateless.city),
+                                        destination,
+                                        stateless.cars[car_index].class,
+                                        stateful,
+                                        stateless,
+                                    )
+                                    .and_then(|rerouted| {
+                                        let turn = self.turn_to_follow(
+                                            segment,
+                                            &rerouted,
+                                            lane.direction_rule,
+                                        )?;
+                                        Some((turn, rerouted))
+                                    });
+                                if let Some((turn, rerouted)) = rerouted {
+                                    about_to_turn = turn;
+                                    route = rerouted;
                                 }
                             }
                         }
                         let acceleration = front_objects
                             .into_iter()
                             .map(|(object_distance, object_velocity)| {
-                                Self::driver_acceleration(
-                                    car.velocity,
-                                    car.acceleration,
-                                    &stateless_car.driving_model,
+                                self.following.acceleration(
                                     object_distance,
+                                    car.velocity,
                                     object_velocity,
+                                    stateless_car,
                                 )
                             })
-                            .min_by(|a, b| a.partial_cmp(b).unwrap())
+                            .min_by(f64::total_cmp)
                             .expect("car can not detect any object front");
-                        let acceleration = acceleration
-                            .min(stateless_car.max_acceleration)
-                            .max(-stateless_car.max_break_acceleration);
-                        Some(Car {
-                            velocity,
-                            acceleration,
-                            location: OnLane {
+                        // A NaN is kept for the limits to report
+                        let acceleration = limits::within(
+                            acceleration + self.acceleration_noise(car.id),
+                            -stateless_car.max_break_acceleration,
+                            stateless_car.max_acceleration,
+                        );
+                        let acceleration = match self.max_jerk {
+                            Some(max_jerk) => {
+                                let max_change = max_jerk * args.dt;
+                                car.acceleration
+                                    + (acceleration - car.acceleration)
+                                        .min(max_change)
+                                        .max(-max_change)
+                            }
+                            None => acceleration,
+                        };
+                        // A car on a lane which does not allow the next turn
+                        // of its route changes towards one which does
+                        let segment = Segment {
+                            road_direction: *road_direction,
+                            road_index: *road_index,
+                            lane_direction: *lane_direction,
+                        };
+                        let needed_turn = route
+                            .first()
+                            .map(|next| segment.heading().should_turn(next.heading()))
+                            .filter(|turn| !lane.direction_rule.contains(turn.to_turn_rule()));
+                        let to_lane_index = match needed_turn {
+                            Some(turn) => self.mandatory_lane_change(
+                                car_index,
+                                lane_key,
+                                last_position,
+                                turn,
+                                stateful,
+                                stateless,
+                            ),
+                            None if self.lane_changes => self.discretionary_lane_change(
+                                car_index,
+                                lane_key,
+                                last_position,
+                                about_to_turn,
+                                stateful,
+                                stateless,
+                            ),
+                            None => None,
+                        };
+                        let location = match to_lane_index {
+                            Some(to_lane_index) => ChangingLane {
+                                road_direction: *road_direction,
+                                road_index: *road_index,
+                                lane_direction: *lane_direction,
+                                from_lane_index: *lane_index,
+                                to_lane_index,
+                                position,
+                                lane_changed_proportion: 0.0,
+                            },
+                            None => OnLane {
                                 road_direction: *road_direction,
                                 road_index: *road_index,
                                 lane_direction: *lane_direction,
                                 lane_index: *lane_index,
-                                about_to_turn: *about_to_turn,
+                                about_to_turn,
                                 position,
                             },
+                        };
+                        Some(Car {
+                            velocity,
+                            acceleration,
+                            perception,
+                            crashed: None,
+                            id: car.id,
+                            destination: car.destination,
+                            route,
+                            location,
                         })
                     }
                 }
+                ChangingLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    from_lane_index,
+                    to_lane_index,
+                    position,
+                    lane_changed_proportion,
+                } => {
+                    let road_length = stateless.city.road_length(*road_direction, *road_index);
+                    let road = stateless.city.board.get_roads(*road_direction)[*road_index]
+                        .as_ref()
+                        .unwrap();
+                    let lane = &road.lanes_to_direction(*lane_direction)[*to_lane_index];
+                    let stateless_car = &self.params.driver(
+                        &stateless.cars[car_index],
+                        self.params.lane_speed_limit(lane),
+                    );
+                    let velocity = (car.velocity + car.acceleration * args.dt)
+                        .min(stateless_car.max_velocity)
+                        .max(0.0);
+                    // The car follows the cars in front of it on both lanes
+                    // until the change is done
+                    let front_objects = [*from_lane_index, *to_lane_index]
+                        .iter()
+                        .filter_map(|lane_index| {
+                            let lane_key =
+                                (*road_direction, *road_index, *lane_direction, *lane_index);
+                            stateful.lanes.leader(lane_key, *position, car_index)
+                        })
+                        .filter_map(|front_car_index| {
+                            let front_car = stateful.cars[front_car_index].as_ref()?;
+                            match front_car.location {
+                                OnLane {
+                                    position: front_position,
+                                    ..
+                                }
+                                | ChangingLane {
+                                    position: front_position,
+                                    ..
+                                } => Some((
+                                    bumper_gap(
+                                        stateless,
+                                        car_index,
+                                        front_car_index,
+                                        front_position - position,
+                                    ),
+                                    front_car.velocity,
+                                )),
+                                _ => None,
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let acceleration = front_objects
+                        .into_iter()
+                        .map(|(object_distance, object_velocity)| {
+                            self.following.acceleration(
+                                object_distance,
+                                car.velocity,
+                                object_velocity,
+                                stateless_car,
+                            )
+                        })
+                        .fold(stateless_car.max_acceleration, f64::min);
+                    let acceleration = limits::within(
+                        acceleration + self.acceleration_noise(car.id),
+                        -stateless_car.max_break_acceleration,
+                        stateless_car.max_acceleration,
+                    );
+                    let position = position + car.velocity * args.dt;
+                    // The proportion advances with time, the view and the
+                    // position in the city ease it sideways
+                    let lane_changed_proportion = if stateless_car.lane_change_time > 0.0 {
+                        lane_changed_proportion + args.dt / stateless_car.lane_change_time
+                    } else {
+                        1.0
+                    };
+                    let location = if lane_changed_proportion >= 1.0 || position >= road_length {
+                        // A change running out of road ends at once, the car
+                        // stops at the line on its new lane
+                        let segment = Segment {
+                            road_direction: *road_direction,
+                            road_index: *road_index,
+                            lane_direction: *lane_direction,
+                        };
+                        let about_to_turn = self
+                            .turn_to_follow(segment, &car.route, lane.direction_rule)
+                            .or_else(|| self.random_choose_relative_direction(lane.direction_rule))
+                            .unwrap_or(RelativeDirection::Front);
+                        OnLane {
+                            road_direction: *road_direction,
+                            road_index: *road_index,
+                            lane_direction: *lane_direction,
+                            lane_index: *to_lane_index,
+                            about_to_turn,
+                            position: position.min(road_length),
+                        }
+                    } else {
+                        ChangingLane {
+                            road_direction: *road_direction,
+                            road_index: *road_index,
+                            lane_direction: *lane_direction,
+                            from_lane_index: *from_lane_index,
+                            to_lane_index: *to_lane_index,
+                            position,
+                            lane_changed_proportion,
+                        }
+                    };
+                    Some(Car {
+                        location,
+                        velocity,
+                        acceleration,
+                        perception: car.perception.clone(),
+                        crashed: None,
+                        id: car.id,
+                        destination: car.destination,
+                        route: car.route.clone(),
+                    })
+                }
                 InIntersection {
                     intersection_index,
                     from_direction,
@@ -437,27 +1552,47 @@ impl UpdateController {
                             .unwrap()
                             .lanes_to_direction(to_lane_direction)[*to_lane_index]
                             .direction_rule;
-                        let about_to_turn = self.random_choose_relative_direction(turn_rule);
-                        match about_to_turn {
-                            Some(about_to_turn) => {
-                                let updated_car = OnLane {
-                                    road_direction: to_direction.axis_direction(),
-                                    road_index: out_road_index,
-                                    lane_direction: to_lane_direction,
-                                    lane_index: *to_lane_index,
-                                    about_to_turn,
-                                    position: 0.0,
-                                };
-                                Some(Car {
-                                    location: updated_car,
-                                    velocity,
-                                    acceleration: 0.0,
-                                })
-                            }
-                            None => None, // remove the car
-                        }
-                    } else {
-                        Some(Car {
+                        let segment = Segment {
+                            road_direction: to_direction.axis_direction(),
+                            road_index: out_road_index,
+                            lane_direction: to_lane_direction,
+                        };
+                        let mut destination = car.destination;
+                        let mut route = car.route.clone();
+                        let about_to_turn = self
+                            .choose_turn(
+                                segment,
+                                turn_rule,
+                                stateless.cars[car_index].class,
+                                &mut destination,
+                                &mut route,
+                                stateful,
+                                stateless,
+                            )
+                            // Cars on an exit lane drive to its end before
+                            // they leave, cars on a lane with no way to leave
+                            // it wait at its end
+                            .unwrap_or(RelativeDirection::Front);
+                        let updated_car = OnLane {
+                            road_direction: to_direction.axis_direction(),
+                            road_index: out_road_index,
+                            lane_direction: to_lane_direction,
+                            lane_index: *to_lane_index,
+                            about_to_turn,
+                            position: 0.0,
+                        };
+                        Some(Car {
+                            location: updated_car,
+                            velocity,
+                            acceleration: 0.0,
+                            perception: Default::default(),
+                            crashed: None,
+                            id: car.id,
+                            destination,
+                            route,
+                        })
+                    } else {
+                        Some(Car {
                             location: InIntersection {
                                 intersection_index: *intersection_index,
                                 from_direction: *from_direction,
@@ -469,21 +1604,56 @@ impl UpdateController {
                             },
                             velocity: car.velocity,
                             acceleration: 0.0,
+                            perception: Default::default(),
+                            crashed: None,
+                            id: car.id,
+                            destination: car.destination,
+                            route: car.route.clone(),
                         })
                     }
                 }
-                _ => unimplemented!(),
             }
         } else if self.car_out_rank == rank && !*outed {
             *outed = true;
-            match self.try_out_car(local_state, stateful, stateless) {
+            let class = stateless.cars[car_index].class;
+            match self.try_out_car(local_state, class, stateful, stateless) {
                 Some((road_direction, road_index, lane_direction, lane_index)) => {
                     let turn_rule = stateless.city.board.get_roads(road_direction)[road_index]
                         .as_ref()
                         .unwrap()
                         .lanes_to_direction(lane_direction)[lane_index]
                         .direction_rule;
-                    let about_to_turn = self.random_choose_relative_direction(turn_rule);
+                    let segment = Segment {
+                        road_direction,
+                        road_index,
+                        lane_direction,
+                    };
+                    // Cars entering at an origin of the OD matrix drive to a
+                    // destination of its row, routed or not
+                    let od_destination = match &stateless.od_matrix {
+                        Some(od_matrix) => {
+                            od_matrix.sample(stateless.city.car_out_intersection, &mut self.rng)
+                        }
+                        None => None,
+                    };
+                    let mut destination = match od_destination {
+                        Some(destination) => Some(destination),
+                        None if self.route_cars => self.random_destination(
+                            stateless,
+                            segment.to_intersection(&stateless.city),
+                        ),
+                        None => None,
+                    };
+                    let mut route = Vec::new();
+                    let about_to_turn = self.choose_turn(
+                        segment,
+                        turn_rule,
+                        class,
+                        &mut destination,
+                        &mut route,
+                        stateful,
+                        stateless,
+                    );
                     match about_to_turn {
                         Some(about_to_turn) => {
                             let car = stateful::Car {
@@ -496,7 +1666,12 @@ impl UpdateController {
                                     about_to_turn,
                                 },
                                 acceleration: 0.0,
+                                perception: Default::default(),
+                                crashed: None,
+                                id: stateful.next_car_id,
                                 velocity: 0.0,
+                                destination,
+                                route,
                             };
                             log::debug!("Crate new car: {:?}", car);
                             Some(car)
@@ -511,80 +1686,543 @@ impl UpdateController {
         }
     }
 
-    pub fn driver_acceleration(
-        velocity: f64,
-        _acceleration: f64,
-        driving_model: &DrivingModel,
-        front_distance: f64,
-        front_velocity: f64,
+    /// Record the current front objects and return the ones the driver
+    /// reacts to, which were perceived `reaction_time` seconds ago.
+    fn perceive(
+        &self,
+        perception: &mut VecDeque<Perception>,
+        front_objects: Vec<(f64, f64)>,
+    ) -> Vec<(f64, f64)> {
+        if self.reaction_time <= 0.0 {
+            return front_objects;
+        }
+        perception.push_back(Perception {
+            time: self.time,
+            objects: front_objects,
+        });
+        let react_time = self.time - self.reaction_time;
+        // Keep the latest perception not newer than `react_time`
+        while perception.len() > 1 && perception[1].time <= react_time {
+            perception.pop_front();
+        }
+        perception.front().unwrap().objects.clone()
+    }
+
+    /// Choose the turn at the end of `segment`.
+    ///
+    /// Cars with a destination follow their route when the lane allows the
+    /// turn, route again when they left it and get a new destination once
+    /// they arrive if cars are routed. Cars arriving with an OD matrix keep
+    /// their destinations, and leave the city at the end of the lane. Other
+    /// cars turn randomly.
+    #[allow(clippy::too_many_arguments)]
+    fn choose_turn(
+        &mut self,
+        segment: Segment,
+        turn_rule: TurnRule,
+        class: VehicleClass,
+        destination: &mut Option<IntersectionIndex>,
+        route: &mut Route,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> Option<RelativeDirection> {
+        if let Some(to) = *destination {
+            let from = segment.to_intersection(&stateless.city);
+            if from == to && stateless.od_matrix.is_some() {
+                route.clear();
+                return self.random_choose_relative_direction(turn_rule);
+            }
+            let to = if from == to {
+                // Arrived, drive to somewhere else if routed
+                *destination = if self.route_cars {
+                    self.random_destination(stateless, from)
+                } else {
+                    None
+                };
+                destination.unwrap_or(from)
+            } else {
+                to
+            };
+            if route.first() == Some(&segment) {
+                route.remove(0);
+            } else {
+                *route = self
+                    .find_route(from, to, class, stateful, stateless)
+                    .unwrap_or_default();
+            }
+            if route.is_empty() {
+                *destination = None;
+            }
+        }
+        self.turn_to_follow(segment, route, turn_rule)
+            .or_else(|| self.random_choose_relative_direction(turn_rule))
+    }
+
+    /// Turn at the end of `segment` onto the first segment of `route`, if the
+    /// lane allows it.
+    fn turn_to_follow(
+        &self,
+        segment: Segment,
+        route: &[Segment],
+        turn_rule: TurnRule,
+    ) -> Option<RelativeDirection> {
+        let next = route.first()?;
+        let turn = segment.heading().should_turn(next.heading());
+        if turn_rule.contains(turn.to_turn_rule()) {
+            Some(turn)
+        } else {
+            None
+        }
+    }
+
+    /// Keep a car waiting with no way to leave its lane since `since`, or
+    /// since now, and warn once it has waited longer than the timeout.
+    fn wait_stuck(&mut self, car_index: CarIndex, lane_key: LaneKey, since: Option<(f64, bool)>) {
+        let (since, mut warned) = since.unwrap_or((self.time, false));
+        if !warned && self.time - since >= self.stuck_timeout {
+            log::warn!(
+                "car {} has waited {:.1} s at the end of lane {:?} with no way to leave it",
+                car_index,
+                self.time - since,
+                lane_key
+            );
+            warned = true;
+        }
+        self.stuck_since.insert(car_index, (since, warned));
+    }
+
+    fn random_destination(
+        &mut self,
+        stateless: &stateless::Model,
+        except: IntersectionIndex,
+    ) -> Option<IntersectionIndex> {
+        let candidates = stateless
+            .city
+            .board
+            .intersections
+            .enumerate()
+            .filter(|(index, intersection)| intersection.is_some() && *index != except)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        candidates.choose(&mut self.rng).copied()
+    }
+
+    /// Fastest route under the current traffic over the roads vehicles of
+    /// `class` may drive on.
+    fn find_route(
+        &self,
+        from: IntersectionIndex,
+        to: IntersectionIndex,
+        class: VehicleClass,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> Option<Route> {
+        let max_speed = stateless
+            .city
+            .board
+            .roads()
+            .filter_map(|(_, road)| road.as_ref())
+            .flat_map(|road| road.lane_to_high.iter().chain(road.lane_to_low.iter()))
+            .map(|lane| self.params.lane_speed_limit(lane))
+            .chain(stateless.cars.iter().map(|car| car.max_velocity))
+            .fold(MIN_ROUTING_SPEED, f64::max);
+        self.routing_algorithm.route_by(
+            &stateless.city,
+            from,
+            to,
+            |segment| self.travel_time(segment, class, stateful, stateless),
+            1.0 / max_speed,
+        )
+    }
+
+    /// Segment length divided by the average speed of the cars on it, or by
+    /// the lane speed limit if it is empty. Infinite if vehicles of `class`
+    /// may drive on none of its lanes, which keeps routes off it.
+    fn travel_time(
+        &self,
+        segment: &Segment,
+        class: VehicleClass,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
     ) -> f64 {
-        match driving_model {
-            DrivingModel::Normal {
-                min_cushion,
-                cushion_velocity_factor,
-                prediction_time,
-            } => {
-                let aim_cushion = min_cushion + cushion_velocity_factor * front_velocity;
-                let dx = front_distance - aim_cushion; // if dx is greater than 0, the car should go faster than front_velocity
-                let aim_average_velocity = dx / *prediction_time;
-                (aim_average_velocity + front_velocity - velocity) * 2.0 / *prediction_time
+        let road = stateless.city.board.get_roads(segment.road_direction)[segment.road_index]
+            .as_ref()
+            .unwrap();
+        if !road.allows(segment.lane_direction, class) {
+            return f64::INFINITY;
+        }
+        let lane_number = road.lanes_to_direction(segment.lane_direction).len();
+        let (car_number, total_velocity) = (0..lane_number)
+            .flat_map(|lane_index| {
+                stateful.lanes.cars((
+                    segment.road_direction,
+                    segment.road_index,
+                    segment.lane_direction,
+                    lane_index,
+                ))
+            })
+            .filter_map(|(_, car_index)| stateful.cars[*car_index].as_ref())
+            .fold((0, 0.0), |(number, total), car| {
+                (number + 1, total + car.velocity)
+            });
+        let speed = if car_number == 0 {
+            road.lanes_to_direction(segment.lane_direction)
+                .iter()
+                .map(|lane| self.params.lane_speed_limit(lane))
+                .fold(0.0, f64::max)
+        } else {
+            total_velocity / car_number as f64
+        };
+        segment.length(&stateless.city) / speed.max(MIN_ROUTING_SPEED)
+    }
+
+    /// Whether the exit of `lane_key` takes a waiting car in this update,
+    /// `exit_headway` after the last car it took.
+    fn exit_open(&self, lane_key: LaneKey, dt: f64) -> bool {
+        self.exit_headway <= 0.0
+            || match self.last_exits.get(&lane_key) {
+                Some(last_exit) => self.time + dt - last_exit >= self.exit_headway,
+                None => true,
+            }
+    }
+
+    /// Remember the time of the cars of `stateful` leaving through exit
+    /// lanes in `cars`, the cars after an update of `dt`.
+    ///
+    /// All processes see the same cars, so they agree on the times without
+    /// communication.
+    fn record_exits(
+        &mut self,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        cars: &[Option<Car>],
+        dt: f64,
+    ) {
+        if self.exit_headway <= 0.0 {
+            return;
+        }
+        let time = self.time + dt;
+        for (before, after) in stateful.cars.iter().zip(cars.iter()) {
+            let car = match (before, after) {
+                (Some(car), None) if car.crashed.is_none() => car,
+                _ => continue,
+            };
+            if let stateful::car::Location::OnLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                lane_index,
+                ..
+            } = car.location
+            {
+                let lane = stateless
+                    .city
+                    .board
+                    .get_road(road_direction, road_index)
+                    .and_then(Option::as_ref)
+                    .and_then(|road| road.lanes_to_direction(lane_direction).get(lane_index));
+                if matches!(lane, Some(lane) if lane.direction_rule.is_empty()) {
+                    let lane_key = (road_direction, road_index, lane_direction, lane_index);
+                    self.last_exits.insert(lane_key, time);
+                }
             }
         }
     }
 
-    fn get_front_car(
+    /// Cars waiting at the end of exit lanes for the exit to take them.
+    pub fn exit_queue(&self, stateful: &stateful::Model, stateless: &stateless::Model) -> usize {
+        stateful
+            .cars
+            .iter()
+            .filter_map(Option::as_ref)
+            .filter(|car| match car.location {
+                stateful::car::Location::OnLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    lane_index,
+                    position,
+                    ..
+                } => {
+                    let lane = stateless
+                        .city
+                        .board
+                        .get_road(road_direction, road_index)
+                        .and_then(Option::as_ref)
+                        .and_then(|road| road.lanes_to_direction(lane_direction).get(lane_index));
+                    lane.map(|lane| lane.direction_rule.is_empty())
+                        .unwrap_or(false)
+                        && position >= stateless.city.road_length(road_direction, road_index)
+                }
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Whether a reroute interval ends in this update.
+    fn reroute_now(&self, dt: f64) -> bool {
+        self.reroute_interval > 0.0
+            && (self.time / self.reroute_interval).floor()
+                != ((self.time + dt) / self.reroute_interval).floor()
+    }
+
+    /// Whether `movement` conflicts with a car already in the intersection.
+    fn conflicts_with_occupancy(
         &self,
-        current_car: CarIndex,
-        local_state: &ProcessLocalState,
-        road_direction: AxisDirection,
-        road_index: RoadIndex,
-        lane_direction: LaneDirection,
-        lane_index: LaneIndex,
-    ) -> Option<CarIndex> {
-        let lane_cars = &local_state.board.get_roads(road_direction)[road_index]
+        intersection_index: IntersectionIndex,
+        movement: &Movement,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> bool {
+        stateful.city.board.intersections[intersection_index]
+            .as_ref()
+            .unwrap()
+            .occupancy()
+            .iter()
+            .any(|occupied| {
+                stateless
+                    .city
+                    .movements_conflict(intersection_index, movement, occupied)
+            })
+    }
+
+    /// Whether a car entering the intersection in the same update with a
+    /// conflicting movement goes first.
+    ///
+    /// Only the front car of each incoming lane may enter, ties are broken
+    /// by car index so every process makes the same decision.
+    #[allow(clippy::too_many_arguments)]
+    fn yields_to_entrant(
+        &self,
+        car_index: CarIndex,
+        intersection_index: IntersectionIndex,
+        movement: &Movement,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        dt: f64,
+    ) -> bool {
+        let context = stateless
+            .city
+            .board
+            .context_of_intersection(intersection_index);
+        for direction in AbsoluteDirection::directions() {
+            if let Some(road_index) = context.get(*direction) {
+                let road_direction = direction.axis_direction();
+                let lane_direction =
+                    LaneDirection::absolute_in_out_to_lane(*direction, InOutDirection::In);
+                let road_length = stateless.city.road_length(road_direction, *road_index);
+                let lane_number = stateless.city.board.get_roads(road_direction)[*road_index]
+                    .as_ref()
+                    .unwrap()
+                    .lanes_to_direction(lane_direction)
+                    .len();
+                let last_cars = (0..lane_number).filter_map(|lane_index| {
+                    stateful
+                        .lanes
+                        .cars((road_direction, *road_index, lane_direction, lane_index))
+                        .last()
+                });
+                for (_, other_index) in last_cars {
+                    if *other_index >= car_index {
+                        continue;
+                    }
+                    let other = stateful.cars[*other_index].as_ref().unwrap();
+                    if let stateful::car::Location::OnLane {
+                        lane_index,
+                        about_to_turn,
+                        position,
+                        ..
+                    } = &other.location
+                    {
+                        if position + other.velocity * dt >= road_length {
+                            let other_movement = Movement {
+                                from_direction: *direction,
+                                from_lane_index: *lane_index,
+                                to_direction: direction.turn_back().turn(*about_to_turn),
+                            };
+                            if stateless.city.movements_conflict(
+                                intersection_index,
+                                movement,
+                                &other_movement,
+                            ) {
+                                return true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Whether the signal of an intersection is red for a car coming by
+    /// `movement` which is about to turn `about_to_turn`, never while the
+    /// signals have failed.
+    fn stopped_by_signal(
+        &self,
+        intersection_index: IntersectionIndex,
+        movement: &Movement,
+        about_to_turn: RelativeDirection,
+        stateful: &stateful::Model,
+    ) -> bool {
+        if stateful.city.signals_failed {
+            return false;
+        }
+        let current = match stateful.city.board.intersections[intersection_index]
+            .as_ref()
+            .and_then(|intersection| intersection.current())
+        {
+            Some(current) => current,
+            None => return false,
+        };
+        !about_to_turn
+            .to_turn_rule()
+            .intersects(*current.get(movement.from_direction))
+    }
+
+    /// Whether a car turning across the oncoming traffic waits for a gap.
+    ///
+    /// On a permissive green, the car waits while the next oncoming car going
+    /// straight on a green signal arrives at the intersection within the
+    /// critical gap. A protected turn does not wait, and there are no green
+    /// signals while the signals have failed.
+    fn waits_for_gap(
+        &self,
+        intersection_index: IntersectionIndex,
+        movement: &Movement,
+        about_to_turn: RelativeDirection,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+    ) -> bool {
+        if stateful.city.signals_failed
+            || about_to_turn.to_turn_rule() != stateless.city.handed_traffic.crossing_turn()
+        {
+            return false;
+        }
+        let current = match stateful.city.board.intersections[intersection_index]
+            .as_ref()
+            .and_then(|intersection| intersection.current())
+        {
+            Some(current) => current,
+            None => return false,
+        };
+        if !stateless
+            .city
+            .handed_traffic
+            .permissive_crossing(current, movement.from_direction)
+        {
+            return false;
+        }
+        // Oncoming cars enter from the side the car heads to
+        let direction = movement.from_direction.turn_back();
+        let road_index = match stateless
+            .city
+            .board
+            .context_of_intersection(intersection_index)
+            .get(direction)
+        {
+            Some(road_index) => *road_index,
+            None => return false,
+        };
+        let road_direction = direction.axis_direction();
+        let lane_direction = LaneDirection::absolute_in_out_to_lane(direction, InOutDirection::In);
+        let road_length = stateless.city.road_length(road_direction, road_index);
+        let lane_number = stateless.city.board.get_roads(road_direction)[road_index]
             .as_ref()
             .unwrap()
-            .lanes_to_direction(lane_direction)[lane_index];
-        for (index, (_, car_index)) in lane_cars.cars.iter().enumerate() {
-            if *car_index == current_car && index != lane_cars.cars.len() - 1 {
-                return Some(lane_cars.cars[index + 1].1);
+            .lanes_to_direction(lane_direction)
+            .len();
+        (0..lane_number)
+            .filter_map(|lane_index| {
+                stateful
+                    .lanes
+                    .cars((road_direction, road_index, lane_direction, lane_index))
+                    .last()
+            })
+            .filter_map(|(_, other_index)| stateful.cars[*other_index].as_ref())
+            .any(|other| match other.location {
+                stateful::car::Location::OnLane {
+                    about_to_turn: RelativeDirection::Front,
+                    position,
+                    ..
+                } => road_length - position < self.params.critical_gap * other.velocity,
+                _ => false,
+            })
+    }
+
+    /// Noise added to the acceleration a driver chooses, drawn from the
+    /// noise seed, the physics step and the id of the car, so the process
+    /// updating the car does not matter.
+    fn acceleration_noise(&self, car_id: u64) -> f64 {
+        let mut seed = <StdRng as SeedableRng>::Seed::default();
+        seed[..8].copy_from_slice(&self.noise_seed.to_le_bytes());
+        seed[8..16].copy_from_slice(&self.physics_step.to_le_bytes());
+        seed[16..24].copy_from_slice(&car_id.to_le_bytes());
+        self.params
+            .noise_distribution
+            .sample(self.params.acceleration_noise, &mut StdRng::from_seed(seed))
+    }
+
+    fn random_choose_relative_direction(
+        &mut self,
+        turn_rule: TurnRule,
+    ) -> Option<RelativeDirection> {
+        self.turn_weights.choose(turn_rule, &mut self.rng)
+    }
+
+    /// Add the demand of an update of `dt` seconds from now and return
+    /// whether a car is due to enter the city.
+    ///
+    /// Without a demand profile a car is due in every update. At most one car
+    /// enters per update, so rates above one car per update are not met.
+    fn car_due(&mut self, stateless: &stateless::Model, dt: f64) -> bool {
+        match &stateless.demand {
+            Some(demand) => {
+                self.demand_due += demand.cars_between(self.time, self.time + dt);
+                self.demand_due >= 1.0
             }
+            None => true,
         }
-        None
     }
 
-    fn random_choose_relative_direction(&self, turn_rule: TurnRule) -> Option<RelativeDirection> {
-        use crate::model::common::RelativeDirection::*;
-        let all_rule = [
-            TurnRule::FRONT,
-            TurnRule::BACK,
-            TurnRule::LEFT,
-            TurnRule::RIGHT,
-        ];
-        let enabled_rule = all_rule
+    /// Take the car which entered the city in `cars` from the demand, or
+    /// count the due car as refused if it had a free slot and did not enter.
+    ///
+    /// All processes see the same gathered cars, so they agree on the demand
+    /// without communication.
+    fn serve_demand(
+        &mut self,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        cars: &[Option<Car>],
+        allow_car_out: bool,
+    ) {
+        if !allow_car_out || stateless.demand.is_none() {
+            return;
+        }
+        let entered = stateful
+            .cars
             .iter()
-            .filter(|&rule| (turn_rule & *rule) == *rule)
-            .collect::<Vec<_>>();
-        match enabled_rule.len() {
-            0 => None,
-            len => {
-                let mut rng = rand::thread_rng();
-                let rule = enabled_rule[rng.gen_range(0..len)];
-                Some(match *rule {
-                    TurnRule::FRONT => Front,
-                    TurnRule::BACK => Back,
-                    TurnRule::LEFT => Left,
-                    TurnRule::RIGHT => Right,
-                    _ => unreachable!(),
-                })
-            }
+            .zip(cars.iter())
+            .any(|(before, after)| before.is_none() && after.is_some());
+        if entered {
+            self.demand_due -= 1.0;
+        } else if stateful.cars.iter().any(Option::is_none) {
+            self.refused_car_outs += 1;
         }
     }
 
+    /// Cars due to enter the city by the demand profile and not entered yet.
+    pub fn demand_due(&self) -> f64 {
+        self.demand_due
+    }
+
+    /// An entry lane with room for a vehicle of `class`.
     pub fn try_out_car(
-        &self,
+        &mut self,
         local_state: &ProcessLocalState,
-        _stateful: &stateful::Model,
+        class: VehicleClass,
+        stateful: &stateful::Model,
         stateless: &stateless::Model,
     ) -> Option<(AxisDirection, RoadIndex, LaneDirection, LaneIndex)> {
         log::trace!("try_out_car called");
@@ -599,12 +2237,26 @@ impl UpdateController {
             for (lane_index, availability) in lanes_availability.iter().enumerate() {
                 if *availability {
                     let road_index = context.get(*direction).unwrap();
-                    let car_out_parameter = (
-                        direction.axis_direction(),
-                        road_index,
-                        LaneDirection::absolute_in_out_to_lane(*direction, InOutDirection::Out),
-                        lane_index,
-                    );
+                    let road_direction = direction.axis_direction();
+                    let lane_direction =
+                        LaneDirection::absolute_in_out_to_lane(*direction, InOutDirection::Out);
+                    let car_number = stateful
+                        .lanes
+                        .cars((road_direction, road_index, lane_direction, lane_index))
+                        .len();
+                    let capacity = self
+                        .params
+                        .lane_capacity(stateless.city.road_length(road_direction, road_index));
+                    let allowed = stateless.city.board.get_roads(road_direction)[road_index]
+                        .as_ref()
+                        .unwrap()
+                        .lanes_to_direction(lane_direction)[lane_index]
+                        .allows(class);
+                    if car_number >= capacity || !allowed {
+                        continue;
+                    }
+                    let car_out_parameter =
+                        (road_direction, road_index, lane_direction, lane_index);
                     log::debug!("car out parameter: {:?}", car_out_parameter);
                     return Some(car_out_parameter);
                 }
@@ -618,28 +2270,74 @@ impl UpdateController {
         root: Rank,
         communicator: Comm,
         stateful: &mut stateful::City,
+        cars: &[Option<Car>],
         stateless: &stateless::City,
         args: UpdateArgs,
     ) where
         Comm: CommunicatorCollectives,
     {
         if communicator.rank() == root {
-            // Update intersection first
-            for (stateful_intersection, stateless_intersection) in stateful
-                .board
-                .intersections
-                .iter_mut()
-                .zip(stateless.board.intersections.iter())
+            self.update_intersections(stateful, cars, stateless, args);
+        }
+        let root_process = communicator.process_at_rank(root);
+        communication::bincode_broadcast(communicator.rank(), root_process, stateful).unwrap();
+    }
+
+    /// Update signals and occupancy of all intersections.
+    pub fn update_intersections(
+        &mut self,
+        stateful: &mut stateful::City,
+        cars: &[Option<Car>],
+        stateless: &stateless::City,
+        args: UpdateArgs,
+    ) {
+        // Update intersection first
+        for (stateful_intersection, stateless_intersection) in stateful
+            .board
+            .intersections
+            .iter_mut()
+            .zip(stateless.board.intersections.iter())
+        {
+            if let Some(stateful_intersection) = stateful_intersection.as_mut() {
+                let stateless_intersection = stateless_intersection.as_ref().unwrap();
+                self.update_intersection(stateful_intersection, stateless_intersection, args);
+                stateful_intersection.update_current(stateless_intersection);
+                stateful_intersection.occupancy_mut().clear();
+            }
+        }
+        stateful.signals_failed = self.signals_failed;
+        for (intersection_index, rule) in self.signal_overrides.iter() {
+            if let Some(current) = stateful.board.intersections[*intersection_index]
+                .as_mut()
+                .and_then(|intersection| intersection.current_mut())
             {
-                if let Some(stateful_intersection) = stateful_intersection.as_mut() {
-                    let stateless_intersection = stateless_intersection.as_ref().unwrap();
-                    self.update_intersection(stateful_intersection, stateless_intersection, args);
-                    stateful_intersection.update_current(stateless_intersection);
+                *current = *rule;
+            }
+        }
+        // Record movements of the cars in intersections
+        for car in cars.iter().filter_map(|car| car.as_ref()) {
+            if let stateful::car::Location::InIntersection {
+                intersection_index,
+                from_direction,
+                from_lane_index,
+                to_direction,
+                ..
+            } = car.location
+            {
+                let movement = Movement {
+                    from_direction,
+                    from_lane_index,
+                    to_direction,
+                };
+                let occupancy = stateful.board.intersections[intersection_index]
+                    .as_mut()
+                    .unwrap()
+                    .occupancy_mut();
+                if !occupancy.contains(&movement) {
+                    occupancy.push(movement);
                 }
             }
         }
-        let root_process = communicator.process_at_rank(root);
-        communication::bincode_broadcast(communicator.rank(), root_process, stateful).unwrap();
     }
 
     fn update_intersection(
@@ -650,60 +2348,1521 @@ impl UpdateController {
     ) {
         match (stateful, stateless) {
             (
-                stateful::Intersection::Crossroad {
-                    switch_state:
-                        stateful::intersection::SwitchState::LoopTimeout {
-                            remain_time,
-                            time_index,
-                            rule_index,
-                        },
-                    ..
-                },
+                stateful::Intersection::Crossroad { switch_state, .. },
                 stateless::Intersection::Crossroad {
-                    rules,
-                    switch_rule: stateless::intersection::SwitchRule::LoopTimeout { times },
-                    ..
+                    rules, switch_rule, ..
                 },
-            ) => {
-                *remain_time -= dt;
-                if *remain_time < 0.0 {
-                    // Timeout
-                    *time_index += 1;
-                    *time_index %= times.len();
-                    *rule_index += 1;
-                    *rule_index %= rules.len();
-                    *remain_time += times[*time_index]; // Set new timeout
-                }
-            }
+            ) => switch_state.advance(dt, switch_rule, rules.len()),
             (
-                stateful::Intersection::TJunction {
-                    switch_state:
-                        stateful::intersection::SwitchState::LoopTimeout {
-                            remain_time,
-                            time_index,
-                            rule_index,
-                        },
-                    ..
-                },
+                stateful::Intersection::TJunction { switch_state, .. },
                 stateless::Intersection::TJunction {
                     rule_set,
-                    switch_rule: stateless::intersection::SwitchRule::LoopTimeout { times },
+                    switch_rule,
                     ..
                 },
-            ) => {
-                *remain_time -= dt;
-                if *remain_time < 0.0 {
-                    // Timeout
-                    *time_index += 1;
-                    *time_index %= times.len();
-                    *rule_index += 1;
-                    *rule_index %= rule_set.len();
-                    *remain_time += times[*time_index]; // Set new timeout
-                }
-            }
+            ) => switch_state.advance(dt, switch_rule, rule_set.len()),
             (stateful::Intersection::Crossroad { .. }, _) => unreachable!(),
             (stateful::Intersection::TJunction { .. }, _) => unreachable!(),
             _ => (), // no need to update current
         }
     }
 }
+
+/// Gap between the front bumper of the car at `back` and the rear bumper
+/// of the car at `front`, `distance` apart from center to center.
+fn bumper_gap(stateless: &stateless::Model, back: CarIndex, front: CarIndex, distance: f64) -> f64 {
+    distance - (stateless.cars[back].length + stateless.cars[front].length) / 2.0
+}
+
+/// Turn at the end of `segment` which follows `route`, if the car will
+/// drive on `segment` next.
+fn planned_turn(segment: Segment, route: &[Segment]) -> Option<RelativeDirection> {
+    match route {
+        [first, next, ..] if *first == segment => {
+            Some(segment.heading().should_turn(next.heading()))
+        }
+        _ => None,
+    }
+}
+
+/// Turns allowed by `turn_rule` at the end of a lane heading to
+/// `driver_direction` into an intersection, which lead onto lanes leaving
+/// it that vehicles of `class` may drive on.
+fn reachable_turns(
+    city: &stateless::City,
+    intersection_index: IntersectionIndex,
+    driver_direction: AbsoluteDirection,
+    turn_rule: TurnRule,
+    class: VehicleClass,
+) -> TurnRule {
+    use RelativeDirection::*;
+    let context = city.board.context_of_intersection(intersection_index);
+    [Front, Back, Left, Right]
+        .iter()
+        .filter(|turn| turn_rule.contains(turn.to_turn_rule()))
+        .filter(|turn| {
+            let to_direction = driver_direction.turn(**turn);
+            let lane_direction = LaneDirection::absolute_in_out_to_lane(to_direction, Out);
+            match context.get(to_direction).and_then(|road_index| {
+                city.board
+                    .get_road(to_direction.axis_direction(), road_index)
+            }) {
+                Some(Some(road)) => road.allows(lane_direction, class),
+                _ => false,
+            }
+        })
+        .fold(TurnRule::empty(), |rule, turn| rule | turn.to_turn_rule())
+}
+
+/// A random lane among the `lanes` vehicles of `class` may drive on whose
+/// turn rule allows `turn`, or among all of them if no turn is planned or no
+/// lane allows it.
+fn lane_for_turn<R: Rng + ?Sized>(
+    lanes: &[stateless::Lane],
+    turn: Option<RelativeDirection>,
+    class: VehicleClass,
+    rng: &mut R,
+) -> LaneIndex {
+    let open = (0..lanes.len())
+        .filter(|index| lanes[*index].allows(class))
+        .collect::<Vec<_>>();
+    // A restricted road reached anyway is driven on any of its lanes
+    let open = if open.is_empty() {
+        (0..lanes.len()).collect()
+    } else {
+        open
+    };
+    let allowed = match turn {
+        Some(turn) => open
+            .iter()
+            .copied()
+            .filter(|index| lanes[*index].direction_rule.contains(turn.to_turn_rule()))
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+    if allowed.is_empty() {
+        open[rng.gen_range(0..open.len())]
+    } else {
+        allowed[rng.gen_range(0..allowed.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        board::Board,
+        generate::stateful::generate_from_stateless,
+        stateful::testing::{car_at, on_lane},
+        stateless::car::DrivingModel,
+    };
+
+    fn update_settings() -> UpdateSettings {
+        UpdateSettings {
+            car_following_model: CarFollowingModelKind::Cushion,
+            reaction_time: 0.0,
+            routing_algorithm: RoutingAlgorithm::Dijkstra,
+            route_cars: false,
+            turn_policy: TurnPolicy::Random(Default::default()),
+            reroute_interval: 0.0,
+            reroute_fraction: 0.0,
+            max_jerk: None,
+            lane_changes: false,
+            max_physics_dt: None,
+            collision_policy: CollisionPolicy::Ignore,
+            crash_clearance_time: 120.0,
+            exit_headway: 0.0,
+            stuck_timeout: 60.0,
+            log_clamps: false,
+            seed: None,
+            sim_params: None,
+        }
+    }
+
+    fn controller_settings() -> ControllerSettings {
+        ControllerSettings {
+            zoom_step: 0.1,
+            history_window: 60.0,
+            flow_window: 60.0,
+            detector_output: None,
+            detector_interval: 60.0,
+            diagram_interval: 60.0,
+            diagram_output: None,
+            summary_output: None,
+            cordon_interval: 300.0,
+            cordon_output: None,
+            break_on_violation: false,
+            check_invariants: false,
+            start_paused: false,
+            collision_distance: 4.5,
+            warmup: 0.0,
+            slowdown_fraction: 0.5,
+            state_output: None,
+            state_baseline: None,
+            #[cfg(feature = "binary-state")]
+            replay_output: None,
+            #[cfg(feature = "binary-state")]
+            replay: None,
+            #[cfg(feature = "binary-state")]
+            replay_seek_frames: 60,
+            #[cfg(feature = "metrics")]
+            metrics_port: None,
+        }
+    }
+
+    fn stateless_car(max_velocity: f64) -> stateless::Car {
+        stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        }
+    }
+
+    /// A single one way road with a leader at index 1 and a follower at
+    /// index 0.
+    fn two_car_model(
+        leader: (f64, f64),
+        follower: (f64, f64),
+    ) -> (stateless::Model, stateful::Model) {
+        let lane = stateless::Lane {
+            max_speed: 30.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::FRONT,
+        };
+        let mut board = Board::with_shape(
+            Some(stateless::Intersection::End { max_speed: 10.0 }),
+            None,
+            (1, 2),
+        );
+        board.horizontal_roads[(0, 0)] = Some(stateless::Road {
+            lane_to_high: vec![lane],
+            lane_to_low: Vec::new(),
+        });
+        let stateless = stateless::Model {
+            city: stateless::City {
+                board,
+                car_out_intersection: (0, 0),
+                car_out_min_distance: 8.0,
+                lane_width: 3.5,
+                horizontal_road_length: vec![10_000.0],
+                vertical_road_length: Vec::new(),
+                intersection_height: vec![20.0],
+                intersection_width: vec![20.0, 20.0],
+                handed_traffic: Default::default(),
+            },
+            cars: vec![stateless_car(20.0), stateless_car(15.0)],
+            detectors: Vec::new(),
+            demand: None,
+            od_matrix: None,
+            zones: Vec::new(),
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        stateful.replace_cars(vec![
+            car_at(on_lane(0, follower.0), follower.1),
+            car_at(on_lane(0, leader.0), leader.1),
+        ]);
+        (stateless, stateful)
+    }
+
+    /// Step the model and return the (position, velocity) of all cars after
+    /// each step.
+    fn trajectory(
+        settings: UpdateSettings,
+        stateless: &stateless::Model,
+        mut stateful: stateful::Model,
+        steps: usize,
+        dt: f64,
+    ) -> Vec<Vec<(f64, f64)>> {
+        let mut controller = UpdateController::new(settings, SimParams::default());
+        (0..steps)
+            .map(|_| {
+                controller.step(&mut stateful, stateless, UpdateArgs { dt });
+                stateful
+                    .cars
+                    .iter()
+                    .map(|car| {
+                        let car = car.as_ref().expect("car removed");
+                        match car.location {
+                            stateful::car::Location::OnLane { position, .. } => {
+                                (position, car.velocity)
+                            }
+                            _ => panic!("car left the road"),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enter_lane_allowing_planned_turn() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let lane = |direction_rule| stateless::Lane {
+            max_speed: 30.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule,
+        };
+        let lanes = [
+            lane(TurnRule::LEFT),
+            lane(TurnRule::FRONT),
+            lane(TurnRule::FRONT | TurnRule::RIGHT),
+        ];
+        let segment = |road_index| Segment {
+            road_direction: AxisDirection::Horizontal,
+            road_index,
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let turn_right = Segment {
+            road_direction: AxisDirection::Vertical,
+            road_index: (0, 2),
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let route = vec![segment((0, 1)), turn_right];
+        let turn = planned_turn(segment((0, 1)), &route);
+        assert_eq!(turn, Some(RelativeDirection::Right));
+        assert_eq!(planned_turn(segment((0, 0)), &route), None);
+        let car = VehicleClass::Car;
+        for _ in 0..20 {
+            assert_eq!(lane_for_turn(&lanes, turn, car, &mut rng), 2);
+        }
+        let lane_index = lane_for_turn(&lanes, Some(RelativeDirection::Back), car, &mut rng);
+        assert!(lane_index < lanes.len());
+    }
+
+    #[test]
+    fn only_allowed_classes_enter_bus_lanes() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let bus_lane = stateless::Lane {
+            max_speed: 30.0,
+            speed_limit: None,
+            allowed_classes: Some(vec![VehicleClass::Bus]),
+            direction_rule: TurnRule::FRONT | TurnRule::RIGHT,
+        };
+        let lanes = [
+            stateless::Lane {
+                allowed_classes: None,
+                direction_rule: TurnRule::FRONT,
+                ..bus_lane.clone()
+            },
+            bus_lane,
+        ];
+        let right = Some(RelativeDirection::Right);
+        for _ in 0..20 {
+            // Cars keep off the bus lane even for a turn only it allows
+            assert_eq!(lane_for_turn(&lanes, right, VehicleClass::Car, &mut rng), 0);
+            assert_eq!(lane_for_turn(&lanes, right, VehicleClass::Bus, &mut rng), 1);
+        }
+        let road = stateless::Road {
+            lane_to_high: lanes[1..].to_vec(),
+            lane_to_low: lanes.to_vec(),
+        };
+        assert!(road.allows(LaneDirection::LowToHigh, VehicleClass::Bus));
+        assert!(!road.allows(LaneDirection::LowToHigh, VehicleClass::Car));
+        assert!(road.allows(LaneDirection::HighToLow, VehicleClass::Truck));
+    }
+
+    #[test]
+    fn preview_step_leaves_model_as_it_is() {
+        let (stateless, mut stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        let update_controller = UpdateController::new(update_settings(), SimParams::default());
+        let mut controller = Controller::new(update_controller, controller_settings(), &stateless);
+        let before = stateful.cars.clone();
+        let ghosts = controller.preview_step(&stateful, &stateless, 0.5).to_vec();
+        assert_eq!(ghosts.len(), 2);
+        for (car, before) in stateful.cars.iter().zip(before.iter()) {
+            assert_eq!(
+                car.as_ref().unwrap().location,
+                before.as_ref().unwrap().location
+            );
+        }
+        // The preview is where the update puts the cars
+        let mut next = stateful.clone();
+        controller
+            .update_controller
+            .clone()
+            .step(&mut next, &stateless, UpdateArgs { dt: 0.5 });
+        for (car_index, location) in ghosts.iter() {
+            assert_eq!(*location, next.cars[*car_index].as_ref().unwrap().location);
+        }
+
+        // The preview is kept until an edit, as failing the signals
+        stateful.cars[0].as_mut().unwrap().velocity = 0.0;
+        assert_eq!(
+            controller.preview_step(&stateful, &stateless, 0.5),
+            &ghosts[..]
+        );
+        controller.toggle_signal_failure(&mut Info::new());
+        assert_eq!(controller.preview(), None);
+        assert_ne!(
+            controller.preview_step(&stateful, &stateless, 0.5),
+            &ghosts[..]
+        );
+    }
+
+    #[test]
+    fn inject_car_on_free_spot() {
+        use inject::InjectError::*;
+        let (stateless, mut stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        let controller = UpdateController::new(update_settings(), SimParams::default());
+        let road = Segment {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let inject = |stateful: &mut stateful::Model, lane, position| {
+            controller.inject_car(stateful, &stateless, road, lane, position, Vec::new())
+        };
+        assert_eq!(inject(&mut stateful, 0, 100.0), Err(NoFreeSlot));
+        stateful.replace_cars(vec![None, car_at(on_lane(0, 50.0), 10.0)]);
+        assert_eq!(inject(&mut stateful, 1, 100.0), Err(NoLane(road, 1)));
+        assert_eq!(
+            inject(&mut stateful, 0, 10_001.0),
+            Err(PositionOutOfRange(10_001.0, 10_000.0))
+        );
+        // The cars are 4.5 meters long and keep a gap of 2 meters
+        assert_eq!(inject(&mut stateful, 0, 45.0), Err(Occupied(1)));
+        assert_eq!(inject(&mut stateful, 0, 43.0), Ok(0));
+        let lane_key = (
+            AxisDirection::Horizontal,
+            (0, 0),
+            LaneDirection::LowToHigh,
+            0,
+        );
+        assert_eq!(stateful.lanes.cars(lane_key), &[(43.0, 0), (50.0, 1)]);
+    }
+
+    #[test]
+    fn crashed_cars_block_until_cleared() {
+        let (stateless, mut stateful) = two_car_model((50.0, 0.0), (47.0, 10.0));
+        let settings = UpdateSettings {
+            collision_policy: CollisionPolicy::Crash,
+            crash_clearance_time: 10.0,
+            ..update_settings()
+        };
+        let mut controller = UpdateController::new(settings, SimParams::default());
+        stateful.replace_cars(vec![
+            car_at(on_lane(0, 47.0), 10.0),
+            car_at(on_lane(0, 50.0), 0.0),
+        ]);
+        controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        let crashed = |stateful: &stateful::Model| {
+            stateful
+                .cars
+                .iter()
+                .map(|car| {
+                    car.as_ref()
+                        .map(|car| (car.crashed.is_some(), car.velocity))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(crashed(&stateful), vec![Some((true, 0.0)); 2]);
+        for _ in 0..50 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        assert_eq!(crashed(&stateful), vec![Some((true, 0.0)); 2]);
+        for _ in 0..51 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        // Only new cars from the start of the road are left
+        for car in stateful.cars.iter().flatten() {
+            assert!(car.crashed.is_none());
+            match car.location {
+                stateful::car::Location::OnLane { position, .. } => assert!(position < 47.0),
+                _ => panic!("car left the road"),
+            }
+        }
+    }
+
+    #[test]
+    fn signal_edited_by_hand_overrides_schedule() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let update_controller = UpdateController::new(update_settings(), SimParams::default());
+        let mut controller = Controller::new(update_controller, controller_settings(), &stateless);
+        let mut info = Info::new();
+        let current = |stateful: &stateful::Model| {
+            *stateful.city.board.intersections[(1, 1)]
+                .as_ref()
+                .unwrap()
+                .current()
+                .unwrap()
+        };
+        let scheduled = current(&stateful);
+
+        // Keys do nothing without a selected intersection
+        controller.edit_signal(&mut info, &mut stateful, Key::F);
+        assert_eq!(current(&stateful), scheduled);
+        info.selected_intersection = Some((1, 1));
+        for key in [Key::D2, Key::F, Key::L].iter() {
+            controller.edit_signal(&mut info, &mut stateful, *key);
+        }
+        let mut edited = scheduled;
+        edited.west.toggle(TurnRule::FRONT | TurnRule::LEFT);
+        assert_eq!(current(&stateful), edited);
+
+        // The edited signal is kept through switches of the schedule
+        let args = UpdateArgs { dt: 1000.0 };
+        controller.update_controller.update_intersections(
+            &mut stateful.city,
+            &[],
+            &stateless.city,
+            args,
+        );
+        assert_eq!(current(&stateful), edited);
+        controller.edit_signal(&mut info, &mut stateful, Key::S);
+        assert_eq!(controller.update_controller.signal_override((1, 1)), None);
+        controller.update_controller.update_intersections(
+            &mut stateful.city,
+            &[],
+            &stateless.city,
+            args,
+        );
+        let intersection = stateful.city.board.intersections[(1, 1)].as_ref().unwrap();
+        let rule_index = intersection.switch_state().unwrap().rule_index();
+        let stateless_intersection = stateless.city.board.intersections[(1, 1)].as_ref();
+        assert_eq!(
+            Some(current(&stateful)),
+            stateless_intersection.unwrap().rule(rule_index)
+        );
+        controller.edit_signal(&mut info, &mut stateful, Key::Escape);
+        assert_eq!(info.selected_intersection, None);
+    }
+
+    #[test]
+    fn goto_typed_intersection() {
+        use crate::{
+            model::stateless::CityBuilder,
+            view::{Camera, View, ViewSettings},
+        };
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let update_controller = UpdateController::new(update_settings(), SimParams::default());
+        let mut controller = Controller::new(update_controller, controller_settings(), &stateless);
+        let mut info = Info::new();
+        let press = |key| {
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(key),
+                scancode: None,
+            })
+        };
+        let mut type_in = |info: &mut Info, inputs: Vec<Input>| {
+            for input in inputs {
+                controller.input(info, &mut stateful, &stateless, input);
+            }
+        };
+
+        type_in(
+            &mut info,
+            vec![
+                press(Key::G),
+                Input::Text("g".to_string()),
+                Input::Text("(1, 12".to_string()),
+                press(Key::Backspace),
+                Input::Text(")".to_string()),
+            ],
+        );
+        assert_eq!(info.goto_input.as_deref(), Some("(1, 1)"));
+        type_in(&mut info, vec![press(Key::Return)]);
+        assert_eq!(info.goto_input, None);
+        assert_eq!(info.goto_intersection, Some((1, 1)));
+
+        // The view centers on the intersection and highlights it
+        let view = View::new(ViewSettings::default());
+        let window_size = [800.0, 600.0];
+        assert!(view.goto_intersection(&mut info, &stateless.city, window_size));
+        let center = stateless.city.intersection_center((1, 1));
+        let [x, y] = info
+            .camera
+            .compose(Camera::fit(
+                stateless.city.geometry(),
+                window_size,
+                view.settings.padding,
+            ))
+            .world_to_screen(center);
+        assert!((x - 400.0).abs() < 1e-9 && (y - 300.0).abs() < 1e-9);
+        assert_eq!(info.goto_intersection, None);
+        assert_eq!(
+            info.highlighted_intersection.map(|(index, _)| index),
+            Some((1, 1))
+        );
+
+        // Intersections out of the city and cancelled inputs go nowhere
+        type_in(
+            &mut info,
+            vec![
+                press(Key::G),
+                Input::Text("5 5".to_string()),
+                press(Key::Return),
+            ],
+        );
+        type_in(
+            &mut info,
+            vec![
+                press(Key::G),
+                Input::Text("0,1".to_string()),
+                press(Key::Escape),
+            ],
+        );
+        assert_eq!(info.goto_input, None);
+        assert_eq!(info.goto_intersection, None);
+    }
+
+    #[test]
+    fn seeded_runs_match_across_threads() {
+        use crate::model::stateless::CityBuilder;
+        use std::{sync::Arc, thread};
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .car_out((1, 1), 8.0)
+            .build()
+            .unwrap();
+        let stateless = Arc::new(stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0); 8],
+            ..Default::default()
+        });
+        let run = |stateless: Arc<stateless::Model>, seed| {
+            let settings = UpdateSettings {
+                seed: Some(seed),
+                ..update_settings()
+            };
+            let mut controller = UpdateController::new(settings, SimParams::default());
+            let mut stateful = generate_from_stateless(&stateless);
+            for _ in 0..300 {
+                controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+            }
+            stateful.fingerprint()
+        };
+        // Simulations of the same map share nothing but the map
+        let handles = (0..4)
+            .map(|_| {
+                let stateless = Arc::clone(&stateless);
+                thread::spawn(move || run(stateless, 7))
+            })
+            .collect::<Vec<_>>();
+        let fingerprints = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fingerprints, vec![run(stateless, 7); 4]);
+    }
+
+    #[test]
+    fn od_cars_leave_at_their_destinations() {
+        use crate::model::demand::{DemandProfile, OdMatrix, OdRow};
+        let (mut stateless, mut stateful) = two_car_model((9_990.0, 10.0), (9_900.0, 10.0));
+        stateless.od_matrix = Some(OdMatrix {
+            rows: vec![OdRow {
+                origin: (0, 0),
+                destinations: vec![((0, 1), 1.0)],
+            }],
+        });
+        // No car enters in place of the one leaving
+        stateless.demand = Some(DemandProfile {
+            points: vec![(0.0, 0.0)],
+        });
+        stateful.replace_cars(vec![
+            car_at(on_lane(0, 9_900.0), 10.0),
+            Some(Car {
+                destination: Some((0, 1)),
+                ..car_at(on_lane(0, 9_990.0), 10.0).unwrap()
+            }),
+        ]);
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        for _ in 0..20 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        assert!(stateful.cars[0].is_some());
+        assert!(stateful.cars[1].is_none());
+    }
+
+    #[test]
+    fn cars_leave_at_the_end_of_exit_lanes() {
+        let (mut stateless, mut stateful) = two_car_model((9_990.0, 10.0), (9_970.0, 10.0));
+        let road = stateless.city.board.horizontal_roads[(0, 0)]
+            .as_mut()
+            .unwrap();
+        road.lane_to_high[0].direction_rule = TurnRule::empty();
+        road.lane_to_high.push(road.lane_to_high[0].clone());
+        // A car further back on a second exit lane
+        stateless.cars.push(stateless_car(15.0));
+        let mut other_lane = car_at(on_lane(0, 9_970.0), 10.0).unwrap();
+        if let stateful::car::Location::OnLane { lane_index, .. } = &mut other_lane.location {
+            *lane_index = 1;
+        }
+        let mut cars = stateful.cars.clone();
+        cars.push(Some(other_lane));
+        stateful.replace_cars(cars);
+        let settings = UpdateSettings {
+            exit_headway: 10.0,
+            ..update_settings()
+        };
+        let mut controller = UpdateController::new(settings, SimParams::default());
+        // Positions of the cars near the end of each lane, leaving the cars
+        // entering the city out
+        let near_end = |stateful: &stateful::Model, lane: LaneIndex| {
+            stateful
+                .cars
+                .iter()
+                .filter_map(|car| match car.as_ref()?.location {
+                    stateful::car::Location::OnLane {
+                        lane_index,
+                        position,
+                        ..
+                    } if lane_index == lane && position > 9_000.0 => Some(position),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        for _ in 0..50 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        // The leader left at once, its follower waits at the end of the lane
+        // for the exit, which took the car of the other lane at once
+        assert_eq!(near_end(&stateful, 0), vec![10_000.0]);
+        assert!(near_end(&stateful, 1).is_empty());
+        assert_eq!(controller.exit_queue(&stateful, &stateless), 1);
+        for _ in 0..70 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        assert!(near_end(&stateful, 0).is_empty());
+        assert_eq!(controller.exit_queue(&stateful, &stateless), 0);
+    }
+
+    #[test]
+    fn cars_with_no_way_on_wait_at_the_stop_line() {
+        // The lane goes on to the front, where there is no road
+        let (mut stateless, mut stateful) = two_car_model((9_990.0, 10.0), (0.0, 10.0));
+        stateful.replace_cars(vec![None, car_at(on_lane(0, 9_990.0), 10.0)]);
+        let settings = UpdateSettings {
+            stuck_timeout: 1.0,
+            ..update_settings()
+        };
+        let mut controller = UpdateController::new(settings, SimParams::default());
+        for _ in 0..15 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        assert!(!controller.stuck_since[&1].1);
+        for _ in 0..15 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        let car = stateful.cars[1].as_ref().unwrap();
+        match car.location {
+            stateful::car::Location::OnLane { position, .. } => assert_eq!(position, 10_000.0),
+            _ => panic!("car left the lane: {:?}", car.location),
+        }
+        assert_eq!(car.velocity, 0.0);
+        assert!(controller.stuck_since[&1].1);
+
+        // Turning back is a way on once the road has lanes back
+        let road = stateless.city.board.horizontal_roads[(0, 0)]
+            .as_mut()
+            .unwrap();
+        road.lane_to_high[0].direction_rule = TurnRule::FRONT | TurnRule::BACK;
+        road.lane_to_low = road.lane_to_high.clone();
+        let mut turned_back = false;
+        for _ in 0..100 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+            match stateful.cars[1].as_ref().map(|car| &car.location) {
+                Some(stateful::car::Location::InIntersection { to_direction, .. }) => {
+                    assert_eq!(*to_direction, AbsoluteDirection::West);
+                    turned_back = true;
+                }
+                Some(stateful::car::Location::OnLane { lane_direction, .. })
+                    if *lane_direction == LaneDirection::HighToLow =>
+                {
+                    turned_back = true;
+                }
+                _ => (),
+            }
+        }
+        assert!(turned_back);
+        assert!(!controller.stuck_since.contains_key(&1));
+    }
+
+    #[test]
+    fn left_turn_waits_for_oncoming_gap() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0); 2],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let controller = UpdateController::new(update_settings(), SimParams::default());
+        let green = Around {
+            north: TurnRule::ALL,
+            west: TurnRule::ALL,
+            south: TurnRule::ALL,
+            east: TurnRule::ALL,
+        };
+        let road_length = stateless.city.road_length(AxisDirection::Vertical, (0, 1));
+        // Heading north, turning left across the cars heading south
+        let turning = Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: AxisDirection::Vertical,
+                road_index: (1, 1),
+                lane_direction: LaneDirection::HighToLow,
+                lane_index: 0,
+                about_to_turn: RelativeDirection::Left,
+                position: road_length,
+            },
+            velocity: 0.0,
+            ..car_at(on_lane(0, 0.0), 0.0).unwrap()
+        };
+        let oncoming = |distance: f64| Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: AxisDirection::Vertical,
+                road_index: (0, 1),
+                lane_direction: LaneDirection::LowToHigh,
+                lane_index: 0,
+                about_to_turn: RelativeDirection::Front,
+                position: road_length - distance,
+            },
+            ..car_at(on_lane(0, 0.0), 10.0).unwrap()
+        };
+        let movement = Movement {
+            from_direction: AbsoluteDirection::South,
+            from_lane_index: 0,
+            to_direction: AbsoluteDirection::West,
+        };
+        let mut waits = |signal: Around<TurnRule>, distance| {
+            *stateful.city.board.intersections[(1, 1)]
+                .as_mut()
+                .unwrap()
+                .current_mut()
+                .unwrap() = signal;
+            stateful.replace_cars(vec![Some(turning.clone()), Some(oncoming(distance))]);
+            controller.waits_for_gap(
+                (1, 1),
+                &movement,
+                RelativeDirection::Left,
+                &stateful,
+                &stateless,
+            )
+        };
+        // The critical gap is 4.5 seconds, the oncoming car drives 10 m/s
+        assert!(waits(green, 30.0));
+        assert!(!waits(green, 60.0));
+        let oncoming_red = Around {
+            north: TurnRule::RIGHT,
+            ..green
+        };
+        assert!(!waits(oncoming_red, 30.0));
+        let protected = Around {
+            south: TurnRule::ALL | TurnRule::PROTECTED,
+            ..oncoming_red
+        };
+        assert!(!waits(protected, 30.0));
+    }
+
+    #[test]
+    fn cars_brake_for_red_signal() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0)],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let road_length = stateless.city.road_length(AxisDirection::Vertical, (1, 1));
+        // Heading north to the intersection, 80 m away
+        stateful.replace_cars(vec![Some(Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: AxisDirection::Vertical,
+                road_index: (1, 1),
+                lane_direction: LaneDirection::HighToLow,
+                lane_index: 0,
+                about_to_turn: RelativeDirection::Front,
+                position: road_length - 80.0,
+            },
+            ..car_at(on_lane(0, 0.0), 15.0).unwrap()
+        })]);
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        let red = Around {
+            north: TurnRule::ALL,
+            west: TurnRule::ALL,
+            south: TurnRule::RIGHT,
+            east: TurnRule::ALL,
+        };
+        controller.override_signal((1, 1), red);
+        let args = UpdateArgs { dt: 0.1 };
+        let mut velocity = 15.0;
+        for _ in 0..300 {
+            controller.step(&mut stateful, &stateless, args);
+            let car = stateful.cars[0].as_ref().unwrap();
+            match car.location {
+                stateful::car::Location::OnLane { position, .. } => {
+                    assert!(position <= road_length)
+                }
+                _ => panic!("car entered on red"),
+            }
+            // Braking starts well before the line and never jumps to a stop
+            assert!(car.velocity <= velocity);
+            assert!(velocity - car.velocity <= 8.0 * args.dt + 1e-9);
+            velocity = car.velocity;
+        }
+        assert!(velocity < 0.1);
+
+        controller.override_signal(
+            (1, 1),
+            Around {
+                south: TurnRule::ALL,
+                ..red
+            },
+        );
+        let entered = (0..300).any(|_| {
+            controller.step(&mut stateful, &stateless, args);
+            !matches!(
+                stateful.cars[0].as_ref().map(|car| &car.location),
+                Some(stateful::car::Location::OnLane { .. })
+            )
+        });
+        assert!(entered);
+
+        // A car too close to stop before the line still waits at it
+        stateful.replace_cars(vec![Some(Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: AxisDirection::Vertical,
+                road_index: (1, 1),
+                lane_direction: LaneDirection::HighToLow,
+                lane_index: 0,
+                about_to_turn: RelativeDirection::Front,
+                position: road_length - 1.0,
+            },
+            ..car_at(on_lane(0, 0.0), 15.0).unwrap()
+        })]);
+        controller.override_signal((1, 1), red);
+        for _ in 0..10 {
+            controller.step(&mut stateful, &stateless, args);
+        }
+        match stateful.cars[0].as_ref().unwrap().location {
+            stateful::car::Location::OnLane { position, .. } => assert_eq!(position, road_length),
+            _ => panic!("car entered on red"),
+        }
+    }
+
+    #[test]
+    fn queue_at_red_signal_does_not_crash() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0); 4],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let road_length = stateless.city.road_length(AxisDirection::Vertical, (1, 1));
+        // Heading north to the intersection, 20 m apart
+        let cars = (0..4)
+            .map(|k| {
+                Some(Car {
+                    location: stateful::car::Location::OnLane {
+                        road_direction: AxisDirection::Vertical,
+                        road_index: (1, 1),
+                        lane_direction: LaneDirection::HighToLow,
+                        lane_index: 0,
+                        about_to_turn: RelativeDirection::Front,
+                        position: road_length - 140.0 + 20.0 * k as f64,
+                    },
+                    id: k,
+                    ..car_at(on_lane(0, 0.0), 15.0).unwrap()
+                })
+            })
+            .collect();
+        stateful.replace_cars(cars);
+        let settings = UpdateSettings {
+            collision_policy: CollisionPolicy::Crash,
+            ..update_settings()
+        };
+        let mut controller = UpdateController::new(settings, SimParams::default());
+        controller.override_signal(
+            (1, 1),
+            Around {
+                north: TurnRule::ALL,
+                west: TurnRule::ALL,
+                south: TurnRule::RIGHT,
+                east: TurnRule::ALL,
+            },
+        );
+        for _ in 0..600 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        // The queue stands still behind the stop line, bumper to bumper
+        // with room to spare
+        assert_eq!(stateful.cars.iter().flatten().count(), 4);
+        for car in stateful.cars.iter().flatten() {
+            assert!(car.crashed.is_none(), "{:?}", car);
+            assert!(car.velocity < 0.1, "{:?}", car);
+        }
+    }
+
+    /// A car heading north to the intersection of a small city, 80 m away,
+    /// with a green signal for every approach.
+    fn failed_signal_model() -> (stateless::Model, stateful::Model) {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0)],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        let road_length = stateless.city.road_length(AxisDirection::Vertical, (1, 1));
+        stateful.replace_cars(vec![Some(Car {
+            location: stateful::car::Location::OnLane {
+                road_direction: AxisDirection::Vertical,
+                road_index: (1, 1),
+                lane_direction: LaneDirection::HighToLow,
+                lane_index: 0,
+                about_to_turn: RelativeDirection::Front,
+                position: road_length - 80.0,
+            },
+            ..car_at(on_lane(0, 0.0), 15.0).unwrap()
+        })]);
+        (stateless, stateful)
+    }
+
+    /// Step the model until the car enters the intersection, and return
+    /// whether it came to a stop before and whether it entered.
+    fn stop_and_enter(
+        mut step: impl FnMut(&mut stateful::Model),
+        stateful: &mut stateful::Model,
+    ) -> (bool, bool) {
+        let mut stopped = false;
+        let entered = (0..600).any(|_| {
+            step(stateful);
+            let car = stateful.cars[0].as_ref().unwrap();
+            match car.location {
+                stateful::car::Location::OnLane { .. } => {
+                    stopped |= car.velocity < FAILED_SIGNAL_STOP_SPEED;
+                    false
+                }
+                _ => true,
+            }
+        });
+        (stopped, entered)
+    }
+
+    #[test]
+    fn cars_stop_once_at_failed_signals() {
+        let (stateless, mut stateful) = failed_signal_model();
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        // Green for every approach, but the signals have failed
+        let green = Around {
+            north: TurnRule::ALL,
+            west: TurnRule::ALL,
+            south: TurnRule::ALL,
+            east: TurnRule::ALL,
+        };
+        controller.override_signal((1, 1), green);
+        controller.fail_signals(true);
+        let args = UpdateArgs { dt: 0.1 };
+        let (stopped, entered) = stop_and_enter(
+            |stateful| controller.step(stateful, &stateless, args),
+            &mut stateful,
+        );
+        assert!(stopped);
+        assert!(entered);
+    }
+
+    #[test]
+    fn workers_stop_at_failed_signals() {
+        let (stateless, mut stateful) = failed_signal_model();
+        let mut root = UpdateController::new(update_settings(), SimParams::default());
+        let green = Around {
+            north: TurnRule::ALL,
+            west: TurnRule::ALL,
+            south: TurnRule::ALL,
+            east: TurnRule::ALL,
+        };
+        root.override_signal((1, 1), green);
+        root.fail_signals(true);
+        // Only the root updates the city, a process other than the root
+        // updates the cars with a controller of its own, as `update` does
+        let mut worker = UpdateController::new(update_settings(), SimParams::default());
+        let args = UpdateArgs { dt: 0.1 };
+        let (stopped, entered) = stop_and_enter(
+            |stateful| {
+                root.update_intersections(
+                    &mut stateful.city,
+                    &stateful.cars,
+                    &stateless.city,
+                    args,
+                );
+                let local_state = ProcessLocalState::generate(
+                    &stateless.city,
+                    &stateful.cars[..],
+                    &stateless.cars[..],
+                );
+                let cars = worker.update_car_range(
+                    0..stateful.cars.len(),
+                    1,
+                    &local_state,
+                    stateful,
+                    &stateless,
+                    args,
+                    false,
+                );
+                stateful.replace_cars(cars);
+            },
+            &mut stateful,
+        );
+        assert!(stopped);
+        assert!(entered);
+    }
+
+    #[test]
+    fn lane_change_eases_sideways() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 2, 1)
+            .road_length(AxisDirection::Horizontal, 0, 200.0)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0)],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        stateful.replace_cars(vec![Some(Car {
+            location: stateful::car::Location::ChangingLane {
+                road_direction: AxisDirection::Horizontal,
+                road_index: (0, 0),
+                lane_direction: LaneDirection::LowToHigh,
+                from_lane_index: 0,
+                to_lane_index: 1,
+                position: 10.0,
+                lane_changed_proportion: 0.0,
+            },
+            ..car_at(on_lane(0, 0.0), 10.0).unwrap()
+        })]);
+        let params = SimParams {
+            lane_change_time: Some(2.0),
+            ..Default::default()
+        };
+        let mut controller = UpdateController::new(update_settings(), params);
+        let lane_y = |lane_index| {
+            stateless
+                .city
+                .lane_center(
+                    AxisDirection::Horizontal,
+                    (0, 0),
+                    LaneDirection::LowToHigh,
+                    lane_index,
+                )
+                .unwrap()
+                .y
+        };
+        let sideways = |stateful: &stateful::Model| {
+            let car = stateful.cars[0].as_ref().unwrap();
+            let y = car.location.city_position(&stateless.city).unwrap().y;
+            (y - lane_y(0)) / (lane_y(1) - lane_y(0))
+        };
+        let args = UpdateArgs { dt: 0.1 };
+        let mut moved = Vec::new();
+        for _ in 0..10 {
+            controller.step(&mut stateful, &stateless, args);
+            moved.push(sideways(&stateful));
+        }
+        // Slow at the start, half way after half the time
+        assert!(moved[0] < 0.05);
+        assert!((moved[9] - 0.5).abs() < 1e-9);
+        assert!(moved[5] - moved[4] > moved[1] - moved[0]);
+        for _ in 0..10 {
+            controller.step(&mut stateful, &stateless, args);
+        }
+        match stateful.cars[0].as_ref().unwrap().location {
+            stateful::car::Location::OnLane { lane_index, .. } => assert_eq!(lane_index, 1),
+            ref location => panic!("lane change not done: {:?}", location),
+        }
+        assert!((sideways(&stateful) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn overtake_slow_car_on_free_lane() {
+        use crate::model::stateless::CityBuilder;
+
+        // The road goes on past the next intersection, so both lanes allow
+        // the cars to drive ahead
+        let city = CityBuilder::new((1, 3))
+            .road(AxisDirection::Horizontal, (0, 0), 2, 0)
+            .road(AxisDirection::Horizontal, (0, 1), 2, 0)
+            .road_length(AxisDirection::Horizontal, 0, 500.0)
+            .build()
+            .unwrap();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(20.0), stateless_car(2.0), stateless_car(20.0)],
+            ..Default::default()
+        };
+        let on_lane = |lane_index, position, velocity| {
+            let mut car = car_at(on_lane(0, position), velocity).unwrap();
+            if let stateful::car::Location::OnLane {
+                lane_index: ref mut index,
+                ..
+            } = car.location
+            {
+                *index = lane_index;
+            }
+            Some(car)
+        };
+        let lane_of_first_car = |settings: UpdateSettings, cars: Vec<Option<Car>>| {
+            let mut stateful = generate_from_stateless(&stateless);
+            stateful.replace_cars(cars);
+            let mut controller = UpdateController::new(settings, SimParams::default());
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+            match stateful.cars[0].as_ref().unwrap().location {
+                stateful::car::Location::OnLane { lane_index, .. } => (lane_index, lane_index),
+                stateful::car::Location::ChangingLane {
+                    from_lane_index,
+                    to_lane_index,
+                    ..
+                } => (from_lane_index, to_lane_index),
+                ref location => panic!("car left the road: {:?}", location),
+            }
+        };
+        let changing = UpdateSettings {
+            lane_changes: true,
+            ..update_settings()
+        };
+        let slow_leader = vec![on_lane(0, 10.0, 15.0), on_lane(0, 40.0, 2.0), None];
+        assert_eq!(
+            lane_of_first_car(changing.clone(), slow_leader.clone()),
+            (0, 1)
+        );
+        assert_eq!(lane_of_first_car(update_settings(), slow_leader), (0, 0));
+        // A fast car close behind on the free lane would have to brake too
+        // hard
+        let blocked = vec![
+            on_lane(0, 10.0, 15.0),
+            on_lane(0, 40.0, 2.0),
+            on_lane(1, 5.0, 20.0),
+        ];
+        assert_eq!(lane_of_first_car(changing, blocked), (0, 0));
+    }
+
+    #[test]
+    fn cars_change_to_a_lane_allowing_their_turn() {
+        use crate::model::stateless::CityBuilder;
+
+        let mut city = CityBuilder::new((2, 3))
+            .road(AxisDirection::Horizontal, (0, 0), 2, 0)
+            .road(AxisDirection::Horizontal, (0, 1), 2, 0)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road_length(AxisDirection::Horizontal, 0, 500.0)
+            .build()
+            .unwrap();
+        let segment = Segment {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let next = Segment {
+            road_direction: AxisDirection::Vertical,
+            road_index: (0, 1),
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let turn = segment.heading().should_turn(next.heading());
+        // Only the second lane allows the turn
+        let lanes = &mut city.board.horizontal_roads[(0, 0)]
+            .as_mut()
+            .unwrap()
+            .lane_to_high;
+        lanes[0].direction_rule = TurnRule::FRONT;
+        lanes[1].direction_rule = TurnRule::FRONT | turn.to_turn_rule();
+        let stateless = stateless::Model {
+            city,
+            cars: vec![stateless_car(15.0)],
+            ..Default::default()
+        };
+        let mut stateful = generate_from_stateless(&stateless);
+        stateful.replace_cars(vec![Some(Car {
+            destination: Some((1, 1)),
+            route: vec![next],
+            ..car_at(on_lane(0, 10.0), 10.0).unwrap()
+        })]);
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        for _ in 0..200 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        }
+        match stateful.cars[0].as_ref().unwrap().location {
+            stateful::car::Location::OnLane {
+                lane_index,
+                about_to_turn,
+                position,
+                ..
+            } => {
+                assert!(position < 500.0);
+                assert_eq!((lane_index, about_to_turn), (1, turn));
+            }
+            ref location => panic!("car not on a lane: {:?}", location),
+        }
+    }
+
+    #[test]
+    fn start_without_cars() {
+        let (stateless, mut stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        stateful.replace_cars(vec![None, None]);
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        let mut stats = Stats::new(&stateless.city, 60.0);
+        let sample = Sample::of_model(0.0, &stateful);
+        assert_eq!((sample.car_number, sample.average_speed), (0, 0.0));
+        assert_eq!((stats.density(), stats.flow()), (0.0, 0.0));
+        for step in 1..=10 {
+            let before = Stats::road_positions(&stateful);
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+            stats.update(step as f64 * 0.1, &before, &stateful);
+        }
+        // Cars enter the city from the boundary
+        assert!(stateful.cars.iter().any(Option::is_some));
+        assert!(stats.density().is_finite() && stats.flow().is_finite());
+
+        // A model without car slots stays empty
+        let stateless = stateless::Model {
+            cars: Vec::new(),
+            ..stateless
+        };
+        stateful.replace_cars(Vec::new());
+        controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.1 });
+        assert!(stateful.cars.is_empty());
+        let sample = Sample::of_model(1.0, &stateful);
+        assert_eq!((sample.car_number, sample.average_speed), (0, 0.0));
+        let stats = Stats::new(&stateless::City::default(), 60.0);
+        assert_eq!((stats.density(), stats.flow()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn cars_enter_by_demand() {
+        use crate::model::demand::DemandProfile;
+        let (mut stateless, mut stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        stateful.replace_cars(vec![None, None]);
+        stateless.demand = Some(DemandProfile {
+            points: vec![(0.0, 0.5)],
+        });
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        let car_number = |stateful: &stateful::Model| stateful.cars.iter().flatten().count();
+        for _ in 0..3 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.5 });
+        }
+        assert_eq!(car_number(&stateful), 0);
+        assert_eq!(controller.demand_due(), 0.75);
+        // The first car is due after 2 seconds
+        controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.5 });
+        assert_eq!(car_number(&stateful), 1);
+        assert_eq!(controller.demand_due(), 0.0);
+    }
+
+    #[test]
+    fn full_entry_lane_queues_demand() {
+        use crate::model::demand::DemandProfile;
+        let (mut stateless, mut stateful) = two_car_model((5.0, 0.0), (0.0, 0.0));
+        // A lane of room for a single car, taken by a crashed car
+        stateless.city.horizontal_road_length = vec![10.0];
+        let blocked = || {
+            vec![
+                None,
+                Some(Car {
+                    crashed: Some(1e6),
+                    ..car_at(on_lane(0, 5.0), 0.0).unwrap()
+                }),
+            ]
+        };
+        stateful.replace_cars(blocked());
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        // Without a demand profile no car is due, none waits
+        for _ in 0..10 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.5 });
+        }
+        assert!(stateful.cars[0].is_none());
+        assert_eq!(controller.car_out_queue(), 0);
+        assert_eq!(controller.refused_car_outs(), 0);
+
+        stateless.demand = Some(DemandProfile {
+            points: vec![(0.0, 0.5)],
+        });
+        let mut controller = UpdateController::new(update_settings(), SimParams::default());
+        for _ in 0..10 {
+            controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.5 });
+        }
+        // 2.5 cars are due from 2 seconds on, and refused every update since
+        assert!(stateful.cars[0].is_none());
+        assert_eq!(controller.car_out_queue(), 2);
+        assert_eq!(controller.refused_car_outs(), 7);
+        // Once the lane is clear, the queue is served first
+        stateful.replace_cars(vec![None, None]);
+        controller.step(&mut stateful, &stateless, UpdateArgs { dt: 0.5 });
+        assert!(stateful.cars.iter().any(Option::is_some));
+        assert_eq!(controller.car_out_queue(), 1);
+        assert_eq!(controller.refused_car_outs(), 7);
+    }
+
+    #[test]
+    fn follower_keeps_distance() {
+        let (stateless, stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        let trajectory = trajectory(update_settings(), &stateless, stateful, 2400, 0.05);
+        for cars in trajectory.iter() {
+            let (follower, leader) = (cars[0], cars[1]);
+            assert!(leader.0 - follower.0 > 5.0, "overlap: {:?}", cars);
+        }
+        // Converge to the cushion of the leader velocity, kept between the
+        // bumpers of the 4.5 m cars
+        let cars = trajectory.last().unwrap();
+        let (follower, leader) = (cars[0], cars[1]);
+        assert!((leader.1 - 15.0).abs() < 1e-6);
+        assert!((follower.1 - leader.1).abs() < 0.1, "{:?}", cars);
+        let cushion = 10.0 + 2.0 * leader.1 + 4.5;
+        assert!((leader.0 - follower.0 - cushion).abs() < 1.0, "{:?}", cars);
+    }
+
+    #[test]
+    fn noise_does_not_depend_on_processes() {
+        let (stateless, mut stateful) = two_car_model((50.0, 10.0), (0.0, 10.0));
+        for (id, car) in stateful.cars.iter_mut().flatten().enumerate() {
+            car.id = id as u64;
+        }
+        let settings = UpdateSettings {
+            seed: Some(5),
+            ..update_settings()
+        };
+        let params = SimParams {
+            acceleration_noise: 1.0,
+            ..Default::default()
+        };
+        let local_state =
+            ProcessLocalState::generate(&stateless.city, &stateful.cars[..], &stateless.cars[..]);
+        // Each range is updated by a process of its own
+        let accelerations = |ranges: &[Range<CarIndex>]| {
+            ranges
+                .iter()
+                .enumerate()
+                .flat_map(|(rank, range)| {
+                    let mut controller = UpdateController::new(settings.clone(), params.clone());
+                    controller.update_car_range(
+                        range.clone(),
+                        rank as Rank,
+                        &local_state,
+                        &stateful,
+                        &stateless,
+                        UpdateArgs { dt: 0.1 },
+                        false,
+                    )
+                })
+                .map(|car| car.unwrap().acceleration)
+                .collect::<Vec<_>>()
+        };
+        let single = accelerations(std::slice::from_ref(&(0..2)));
+        assert_ne!(single[0], single[1]);
+        assert_eq!(single, accelerations(&[0..1, 1..2]));
+    }
+
+    #[test]
+    fn follower_stops_behind_stopped_leader() {
+        let (mut stateless, stateful) = two_car_model((100.0, 0.0), (0.0, 15.0));
+        stateless.cars[1].max_velocity = 0.0;
+        let trajectory = trajectory(update_settings(), &stateless, stateful, 1200, 0.05);
+        for cars in trajectory.iter() {
+            let (follower, leader) = (cars[0], cars[1]);
+            assert!(leader.0 - follower.0 > 5.0, "overlap: {:?}", cars);
+        }
+        let cars = trajectory.last().unwrap();
+        assert!(cars[0].1 < 0.1, "{:?}", cars);
+    }
+
+    #[test]
+    fn max_physics_dt_is_positive() {
+        let parse = |dt: &str| {
+            UpdateSettings::from_iter_safe(&["update", "--max-physics-dt", dt])
+                .map(|settings| settings.max_physics_dt)
+        };
+        assert_eq!(parse("0.05").unwrap(), Some(0.05));
+        for dt in &["0", "-1", "NaN", "inf"] {
+            assert!(parse(dt).is_err(), "{}", dt);
+        }
+    }
+
+    #[test]
+    fn reroute_fraction_is_a_fraction() {
+        let parse = |fraction: &str| {
+            UpdateSettings::from_iter_safe(&["update", "--reroute-fraction", fraction])
+                .map(|settings| settings.reroute_fraction)
+        };
+        assert_eq!(parse("0.25").unwrap(), 0.25);
+        assert_eq!(parse("1").unwrap(), 1.0);
+        for fraction in &["1.5", "-0.5", "NaN"] {
+            assert!(parse(fraction).is_err(), "{}", fraction);
+        }
+    }
+}
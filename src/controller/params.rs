@@ -0,0 +1,287 @@
+//! Simulation parameters shared by all drivers, loaded from a RON file.
+
+use crate::{model::stateless, Error};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, path::Path, str::FromStr};
+
+/// Unit of speeds shown to users, speeds in the model are in meters per
+/// second.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum SpeedUnit {
+    MetersPerSecond,
+    #[default]
+    KilometersPerHour,
+    MilesPerHour,
+}
+
+impl SpeedUnit {
+    /// Meters per second of one unit.
+    fn meters_per_second(self) -> f64 {
+        match self {
+            SpeedUnit::MetersPerSecond => 1.0,
+            SpeedUnit::KilometersPerHour => 1000.0 / 3600.0,
+            SpeedUnit::MilesPerHour => 1609.344 / 3600.0,
+        }
+    }
+
+    /// Speed in this unit of `speed` meters per second.
+    pub fn from_si(self, speed: f64) -> f64 {
+        speed / self.meters_per_second()
+    }
+
+    /// Meters per second of `speed` in this unit.
+    pub fn to_si(self, speed: f64) -> f64 {
+        speed * self.meters_per_second()
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            SpeedUnit::MetersPerSecond => "m/s",
+            SpeedUnit::KilometersPerHour => "km/h",
+            SpeedUnit::MilesPerHour => "mph",
+        }
+    }
+}
+
+impl FromStr for SpeedUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "m/s" => Ok(SpeedUnit::MetersPerSecond),
+            "km/h" => Ok(SpeedUnit::KilometersPerHour),
+            "mph" => Ok(SpeedUnit::MilesPerHour),
+            _ => Err(format!("unknown speed unit: {}", s)),
+        }
+    }
+}
+
+/// Distribution of the noise added to the accelerations of the drivers.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum NoiseDistribution {
+    /// Uniform between minus and plus the magnitude.
+    Uniform,
+    /// Normal with the magnitude as standard deviation.
+    #[default]
+    Normal,
+}
+
+impl NoiseDistribution {
+    /// A sample of the noise of `magnitude`, 0 without drawing from `rng`
+    /// if the magnitude is not positive.
+    pub fn sample<R: Rng + ?Sized>(self, magnitude: f64, rng: &mut R) -> f64 {
+        if !(magnitude.is_finite() && magnitude > 0.0) {
+            return 0.0;
+        }
+        match self {
+            NoiseDistribution::Uniform => rng.gen_range(-magnitude..=magnitude),
+            NoiseDistribution::Normal => {
+                // Box-Muller transform, 1 - u keeps the logarithm finite
+                let u: f64 = rng.gen();
+                let v: f64 = rng.gen();
+                magnitude * (-2.0 * (1.0 - u).ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+            }
+        }
+    }
+}
+
+/// Parameters of the car following models, speed limits and units.
+///
+/// Lengths are in meters, times in seconds and speeds in meters per second.
+/// Fields missing in a file take their default values.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SimParams {
+    /// Meters between bumpers kept by stopped cars, 2 by default.
+    pub min_gap: f64,
+    /// Seconds of the gap kept to the car in front, 1.5 by default.
+    pub time_headway: f64,
+    /// Speed drivers aim for on a free road instead of the speed limit of
+    /// the lane. Each driver scales it by the desired speed factor of the
+    /// car, bounded by the max velocity of the car. None by default.
+    pub desired_speed: Option<f64>,
+    /// Meters of road taken by a car, 4.5 by default.
+    pub car_length: f64,
+    /// Speed limit of lanes without a limit of their own, 13.9 (50 km/h) by
+    /// default.
+    pub speed_limit: f64,
+    /// Unit of speeds shown to users, km/h by default.
+    pub speed_unit: SpeedUnit,
+    /// Seconds before the next oncoming car arrives that a driver needs to
+    /// turn across the oncoming traffic on a green signal, 4.5 by default.
+    pub critical_gap: f64,
+    /// Seconds every lane change takes instead of the lane change time of
+    /// each car. None by default.
+    pub lane_change_time: Option<f64>,
+    /// Weight of the gains of the other drivers against the own gain in a
+    /// discretionary lane change, 0.2 by default.
+    pub politeness: f64,
+    /// Gain in acceleration, in meters per second squared, a discretionary
+    /// lane change has to bring, 0.1 by default.
+    pub lane_change_threshold: f64,
+    /// Deceleration the new follower of a discretionary lane change may be
+    /// forced to, in meters per second squared, 4 by default.
+    pub safe_deceleration: f64,
+    /// Magnitude of the noise added to the acceleration of every driver in
+    /// every update, in meters per second squared, 0 by default.
+    pub acceleration_noise: f64,
+    /// Distribution of the acceleration noise, normal by default.
+    pub noise_distribution: NoiseDistribution,
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            min_gap: 2.0,
+            time_headway: 1.5,
+            desired_speed: None,
+            car_length: 4.5,
+            speed_limit: SpeedUnit::KilometersPerHour.to_si(50.0),
+            speed_unit: SpeedUnit::default(),
+            critical_gap: 4.5,
+            lane_change_time: None,
+            politeness: 0.2,
+            lane_change_threshold: 0.1,
+            safe_deceleration: 4.0,
+            acceleration_noise: 0.0,
+            noise_distribution: NoiseDistribution::default(),
+        }
+    }
+}
+
+impl SimParams {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    /// Number of stopped cars a lane of `length` meters holds.
+    pub fn lane_capacity(&self, length: f64) -> usize {
+        (length / (self.car_length + self.min_gap)).floor() as usize
+    }
+
+    /// Speed limit of a lane, its own override first, then the limit of the
+    /// road, then the default speed limit.
+    pub fn lane_speed_limit(&self, lane: &stateless::Lane) -> f64 {
+        let valid = |speed: f64| speed.is_finite() && speed > 0.0;
+        if let Some(speed_limit) = lane.speed_limit.filter(|&speed| valid(speed)) {
+            speed_limit
+        } else if valid(lane.max_speed) {
+            lane.max_speed
+        } else {
+            self.speed_limit
+        }
+    }
+
+    /// Parameters of a car as its driver uses them on a lane with
+    /// `speed_limit`.
+    ///
+    /// The max velocity becomes the speed the driver aims for, and every
+    /// lane change takes the lane change time of the parameters if set.
+    pub fn driver(&self, car: &stateless::Car, speed_limit: f64) -> stateless::Car {
+        let mut car = car.clone();
+        let desired_speed = self.desired_speed.unwrap_or(speed_limit) * car.desired_speed_factor;
+        car.max_velocity = car.max_velocity.min(desired_speed);
+        if let Some(lane_change_time) = self.lane_change_time {
+            car.lane_change_time = lane_change_time;
+        }
+        car
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lane_capacity() {
+        let params = SimParams::default();
+        assert_eq!(params.lane_capacity(65.0), 10);
+        assert_eq!(params.lane_capacity(64.9), 9);
+        assert_eq!(params.lane_capacity(0.0), 0);
+    }
+
+    #[test]
+    fn speed_limits_and_units() {
+        let params = SimParams::default();
+        let lane = |max_speed| stateless::Lane {
+            max_speed,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: Default::default(),
+        };
+        assert_eq!(params.lane_speed_limit(&lane(20.0)), 20.0);
+        assert_eq!(params.lane_speed_limit(&lane(0.0)), params.speed_limit);
+        let pocket = stateless::Lane {
+            speed_limit: Some(8.0),
+            ..lane(20.0)
+        };
+        assert_eq!(params.lane_speed_limit(&pocket), 8.0);
+        let pocket = stateless::Lane {
+            speed_limit: Some(0.0),
+            ..lane(20.0)
+        };
+        assert_eq!(params.lane_speed_limit(&pocket), 20.0);
+        let unit = params.speed_unit;
+        assert!((unit.from_si(params.speed_limit) - 50.0).abs() < 1e-9);
+        assert!((SpeedUnit::MilesPerHour.from_si(26.8224) - 60.0).abs() < 1e-9);
+        assert_eq!(SpeedUnit::MetersPerSecond.to_si(3.0), 3.0);
+        assert_eq!("mph".parse(), Ok(SpeedUnit::MilesPerHour));
+        assert!("knots".parse::<SpeedUnit>().is_err());
+    }
+
+    #[test]
+    fn drivers_aim_for_scaled_speed_limits() {
+        let car = stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity: 30.0,
+            desired_speed_factor: 1.1,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: stateless::car::DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        };
+        let mut params = SimParams::default();
+        assert!((params.driver(&car, 20.0).max_velocity - 22.0).abs() < 1e-9);
+        // Bounded by the car
+        assert_eq!(params.driver(&car, 40.0).max_velocity, 30.0);
+        params.desired_speed = Some(10.0);
+        assert!((params.driver(&car, 20.0).max_velocity - 11.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn noise_of_the_magnitude() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(NoiseDistribution::Normal.sample(0.0, &mut rng), 0.0);
+        let uniform = (0..1000)
+            .map(|_| NoiseDistribution::Uniform.sample(0.5, &mut rng))
+            .collect::<Vec<_>>();
+        assert!(uniform.iter().all(|noise| noise.abs() <= 0.5));
+        let normal = (0..10_000)
+            .map(|_| NoiseDistribution::Normal.sample(0.5, &mut rng))
+            .collect::<Vec<_>>();
+        let mean = normal.iter().sum::<f64>() / normal.len() as f64;
+        let variance = normal
+            .iter()
+            .map(|noise| (noise - mean).powi(2))
+            .sum::<f64>()
+            / normal.len() as f64;
+        assert!(mean.abs() < 0.05, "{}", mean);
+        assert!((variance.sqrt() - 0.5).abs() < 0.05, "{}", variance);
+        // The same seed draws the same noise
+        let draw = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            NoiseDistribution::Normal.sample(1.0, &mut rng)
+        };
+        assert_eq!(draw(7), draw(7));
+    }
+}
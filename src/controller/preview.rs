@@ -0,0 +1,51 @@
+//! Previewing the next update without committing it.
+
+use super::Controller;
+use crate::model::{
+    common::CarIndex,
+    stateful::{self, car::Location},
+    stateless,
+};
+use piston_window::UpdateArgs;
+
+impl Controller {
+    /// Locations of the cars after one more update of `dt` seconds, leaving
+    /// the model as it is.
+    ///
+    /// The update runs on copies of the model and the update controller, as
+    /// `UpdateController::step` does in a single process. Only cars in the
+    /// model before and after the update have a location to preview. The
+    /// preview is kept for the next calls with the same `dt`, until
+    /// `clear_preview` after an edit or an update of the model.
+    pub fn preview_step(
+        &mut self,
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        dt: f64,
+    ) -> &[(CarIndex, Location)] {
+        let cached = matches!(&self.preview, Some((preview_dt, _)) if *preview_dt == dt);
+        if !cached {
+            let mut update_controller = self.update_controller.clone();
+            let mut next = stateful.clone();
+            update_controller.step(&mut next, stateless, UpdateArgs { dt });
+            let ghosts = (0..stateful.cars.len())
+                .filter_map(|car_index| {
+                    let car = next.car(stateful.handle(car_index)?)?;
+                    Some((car_index, car.location.clone()))
+                })
+                .collect();
+            self.preview = Some((dt, ghosts));
+        }
+        &self.preview.as_ref().unwrap().1
+    }
+
+    /// The last preview, unless the model changed since.
+    pub fn preview(&self) -> Option<&[(CarIndex, Location)]> {
+        self.preview.as_ref().map(|(_, ghosts)| &ghosts[..])
+    }
+
+    /// Forget the preview, after the model or the signals were changed.
+    pub fn clear_preview(&mut self) {
+        self.preview = None;
+    }
+}
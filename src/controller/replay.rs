@@ -0,0 +1,172 @@
+//! Playing a replay in the window instead of simulating, seeking with the
+//! arrow keys.
+
+use super::Controller;
+use crate::{
+    model::{file::ReplayReader, stateful, stateless},
+    Error,
+};
+use piston_window::{Button, ButtonArgs, ButtonState, Input, Key};
+use std::{fs::File, io::BufReader, path::Path};
+
+#[derive(Debug)]
+pub struct Replay {
+    reader: ReplayReader<BufReader<File>>,
+    /// Index of the frame shown, `None` before the first one.
+    frame: Option<usize>,
+}
+
+impl Replay {
+    /// Open a replay recorded on `model`.
+    pub fn open<P: AsRef<Path>>(path: P, model: &stateless::Model) -> Result<Self, Error> {
+        Ok(Self {
+            reader: ReplayReader::open(path, model)?,
+            frame: None,
+        })
+    }
+
+    /// Index of the frame shown.
+    pub fn frame(&self) -> Option<usize> {
+        self.frame
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty()
+    }
+}
+
+/// The frame `delta` frames from `frame` among `len` frames, stopping at the
+/// first and the last one.
+pub fn seek(frame: usize, delta: isize, len: usize) -> usize {
+    if delta < 0 {
+        frame.saturating_sub(delta.unsigned_abs())
+    } else {
+        frame
+            .saturating_add(delta as usize)
+            .min(len.saturating_sub(1))
+    }
+}
+
+impl Controller {
+    /// Show the next frame of the replay, the last frame stays at the end.
+    pub fn play_replay(&mut self, stateful: &mut stateful::Model) {
+        let (frame, len) = match self.replay.as_ref() {
+            Some(replay) if !replay.is_empty() => (replay.frame, replay.len()),
+            _ => return,
+        };
+        let next = match frame {
+            Some(frame) => seek(frame, 1, len),
+            None => 0,
+        };
+        if frame != Some(next) {
+            self.show_frame(stateful, next);
+        }
+    }
+
+    /// Seek the replay by `--replay-seek-frames` frames backward with the
+    /// left arrow key and forward with the right one, and return whether
+    /// `input` was used.
+    pub fn replay_input(&mut self, stateful: &mut stateful::Model, input: &Input) -> bool {
+        let (frame, len) = match self.replay.as_ref() {
+            Some(replay) if !replay.is_empty() => (replay.frame.unwrap_or(0), replay.len()),
+            _ => return false,
+        };
+        let step = self.settings.replay_seek_frames as isize;
+        let delta = match input {
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::Left),
+                ..
+            }) => -step,
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::Right),
+                ..
+            }) => step,
+            _ => return false,
+        };
+        self.show_frame(stateful, seek(frame, delta, len));
+        true
+    }
+
+    fn show_frame(&mut self, stateful: &mut stateful::Model, index: usize) {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => return,
+        };
+        match replay.reader.frame(index) {
+            Ok((time, model)) => {
+                *stateful = model;
+                self.time = time;
+                replay.frame = Some(index);
+            }
+            Err(e) => log::warn!("failed to read frame {} of the replay: {}", index, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::file::frame_offsets;
+    use std::io::Cursor;
+
+    #[test]
+    fn seek_frames_by_offsets() {
+        // Frames of 3 and 1 bytes, then a frame cut short
+        let mut bytes = Vec::new();
+        for frame in [&[1, 2, 3][..], &[4][..]].iter() {
+            bytes.extend_from_slice(&(frame.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(frame);
+        }
+        bytes.extend_from_slice(&5u64.to_le_bytes());
+        bytes.push(6);
+        assert_eq!(
+            frame_offsets(&mut Cursor::new(bytes), 0).unwrap(),
+            vec![0, 11]
+        );
+
+        assert_eq!(seek(10, -60, 100), 0);
+        assert_eq!(seek(70, -60, 100), 10);
+        assert_eq!(seek(10, 60, 100), 70);
+        assert_eq!(seek(70, 60, 100), 99);
+        assert_eq!(seek(0, 1, 0), 0);
+    }
+
+    #[test]
+    fn refuse_replays_of_other_models() {
+        use crate::model::{common::AxisDirection, file::ReplayWriter, stateless::CityBuilder};
+
+        let recorded = stateless::Model::default();
+        let mut bytes = Vec::new();
+        let mut writer = ReplayWriter::new(&mut bytes, &recorded).unwrap();
+        writer
+            .write_frame(0.5, &stateful::Model::default())
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut reader = ReplayReader::new(Cursor::new(bytes.clone()), &recorded).unwrap();
+        assert_eq!(reader.len(), 1);
+        assert_eq!(reader.frame(0).unwrap().0, 0.5);
+
+        let other = stateless::Model {
+            city: CityBuilder::new((1, 2))
+                .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+                .build()
+                .unwrap(),
+            ..Default::default()
+        };
+        assert!(ReplayReader::new(Cursor::new(bytes.clone()), &other).is_err());
+        let mut newer = bytes.clone();
+        newer[8] += 1;
+        assert!(ReplayReader::new(Cursor::new(newer), &recorded).is_err());
+        let mut foreign = bytes;
+        foreign[0] = 0;
+        assert!(ReplayReader::new(Cursor::new(foreign), &recorded).is_err());
+    }
+}
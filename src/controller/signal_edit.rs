@@ -0,0 +1,141 @@
+//! Setting the signal of the selected intersection by hand, and failing all
+//! signals at once.
+
+use super::{Controller, UpdateController};
+use crate::{
+    info::Info,
+    model::{
+        board::IntersectionIndex,
+        common::{AbsoluteDirection, Around, TurnRule},
+        stateful,
+    },
+};
+use piston_window::Key;
+use std::time::Instant;
+
+impl Controller {
+    /// Edit the signal of the selected intersection with `key`.
+    ///
+    /// Keys 1 to 4 pick the north, west, south and east approach, F, L, R
+    /// and B toggle the front, left, right and back turns of the picked
+    /// approach, P toggles whether its turn across the oncoming traffic is
+    /// protected, S returns the intersection to its schedule and Escape
+    /// clears the selection.
+    pub fn edit_signal(&mut self, info: &mut Info, stateful: &mut stateful::Model, key: Key) {
+        let intersection_index = match info.selected_intersection {
+            Some(intersection_index) => intersection_index,
+            None => return,
+        };
+        let turn = match key {
+            Key::D1 | Key::D2 | Key::D3 | Key::D4 => {
+                info.selected_approach = Some(match key {
+                    Key::D1 => AbsoluteDirection::North,
+                    Key::D2 => AbsoluteDirection::West,
+                    Key::D3 => AbsoluteDirection::South,
+                    _ => AbsoluteDirection::East,
+                });
+                return;
+            }
+            Key::F => TurnRule::FRONT,
+            Key::L => TurnRule::LEFT,
+            Key::R => TurnRule::RIGHT,
+            Key::B => TurnRule::BACK,
+            Key::P => TurnRule::PROTECTED,
+            Key::S => {
+                self.update_controller
+                    .clear_signal_override(intersection_index);
+                self.clear_preview();
+                return;
+            }
+            Key::Escape => {
+                info.selected_intersection = None;
+                info.selected_approach = None;
+                return;
+            }
+            _ => return,
+        };
+        let approach = match info.selected_approach {
+            Some(approach) => approach,
+            None => return,
+        };
+        let current = match stateful.city.board.intersections[intersection_index]
+            .as_mut()
+            .and_then(|intersection| intersection.current_mut())
+        {
+            Some(current) => current,
+            None => {
+                log::warn!("intersection {:?} has no signal", intersection_index);
+                return;
+            }
+        };
+        let mut rule = *current;
+        rule.get_mut(approach).toggle(turn);
+        if let Some(direction) = rule.protected_against_green() {
+            log::warn!(
+                "the turn from {:?} cannot be protected against a green straight ahead",
+                direction
+            );
+            return;
+        }
+        *current = rule;
+        log::info!(
+            "signal of intersection {:?} set to {:?}",
+            intersection_index,
+            current
+        );
+        self.update_controller
+            .override_signal(intersection_index, *current);
+        self.clear_preview();
+    }
+
+    /// Fail all signals, turning every signalized intersection into an
+    /// all-way stop, or restore them to their plans.
+    pub fn toggle_signal_failure(&mut self, info: &mut Info) {
+        let failed = !self.update_controller.signals_failed();
+        self.update_controller.fail_signals(failed);
+        self.clear_preview();
+        info.signals_failed = if failed { Some(Instant::now()) } else { None };
+        if failed {
+            log::info!("signals failed at time {:.3}", self.time);
+        } else {
+            log::info!("signals restored at time {:.3}", self.time);
+        }
+    }
+}
+
+impl UpdateController {
+    /// Keep the signal of an intersection at `rule` instead of following its
+    /// schedule, which keeps running underneath.
+    pub fn override_signal(
+        &mut self,
+        intersection_index: IntersectionIndex,
+        rule: Around<TurnRule>,
+    ) {
+        self.signal_overrides.insert(intersection_index, rule);
+    }
+
+    /// Return an intersection to its schedule from the next update.
+    pub fn clear_signal_override(&mut self, intersection_index: IntersectionIndex) {
+        self.signal_overrides.remove(&intersection_index);
+    }
+
+    /// Fail all signals, or restore them.
+    pub fn fail_signals(&mut self, failed: bool) {
+        self.signals_failed = failed;
+        if !failed {
+            self.stopped_at_failed_signal.clear();
+        }
+    }
+
+    pub fn signals_failed(&self) -> bool {
+        self.signals_failed
+    }
+
+    /// The signal set by hand at an intersection, if any.
+    pub fn signal_override(
+        &self,
+        intersection_index: IntersectionIndex,
+    ) -> Option<Around<TurnRule>> {
+        self.signal_overrides.get(&intersection_index).copied()
+    }
+}
@@ -0,0 +1,660 @@
+//! Traffic engineering measurements per road and intersection.
+
+use crate::{
+    controller::params::SimParams,
+    model::{
+        board::{Board, IntersectionIndex, RoadIndex},
+        common::{AbsoluteDirection, Around, AxisDirection, LaneDirection, Position},
+        stateful::{self, car::Location, CarHandle},
+        stateless,
+    },
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+/// Measurements of both directions of a road.
+#[derive(Clone, Debug, Default)]
+pub struct RoadStats {
+    pub length: f64,
+    /// Cars currently on the road.
+    pub car_number: usize,
+    /// Cars passed the middle of the road since the start.
+    pub passed: usize,
+    /// Times cars passed the middle of the road within the flow window.
+    crossings: VecDeque<f64>,
+}
+
+impl RoadStats {
+    /// Cars per kilometer.
+    pub fn density(&self) -> f64 {
+        self.car_number as f64 / (self.length / 1000.0)
+    }
+
+    /// Cars per hour passing the middle of the road.
+    pub fn flow(&self, window: f64) -> f64 {
+        self.crossings.len() as f64 / window * 3600.0
+    }
+}
+
+/// Measurements of an intersection.
+#[derive(Clone, Debug, Default)]
+pub struct IntersectionStats {
+    /// Cars entered the intersection since the start.
+    pub entered: usize,
+    /// Seconds of control delay of the cars entered since the start.
+    pub total_delay: f64,
+}
+
+impl IntersectionStats {
+    /// Average seconds of control delay of the cars entered, 0 if no car
+    /// entered.
+    pub fn control_delay(&self) -> f64 {
+        if self.entered == 0 {
+            0.0
+        } else {
+            self.total_delay / self.entered as f64
+        }
+    }
+}
+
+/// Position of a car on a road.
+pub type RoadPosition = (AxisDirection, RoadIndex, LaneDirection, f64);
+
+/// Cars entering and leaving the city in an interval, by the side of the
+/// cordon around the city they crossed.
+///
+/// A car crosses the side nearest to where it appears or disappears.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CordonCount {
+    /// Time the interval started.
+    pub start: f64,
+    pub entering: Around<usize>,
+    pub leaving: Around<usize>,
+}
+
+impl CordonCount {
+    /// Cars entered minus cars left on `side`.
+    pub fn balance(&self, side: AbsoluteDirection) -> isize {
+        *self.entering.get(side) as isize - *self.leaving.get(side) as isize
+    }
+}
+
+/// Side of the city nearest to `position`, in fractions of the width and
+/// the height of the city, so the long sides of a narrow city are not
+/// always the nearest.
+fn cordon_side(city: &stateless::City, position: Position) -> AbsoluteDirection {
+    let geometry = city.geometry();
+    let dx = (position.x - geometry.width / 2.0) / geometry.width;
+    let dy = (position.y - geometry.height / 2.0) / geometry.height;
+    if dx.abs() >= dy.abs() {
+        if dx < 0.0 {
+            AbsoluteDirection::West
+        } else {
+            AbsoluteDirection::East
+        }
+    } else if dy < 0.0 {
+        AbsoluteDirection::North
+    } else {
+        AbsoluteDirection::South
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Stats {
+    /// Seconds of crossings counted in the flow.
+    pub flow_window: f64,
+    pub board: Board<IntersectionStats, Option<RoadStats>>,
+    /// Cars waiting to enter the city because the entry lanes are full.
+    pub car_out_queue: usize,
+    /// Cars waiting at the end of exit lanes for the exit to take them.
+    pub exit_queue: usize,
+    /// Times a car was refused to enter the city since the start.
+    pub refused_car_outs: usize,
+    /// Seconds of control delay of each car on its current approach, by
+    /// car index, with the handle of the car so a new car in the slot starts
+    /// without delay.
+    pub pending_delays: Vec<Option<(CarHandle, f64)>>,
+    /// Points of the network fundamental diagram, the network density in
+    /// cars per kilometer and the average flow in cars per hour at the end
+    /// of each measurement interval.
+    pub fundamental_diagram: Vec<(f64, f64)>,
+    /// Counts of the cordon around the city, one per interval.
+    pub cordon: Vec<CordonCount>,
+}
+
+impl Stats {
+    pub fn new(city: &stateless::City, flow_window: f64) -> Self {
+        let mut board = Board::with_shape(Default::default(), None, city.board.shape());
+        for (index, (direction, road)) in city.board.enumerate_roads() {
+            if road.is_some() {
+                *board.get_road_mut(direction, index).unwrap() = Some(RoadStats {
+                    length: city.road_length(direction, index),
+                    ..Default::default()
+                });
+            }
+        }
+        Self {
+            flow_window,
+            board,
+            car_out_queue: 0,
+            exit_queue: 0,
+            refused_car_outs: 0,
+            pending_delays: Vec::new(),
+            fundamental_diagram: Vec::new(),
+            cordon: Vec::new(),
+        }
+    }
+
+    /// Road positions of the cars, used to detect crossings in the next
+    /// update.
+    pub fn road_positions(stateful: &stateful::Model) -> Vec<Option<RoadPosition>> {
+        stateful
+            .cars
+            .iter()
+            .map(|car| match car.as_ref()?.location {
+                Location::OnLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    position,
+                    ..
+                }
+                | Location::ChangingLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    position,
+                    ..
+                } => Some((road_direction, road_index, lane_direction, position)),
+                Location::InIntersection { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Positions in the city of the cars, used to detect cars crossing the
+    /// cordon in the next update. Crashed cars have none, they are cleared
+    /// without leaving.
+    pub fn cordon_positions(
+        stateful: &stateful::Model,
+        city: &stateless::City,
+    ) -> Vec<Option<Position>> {
+        stateful
+            .cars
+            .iter()
+            .map(|car| {
+                let car = car.as_ref().filter(|car| car.crashed.is_none())?;
+                car.location.city_position(city)
+            })
+            .collect()
+    }
+
+    /// Count the cars appeared in the city since `before` as entering and
+    /// the cars gone as leaving, in the interval of `interval` seconds
+    /// `time` is in.
+    ///
+    /// Intervals start at multiples of `interval`, the ones without
+    /// crossings since the first are counted too.
+    pub fn update_cordon(
+        &mut self,
+        time: f64,
+        interval: f64,
+        before: &[Option<Position>],
+        stateful: &stateful::Model,
+        city: &stateless::City,
+    ) {
+        if !(interval.is_finite() && interval > 0.0) {
+            return;
+        }
+        let current = (time / interval).floor();
+        let next = self
+            .cordon
+            .last()
+            .map_or(current, |last| (last.start / interval).round() + 1.0);
+        for k in next as i64..=current as i64 {
+            self.cordon.push(CordonCount {
+                start: k as f64 * interval,
+                ..Default::default()
+            });
+        }
+        let count = self.cordon.last_mut().unwrap();
+        for (car_index, car) in stateful.cars.iter().enumerate() {
+            let before = before.get(car_index).copied().flatten();
+            match (before, car) {
+                (None, Some(car)) if car.crashed.is_none() => {
+                    if let Some(position) = car.location.city_position(city) {
+                        *count.entering.get_mut(cordon_side(city, position)) += 1;
+                    }
+                }
+                (Some(position), None) => {
+                    *count.leaving.get_mut(cordon_side(city, position)) += 1;
+                }
+                _ => {}
+            }
+        }
+        // Slots beyond the cars of the model are empty
+        for position in before.iter().skip(stateful.cars.len()).flatten() {
+            *count.leaving.get_mut(cordon_side(city, *position)) += 1;
+        }
+    }
+
+    /// Count cars on roads, cars passed road middles and cars entered
+    /// intersections since `before`.
+    pub fn update(
+        &mut self,
+        time: f64,
+        before: &[Option<RoadPosition>],
+        stateful: &stateful::Model,
+    ) {
+        let after = Self::road_positions(stateful);
+        for (_, road) in self.board.roads_mut() {
+            if let Some(road) = road {
+                road.car_number = 0;
+                while let Some(front) = road.crossings.front() {
+                    if *front < time - self.flow_window {
+                        road.crossings.pop_front();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+        for (car_index, position) in after.iter().enumerate() {
+            if let Some((direction, index, lane_direction, position)) = *position {
+                let road = self
+                    .board
+                    .get_road_mut(direction, index)
+                    .unwrap()
+                    .as_mut()
+                    .unwrap();
+                road.car_number += 1;
+                let middle = road.length / 2.0;
+                if let Some(Some((d, i, l, p))) = before.get(car_index) {
+                    if (*d, *i, *l) == (direction, index, lane_direction)
+                        && *p < middle
+                        && position >= middle
+                    {
+                        road.passed += 1;
+                        road.crossings.push_back(time);
+                    }
+                }
+            }
+        }
+        for (car, before) in stateful.cars.iter().zip(before.iter()) {
+            if let (Some(car), Some(_)) = (car, before) {
+                if let Location::InIntersection {
+                    intersection_index, ..
+                } = car.location
+                {
+                    self.board.intersections[intersection_index].entered += 1;
+                }
+            }
+        }
+    }
+
+    /// Add the control delay of the cars on approaches in an update of `dt`
+    /// seconds since `before`, and count the delay of the cars entered
+    /// intersections in the intersections.
+    ///
+    /// A car at velocity `v` below its free-flow speed `vf`, the speed its
+    /// driver aims for on the lane, is delayed by `dt * (1 - v / vf)`.
+    pub fn update_delays(
+        &mut self,
+        dt: f64,
+        before: &[Option<RoadPosition>],
+        stateful: &stateful::Model,
+        stateless: &stateless::Model,
+        params: &SimParams,
+    ) {
+        self.pending_delays.resize(stateful.cars.len(), None);
+        for (car_index, car) in stateful.cars.iter().enumerate() {
+            let (car, handle) = match (car, stateful.handle(car_index)) {
+                (Some(car), Some(handle)) => (car, handle),
+                _ => {
+                    self.pending_delays[car_index] = None;
+                    continue;
+                }
+            };
+            let mut delay = match self.pending_delays[car_index] {
+                Some((pending_handle, delay)) if pending_handle == handle => delay,
+                _ => 0.0,
+            };
+            match car.location {
+                Location::OnLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    lane_index,
+                    ..
+                }
+                | Location::ChangingLane {
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    from_lane_index: lane_index,
+                    ..
+                } => {
+                    let lane = &stateless
+                        .city
+                        .board
+                        .get_road(road_direction, road_index)
+                        .and_then(Option::as_ref)
+                        .expect("car on a missing road")
+                        .lanes_to_direction(lane_direction)[lane_index];
+                    let free_flow_speed = params
+                        .driver(&stateless.cars[car_index], params.lane_speed_limit(lane))
+                        .max_velocity;
+                    if free_flow_speed > 0.0 {
+                        delay += dt * (1.0 - car.velocity / free_flow_speed).max(0.0);
+                    }
+                }
+                Location::InIntersection {
+                    intersection_index, ..
+                } => {
+                    if let Some(Some(_)) = before.get(car_index) {
+                        self.board.intersections[intersection_index].total_delay += delay;
+                    }
+                    delay = 0.0;
+                }
+            }
+            self.pending_delays[car_index] = Some((handle, delay));
+        }
+    }
+
+    /// Restart the counters, keeping the cars currently on roads.
+    pub fn reset(&mut self) {
+        for (_, road) in self.board.roads_mut() {
+            if let Some(road) = road {
+                road.passed = 0;
+                road.crossings.clear();
+            }
+        }
+        for intersection in self.board.intersections.iter_mut() {
+            *intersection = Default::default();
+        }
+        self.refused_car_outs = 0;
+        self.fundamental_diagram.clear();
+        self.cordon.clear();
+    }
+
+    pub fn road(&self, direction: AxisDirection, index: RoadIndex) -> Option<&RoadStats> {
+        self.board.get_road(direction, index)?.as_ref()
+    }
+
+    pub fn intersection(&self, index: IntersectionIndex) -> Option<&IntersectionStats> {
+        self.board.intersections.get(index)
+    }
+
+    /// Cars per kilometer over the whole network.
+    pub fn density(&self) -> f64 {
+        let (car_number, length) = self
+            .board
+            .roads()
+            .filter_map(|(_, road)| road.as_ref())
+            .fold((0, 0.0), |(number, length), road| {
+                (number + road.car_number, length + road.length)
+            });
+        if length > 0.0 {
+            car_number as f64 / (length / 1000.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Average flow of all roads, cars per hour.
+    pub fn flow(&self) -> f64 {
+        let (road_number, flow) = self
+            .board
+            .roads()
+            .filter_map(|(_, road)| road.as_ref())
+            .fold((0, 0.0), |(number, flow), road| {
+                (number + 1, flow + road.flow(self.flow_window))
+            });
+        if road_number == 0 {
+            0.0
+        } else {
+            flow / road_number as f64
+        }
+    }
+
+    /// Add the current density and flow of the network to the fundamental
+    /// diagram.
+    pub fn record_fundamental_diagram(&mut self) {
+        let point = (self.density(), self.flow());
+        self.fundamental_diagram.push(point);
+    }
+
+    /// Write the counts of the cordon as CSV, a row per interval and side.
+    pub fn write_cordon<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "start,side,entering,leaving")?;
+        for count in self.cordon.iter() {
+            for side in AbsoluteDirection::directions() {
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    count.start,
+                    format!("{:?}", side).to_lowercase(),
+                    count.entering.get(*side),
+                    count.leaving.get(*side)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the points of the fundamental diagram as CSV.
+    pub fn write_fundamental_diagram<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "density,flow")?;
+        for (density, flow) in self.fundamental_diagram.iter() {
+            writeln!(writer, "{},{}", density, flow)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stateful::testing::{car_at, on_lane};
+
+    #[test]
+    fn density_and_flow() {
+        let mut stats = Stats {
+            flow_window: 60.0,
+            board: Board::with_shape(Default::default(), None, (1, 2)),
+            car_out_queue: 0,
+            exit_queue: 0,
+            refused_car_outs: 0,
+            pending_delays: Vec::new(),
+            fundamental_diagram: Vec::new(),
+            cordon: Vec::new(),
+        };
+        *stats
+            .board
+            .get_road_mut(AxisDirection::Horizontal, (0, 0))
+            .unwrap() = Some(RoadStats {
+            length: 500.0,
+            ..Default::default()
+        });
+        let mut model = stateful::Model::default();
+        model.replace_cars(vec![
+            car_at(on_lane(0, 240.0), 10.0),
+            car_at(on_lane(0, 100.0), 10.0),
+        ]);
+        let before = Stats::road_positions(&model);
+        model.replace_cars(vec![
+            car_at(on_lane(0, 260.0), 10.0),
+            car_at(on_lane(0, 110.0), 10.0),
+        ]);
+        stats.update(1.0, &before, &model);
+
+        let road = stats.road(AxisDirection::Horizontal, (0, 0)).unwrap();
+        assert_eq!(road.car_number, 2);
+        assert_eq!(road.passed, 1);
+        assert_eq!(road.density(), 4.0);
+        assert_eq!(road.flow(60.0), 60.0);
+        assert_eq!(stats.density(), 4.0);
+        stats.record_fundamental_diagram();
+        assert_eq!(stats.fundamental_diagram, vec![(4.0, 60.0)]);
+        let mut csv = Vec::new();
+        stats.write_fundamental_diagram(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "density,flow\n4,60\n");
+
+        // Crossings older than the window no longer count in the flow
+        let before = Stats::road_positions(&model);
+        stats.update(100.0, &before, &model);
+        let road = stats.road(AxisDirection::Horizontal, (0, 0)).unwrap();
+        assert_eq!(road.passed, 1);
+        assert_eq!(road.flow(60.0), 0.0);
+
+        // Cars leaving a road enter the intersection at its end
+        let before = Stats::road_positions(&model);
+        let mut cars = model.cars.clone();
+        cars[0].as_mut().unwrap().location = Location::InIntersection {
+            intersection_index: (0, 1),
+            from_direction: AbsoluteDirection::West,
+            from_lane_index: 0,
+            to_direction: AbsoluteDirection::East,
+            to_lane_index: 0,
+            total_length: 20.0,
+            position: 0.0,
+        };
+        model.replace_cars(cars);
+        stats.update(101.0, &before, &model);
+        let before = Stats::road_positions(&model);
+        stats.update(102.0, &before, &model);
+        assert_eq!(stats.intersection((0, 1)).unwrap().entered, 1);
+        assert_eq!(stats.intersection((0, 0)).unwrap().entered, 0);
+
+        // Counters restart after a warm-up, cars on roads are kept
+        stats.reset();
+        let road = stats.road(AxisDirection::Horizontal, (0, 0)).unwrap();
+        assert_eq!((road.car_number, road.passed), (1, 0));
+        assert_eq!(stats.intersection((0, 1)).unwrap().entered, 0);
+        assert!(stats.fundamental_diagram.is_empty());
+    }
+
+    #[test]
+    fn control_delay_of_entered_cars() {
+        use crate::model::stateless::{car::DrivingModel, CityBuilder};
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let car = stateless::Car {
+            class: Default::default(),
+            length: 4.5,
+            width: 1.7,
+            max_velocity: 30.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        };
+        let stateless = stateless::Model {
+            city,
+            cars: vec![car],
+            ..Default::default()
+        };
+        let params = SimParams {
+            desired_speed: Some(10.0),
+            ..Default::default()
+        };
+        let mut stats = Stats::new(&stateless.city, 60.0);
+        let mut model = stateful::Model::default();
+        model.replace_cars(vec![car_at(on_lane(0, 10.0), 10.0)]);
+        // Half the free-flow speed for two seconds
+        model.cars[0].as_mut().unwrap().velocity = 5.0;
+        for time in 1..=2 {
+            let before = Stats::road_positions(&model);
+            stats.update(time as f64, &before, &model);
+            stats.update_delays(1.0, &before, &model, &stateless, &params);
+        }
+        assert_eq!(stats.intersection((0, 1)).unwrap().control_delay(), 0.0);
+        let before = Stats::road_positions(&model);
+        let mut cars = model.cars.clone();
+        cars[0].as_mut().unwrap().location = Location::InIntersection {
+            intersection_index: (0, 1),
+            from_direction: AbsoluteDirection::West,
+            from_lane_index: 0,
+            to_direction: AbsoluteDirection::East,
+            to_lane_index: 0,
+            total_length: 20.0,
+            position: 0.0,
+        };
+        let entered = cars;
+        model.replace_cars(entered.clone());
+        stats.update(3.0, &before, &model);
+        stats.update_delays(1.0, &before, &model, &stateless, &params);
+        let intersection = stats.intersection((0, 1)).unwrap();
+        assert_eq!(
+            (intersection.entered, intersection.control_delay()),
+            (1, 1.0)
+        );
+        assert_eq!(
+            stats.pending_delays,
+            vec![Some((model.handle(0).unwrap(), 0.0))]
+        );
+
+        // A car leaving and a new car taking its slot within an update does
+        // not pass on its delay
+        let mut stats = Stats::new(&stateless.city, 60.0);
+        let mut model = stateful::Model::default();
+        model.replace_cars(vec![car_at(on_lane(0, 10.0), 10.0)]);
+        model.cars[0].as_mut().unwrap().velocity = 5.0;
+        let before = Stats::road_positions(&model);
+        stats.update_delays(1.0, &before, &model, &stateless, &params);
+        model.replace_cars(vec![None]);
+        model.replace_cars(entered);
+        stats.update_delays(1.0, &before, &model, &stateless, &params);
+        assert_eq!(stats.intersection((0, 1)).unwrap().total_delay, 0.0);
+    }
+
+    #[test]
+    fn count_cars_crossing_the_cordon() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road_length(AxisDirection::Horizontal, 0, 500.0)
+            .build()
+            .unwrap();
+        let mut stats = Stats::new(&city, 60.0);
+        let mut model = stateful::Model::default();
+        model.replace_cars(vec![None, car_at(on_lane(0, 400.0), 10.0)]);
+        let before = Stats::cordon_positions(&model, &city);
+        model.replace_cars(vec![car_at(on_lane(0, 10.0), 10.0), None]);
+        stats.update_cordon(10.0, 300.0, &before, &model, &city);
+        let before = Stats::cordon_positions(&model, &city);
+        stats.update_cordon(650.0, 300.0, &before, &model, &city);
+
+        let starts = stats.cordon.iter().map(|c| c.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0.0, 300.0, 600.0]);
+        let first = &stats.cordon[0];
+        assert_eq!(first.entering.west, 1);
+        assert_eq!(first.leaving.east, 1);
+        assert_eq!(first.balance(AbsoluteDirection::West), 1);
+        assert_eq!(first.balance(AbsoluteDirection::East), -1);
+        assert_eq!(
+            stats.cordon[2],
+            CordonCount {
+                start: 600.0,
+                ..Default::default()
+            }
+        );
+        let mut csv = Vec::new();
+        stats.write_cordon(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with(
+            "start,side,entering,leaving\n0,north,0,0\n0,south,0,0\n0,east,0,1\n0,west,1,0\n"
+        ));
+        assert_eq!(csv.lines().count(), 1 + 3 * 4);
+    }
+}
@@ -0,0 +1,611 @@
+//! Transitions of cars between updates, logged at the debug level.
+//!
+//! Each kind of transition is logged to its own target under this module,
+//! so env_logger can enable them one by one, for example
+//! `RUST_LOG=mpi_traffic::controller::transition::lane_change=debug` for
+//! lane changes only, or `RUST_LOG=mpi_traffic::controller::transition=debug`
+//! for all of them.
+//!
+//! Slowdowns are the times a car on a lane falls below a fraction of the
+//! speed limit of the lane, set with `--slowdown-fraction`, and gets back
+//! above it or into an intersection. They are logged with the position of
+//! the car in the city.
+
+use crate::{
+    controller::params::SimParams,
+    model::{
+        board::IntersectionIndex,
+        common::{AbsoluteDirection, CarIndex, LaneIndex, Position},
+        routing::Segment,
+        stateful::{self, car::Location, Car},
+        stateless,
+    },
+};
+use log::Level;
+use std::fmt;
+
+pub const SPAWN: &str = concat!(module_path!(), "::spawn");
+pub const DESPAWN: &str = concat!(module_path!(), "::despawn");
+pub const LANE_CHANGE: &str = concat!(module_path!(), "::lane_change");
+pub const INTERSECTION: &str = concat!(module_path!(), "::intersection");
+pub const REROUTE: &str = concat!(module_path!(), "::reroute");
+pub const SLOWDOWN: &str = concat!(module_path!(), "::slowdown");
+
+/// Log targets of all kinds of transitions.
+pub const TARGETS: &[&str] = &[SPAWN, DESPAWN, LANE_CHANGE, INTERSECTION, REROUTE, SLOWDOWN];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition {
+    /// A new car took an empty slot.
+    Spawned {
+        car_index: CarIndex,
+        destination: Option<IntersectionIndex>,
+    },
+    /// A car was removed from its slot.
+    Despawned { car_index: CarIndex },
+    /// A car moved to another lane of its road.
+    LaneChanged {
+        car_index: CarIndex,
+        segment: Segment,
+        from_lane_index: LaneIndex,
+        to_lane_index: LaneIndex,
+    },
+    EnteredIntersection {
+        car_index: CarIndex,
+        intersection_index: IntersectionIndex,
+        from_direction: AbsoluteDirection,
+        to_direction: AbsoluteDirection,
+    },
+    LeftIntersection {
+        car_index: CarIndex,
+        intersection_index: IntersectionIndex,
+        segment: Segment,
+        lane_index: LaneIndex,
+    },
+    /// The route of a car changed other than by driving along it.
+    Rerouted {
+        car_index: CarIndex,
+        destination: Option<IntersectionIndex>,
+        segment_number: usize,
+    },
+    /// A car fell below the slowdown fraction of the speed limit of its
+    /// lane.
+    SlowedDown {
+        car_index: CarIndex,
+        segment: Segment,
+        lane_index: LaneIndex,
+        position: Position,
+        speed: f64,
+        speed_limit: f64,
+    },
+    /// A slowed down car got back above the fraction or into an
+    /// intersection.
+    Recovered {
+        car_index: CarIndex,
+        position: Position,
+        speed: f64,
+    },
+}
+
+impl Transition {
+    /// Index of the slot of the car.
+    pub fn car_index(&self) -> CarIndex {
+        match *self {
+            Transition::Spawned { car_index, .. }
+            | Transition::Despawned { car_index }
+            | Transition::LaneChanged { car_index, .. }
+            | Transition::EnteredIntersection { car_index, .. }
+            | Transition::LeftIntersection { car_index, .. }
+            | Transition::Rerouted { car_index, .. }
+            | Transition::SlowedDown { car_index, .. }
+            | Transition::Recovered { car_index, .. } => car_index,
+        }
+    }
+
+    /// The log target of the kind of the transition.
+    pub fn target(&self) -> &'static str {
+        match self {
+            Transition::Spawned { .. } => SPAWN,
+            Transition::Despawned { .. } => DESPAWN,
+            Transition::LaneChanged { .. } => LANE_CHANGE,
+            Transition::EnteredIntersection { .. } | Transition::LeftIntersection { .. } => {
+                INTERSECTION
+            }
+            Transition::Rerouted { .. } => REROUTE,
+            Transition::SlowedDown { .. } | Transition::Recovered { .. } => SLOWDOWN,
+        }
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transition::Spawned {
+                car_index,
+                destination,
+            } => write!(
+                f,
+                "car {} spawned with destination {:?}",
+                car_index, destination
+            ),
+            Transition::Despawned { car_index } => write!(f, "car {} despawned", car_index),
+            Transition::LaneChanged {
+                car_index,
+                segment,
+                from_lane_index,
+                to_lane_index,
+            } => write!(
+                f,
+                "car {} changed from lane {} to lane {} of {:?}",
+                car_index, from_lane_index, to_lane_index, segment
+            ),
+            Transition::EnteredIntersection {
+                car_index,
+                intersection_index,
+                from_direction,
+                to_direction,
+            } => write!(
+                f,
+                "car {} entered intersection {:?} from the {:?} to the {:?}",
+                car_index, intersection_index, from_direction, to_direction
+            ),
+            Transition::LeftIntersection {
+                car_index,
+                intersection_index,
+                segment,
+                lane_index,
+            } => write!(
+                f,
+                "car {} left intersection {:?} to lane {} of {:?}",
+                car_index, intersection_index, lane_index, segment
+            ),
+            Transition::Rerouted {
+                car_index,
+                destination,
+                segment_number,
+            } => write!(
+                f,
+                "car {} rerouted to {:?} through {} segments",
+                car_index, destination, segment_number
+            ),
+            Transition::SlowedDown {
+                car_index,
+                segment,
+                lane_index,
+                position,
+                speed,
+                speed_limit,
+            } => write!(
+                f,
+                "car {} slowed down to {:.2} m/s of {:.2} m/s on lane {} of {:?} at ({:.1}, {:.1})",
+                car_index, speed, speed_limit, lane_index, segment, position.x, position.y
+            ),
+            Transition::Recovered {
+                car_index,
+                position,
+                speed,
+            } => write!(
+                f,
+                "car {} recovered at {:.2} m/s at ({:.1}, {:.1})",
+                car_index, speed, position.x, position.y
+            ),
+        }
+    }
+}
+
+/// Whether transitions of any kind are logged.
+pub fn log_enabled() -> bool {
+    TARGETS
+        .iter()
+        .any(|target| log::log_enabled!(target: target, Level::Debug))
+}
+
+/// Log the transitions from `before_cars` with `before_generations` to the
+/// cars of `after`, at the end of an update at `time`, with the id of each
+/// car.
+pub fn log_transitions(
+    time: f64,
+    before_cars: &[Option<Car>],
+    before_generations: &[u32],
+    after: &stateful::Model,
+    slowdowns: &Slowdowns,
+) {
+    let mut transitions = find_transitions(before_cars, before_generations, after);
+    if log::log_enabled!(target: SLOWDOWN, Level::Debug) {
+        transitions.extend(slowdowns.find(before_cars, before_generations, after));
+    }
+    for transition in transitions {
+        // A despawned car is only in the cars before
+        let cars = match transition {
+            Transition::Despawned { .. } => before_cars,
+            _ => &after.cars,
+        };
+        let id = cars
+            .get(transition.car_index())
+            .and_then(Option::as_ref)
+            .map_or(0, |car| car.id);
+        log::debug!(
+            target: transition.target(),
+            "at time {:.3}: {} (id {})",
+            time,
+            transition,
+            id
+        );
+    }
+}
+
+/// Finds the slowdowns of cars on lanes.
+pub struct Slowdowns<'a> {
+    pub city: &'a stateless::City,
+    pub params: &'a SimParams,
+    /// Fraction of the speed limit of a lane below which a car is slowed
+    /// down.
+    pub fraction: f64,
+}
+
+impl Slowdowns<'_> {
+    /// The speed limit of the lane of a car below the fraction of it, or
+    /// `None` if the car is not on a lane or not that slow.
+    fn slowed_down(&self, car: &Car) -> Option<(Segment, LaneIndex, f64)> {
+        let (segment, lane_index) = lane_of(&car.location)?;
+        let lane = &self
+            .city
+            .board
+            .get_road(segment.road_direction, segment.road_index)?
+            .as_ref()?
+            .lanes_to_direction(segment.lane_direction)[lane_index];
+        let speed_limit = self.params.lane_speed_limit(lane);
+        if car.velocity < self.fraction * speed_limit {
+            Some((segment, lane_index, speed_limit))
+        } else {
+            None
+        }
+    }
+
+    /// Slowdowns and recoveries of cars from `before_cars` with
+    /// `before_generations` to the cars of `after`, by car index.
+    ///
+    /// Only cars in the same slot before and after count, a car is never
+    /// slowed down when it appears.
+    pub fn find(
+        &self,
+        before_cars: &[Option<Car>],
+        before_generations: &[u32],
+        after: &stateful::Model,
+    ) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        for (car_index, (before, after_car)) in
+            before_cars.iter().zip(after.cars.iter()).enumerate()
+        {
+            let (before, after_car) = match (before, after_car) {
+                (Some(before), Some(after_car))
+                    if before_generations.get(car_index).copied().unwrap_or(0)
+                        == after.generation(car_index) =>
+                {
+                    (before, after_car)
+                }
+                _ => continue,
+            };
+            let position = match after_car.location.city_position(self.city) {
+                Some(position) => position,
+                None => continue,
+            };
+            match (self.slowed_down(before), self.slowed_down(after_car)) {
+                (None, Some((segment, lane_index, speed_limit))) => {
+                    transitions.push(Transition::SlowedDown {
+                        car_index,
+                        segment,
+                        lane_index,
+                        position,
+                        speed: after_car.velocity,
+                        speed_limit,
+                    })
+                }
+                (Some(_), None) => transitions.push(Transition::Recovered {
+                    car_index,
+                    position,
+                    speed: after_car.velocity,
+                }),
+                _ => (),
+            }
+        }
+        transitions
+    }
+}
+
+/// Transitions from `before_cars` with `before_generations` to the cars of
+/// `after`, by car index.
+pub fn find_transitions(
+    before_cars: &[Option<Car>],
+    before_generations: &[u32],
+    after: &stateful::Model,
+) -> Vec<Transition> {
+    let mut transitions = Vec::new();
+    let slot_number = before_cars.len().max(after.cars.len());
+    for car_index in 0..slot_number {
+        let before = before_cars.get(car_index).and_then(Option::as_ref);
+        let after_car = after.cars.get(car_index).and_then(Option::as_ref);
+        let same_car =
+            before_generations.get(car_index).copied().unwrap_or(0) == after.generation(car_index);
+        let (before, after_car) = match (before, after_car) {
+            (Some(before), Some(after_car)) if same_car => (before, after_car),
+            (before, after_car) => {
+                if before.is_some() {
+                    transitions.push(Transition::Despawned { car_index });
+                }
+                if let Some(after_car) = after_car {
+                    transitions.push(Transition::Spawned {
+                        car_index,
+                        destination: after_car.destination,
+                    });
+                }
+                continue;
+            }
+        };
+        match (lane_of(&before.location), lane_of(&after_car.location)) {
+            (Some((segment, from_lane_index)), Some((after_segment, to_lane_index)))
+                if segment == after_segment && from_lane_index != to_lane_index =>
+            {
+                transitions.push(Transition::LaneChanged {
+                    car_index,
+                    segment,
+                    from_lane_index,
+                    to_lane_index,
+                });
+            }
+            _ => (),
+        }
+        match (&before.location, &after_car.location) {
+            (Location::InIntersection { .. }, Location::InIntersection { .. }) => (),
+            (
+                _,
+                Location::InIntersection {
+                    intersection_index,
+                    from_direction,
+                    to_direction,
+                    ..
+                },
+            ) => transitions.push(Transition::EnteredIntersection {
+                car_index,
+                intersection_index: *intersection_index,
+                from_direction: *from_direction,
+                to_direction: *to_direction,
+            }),
+            (
+                Location::InIntersection {
+                    intersection_index, ..
+                },
+                location,
+            ) => {
+                if let Some((segment, lane_index)) = lane_of(location) {
+                    transitions.push(Transition::LeftIntersection {
+                        car_index,
+                        intersection_index: *intersection_index,
+                        segment,
+                        lane_index,
+                    });
+                }
+            }
+            _ => (),
+        }
+        let route_followed = before.route.get(1..) == Some(&after_car.route[..]);
+        if before.destination != after_car.destination
+            || (before.route != after_car.route && !route_followed)
+        {
+            transitions.push(Transition::Rerouted {
+                car_index,
+                destination: after_car.destination,
+                segment_number: after_car.route.len(),
+            });
+        }
+    }
+    transitions
+}
+
+/// The segment and the lane of a car on a road, cars changing lane are on
+/// their original lane.
+fn lane_of(location: &Location) -> Option<(Segment, LaneIndex)> {
+    match *location {
+        Location::OnLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            lane_index,
+            ..
+        } => Some((
+            Segment {
+                road_direction,
+                road_index,
+                lane_direction,
+            },
+            lane_index,
+        )),
+        Location::ChangingLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            from_lane_index,
+            ..
+        } => Some((
+            Segment {
+                road_direction,
+                road_index,
+                lane_direction,
+            },
+            from_lane_index,
+        )),
+        Location::InIntersection { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        common::{AxisDirection, LaneDirection},
+        stateful::testing::{car_at, on_lane},
+    };
+
+    #[test]
+    fn transitions_between_updates() {
+        let segment = Segment {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+        };
+        let in_intersection = Location::InIntersection {
+            intersection_index: (0, 1),
+            from_direction: AbsoluteDirection::West,
+            from_lane_index: 0,
+            to_direction: AbsoluteDirection::East,
+            to_lane_index: 0,
+            total_length: 20.0,
+            position: 0.0,
+        };
+        let mut before = stateful::Model::default();
+        let mut routed = car_at(on_lane(0, 30.0), 10.0);
+        routed.as_mut().unwrap().route = vec![segment, segment];
+        before.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 10.0),
+            car_at(on_lane(1, 95.0), 10.0),
+            routed,
+            car_at(on_lane(0, 50.0), 10.0),
+            None,
+        ]);
+        let mut after = before.clone();
+        let mut cars = before.cars.clone();
+        cars[0].as_mut().unwrap().location = Location::ChangingLane {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+            from_lane_index: 0,
+            to_lane_index: 1,
+            position: 11.0,
+            lane_changed_proportion: 0.5,
+        };
+        cars[1].as_mut().unwrap().location = in_intersection;
+        // Driving along the route is no rerouting
+        cars[2].as_mut().unwrap().route.remove(0);
+        cars[3] = None;
+        cars[4] = car_at(on_lane(1, 0.0), 10.0);
+        after.replace_cars(cars);
+        let transitions = find_transitions(&before.cars, &before.generations, &after);
+        assert_eq!(
+            transitions,
+            vec![
+                Transition::EnteredIntersection {
+                    car_index: 1,
+                    intersection_index: (0, 1),
+                    from_direction: AbsoluteDirection::West,
+                    to_direction: AbsoluteDirection::East,
+                },
+                Transition::Despawned { car_index: 3 },
+                Transition::Spawned {
+                    car_index: 4,
+                    destination: None,
+                },
+            ]
+        );
+
+        // The lane changes when the change ends
+        let mut cars = after.cars.clone();
+        cars[0].as_mut().unwrap().location = on_lane(1, 12.0);
+        cars[1].as_mut().unwrap().location = on_lane(0, 0.0);
+        cars[2].as_mut().unwrap().route.clear();
+        cars[2].as_mut().unwrap().destination = Some((0, 0));
+        let mut next = after.clone();
+        next.replace_cars(cars);
+        let transitions = find_transitions(&after.cars, &after.generations, &next);
+        assert_eq!(
+            transitions,
+            vec![
+                Transition::LaneChanged {
+                    car_index: 0,
+                    segment,
+                    from_lane_index: 0,
+                    to_lane_index: 1,
+                },
+                Transition::LeftIntersection {
+                    car_index: 1,
+                    intersection_index: (0, 1),
+                    segment,
+                    lane_index: 0,
+                },
+                Transition::Rerouted {
+                    car_index: 2,
+                    destination: Some((0, 0)),
+                    segment_number: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn slowdowns_below_fraction_of_speed_limit() {
+        use crate::model::stateless::CityBuilder;
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let params = SimParams::default();
+        let speed_limit = params.lane_speed_limit(
+            &city.board.horizontal_roads[(0, 0)]
+                .as_ref()
+                .unwrap()
+                .lane_to_high[0],
+        );
+        let slowdowns = Slowdowns {
+            city: &city,
+            params: &params,
+            fraction: 0.5,
+        };
+        let with_speed = |car: &mut Option<Car>, speed: f64| {
+            car.as_mut().unwrap().velocity = speed * speed_limit;
+        };
+        let mut before = stateful::Model::default();
+        before.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 10.0),
+            car_at(on_lane(0, 20.0), 10.0),
+            car_at(on_lane(0, 30.0), 10.0),
+        ]);
+        let mut cars = before.cars.clone();
+        with_speed(&mut before.cars[0], 0.8);
+        with_speed(&mut cars[0], 0.2);
+        with_speed(&mut before.cars[1], 0.2);
+        with_speed(&mut cars[1], 0.6);
+        with_speed(&mut before.cars[2], 0.8);
+        with_speed(&mut cars[2], 0.7);
+        let mut after = before.clone();
+        after.replace_cars(cars);
+        let position = |car_index: CarIndex| {
+            after.cars[car_index]
+                .as_ref()
+                .unwrap()
+                .location
+                .city_position(&city)
+                .unwrap()
+        };
+        assert_eq!(
+            slowdowns.find(&before.cars, &before.generations, &after),
+            vec![
+                Transition::SlowedDown {
+                    car_index: 0,
+                    segment: Segment {
+                        road_direction: AxisDirection::Horizontal,
+                        road_index: (0, 0),
+                        lane_direction: LaneDirection::LowToHigh,
+                    },
+                    lane_index: 0,
+                    position: position(0),
+                    speed: 0.2 * speed_limit,
+                    speed_limit,
+                },
+                Transition::Recovered {
+                    car_index: 1,
+                    position: position(1),
+                    speed: 0.6 * speed_limit,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! How cars choose their turns at intersections.
+
+use crate::model::common::{RelativeDirection, TurnRule};
+use rand::Rng;
+use std::str::FromStr;
+
+/// Relative chances of the turns, only the turns allowed by a lane count.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TurnWeights {
+    pub left: f64,
+    pub front: f64,
+    pub right: f64,
+    pub back: f64,
+}
+
+impl Default for TurnWeights {
+    fn default() -> Self {
+        Self {
+            left: 1.0,
+            front: 1.0,
+            right: 1.0,
+            back: 1.0,
+        }
+    }
+}
+
+impl TurnWeights {
+    pub fn weight(&self, direction: RelativeDirection) -> f64 {
+        match direction {
+            RelativeDirection::Left => self.left,
+            RelativeDirection::Front => self.front,
+            RelativeDirection::Right => self.right,
+            RelativeDirection::Back => self.back,
+        }
+    }
+
+    /// Choose a turn allowed by `turn_rule` by the weights.
+    ///
+    /// Allowed turns are equally likely when all of their weights are zero,
+    /// so cars never get stuck on a lane.
+    pub fn choose<R: Rng + ?Sized>(
+        &self,
+        turn_rule: TurnRule,
+        rng: &mut R,
+    ) -> Option<RelativeDirection> {
+        use RelativeDirection::*;
+        let allowed = [Front, Back, Left, Right]
+            .iter()
+            .copied()
+            .filter(|direction| turn_rule.contains(direction.to_turn_rule()))
+            .collect::<Vec<_>>();
+        let total = allowed
+            .iter()
+            .map(|direction| self.weight(*direction))
+            .sum::<f64>();
+        if total <= 0.0 {
+            return if allowed.is_empty() {
+                None
+            } else {
+                Some(allowed[rng.gen_range(0..allowed.len())])
+            };
+        }
+        let mut chosen = rng.gen_range(0.0..total);
+        for direction in allowed.iter() {
+            let weight = self.weight(*direction);
+            if chosen < weight {
+                return Some(*direction);
+            }
+            chosen -= weight;
+        }
+        allowed
+            .iter()
+            .rev()
+            .find(|direction| self.weight(**direction) > 0.0)
+            .copied()
+    }
+}
+
+/// Choice of turns of cars.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TurnPolicy {
+    /// Turn randomly by the weights.
+    Random(TurnWeights),
+    /// Drive along the fastest routes to random destinations, turning
+    /// randomly when a route can not be followed.
+    Route,
+}
+
+impl FromStr for TurnPolicy {
+    type Err = String;
+
+    /// Parse "route", "random" for equally likely turns, or
+    /// "random:<left>,<front>,<right>,<back>" for weighted turns.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("route"), None) => Ok(TurnPolicy::Route),
+            (Some("random"), None) => Ok(TurnPolicy::Random(Default::default())),
+            (Some("random"), Some(weights)) => {
+                let weights = weights
+                    .split(',')
+                    .map(|weight| weight.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("invalid turn weight in {}: {}", s, e))?;
+                let valid = weights
+                    .iter()
+                    .all(|weight| weight.is_finite() && *weight >= 0.0)
+                    && weights.iter().sum::<f64>() > 0.0;
+                match weights[..] {
+                    [left, front, right, back] if valid => Ok(TurnPolicy::Random(TurnWeights {
+                        left,
+                        front,
+                        right,
+                        back,
+                    })),
+                    _ => Err(format!(
+                        "expect 4 finite non-negative turn weights with a positive sum: {}",
+                        s
+                    )),
+                }
+            }
+            _ => Err(format!("unknown turn policy: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn parse_turn_policy() {
+        assert_eq!("route".parse(), Ok(TurnPolicy::Route));
+        assert_eq!(
+            "random".parse(),
+            Ok(TurnPolicy::Random(TurnWeights::default()))
+        );
+        assert_eq!(
+            "random:1,2.5,1,0".parse(),
+            Ok(TurnPolicy::Random(TurnWeights {
+                left: 1.0,
+                front: 2.5,
+                right: 1.0,
+                back: 0.0,
+            }))
+        );
+        assert!("random:1,2".parse::<TurnPolicy>().is_err());
+        assert!("random:1,2,-1,0".parse::<TurnPolicy>().is_err());
+        assert!("random:inf,1,1,1".parse::<TurnPolicy>().is_err());
+        assert!("random:NaN,1,1,1".parse::<TurnPolicy>().is_err());
+        assert!("random:0,0,0,0".parse::<TurnPolicy>().is_err());
+        assert!("straight".parse::<TurnPolicy>().is_err());
+    }
+
+    #[test]
+    fn choose_allowed_weighted_turns() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let weights = TurnWeights {
+            left: 0.0,
+            front: 1.0,
+            right: 3.0,
+            back: 0.0,
+        };
+        let rule = TurnRule::LEFT | TurnRule::FRONT | TurnRule::RIGHT;
+        let mut fronts = 0;
+        for _ in 0..1000 {
+            match weights.choose(rule, &mut rng) {
+                Some(RelativeDirection::Front) => fronts += 1,
+                Some(RelativeDirection::Right) => (),
+                other => panic!("unexpected turn: {:?}", other),
+            }
+        }
+        assert!(fronts > 150 && fronts < 350, "fronts: {}", fronts);
+        // Only zero weights left, turn anyway
+        assert_eq!(
+            weights.choose(TurnRule::LEFT, &mut rng),
+            Some(RelativeDirection::Left)
+        );
+        assert_eq!(weights.choose(TurnRule::empty(), &mut rng), None);
+    }
+}
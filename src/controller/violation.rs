@@ -0,0 +1,173 @@
+//! Checks of the model invariants, used to catch collisions while debugging.
+
+use crate::model::{
+    board::RoadIndex,
+    common::{AxisDirection, CarIndex, LaneDirection, LaneIndex},
+    stateful::{self, car::Location},
+};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// Two cars on the same lane are closer than a car length.
+    Overlap {
+        car_index: CarIndex,
+        other_car_index: CarIndex,
+        distance: f64,
+    },
+    /// Velocity or position of a car is not finite, or the velocity is
+    /// negative.
+    InvalidMotion {
+        car_index: CarIndex,
+        velocity: f64,
+        position: f64,
+    },
+}
+
+impl Violation {
+    /// The car to inspect.
+    pub fn car_index(&self) -> CarIndex {
+        match *self {
+            Violation::Overlap { car_index, .. } => car_index,
+            Violation::InvalidMotion { car_index, .. } => car_index,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Overlap {
+                car_index,
+                other_car_index,
+                distance,
+            } => write!(
+                f,
+                "car {} overlaps car {} at distance {:.3}",
+                car_index, other_car_index, distance
+            ),
+            Violation::InvalidMotion {
+                car_index,
+                velocity,
+                position,
+            } => write!(
+                f,
+                "car {} has invalid velocity {} or position {}",
+                car_index, velocity, position
+            ),
+        }
+    }
+}
+
+type LaneKey = (AxisDirection, RoadIndex, LaneDirection, LaneIndex);
+
+/// Find cars closer than `min_distance` on a lane and cars with invalid
+/// motion.
+///
+/// Cars changing lane occupy both lanes.
+pub fn find_violations(stateful: &stateful::Model, min_distance: f64) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut lanes: BTreeMap<LaneKey, Vec<(f64, CarIndex)>> = BTreeMap::new();
+    for (car_index, car) in stateful.cars.iter().enumerate() {
+        let car = match car {
+            Some(car) => car,
+            None => continue,
+        };
+        let position = match car.location {
+            Location::OnLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                lane_index,
+                position,
+                ..
+            } => {
+                lanes
+                    .entry((road_direction, road_index, lane_direction, lane_index))
+                    .or_default()
+                    .push((position, car_index));
+                position
+            }
+            Location::ChangingLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                from_lane_index,
+                to_lane_index,
+                position,
+                ..
+            } => {
+                for &lane_index in [from_lane_index, to_lane_index].iter() {
+                    lanes
+                        .entry((road_direction, road_index, lane_direction, lane_index))
+                        .or_default()
+                        .push((position, car_index));
+                }
+                position
+            }
+            Location::InIntersection { position, .. } => position,
+        };
+        if !car.velocity.is_finite() || car.velocity < 0.0 || !position.is_finite() {
+            violations.push(Violation::InvalidMotion {
+                car_index,
+                velocity: car.velocity,
+                position,
+            });
+        }
+    }
+    for cars in lanes.values_mut() {
+        cars.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        for pair in cars.windows(2) {
+            let ((back_position, back), (front_position, front)) = (pair[0], pair[1]);
+            let distance = front_position - back_position;
+            if distance < min_distance {
+                violations.push(Violation::Overlap {
+                    car_index: back,
+                    other_car_index: front,
+                    distance,
+                });
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stateful::testing::{car_at, on_lane};
+
+    #[test]
+    fn overlap_and_invalid_motion() {
+        let model = stateful::Model {
+            cars: vec![
+                car_at(on_lane(0, 10.0), 5.0),
+                car_at(on_lane(0, 30.0), 5.0),
+                car_at(on_lane(0, 12.0), 5.0),
+                car_at(on_lane(1, 11.0), 5.0),
+                None,
+                car_at(on_lane(1, 50.0), -1.0),
+            ],
+            ..Default::default()
+        };
+        let violations = find_violations(&model, 4.5);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::InvalidMotion {
+                    car_index: 5,
+                    velocity: -1.0,
+                    position: 50.0,
+                },
+                Violation::Overlap {
+                    car_index: 0,
+                    other_car_index: 2,
+                    distance: 2.0,
+                },
+            ]
+        );
+        assert!(find_violations(&model, 1.0)
+            .iter()
+            .all(|violation| violation.car_index() == 5));
+    }
+}
@@ -8,5 +8,23 @@ quick_error! {
             from()
             display("Communication error: {}", err)
         }
+        Io(err: std::io::Error) {
+            from()
+            display("I/O error: {}", err)
+        }
+        ModelFile(err: ron::Error) {
+            from()
+            display("Model file error: {}", err)
+        }
+        StateFile(err: bincode::Error) {
+            from()
+            display("State file error: {}", err)
+        }
+        InvalidModel(message: String) {
+            display("Invalid model: {}", message)
+        }
+        InvalidReplay(message: String) {
+            display("Invalid replay: {}", message)
+        }
     }
 }
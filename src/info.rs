@@ -1,16 +1,63 @@
+use crate::{
+    model::{board::IntersectionIndex, common::AbsoluteDirection, stateful::CarHandle},
+    view::Camera,
+};
+use std::{collections::HashSet, time::Instant};
+
 #[derive(Debug, Clone, Default)]
 pub struct Info {
-    pub x: f64,
-    pub y: f64,
-    pub zoom: f64,
+    /// Pan and zoom of the window over the fitted city.
+    pub camera: Camera,
+    /// Car highlighted in the view.
+    pub selected_car: Option<CarHandle>,
+    /// Keep the selected car in the center of the window.
+    pub follow_selected: bool,
+    /// Cars selected together by dragging a rectangle, highlighted in the
+    /// view.
+    pub selected_cars: HashSet<CarHandle>,
+    /// Corners of a finished selection drag in logical window coordinates,
+    /// the cars in it are selected in the next frame.
+    pub selection_box: Option<([f64; 2], [f64; 2])>,
+    /// Intersection whose signal is edited with the keyboard.
+    pub selected_intersection: Option<IntersectionIndex>,
+    /// Approach of the selected intersection whose turns are toggled.
+    pub selected_approach: Option<AbsoluteDirection>,
+    /// Coordinates of an intersection being typed to move the view to it.
+    pub goto_input: Option<String>,
+    /// Intersection to move the view to in the next frame.
+    pub goto_intersection: Option<IntersectionIndex>,
+    /// Intersection highlighted after moving the view to it, and since when.
+    pub highlighted_intersection: Option<(IntersectionIndex, Instant)>,
+    /// Since when all signals have failed, their signs flash meanwhile.
+    pub signals_failed: Option<Instant>,
+    /// Device pixels per logical pixel of the window, on each axis.
+    pub device_scale: [f64; 2],
+    /// Last position of the cursor in logical window coordinates.
+    pub cursor: [f64; 2],
 }
 
 impl Info {
     pub fn new() -> Self {
         Self {
-            x: 0.0,
-            y: 0.0,
-            zoom: 1.0,
+            camera: Camera::new(),
+            selected_car: None,
+            follow_selected: false,
+            selected_cars: HashSet::new(),
+            selection_box: None,
+            selected_intersection: None,
+            selected_approach: None,
+            goto_input: None,
+            goto_intersection: None,
+            highlighted_intersection: None,
+            signals_failed: None,
+            device_scale: [1.0, 1.0],
+            cursor: [0.0, 0.0],
         }
     }
+
+    /// Logical window coordinates of a cursor position in device pixels.
+    pub fn logical_position(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let [sx, sy] = self.device_scale;
+        [x / sx, y / sy]
+    }
 }
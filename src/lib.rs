@@ -1,4 +1,6 @@
+pub mod batch;
 pub mod communication;
+pub mod config;
 pub mod controller;
 pub mod error;
 pub mod info;
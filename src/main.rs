@@ -1,64 +1,470 @@
-use log::trace;
-use mpi::topology::{Communicator, Rank};
+use log::{error, info, trace, warn};
+use mpi::topology::{Communicator, Rank, SystemCommunicator};
 use mpi_traffic::{
+    batch::{self, Batch, Summary},
     communication,
-    controller::{Controller, ControllerSettings, UpdateController},
+    config::{self, Config},
+    controller::{
+        params::SimParams, Controller, ControllerSettings, UpdateController, UpdateSettings,
+    },
     info::Info,
-    model::generate::{self, ModelGenerationSettings},
-    view::{View, ViewSettings},
+    model::{
+        demand::DemandProfile,
+        file,
+        generate::{self, ModelGenerationSettings},
+        routing, signals,
+        stateful::{self, Tolerance},
+        stateless, Model,
+    },
+    util::pace::Pacer,
+    view::{overlay::Overlay, View, ViewSettings},
 };
 use piston_window::{
-    color, Event, EventLoop, EventSettings, Loop, PistonWindow, UpdateArgs, WindowSettings,
+    color, Event, EventLoop, EventSettings, Loop, PistonWindow, UpdateArgs, Window, WindowSettings,
 };
-use structopt::StructOpt;
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    iter,
+    path::{Path, PathBuf},
+    process, slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use structopt::{clap::AppSettings, StructOpt};
+
+const ROOT: Rank = 0;
+
+/// Whether Ctrl-C was pressed, the run ends as if the window was closed.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
 
 fn main() {
     env_logger::init();
-    let settings = MpiTrafficOpt::from_args();
+    let (settings, args) = parse_args(env::args().collect(), env::var_os(config::CONFIG_ENV))
+        .unwrap_or_else(|e| e.exit());
+    // The handler only raises the flag, the main thread ends the run and
+    // writes its outputs. A second Ctrl-C exits at once.
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(130);
+        }
+    })
+    .unwrap_or_else(|e| panic!("failed to set the Ctrl-C handler: {}", e));
 
     // Initialize MPI
     let universe = mpi::initialize().unwrap();
-    const ROOT: Rank = 0;
     let world = universe.world();
-    let root = world.process_at_rank(ROOT);
 
-    let mut model = if world.rank() == ROOT {
-        generate::generate_model(settings.model_generation_settings)
+    match settings.command {
+        Command::Run(settings) => {
+            if let Some(path) = settings.batch.clone() {
+                run_batch(&path, world);
+            } else if settings.self_test_determinism {
+                if !self_test_determinism(*settings, world) {
+                    // Finalize MPI before exiting with the failure
+                    drop(universe);
+                    process::exit(1);
+                }
+            } else {
+                let compared = settings
+                    .compare
+                    .as_ref()
+                    .map(|compare| compared_run(&args, compare));
+                run_window(*settings, compared, world);
+            }
+        }
+        Command::Generate(settings) => generate(*settings, world),
+        Command::Convert(settings) => {
+            if world.rank() == ROOT {
+                convert(&settings.input, &settings.output);
+            }
+        }
+        #[cfg(feature = "binary-state")]
+        Command::Replay(settings) => replay(*settings, world),
+    }
+}
+
+/// Load or generate the model on the root process, and share it and the
+/// simulation parameters with the other processes.
+fn setup(
+    model_generation_settings: ModelGenerationSettings,
+    sim_params_path: Option<&PathBuf>,
+    world: SystemCommunicator,
+) -> (stateless::Model, stateful::Model, SimParams) {
+    let model = if world.rank() == ROOT {
+        base_model(&model_generation_settings)
     } else {
         Default::default()
     };
+    setup_from(model, &model_generation_settings, sim_params_path, world)
+}
+
+/// Load or generate the model of `model_generation_settings`, before the
+/// signal and demand options apply to it.
+fn base_model(model_generation_settings: &ModelGenerationSettings) -> Model {
+    model_generation_settings
+        .validate()
+        .unwrap_or_else(|e| panic!("invalid model generation settings: {}", e));
+    let model = match &model_generation_settings.model_file {
+        Some(path) => file::load_model(path)
+            .unwrap_or_else(|e| panic!("failed to load model {:?}: {}", path, e)),
+        None => generate::generate_model(model_generation_settings.clone()),
+    };
+    let geometry = model.stateless.city.geometry();
+    if !(geometry.width > 0.0 && geometry.height > 0.0) {
+        warn!("the city has no area, nothing will be drawn");
+    }
+    let components = routing::components(&model.stateless.city);
+    if components.len() > 1 {
+        warn!(
+            "the road network has {} disconnected parts, around {:?}",
+            components.len(),
+            components.iter().map(|c| c[0]).collect::<Vec<_>>()
+        );
+    }
+    model
+}
+
+/// Apply the signal and demand options of `model_generation_settings` to
+/// `model` on the root process, and share it and the simulation parameters
+/// with the other processes.
+fn setup_from(
+    mut model: Model,
+    model_generation_settings: &ModelGenerationSettings,
+    sim_params_path: Option<&PathBuf>,
+    world: SystemCommunicator,
+) -> (stateless::Model, stateful::Model, SimParams) {
+    let root = world.process_at_rank(ROOT);
+    if world.rank() == ROOT {
+        if let Some(corridor) = model_generation_settings.green_wave {
+            let offsets = signals::green_wave(
+                &model.stateless.city,
+                corridor,
+                model_generation_settings.green_wave_speed,
+            )
+            .unwrap_or_else(|e| panic!("invalid green wave: {}", e));
+            signals::apply_offsets(&mut model, &offsets);
+        }
+        if let Some(path) = &model_generation_settings.demand_profile {
+            let demand = DemandProfile::load(path)
+                .unwrap_or_else(|e| panic!("failed to load demand profile {:?}: {}", path, e));
+            model.stateless.demand = Some(demand);
+        }
+        if let Some(demand) = &model.stateless.demand {
+            demand
+                .validate()
+                .unwrap_or_else(|e| panic!("invalid demand profile: {}", e));
+        }
+        if let Some(od_matrix) = &model.stateless.od_matrix {
+            od_matrix
+                .validate(&model.stateless.city)
+                .unwrap_or_else(|e| panic!("invalid OD matrix: {}", e));
+        }
+    }
     communication::bincode_broadcast(world.rank(), root, &mut model).unwrap();
-    let stateless_model = model.stateless;
-    let mut stateful_model = model.stateful;
+    let mut sim_params = match sim_params_path {
+        Some(path) if world.rank() == ROOT => SimParams::load(path)
+            .unwrap_or_else(|e| panic!("failed to load parameters {:?}: {}", path, e)),
+        _ => SimParams::default(),
+    };
+    communication::bincode_broadcast(world.rank(), root, &mut sim_params).unwrap();
+    (model.stateless, model.stateful, sim_params)
+}
+
+/// Generate or load a model as for a run and save it to a model file.
+fn generate(settings: GenerateOpt, world: SystemCommunicator) {
+    let output = settings.output;
+    let (stateless_model, _, _) = setup(settings.model_generation_settings, None, world);
+    if world.rank() == ROOT {
+        file::save_stateless_model(&output, &stateless_model)
+            .unwrap_or_else(|e| panic!("failed to write model to {:?}: {}", output, e));
+        info!("model written to {:?}", output);
+    }
+}
+
+/// Read the model file at `input` and write it to `output`, with the fields
+/// left out of `input` at their defaults.
+///
+/// Files ending in `.bin` are in bincode, the others in RON.
+fn convert(input: &Path, output: &Path) {
+    #[cfg(not(feature = "binary-state"))]
+    {
+        for path in [input, output].iter() {
+            if path.extension() == Some("bin".as_ref()) {
+                panic!("binary model {:?} needs the binary-state feature", path);
+            }
+        }
+    }
+    let model = file::load_stateless_model(input)
+        .unwrap_or_else(|e| panic!("failed to load model {:?}: {}", input, e));
+    file::save_stateless_model(output, &model)
+        .unwrap_or_else(|e| panic!("failed to write model to {:?}: {}", output, e));
+    info!("model {:?} written to {:?}", input, output);
+}
+
+/// Play a replay in the window instead of simulating.
+#[cfg(feature = "binary-state")]
+fn replay(settings: ReplayOpt, world: SystemCommunicator) {
+    // A run with the default settings of everything the replay does not set
+    let mut run = RunOpt::from_iter(iter::once("run"));
+    run.realtime_factor = settings.realtime_factor;
+    run.model_generation_settings = settings.model_generation_settings;
+    run.view_settings = settings.view_settings;
+    run.controller_settings.replay = Some(settings.replay);
+    run.controller_settings.replay_seek_frames = settings.replay_seek_frames;
+    run_window(run, None, world);
+}
+
+/// A second run of the model of the window, drawn next to the first.
+struct ComparedRun {
+    controller: UpdateController,
+    stateless_model: stateless::Model,
+    stateful_model: stateful::Model,
+}
+
+impl ComparedRun {
+    fn update(&mut self, world: SystemCommunicator, args: UpdateArgs) {
+        self.controller.update(
+            ROOT,
+            world,
+            &mut self.stateful_model,
+            &self.stateless_model,
+            args,
+        );
+    }
+}
+
+/// Settings of the run compared with the run of `args`, with the arguments
+/// of `compare` added to `args`.
+fn compared_run(args: &[String], compare: &str) -> RunOpt {
+    let args = args
+        .iter()
+        .cloned()
+        .chain(compare.split_whitespace().map(String::from));
+    let settings = MpiTrafficOpt::from_iter_safe(args)
+        .unwrap_or_else(|e| panic!("invalid arguments of the compared run: {}", e));
+    match settings.command {
+        Command::Run(settings) => *settings,
+        _ => unreachable!("the compared run is a run"),
+    }
+}
+
+/// Run the simulation in a window on the root process, next to the run of
+/// `compared` if given.
+fn run_window(settings: RunOpt, compared: Option<RunOpt>, world: SystemCommunicator) {
+    let root = world.process_at_rank(ROOT);
+    // Both runs start from the same model, however it was made
+    let model = if world.rank() == ROOT {
+        base_model(&settings.model_generation_settings)
+    } else {
+        Default::default()
+    };
+    let mut compared = compared.map(|compared| {
+        if world.rank() == ROOT {
+            compared
+                .model_generation_settings
+                .validate()
+                .unwrap_or_else(|e| panic!("invalid settings of the compared run: {}", e));
+        }
+        let (stateless_model, stateful_model, sim_params) = setup_from(
+            model.clone(),
+            &compared.model_generation_settings,
+            compared.update_settings.sim_params.as_ref(),
+            world,
+        );
+        ComparedRun {
+            controller: UpdateController::new(compared.update_settings, sim_params),
+            stateless_model,
+            stateful_model,
+        }
+    });
+    let (stateless_model, mut stateful_model, sim_params) = setup_from(
+        model,
+        &settings.model_generation_settings,
+        settings.update_settings.sim_params.as_ref(),
+        world,
+    );
 
     if world.rank() == ROOT {
         let mut window: PistonWindow = WindowSettings::new("MPI Traffic", [1000, 500])
             .exit_on_esc(true)
             .build()
             .unwrap_or_else(|e| panic!("failed to build PistonWindow: {}", e));
-        let event_settings = EventSettings::new().ups(60).ups_reset(10).max_fps(30);
+        let mut pacer = settings.realtime_factor.map(|factor| {
+            Pacer::new(factor).unwrap_or_else(|e| panic!("invalid realtime factor: {}", e))
+        });
+        // Each update is 1/60 of a simulated second. With a realtime factor
+        // the window asks for updates a little faster than the pace, and the
+        // pacer sleeps between them, still handling input in between
+        let ups = match settings.realtime_factor {
+            Some(factor) => (60.0 * factor).ceil().max(1.0) as u64,
+            None => 60,
+        };
+        let event_settings = EventSettings::new().ups(ups).ups_reset(10).max_fps(30);
         window.set_event_settings(event_settings);
 
-        let view = View::new(settings.view_settings);
+        let mut view = View::new(settings.view_settings);
         let mut info = Info::new();
-        let update_controller = UpdateController::new();
-        let mut controller = Controller::new(update_controller, settings.controller_settings);
+        let update_controller = UpdateController::new(settings.update_settings, sim_params);
+        let mut controller = Controller::new(
+            update_controller,
+            settings.controller_settings,
+            &stateless_model,
+        );
 
         while let Some(e) = window.next() {
             trace!("event: {:?}", e);
+            let e = match e {
+                Event::Loop(Loop::Update(_)) if pacer.is_some() => {
+                    Event::Loop(Loop::Update(UpdateArgs { dt: 1.0 / 60.0 }))
+                }
+                e => e,
+            };
+            if interrupted() {
+                info!("interrupted, ending the run");
+                window.set_should_close(true);
+            }
             window.draw_2d(&e, |c, g, _| {
                 use piston_window::clear;
                 let clear_color = color::BLACK;
                 clear(clear_color, g);
-                view.draw(&info, &stateless_model, &stateful_model, c, g);
+                view.update_device_scale(&mut info, c);
+                // The run of the window is drawn into the first half when
+                // compared
+                let first = match &compared {
+                    Some(_) => view.settings.split.context(c, 0),
+                    None => c,
+                };
+                view.follow_selected(&mut info, &stateless_model, &stateful_model, first);
+                view.draw(&info, &stateless_model, &stateful_model, first, g);
+                // Where the next update puts the cars, previewed while paused
+                if let Some(ghosts) = controller.preview() {
+                    if view.settings.overlay_shown(Overlay::Ghosts) {
+                        view.draw_ghosts(
+                            &info,
+                            &stateless_model,
+                            &stateful_model,
+                            ghosts,
+                            first,
+                            g,
+                        );
+                    }
+                }
+                if let Some(compared) = &compared {
+                    // Cars are selected in the first run only
+                    let compared_info = Info {
+                        selected_car: None,
+                        selected_cars: Default::default(),
+                        ..info.clone()
+                    };
+                    view.draw(
+                        &compared_info,
+                        &compared.stateless_model,
+                        &compared.stateful_model,
+                        view.settings.split.context(c, 1),
+                        g,
+                    );
+                    view.draw_comparison(["A", "B"], c, g);
+                }
+                view.draw_graph(
+                    &controller.history,
+                    controller.update_controller.params().speed_unit,
+                    c,
+                    g,
+                );
+                view.draw_legend(c, g);
+                view.draw_minimap(&info, &stateless_model.city, c, g);
+                view.draw_help(c, g);
+                view.draw_goto_prompt(&info, c, g);
+                view.draw_selection_box(&info, controller.selection_start, c, g);
             });
             match e {
                 Event::Input(e, _) => {
+                    if info.selected_intersection.is_none() && info.goto_input.is_none() {
+                        view.input(&e);
+                    }
+                    if info.goto_input.is_none() {
+                        if let Some(path) = view.svg_export(&e) {
+                            match view.render_to_svg(&stateless_model, &stateful_model, path) {
+                                Ok(()) => info!("frame written to {:?}", path),
+                                Err(e) => error!("failed to write frame to {:?}: {}", path, e),
+                            }
+                        }
+                    }
+                    let size = window.size();
+                    // A click on the minimap only moves the view
+                    if view.minimap_click(
+                        &mut info,
+                        &stateless_model.city,
+                        [size.width, size.height],
+                        &e,
+                    ) {
+                        continue;
+                    }
+                    // The first run fills the window unless compared
+                    let size = match &compared {
+                        Some(_) => view.settings.split.half([size.width, size.height], 0).1,
+                        None => [size.width, size.height],
+                    };
+                    if info.goto_input.is_none() {
+                        view.select_intersection(&mut info, &stateless_model.city, size, &e);
+                    }
                     controller.input(&mut info, &mut stateful_model, &stateless_model, e);
+                    if view.select_cars(&mut info, &stateless_model, &stateful_model, size) {
+                        let cars = info
+                            .selected_cars
+                            .iter()
+                            .filter_map(|handle| stateful_model.car(*handle))
+                            .collect::<Vec<_>>();
+                        let mean = cars.iter().map(|car| car.velocity).sum::<f64>()
+                            / cars.len().max(1) as f64;
+                        let mut ids = cars.iter().map(|car| car.id).collect::<Vec<_>>();
+                        ids.sort_unstable();
+                        info!(
+                            "{} cars selected, mean velocity {:.2} m/s, ids {:?}",
+                            cars.len(),
+                            mean,
+                            ids
+                        );
+                    }
+                    view.goto_intersection(&mut info, &stateless_model.city, size);
+                }
+                #[cfg(feature = "binary-state")]
+                Event::Loop(Loop::Update(args)) if controller.replay.is_some() => {
+                    if let Some(pacer) = pacer.as_mut() {
+                        pacer.wait(args.dt);
+                    }
+                    if !controller.paused {
+                        controller.play_replay(&mut stateful_model);
+                    }
+                }
+                Event::Loop(Loop::Update(args)) if controller.paused => {
+                    if let Some(pacer) = pacer.as_mut() {
+                        pacer.wait(args.dt);
+                    }
+                    if view.settings.overlay_shown(Overlay::Ghosts) {
+                        controller.preview_step(&stateful_model, &stateless_model, args.dt);
+                    }
                 }
                 Event::Loop(Loop::Update(args)) => {
+                    if let Some(pacer) = pacer.as_mut() {
+                        pacer.wait(args.dt);
+                    }
                     let mut send_args = Some(args);
                     communication::bincode_broadcast(world.rank(), root, &mut send_args).unwrap();
+                    if view.settings.show_trails {
+                        stateful_model.record_trails(view.settings.trail_length);
+                        if let Some(compared) = compared.as_mut() {
+                            compared
+                                .stateful_model
+                                .record_trails(view.settings.trail_length);
+                        }
+                    }
                     controller.update(
                         ROOT,
                         world,
@@ -67,10 +473,24 @@ fn main() {
                         &stateless_model,
                         args,
                     );
+                    if let Some(compared) = compared.as_mut() {
+                        compared.update(world, args);
+                    }
                 }
                 _ => {}
             }
         }
+        finish(&mut controller, &stateful_model);
+        info!(
+            "final stats: {}",
+            summary_line(&Summary::of("run", &controller, &stateful_model))
+        );
+        if let Some(compared) = &compared {
+            info!(
+                "fingerprint of the compared run: {:016x}",
+                compared.stateful_model.fingerprint()
+            );
+        }
         communication::bincode_broadcast::<_, Option<UpdateArgs>>(
             world.rank(),
             root,
@@ -78,28 +498,496 @@ fn main() {
         )
         .unwrap();
     } else {
-        let mut controller = UpdateController::new();
-        loop {
-            let mut args: Option<UpdateArgs> = None;
-            communication::bincode_broadcast(world.rank(), root, &mut args).unwrap();
-            if let Some(args) = args {
-                controller.update(ROOT, world, &mut stateful_model, &stateless_model, args);
-            } else {
-                break;
+        follow_root(
+            settings.update_settings,
+            sim_params,
+            world,
+            &mut stateful_model,
+            &stateless_model,
+            compared.as_mut(),
+        );
+    }
+}
+
+/// Update the part of the cars of a process other than the root until the
+/// root ends the run, and of the run of `compared` after each update.
+fn follow_root(
+    update_settings: UpdateSettings,
+    sim_params: SimParams,
+    world: SystemCommunicator,
+    stateful_model: &mut stateful::Model,
+    stateless_model: &stateless::Model,
+    mut compared: Option<&mut ComparedRun>,
+) {
+    let root = world.process_at_rank(ROOT);
+    let mut controller = UpdateController::new(update_settings, sim_params);
+    loop {
+        let mut args: Option<UpdateArgs> = None;
+        communication::bincode_broadcast(world.rank(), root, &mut args).unwrap();
+        if let Some(args) = args {
+            controller.update(ROOT, world, stateful_model, stateless_model, args);
+            if let Some(compared) = compared.as_mut() {
+                compared.update(world, args);
             }
+        } else {
+            break;
         }
     }
 }
 
+/// Report the end of a run on the root process, flush the records written
+/// during the run and write its outputs.
+fn finish(controller: &mut Controller, stateful: &stateful::Model) {
+    info!("fingerprint: {:016x}", stateful.fingerprint());
+    controller.flush_outputs();
+    if let Some(path) = controller.settings.state_output.as_ref() {
+        match file::save_state(path, stateful) {
+            Ok(()) => info!("state written to {:?}", path),
+            Err(e) => error!("failed to write state to {:?}: {}", path, e),
+        }
+    }
+    if let Some(path) = controller.settings.diagram_output.as_ref() {
+        match write_file(path, |writer| {
+            controller.stats.write_fundamental_diagram(writer)
+        }) {
+            Ok(()) => info!("fundamental diagram written to {:?}", path),
+            Err(e) => error!("failed to write fundamental diagram to {:?}: {}", path, e),
+        }
+    }
+    if let Some(path) = controller.settings.summary_output.as_ref() {
+        let summary = Summary::of("run", controller, stateful);
+        match write_file(path, |writer| {
+            batch::write_summaries(writer, slice::from_ref(&summary))
+        }) {
+            Ok(()) => info!("summary written to {:?}", path),
+            Err(e) => error!("failed to write summary to {:?}: {}", path, e),
+        }
+    }
+    if let Some(path) = controller.settings.cordon_output.as_ref() {
+        match write_file(path, |writer| controller.stats.write_cordon(writer)) {
+            Ok(()) => info!("cordon counts written to {:?}", path),
+            Err(e) => error!("failed to write cordon counts to {:?}: {}", path, e),
+        }
+    }
+    if let Some(path) = controller.settings.state_baseline.as_ref() {
+        match file::load_state(path) {
+            Ok(baseline) => {
+                let diff = baseline.diff(stateful, Tolerance::default());
+                if diff.is_empty() {
+                    info!("state matches the baseline {:?}", path);
+                } else {
+                    warn!("state differs from the baseline {:?}: {}", path, diff);
+                }
+            }
+            Err(e) => error!("failed to load baseline state {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Run the scenarios of a batch file one after the other without a window,
+/// see `mpi_traffic::batch`.
+fn run_batch(path: &Path, world: SystemCommunicator) {
+    let root = world.process_at_rank(ROOT);
+    let mut batch = if world.rank() == ROOT {
+        let batch =
+            Batch::load(path).unwrap_or_else(|e| panic!("failed to load batch {:?}: {}", path, e));
+        batch
+            .validate()
+            .unwrap_or_else(|e| panic!("invalid batch {:?}: {}", path, e));
+        fs::create_dir_all(&batch.output).unwrap_or_else(|e| {
+            panic!(
+                "failed to create output directory {:?}: {}",
+                batch.output, e
+            )
+        });
+        batch
+    } else {
+        Batch {
+            output: PathBuf::new(),
+            scenarios: Vec::new(),
+        }
+    };
+    communication::bincode_broadcast(world.rank(), root, &mut batch).unwrap();
+    let mut summaries = Vec::new();
+    for scenario in batch.scenarios.iter() {
+        let args = iter::once("mpi-traffic".to_string())
+            .chain(iter::once("run".to_string()))
+            .chain(scenario.args.iter().cloned())
+            .collect();
+        let (settings, _) = parse_args(args, None)
+            .unwrap_or_else(|e| panic!("invalid arguments of scenario {:?}: {}", scenario.name, e));
+        let mut settings = match settings.command {
+            Command::Run(settings) => *settings,
+            _ => unreachable!("the arguments of a scenario are the ones of a run"),
+        };
+        if settings.batch.is_some() || settings.self_test_determinism {
+            panic!("scenario {:?} runs a batch or a self test", scenario.name);
+        }
+        if scenario.seed.is_some() {
+            settings.update_settings.seed = scenario.seed;
+            settings
+                .model_generation_settings
+                .stateless_model_settings
+                .seed = scenario.seed;
+        }
+        if settings.controller_settings.diagram_output.is_none() {
+            settings.controller_settings.diagram_output = Some(batch.diagram_path(scenario));
+        }
+        info!(
+            "running scenario {:?} for {} ticks",
+            scenario.name, scenario.ticks
+        );
+        let (stateless_model, mut stateful_model, sim_params) = setup(
+            settings.model_generation_settings,
+            settings.update_settings.sim_params.as_ref(),
+            world,
+        );
+        if world.rank() == ROOT {
+            let update_controller = UpdateController::new(settings.update_settings, sim_params);
+            let mut controller = Controller::new(
+                update_controller,
+                settings.controller_settings,
+                &stateless_model,
+            );
+            run_headless(
+                &mut controller,
+                world,
+                &mut stateful_model,
+                &stateless_model,
+                scenario.ticks,
+                scenario.dt,
+            );
+            finish(&mut controller, &stateful_model);
+            let summary = Summary::of(&scenario.name, &controller, &stateful_model);
+            let path = batch.stats_path(scenario);
+            match write_file(&path, |writer| {
+                batch::write_summaries(writer, slice::from_ref(&summary))
+            }) {
+                Ok(()) => info!(
+                    "stats of scenario {:?} written to {:?}",
+                    scenario.name, path
+                ),
+                Err(e) => error!("failed to write stats to {:?}: {}", path, e),
+            }
+            summaries.push(summary);
+        } else {
+            follow_root(
+                settings.update_settings,
+                sim_params,
+                world,
+                &mut stateful_model,
+                &stateless_model,
+                None,
+            );
+        }
+        // The other processes leave the batch with the root
+        let mut stop = interrupted();
+        communication::bincode_broadcast(world.rank(), root, &mut stop).unwrap();
+        if stop {
+            if world.rank() == ROOT {
+                warn!(
+                    "interrupted, the scenarios after {:?} are not run",
+                    scenario.name
+                );
+            }
+            break;
+        }
+    }
+    if world.rank() == ROOT {
+        for summary in summaries.iter() {
+            info!("{}", summary_line(summary));
+        }
+        let path = batch.summary_path();
+        match write_file(&path, |writer| batch::write_summaries(writer, &summaries)) {
+            Ok(()) => info!("summary written to {:?}", path),
+            Err(e) => error!("failed to write summary to {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Run `ticks` updates of `dt` seconds without a window on the root process,
+/// or fewer if interrupted, then end the run of the other processes.
+fn run_headless(
+    controller: &mut Controller,
+    world: SystemCommunicator,
+    stateful_model: &mut stateful::Model,
+    stateless_model: &stateless::Model,
+    ticks: usize,
+    dt: f64,
+) {
+    let root = world.process_at_rank(ROOT);
+    let mut info = Info::new();
+    let args = UpdateArgs { dt };
+    for tick in 0..ticks {
+        if interrupted() {
+            warn!("interrupted after {} of {} ticks", tick, ticks);
+            break;
+        }
+        let mut send_args = Some(args);
+        communication::bincode_broadcast(world.rank(), root, &mut send_args).unwrap();
+        controller.update(
+            ROOT,
+            world,
+            &mut info,
+            stateful_model,
+            stateless_model,
+            args,
+        );
+    }
+    communication::bincode_broadcast::<_, Option<UpdateArgs>>(
+        world.rank(),
+        root,
+        &mut Option::None,
+    )
+    .unwrap();
+}
+
+/// Run the simulation twice from the same model with the same seed without
+/// a window, and return whether the final states have the same fingerprint.
+///
+/// The seed of the update is 0 unless set. Other processes than the root
+/// always return true.
+fn self_test_determinism(settings: RunOpt, world: SystemCommunicator) -> bool {
+    let (stateless_model, stateful_model, sim_params) = setup(
+        settings.model_generation_settings,
+        settings.update_settings.sim_params.as_ref(),
+        world,
+    );
+    let mut update_settings = settings.update_settings;
+    update_settings.seed = Some(update_settings.seed.unwrap_or(0));
+    let mut fingerprints = Vec::new();
+    for run in 0..2 {
+        let mut stateful_model = stateful_model.clone();
+        if world.rank() == ROOT {
+            let update_controller =
+                UpdateController::new(update_settings.clone(), sim_params.clone());
+            let mut controller = Controller::new(
+                update_controller,
+                settings.controller_settings.clone(),
+                &stateless_model,
+            );
+            run_headless(
+                &mut controller,
+                world,
+                &mut stateful_model,
+                &stateless_model,
+                settings.self_test_ticks,
+                1.0 / 60.0,
+            );
+            let fingerprint = stateful_model.fingerprint();
+            info!(
+                "run {} of the determinism self test: {:016x}",
+                run, fingerprint
+            );
+            fingerprints.push(fingerprint);
+        } else {
+            follow_root(
+                update_settings.clone(),
+                sim_params.clone(),
+                world,
+                &mut stateful_model,
+                &stateless_model,
+                None,
+            );
+        }
+    }
+    if world.rank() != ROOT {
+        return true;
+    }
+    if fingerprints[0] == fingerprints[1] {
+        info!(
+            "determinism self test passed after {} ticks",
+            settings.self_test_ticks
+        );
+        true
+    } else {
+        error!(
+            "determinism self test failed after {} ticks: {:016x} != {:016x}",
+            settings.self_test_ticks, fingerprints[0], fingerprints[1]
+        );
+        false
+    }
+}
+
+/// A line of the summary of a run for the log.
+fn summary_line(summary: &Summary) -> String {
+    format!(
+        "{}: {:.0} s, {} cars, {} entered, delay {:.1} s, density {:.1}/km, flow {:.0}/h",
+        summary.name,
+        summary.time,
+        summary.cars,
+        summary.entered,
+        summary.control_delay,
+        summary.density,
+        summary.flow
+    )
+}
+
+/// Parse the command line `args`, with the options of the config file
+/// named by `--config`, or else by `env`, before their own. Only `run` reads
+/// a config file.
+///
+/// Returns the settings and the arguments they are parsed from.
+fn parse_args(
+    args: Vec<String>,
+    env: Option<OsString>,
+) -> Result<(MpiTrafficOpt, Vec<String>), structopt::clap::Error> {
+    let settings = MpiTrafficOpt::from_iter_safe(&args)?;
+    let path = match &settings.command {
+        Command::Run(settings) => settings.config.clone().or_else(|| env.map(PathBuf::from)),
+        _ => None,
+    };
+    let path = match path {
+        Some(path) => path,
+        None => return Ok((settings, args)),
+    };
+    let config =
+        Config::load(&path).unwrap_or_else(|e| panic!("failed to load config {:?}: {}", path, e));
+    config
+        .validate()
+        .unwrap_or_else(|e| panic!("invalid config {:?}: {}", path, e));
+    let args = config.under(args);
+    Ok((MpiTrafficOpt::from_iter_safe(&args)?, args))
+}
+
+/// Create a file at `path` and write it with `write`.
+fn write_file<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()
+}
+
 #[derive(StructOpt)]
-#[structopt(name = "mpi-traffic", about = "Simple traffic simulation with MPI.")]
+#[structopt(
+    name = "mpi-traffic",
+    about = "Simple traffic simulation with MPI.",
+    // Options of the command line override the ones of the config file
+    global_settings = &[AppSettings::AllArgsOverrideSelf]
+)]
 struct MpiTrafficOpt {
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Run the simulation in a window.
+    #[structopt(name = "run")]
+    Run(Box<RunOpt>),
+    /// Generate a model, or load one, and save it to a model file.
+    #[structopt(name = "generate")]
+    Generate(Box<GenerateOpt>),
+    /// Convert a model file between RON and bincode, by the extensions of the
+    /// files.
+    #[structopt(name = "convert")]
+    Convert(ConvertOpt),
+    /// Play a replay recorded with --replay-output in a window.
+    #[cfg(feature = "binary-state")]
+    #[structopt(name = "replay")]
+    Replay(Box<ReplayOpt>),
+}
+
+#[derive(StructOpt)]
+struct RunOpt {
+    /// Read default options from this RON file, the options of the command
+    /// line override them. Without it the file is the one named by the
+    /// MPI_TRAFFIC_CONFIG environment variable, if any.
+    #[structopt(name = "config", long = "config", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Run the scenarios of this RON file one after the other without a
+    /// window, instead of the settings of the command line.
+    #[structopt(name = "batch", long = "batch", parse(from_os_str))]
+    pub batch: Option<PathBuf>,
+
+    /// Run the simulation twice from the same model and seed without a
+    /// window, and exit with an error if the final states differ.
+    #[structopt(name = "self-test-determinism", long = "self-test-determinism")]
+    pub self_test_determinism: bool,
+
+    /// Number of updates of each run of the determinism self test.
+    #[structopt(
+        name = "self-test-ticks",
+        long = "self-test-ticks",
+        default_value = "600"
+    )]
+    pub self_test_ticks: usize,
+
+    /// Simulated seconds of a second of the window, kept by sleeping
+    /// between updates and warned about when the updates are too slow.
+    /// Without it the window aims at 60 updates a second and drops updates
+    /// it falls behind on.
+    #[structopt(name = "realtime-factor", long = "realtime-factor")]
+    pub realtime_factor: Option<f64>,
+
+    /// Run a second simulation of the same model next to the first in a
+    /// split window, with these arguments added to the ones of the first,
+    /// such as "--green-wave east:1 --seed 2". Only the update settings and
+    /// the signal and demand options apply to the second run, which is
+    /// stepped with the first.
+    #[structopt(name = "compare", long = "compare")]
+    pub compare: Option<String>,
+
     #[structopt(flatten)]
     pub model_generation_settings: ModelGenerationSettings,
 
     #[structopt(flatten)]
     pub controller_settings: ControllerSettings,
 
+    #[structopt(flatten)]
+    pub update_settings: UpdateSettings,
+
+    #[structopt(flatten)]
+    pub view_settings: ViewSettings,
+}
+
+#[derive(StructOpt)]
+struct GenerateOpt {
+    /// Model file to write.
+    #[structopt(name = "output", parse(from_os_str))]
+    pub output: PathBuf,
+
+    #[structopt(flatten)]
+    pub model_generation_settings: ModelGenerationSettings,
+}
+
+#[derive(StructOpt)]
+struct ConvertOpt {
+    /// Model file to read, in bincode if it ends in `.bin`.
+    #[structopt(name = "input", parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// Model file to write, in bincode if it ends in `.bin`.
+    #[structopt(name = "output", parse(from_os_str))]
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "binary-state")]
+#[derive(StructOpt)]
+struct ReplayOpt {
+    /// Bincode replay file to play, the model has to be the one the replay
+    /// was recorded with.
+    #[structopt(name = "replay", parse(from_os_str))]
+    pub replay: PathBuf,
+
+    /// Frames skipped backward or forward by the left and right arrow keys.
+    #[structopt(
+        name = "replay-seek-frames",
+        long = "replay-seek-frames",
+        default_value = "60"
+    )]
+    pub replay_seek_frames: usize,
+
+    /// Simulated seconds of a second of the window.
+    #[structopt(name = "realtime-factor", long = "realtime-factor")]
+    pub realtime_factor: Option<f64>,
+
+    #[structopt(flatten)]
+    pub model_generation_settings: ModelGenerationSettings,
+
     #[structopt(flatten)]
     pub view_settings: ViewSettings,
 }
@@ -22,8 +22,8 @@ where
     pub fn with_shape(i: I, r: R, (m, n): MatrixShape) -> Self {
         Board {
             intersections: Matrix::with_shape(i, (m, n)),
-            horizontal_roads: Matrix::with_shape(r.clone(), (m, n - 1)),
-            vertical_roads: Matrix::with_shape(r, (m - 1, n)),
+            horizontal_roads: Matrix::with_shape(r.clone(), (m, n.saturating_sub(1))),
+            vertical_roads: Matrix::with_shape(r, (m.saturating_sub(1), n)),
         }
     }
 }
@@ -71,6 +71,7 @@ impl<I, R> Board<I, R> {
             .chain(std::iter::repeat(Vertical).zip(self.vertical_roads.iter_mut()))
     }
 
+    /// Iterate over all roads with their indices, horizontal roads first
     pub fn enumerate_roads(&self) -> impl Iterator<Item = (RoadIndex, (AxisDirection, &R))> {
         use AxisDirection::*;
         self.horizontal_roads
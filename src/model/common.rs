@@ -3,6 +3,7 @@ use rand::{
     Rng,
 };
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 use bitflags::bitflags;
 
@@ -20,6 +21,15 @@ bitflags! {
     }
 }
 
+impl TurnRule {
+    /// In a rule of a signal, the turn across the oncoming traffic is
+    /// protected and does not yield to it, an arrow rather than a permissive
+    /// green. It is not a turn, so it is left out of `all()` and only valid
+    /// in the rules of signals whose oncoming traffic has no green straight
+    /// ahead, never in the rule of a lane.
+    pub const PROTECTED: TurnRule = TurnRule { bits: 0b0001_0000 };
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum AbsoluteDirection {
     North,
@@ -187,6 +197,70 @@ impl LaneDirection {
     }
 }
 
+/// Side of the road traffic keeps to.
+///
+/// The innermost lane of a road is next to the opposite traffic in both, so
+/// the turn across the opposite traffic is left in right-hand traffic and
+/// right in left-hand traffic.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum HandedTraffic {
+    #[default]
+    Right,
+    Left,
+}
+
+impl HandedTraffic {
+    /// Turn across the opposite traffic, made from the innermost lane.
+    pub fn crossing_turn(self) -> TurnRule {
+        match self {
+            HandedTraffic::Right => TurnRule::LEFT,
+            HandedTraffic::Left => TurnRule::RIGHT,
+        }
+    }
+
+    /// Whether cars from `direction` turn across the oncoming traffic on a
+    /// permissive green under `rule`, yielding to the oncoming cars going
+    /// straight, rather than on a protected arrow or not at all.
+    pub fn permissive_crossing(
+        self,
+        rule: &Around<TurnRule>,
+        direction: AbsoluteDirection,
+    ) -> bool {
+        let turns = *rule.get(direction);
+        turns.contains(self.crossing_turn())
+            && !turns.contains(TurnRule::PROTECTED)
+            && rule.get(direction.turn_back()).contains(TurnRule::FRONT)
+    }
+
+    /// Turn toward the side of the road, made from the outermost lane.
+    pub fn kerb_turn(self) -> TurnRule {
+        match self {
+            HandedTraffic::Right => TurnRule::RIGHT,
+            HandedTraffic::Left => TurnRule::LEFT,
+        }
+    }
+
+    /// 1 for right-hand traffic, -1 for mirrored left-hand traffic.
+    pub fn sign(self) -> f64 {
+        match self {
+            HandedTraffic::Right => 1.0,
+            HandedTraffic::Left => -1.0,
+        }
+    }
+}
+
+impl FromStr for HandedTraffic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "right" => Ok(HandedTraffic::Right),
+            "left" => Ok(HandedTraffic::Left),
+            _ => Err(format!("unknown handed traffic: {}", s)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum InOutDirection {
     In,
@@ -267,6 +341,38 @@ impl<T> Around<T> {
             East => &mut self.east,
         }
     }
+
+    /// Pair the values of the same direction
+    pub fn zip<U>(self, other: Around<U>) -> Around<(T, U)> {
+        Around {
+            north: (self.north, other.north),
+            west: (self.west, other.west),
+            south: (self.south, other.south),
+            east: (self.east, other.east),
+        }
+    }
+
+    /// Pair references to the values of the same direction
+    pub fn zip_ref<'a, U>(&'a self, other: &'a Around<U>) -> Around<(&'a T, &'a U)> {
+        Around {
+            north: (&self.north, &other.north),
+            west: (&self.west, &other.west),
+            south: (&self.south, &other.south),
+            east: (&self.east, &other.east),
+        }
+    }
+}
+
+impl Around<TurnRule> {
+    /// Direction whose turn across the oncoming traffic is protected while
+    /// the oncoming traffic has a green straight ahead, which a signal rule
+    /// may not have.
+    pub fn protected_against_green(&self) -> Option<AbsoluteDirection> {
+        AbsoluteDirection::directions().copied().find(|&direction| {
+            self.get(direction).contains(TurnRule::PROTECTED)
+                && self.get(direction.turn_back()).contains(TurnRule::FRONT)
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
@@ -363,4 +469,63 @@ mod test {
             assert_eq!(a.distance(b), result);
         }
     }
+
+    #[test]
+    fn permissive_and_protected_crossing() {
+        let green = Around {
+            north: TurnRule::ALL,
+            west: TurnRule::RIGHT,
+            south: TurnRule::ALL,
+            east: TurnRule::RIGHT,
+        };
+        let traffic = HandedTraffic::Right;
+        assert!(traffic.permissive_crossing(&green, South));
+        // Lefts without oncoming traffic or on an arrow do not yield
+        assert!(!traffic.permissive_crossing(
+            &Around {
+                north: TurnRule::RIGHT,
+                ..green
+            },
+            South
+        ));
+        assert!(!traffic.permissive_crossing(
+            &Around {
+                south: TurnRule::ALL | TurnRule::PROTECTED,
+                ..green
+            },
+            South
+        ));
+        assert!(!traffic.permissive_crossing(&green, West));
+        // Left-hand traffic crosses turning right
+        let lefts_only = Around {
+            south: TurnRule::FRONT | TurnRule::LEFT,
+            ..green
+        };
+        assert!(traffic.permissive_crossing(&lefts_only, South));
+        assert!(!HandedTraffic::Left.permissive_crossing(&lefts_only, South));
+    }
+
+    #[test]
+    fn around_zip() {
+        let directions = Around {
+            north: North,
+            west: West,
+            south: South,
+            east: East,
+        };
+        let numbers = Around {
+            north: 0,
+            west: 1,
+            south: 2,
+            east: 3,
+        };
+        let zipped = directions.zip_ref(&numbers);
+        for (direction, number) in AbsoluteDirection::directions().zip([0, 2, 3, 1].iter()) {
+            assert_eq!(*zipped.get(*direction), (direction, number));
+        }
+        let zipped = directions.zip(numbers);
+        for (direction, number) in AbsoluteDirection::directions().zip([0, 2, 3, 1].iter()) {
+            assert_eq!(*zipped.get(*direction), (*direction, *number));
+        }
+    }
 }
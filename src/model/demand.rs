@@ -0,0 +1,267 @@
+//! Demand of cars entering the city over simulated time.
+//!
+//! A profile in RON lists the rate at some times, such as a rush hour:
+//!
+//! ```text
+//! DemandProfile(points: [(0.0, 0.1), (600.0, 0.5), (900.0, 0.5), (1500.0, 0.1)])
+//! ```
+//!
+//! Where the cars drive to is given by an origin-destination matrix of the
+//! model file, with the weights of the destinations of the cars entering at
+//! an origin. Cars only enter at the car out intersection of the city, which
+//! is the origin of the single row, and leave the city once they arrive at
+//! their destinations:
+//!
+//! ```text
+//! od_matrix: Some(OdMatrix(rows: [(origin: (0, 0), destinations: [((2, 3), 3.0), ((4, 0), 1.0)])])),
+//! ```
+
+use crate::{
+    model::{board::IntersectionIndex, stateless::City},
+    Error,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, path::Path};
+
+/// Piecewise-linear rate of cars entering the city.
+///
+/// The rate is linear between the points and constant before the first
+/// point and after the last one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DemandProfile {
+    /// Seconds of simulated time and cars per second at that time, in
+    /// increasing order of time.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl DemandProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    /// Check the profile has points in increasing order of time with finite
+    /// non-negative rates.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.is_empty() {
+            return Err("demand profile has no point".to_string());
+        }
+        for (time, rate) in self.points.iter() {
+            if !time.is_finite() {
+                return Err(format!("demand profile time {} is not finite", time));
+            }
+            if !(rate.is_finite() && *rate >= 0.0) {
+                return Err(format!(
+                    "demand rate {} at {} is not a non-negative number",
+                    rate, time
+                ));
+            }
+        }
+        match self.points.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            Some(pair) => Err(format!(
+                "demand profile time {} is not after {}",
+                pair[1].0, pair[0].0
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Cars per second at `time`.
+    pub fn rate(&self, time: f64) -> f64 {
+        let index = self.points.partition_point(|(t, _)| *t <= time);
+        match (index.checked_sub(1), self.points.get(index)) {
+            (Some(before), Some(&(t1, r1))) => {
+                let (t0, r0) = self.points[before];
+                r0 + (r1 - r0) * (time - t0) / (t1 - t0)
+            }
+            (Some(before), None) => self.points[before].1,
+            (None, Some(&(_, rate))) => rate,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Expected number of cars entering from `start` to `end`, the integral
+    /// of the rate.
+    pub fn cars_between(&self, start: f64, end: f64) -> f64 {
+        let mut times = vec![start];
+        times.extend(
+            self.points
+                .iter()
+                .map(|(time, _)| *time)
+                .filter(|time| start < *time && *time < end),
+        );
+        times.push(end);
+        times
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]) * (self.rate(pair[0]) + self.rate(pair[1])) / 2.0)
+            .sum()
+    }
+}
+
+/// Origin-destination matrix of the cars entering the city.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OdMatrix {
+    pub rows: Vec<OdRow>,
+}
+
+/// Destinations of the cars entering the city at an origin.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OdRow {
+    /// Intersection where the cars enter the city.
+    pub origin: IntersectionIndex,
+    /// Intersections the cars drive to, with weights relative to each other.
+    pub destinations: Vec<(IntersectionIndex, f64)>,
+}
+
+impl OdMatrix {
+    /// Check every origin and destination is an intersection of `city`, the
+    /// car out intersection is the origin of exactly one row and of no other,
+    /// and the weights of a row are finite, non-negative and not all 0.
+    pub fn validate(&self, city: &City) -> Result<(), String> {
+        if !self
+            .rows
+            .iter()
+            .any(|row| row.origin == city.car_out_intersection)
+        {
+            return Err(format!(
+                "OD matrix has no row for the car out intersection {:?}",
+                city.car_out_intersection
+            ));
+        }
+        let exists =
+            |index: IntersectionIndex| matches!(city.board.intersections.get(index), Some(Some(_)));
+        for (n, row) in self.rows.iter().enumerate() {
+            if !exists(row.origin) {
+                return Err(format!("OD origin {:?} is not an intersection", row.origin));
+            }
+            if row.origin != city.car_out_intersection {
+                return Err(format!(
+                    "OD origin {:?} is not the car out intersection {:?}, no car enters there",
+                    row.origin, city.car_out_intersection
+                ));
+            }
+            if self.rows[..n]
+                .iter()
+                .any(|other| other.origin == row.origin)
+            {
+                return Err(format!("OD origin {:?} has several rows", row.origin));
+            }
+            for (destination, weight) in row.destinations.iter() {
+                if !exists(*destination) {
+                    return Err(format!(
+                        "OD destination {:?} of origin {:?} is not an intersection",
+                        destination, row.origin
+                    ));
+                }
+                if !(weight.is_finite() && *weight >= 0.0) {
+                    return Err(format!(
+                        "OD weight {} of {:?} to {:?} is not a non-negative number",
+                        weight, row.origin, destination
+                    ));
+                }
+            }
+            if !row.destinations.iter().any(|(_, weight)| *weight > 0.0) {
+                return Err(format!("OD origin {:?} has no destination", row.origin));
+            }
+        }
+        Ok(())
+    }
+
+    /// Destination of a car entering at `origin` drawn by the weights of the
+    /// row of `origin`, if the matrix has one.
+    pub fn sample<R: Rng + ?Sized>(
+        &self,
+        origin: IntersectionIndex,
+        rng: &mut R,
+    ) -> Option<IntersectionIndex> {
+        let row = self.rows.iter().find(|row| row.origin == origin)?;
+        row.destinations
+            .choose_weighted(rng, |(_, weight)| *weight)
+            .ok()
+            .map(|(destination, _)| *destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rush_hour() -> DemandProfile {
+        DemandProfile {
+            points: vec![(0.0, 0.1), (100.0, 0.5), (200.0, 0.5), (300.0, 0.1)],
+        }
+    }
+
+    #[test]
+    fn piecewise_linear_rate() {
+        let profile = rush_hour();
+        assert_eq!(profile.validate(), Ok(()));
+        assert_eq!(profile.rate(-10.0), 0.1);
+        assert!((profile.rate(50.0) - 0.3).abs() < 1e-12);
+        assert_eq!(profile.rate(100.0), 0.5);
+        assert_eq!(profile.rate(150.0), 0.5);
+        assert_eq!(profile.rate(1000.0), 0.1);
+        // 0.1 * 10 before the profile, then 30 in the first ramp and 25 on
+        // the peak
+        assert!((profile.cars_between(-10.0, 150.0) - 56.0).abs() < 1e-9);
+        assert_eq!(profile.cars_between(150.0, 150.0), 0.0);
+    }
+
+    #[test]
+    fn invalid_profiles() {
+        assert!(DemandProfile { points: Vec::new() }.validate().is_err());
+        let unordered = DemandProfile {
+            points: vec![(100.0, 0.1), (100.0, 0.2)],
+        };
+        assert!(unordered.validate().is_err());
+        let negative = DemandProfile {
+            points: vec![(0.0, -0.1)],
+        };
+        assert!(negative.validate().is_err());
+    }
+
+    #[test]
+    fn sample_destinations_by_od_weights() {
+        use crate::model::{common::AxisDirection, stateless::CityBuilder};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let city = CityBuilder::new((1, 3))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (0, 1), 1, 1)
+            .build()
+            .unwrap();
+        let matrix = OdMatrix {
+            rows: vec![OdRow {
+                origin: (0, 0),
+                destinations: vec![((0, 1), 1.0), ((0, 2), 3.0)],
+            }],
+        };
+        assert_eq!(matrix.validate(&city), Ok(()));
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(matrix.sample((0, 2), &mut rng), None);
+        let far = (0..1000)
+            .filter(|_| matrix.sample((0, 0), &mut rng) == Some((0, 2)))
+            .count();
+        assert!((700..800).contains(&far), "{}", far);
+
+        let invalid = |rows| OdMatrix { rows }.validate(&city).is_err();
+        let row = |origin, destinations| OdRow {
+            origin,
+            destinations,
+        };
+        assert!(invalid(Vec::new()));
+        assert!(invalid(vec![row((0, 3), vec![((0, 1), 1.0)])]));
+        assert!(invalid(vec![
+            row((0, 0), vec![((0, 1), 1.0)]),
+            row((0, 1), vec![((0, 2), 1.0)]),
+        ]));
+        assert!(invalid(vec![row((0, 0), vec![((1, 0), 1.0)])]));
+        assert!(invalid(vec![row((0, 0), vec![((0, 1), 0.0)])]));
+        assert!(invalid(vec![row((0, 0), vec![((0, 1), f64::NAN)])]));
+        assert!(invalid(vec![
+            row((0, 0), vec![((0, 1), 1.0)]),
+            row((0, 0), vec![((0, 2), 1.0)]),
+        ]));
+    }
+}
@@ -0,0 +1,244 @@
+//! Model files store the stateless part of a model in RON, state files the
+//! stateful part.
+//!
+//! With the `binary-state` feature, model and state files ending in `.bin`
+//! are in bincode instead, and replays record the state after every update as
+//! bincode frames. A replay starts with a header of a magic number, the
+//! version of the format and a hash of the model it was recorded on, and a
+//! replay of another version or model is refused. Each frame is preceded by
+//! its length in bytes as a little endian `u64`, so frames can be found
+//! without decoding the ones before.
+
+use crate::{
+    model::{generate::stateful::generate_from_stateless, stateful, stateless, Model},
+    Error,
+};
+#[cfg(feature = "binary-state")]
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::{fs::File, io::BufReader, path::Path};
+
+pub fn load_stateless_model<P: AsRef<Path>>(path: P) -> Result<stateless::Model, Error> {
+    let file = File::open(&path)?;
+    #[cfg(feature = "binary-state")]
+    {
+        if is_binary(path.as_ref()) {
+            return Ok(bincode::deserialize_from(BufReader::new(file))?);
+        }
+    }
+    Ok(ron::de::from_reader(BufReader::new(file))?)
+}
+
+pub fn save_stateless_model<P: AsRef<Path>>(
+    path: P,
+    model: &stateless::Model,
+) -> Result<(), Error> {
+    #[cfg(feature = "binary-state")]
+    {
+        if is_binary(path.as_ref()) {
+            let mut writer = BufWriter::new(File::create(path)?);
+            bincode::serialize_into(&mut writer, model)?;
+            writer.flush()?;
+            return Ok(());
+        }
+    }
+    let content = ron::ser::to_string_pretty(model, ron::ser::PrettyConfig::new())?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Load a model file, the simulation starts without cars on the board.
+pub fn load_model<P: AsRef<Path>>(path: P) -> Result<Model, Error> {
+    let stateless = load_stateless_model(path)?;
+    stateless.validate().map_err(Error::InvalidModel)?;
+    let stateful = generate_from_stateless(&stateless);
+    Ok(Model {
+        stateless,
+        stateful,
+    })
+}
+
+/// Whether a model or state file is in bincode, by its `.bin` extension.
+#[cfg(feature = "binary-state")]
+fn is_binary(path: &Path) -> bool {
+    path.extension() == Some("bin".as_ref())
+}
+
+pub fn load_state<P: AsRef<Path>>(path: P) -> Result<stateful::Model, Error> {
+    let file = File::open(&path)?;
+    #[cfg(feature = "binary-state")]
+    let mut model: stateful::Model = if is_binary(path.as_ref()) {
+        bincode::deserialize_from(BufReader::new(file))?
+    } else {
+        ron::de::from_reader(BufReader::new(file))?
+    };
+    #[cfg(not(feature = "binary-state"))]
+    let mut model: stateful::Model = ron::de::from_reader(BufReader::new(file))?;
+    model.fix_car_ids();
+    model.lanes = stateful::LaneCars::from_cars(&model.cars);
+    Ok(model)
+}
+
+/// Save the state of a simulation, such as a baseline to diff later runs
+/// against.
+pub fn save_state<P: AsRef<Path>>(path: P, model: &stateful::Model) -> Result<(), Error> {
+    #[cfg(feature = "binary-state")]
+    {
+        if is_binary(path.as_ref()) {
+            let mut writer = BufWriter::new(File::create(path)?);
+            bincode::serialize_into(&mut writer, model)?;
+            writer.flush()?;
+            return Ok(());
+        }
+    }
+    let content = ron::ser::to_string_pretty(model, ron::ser::PrettyConfig::new())?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// First bytes of a replay.
+#[cfg(feature = "binary-state")]
+const REPLAY_MAGIC: [u8; 8] = *b"MPITRPLY";
+/// Version of the format of replays, changed with the format of the frames.
+#[cfg(feature = "binary-state")]
+const REPLAY_VERSION: u32 = 1;
+/// Length of the magic number, the version and the model hash.
+#[cfg(feature = "binary-state")]
+const REPLAY_HEADER_LENGTH: u64 = 8 + 4 + 8;
+
+/// Hash of the bincode of the model a replay is recorded on.
+#[cfg(feature = "binary-state")]
+fn model_hash(model: &stateless::Model) -> Result<u64, Error> {
+    let mut hash = stateful::Fnv1a::new();
+    hash.write(&bincode::serialize(model)?);
+    Ok(hash.finish())
+}
+
+/// Writer of a replay, the header and then the time and the state after
+/// every update as successive bincode frames.
+#[cfg(feature = "binary-state")]
+#[derive(Debug)]
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+}
+
+#[cfg(feature = "binary-state")]
+impl ReplayWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, model: &stateless::Model) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?), model)
+    }
+}
+
+#[cfg(feature = "binary-state")]
+impl<W: Write> ReplayWriter<W> {
+    /// Write the header of a replay recorded on `model`.
+    pub fn new(mut writer: W, model: &stateless::Model) -> Result<Self, Error> {
+        writer.write_all(&REPLAY_MAGIC)?;
+        writer.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        writer.write_all(&model_hash(model)?.to_le_bytes())?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_frame(&mut self, time: f64, model: &stateful::Model) -> Result<(), Error> {
+        let frame = bincode::serialize(&(time, model))?;
+        self.writer.write_all(&(frame.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&frame)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reader of the frames of a replay in any order.
+#[cfg(feature = "binary-state")]
+#[derive(Debug)]
+pub struct ReplayReader<R: Read + Seek> {
+    reader: R,
+    /// Offset of the length of each frame.
+    offsets: Vec<u64>,
+}
+
+#[cfg(feature = "binary-state")]
+impl ReplayReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P, model: &stateless::Model) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?), model)
+    }
+}
+
+#[cfg(feature = "binary-state")]
+impl<R: Read + Seek> ReplayReader<R> {
+    /// Check the header of `reader` is the one of a replay of this version
+    /// recorded on `model`, and index its frames by their lengths.
+    pub fn new(mut reader: R, model: &stateless::Model) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        let mut version = [0; 4];
+        let mut hash = [0; 8];
+        reader.seek(SeekFrom::Start(0))?;
+        let header = reader
+            .read_exact(&mut magic)
+            .and_then(|()| reader.read_exact(&mut version))
+            .and_then(|()| reader.read_exact(&mut hash));
+        if header.is_err() || magic != REPLAY_MAGIC {
+            return Err(Error::InvalidReplay("not a replay".to_string()));
+        }
+        let version = u32::from_le_bytes(version);
+        if version != REPLAY_VERSION {
+            return Err(Error::InvalidReplay(format!(
+                "version {} instead of {}",
+                version, REPLAY_VERSION
+            )));
+        }
+        if u64::from_le_bytes(hash) != model_hash(model)? {
+            return Err(Error::InvalidReplay(
+                "recorded on another model".to_string(),
+            ));
+        }
+        let offsets = frame_offsets(&mut reader, REPLAY_HEADER_LENGTH)?;
+        Ok(Self { reader, offsets })
+    }
+
+    /// Number of frames.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The time and the state of the `index`-th frame.
+    pub fn frame(&mut self, index: usize) -> Result<(f64, stateful::Model), Error> {
+        self.reader.seek(SeekFrom::Start(self.offsets[index]))?;
+        let mut length = [0; 8];
+        self.reader.read_exact(&mut length)?;
+        let mut frame = vec![0; u64::from_le_bytes(length) as usize];
+        self.reader.read_exact(&mut frame)?;
+        let (time, mut model): (f64, stateful::Model) = bincode::deserialize(&frame)?;
+        model.lanes = stateful::LaneCars::from_cars(&model.cars);
+        Ok((time, model))
+    }
+}
+
+/// Offsets of the frames of a replay from `start`, skipping from the length
+/// of a frame to the next one. A frame cut short at the end, as by a run
+/// which did not finish writing it, is left out.
+#[cfg(feature = "binary-state")]
+pub fn frame_offsets<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Vec<u64>> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let mut offsets = Vec::new();
+    let mut offset = start;
+    while offset + 8 <= end {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut length = [0; 8];
+        reader.read_exact(&mut length)?;
+        let next = (offset + 8).saturating_add(u64::from_le_bytes(length));
+        if next > end {
+            break;
+        }
+        offsets.push(offset);
+        offset = next;
+    }
+    Ok(offsets)
+}
@@ -3,19 +3,88 @@ use crate::model::{
         stateful::generate_from_stateless,
         stateless::{generate_stateless_model, StatelessModelGenerationSettings},
     },
+    signals::Corridor,
     Model,
 };
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 pub mod stateful;
 pub mod stateless;
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Clone, Debug)]
 pub struct ModelGenerationSettings {
+    /// Load the model from a RON file instead of generating it.
+    #[structopt(name = "model-file", long = "model-file", parse(from_os_str))]
+    pub model_file: Option<PathBuf>,
+    /// Load the rate of cars entering the city over time from a RON file of
+    /// `DemandProfile`, instead of the profile of the model file.
+    #[structopt(name = "demand-profile", long = "demand-profile", parse(from_os_str))]
+    pub demand_profile: Option<PathBuf>,
+
+    /// Coordinate the signals along a corridor, such as "east:1" for cars
+    /// driving east through the second row of intersections.
+    #[structopt(name = "green-wave", long = "green-wave")]
+    pub green_wave: Option<Corridor>,
+    /// Meters per second of the cars meeting the green wave.
+    #[structopt(
+        name = "green-wave-speed",
+        long = "green-wave-speed",
+        default_value = "10.0"
+    )]
+    pub green_wave_speed: f64,
+
     #[structopt(flatten)]
     pub stateless_model_settings: StatelessModelGenerationSettings,
 }
 
+impl Default for ModelGenerationSettings {
+    fn default() -> Self {
+        Self {
+            model_file: None,
+            demand_profile: None,
+            green_wave: None,
+            green_wave_speed: 10.0,
+            stateless_model_settings: Default::default(),
+        }
+    }
+}
+
+impl ModelGenerationSettings {
+    /// Settings generating a model by `stateless_model_settings`, checked.
+    ///
+    /// ```ignore
+    /// let settings = ModelGenerationSettings::new(StatelessModelGenerationSettings {
+    ///     board_shape_rows: 5,
+    ///     ..Default::default()
+    /// })?;
+    /// let model = generate_model(settings);
+    /// ```
+    pub fn new(stateless_model_settings: StatelessModelGenerationSettings) -> Result<Self, String> {
+        let settings = Self {
+            stateless_model_settings,
+            ..Default::default()
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Check the settings before generating a model, a model loaded from a
+    /// file is not checked.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.green_wave_speed.is_finite() && self.green_wave_speed > 0.0) {
+            return Err(format!(
+                "green wave speed {} is not positive",
+                self.green_wave_speed
+            ));
+        }
+        match self.model_file {
+            Some(_) => Ok(()),
+            None => self.stateless_model_settings.validate(),
+        }
+    }
+}
+
 pub fn generate_model(model_settings: ModelGenerationSettings) -> Model {
     let stateless_model = generate_stateless_model(model_settings.stateless_model_settings);
     let stateful_model = generate_from_stateless(&stateless_model);
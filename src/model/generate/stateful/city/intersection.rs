@@ -8,30 +8,30 @@ pub fn generate_intersection_from_stateless(
 ) -> Intersection {
     let mut result = match stateless_model {
         stateless::Intersection::Crossroad {
-            switch_rule: stateless::intersection::SwitchRule::LoopTimeout { times },
-            ..
+            rules, switch_rule, ..
         } => Intersection::Crossroad {
             current: Default::default(),
-            switch_state: intersection::SwitchState::LoopTimeout {
-                remain_time: times[0],
-                time_index: 0,
-                rule_index: 0,
-            },
+            switch_state: intersection::SwitchState::start(switch_rule, rules.len()),
+            occupancy: Vec::new(),
         },
         stateless::Intersection::TJunction {
-            switch_rule: stateless::intersection::SwitchRule::LoopTimeout { times },
+            rule_set,
+            switch_rule,
             ..
         } => Intersection::TJunction {
             current: Default::default(),
-            switch_state: intersection::SwitchState::LoopTimeout {
-                remain_time: times[0],
-                time_index: 0,
-                rule_index: 0,
-            },
+            switch_state: intersection::SwitchState::start(switch_rule, rule_set.len()),
+            occupancy: Vec::new(),
+        },
+        stateless::Intersection::Turn { .. } => Intersection::Turn {
+            occupancy: Vec::new(),
+        },
+        stateless::Intersection::Straight => Intersection::Straight {
+            occupancy: Vec::new(),
+        },
+        stateless::Intersection::End { .. } => Intersection::End {
+            occupancy: Vec::new(),
         },
-        stateless::Intersection::Turn { .. } => Intersection::Turn,
-        stateless::Intersection::Straight => Intersection::Straight,
-        stateless::Intersection::End { .. } => Intersection::End,
     };
     result.update_current(stateless_model);
     result
@@ -6,6 +6,7 @@ pub mod road;
 pub fn generate_city_from_stateless(stateless_model: &stateless::City) -> City {
     let mut city = City {
         board: Board::with_shape(None, (), stateless_model.board.shape()),
+        signals_failed: false,
     };
     for index in stateless_model.board.intersections.indices() {
         if let Some(stateless_intersection) = &stateless_model.board.intersections[index] {
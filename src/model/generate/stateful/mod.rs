@@ -7,5 +7,9 @@ pub fn generate_from_stateless(stateless_model: &stateless::Model) -> Model {
     Model {
         city: city::generate_city_from_stateless(&stateless_model.city),
         cars: vec![None; car_number],
+        generations: vec![0; car_number],
+        next_car_id: 0,
+        lanes: Default::default(),
+        trails: Default::default(),
     }
 }
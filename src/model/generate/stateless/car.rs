@@ -1,20 +1,31 @@
-use rand::{self, Rng};
+use rand::Rng;
 
 use crate::model::{
     generate::stateless::StatelessModelGenerationSettings,
-    stateless::{car::DrivingModel, Car},
+    stateless::{
+        car::{DrivingModel, VehicleClass},
+        Car,
+    },
 };
 
-pub fn generate_cars(settings: &StatelessModelGenerationSettings) -> Vec<Car> {
+pub fn generate_cars<R: Rng + ?Sized>(
+    settings: &StatelessModelGenerationSettings,
+    rng: &mut R,
+) -> Vec<Car> {
     (0..settings.initial_car_number)
-        .map(|_| generate_car(settings))
+        .map(|_| generate_car(settings, rng))
         .collect()
 }
 
-fn generate_car(settings: &StatelessModelGenerationSettings) -> Car {
-    let mut rng = rand::thread_rng();
+fn generate_car<R: Rng + ?Sized>(settings: &StatelessModelGenerationSettings, rng: &mut R) -> Car {
+    let class = VehicleClass::Car;
     Car {
+        class,
+        length: class.length(),
+        width: class.width(),
         max_velocity: rng.gen_range(settings.min_max_velocity..=settings.max_max_velocity),
+        desired_speed_factor: rng
+            .gen_range(settings.min_desired_speed_factor..=settings.max_desired_speed_factor),
         max_acceleration: rng
             .gen_range(settings.min_max_acceleration..=settings.max_max_acceleration),
         max_break_acceleration: rng
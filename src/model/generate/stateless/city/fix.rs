@@ -1,6 +1,9 @@
 use crate::model::{
     board::{Board, IntersectionContext},
-    common::{AbsoluteDirection, InOutDirection, LaneDirection, RelativeDirection, TurnRule},
+    common::{
+        AbsoluteDirection, HandedTraffic, InOutDirection, LaneDirection, RelativeDirection,
+        TurnRule,
+    },
     generate::stateless::{
         city::{intersection::is_turn_intersection, road::basic_lane},
         StatelessModelGenerationSettings,
@@ -14,7 +17,7 @@ pub fn fix(
     city_settings: &StatelessModelGenerationSettings,
 ) {
     fix_intersection_connectivity(board, city_settings);
-    fix_lane_direction_rule(board);
+    fix_lane_direction_rule(board, city_settings.handed_traffic);
 }
 
 fn fix_intersection_connectivity(
@@ -91,26 +94,33 @@ fn add_lane(
         .push(basic_lane(lane_max_speed));
 }
 
-fn fix_lane_direction_rule(board: &mut Board<Option<Intersection>, Option<Road>>) {
-    fix_lane_direction_rule_by_road(board);
+/// Restrict turn rules of lanes to the turns their lane position and the
+/// roads of the next intersection allow.
+pub(crate) fn fix_lane_direction_rule(
+    board: &mut Board<Option<Intersection>, Option<Road>>,
+    handed_traffic: HandedTraffic,
+) {
+    fix_lane_direction_rule_by_road(board, handed_traffic);
     fix_lane_direction_rule_by_context(board);
 }
 
-fn fix_lane_direction_rule_by_road(board: &mut Board<Option<Intersection>, Option<Road>>) {
+fn fix_lane_direction_rule_by_road(
+    board: &mut Board<Option<Intersection>, Option<Road>>,
+    handed_traffic: HandedTraffic,
+) {
     for road in board.roads_mut().filter_map(|(_, road)| road.as_mut()) {
         LaneDirection::directions().for_each(|&lane_direction| {
             let lanes = road.lanes_to_direction_mut(lane_direction);
             let len = lanes.len();
             if len > 1 {
-                // only last lane can turn right
+                // only last lane can turn toward the side of the road
                 lanes.iter_mut().take(len - 1).for_each(|lane| {
-                    lane.direction_rule -= TurnRule::RIGHT;
+                    lane.direction_rule -= handed_traffic.kerb_turn();
+                });
+                // only first lane can turn across the opposite traffic and back
+                lanes.iter_mut().skip(1).for_each(|lane| {
+                    lane.direction_rule -= handed_traffic.crossing_turn() | TurnRule::BACK
                 });
-                // only first len can turn left and back
-                lanes
-                    .iter_mut()
-                    .skip(1)
-                    .for_each(|lane| lane.direction_rule -= TurnRule::LEFT | TurnRule::BACK);
             }
         });
         LaneDirection::directions().for_each(|&lane_direction| {
@@ -118,7 +128,10 @@ fn fix_lane_direction_rule_by_road(board: &mut Board<Option<Intersection>, Optio
                 .lanes_to_direction(lane_direction.opposite())
                 .is_empty()
             {
-                road.lanes_to_direction_mut(lane_direction)[0].direction_rule -= TurnRule::BACK;
+                // The other direction of a one-way road has no lane either
+                if let Some(lane) = road.lanes_to_direction_mut(lane_direction).first_mut() {
+                    lane.direction_rule -= TurnRule::BACK;
+                }
             }
         })
     }
@@ -168,8 +181,7 @@ fn fix_lane_rule_with_context(
 
     for &&direction in directions_with_in_road.iter() {
         let lanes_to_be_fix = find_lanes_to_be_fix(direction, board, context);
-        let len = lanes_to_be_fix.len();
-        if len == 0 {
+        if lanes_to_be_fix.is_empty() {
             continue;
         }
         for &&direction_without_out_lane in directions_without_out_lanes.iter() {
@@ -182,8 +194,14 @@ fn fix_lane_rule_with_context(
                     );
                     lane.direction_rule -= TurnRule::FRONT;
                 }),
-                Right => lanes_to_be_fix[0].direction_rule -= TurnRule::LEFT,
-                Left => lanes_to_be_fix[len - 1].direction_rule -= TurnRule::RIGHT,
+                // Only one lane is left with each turn, whichever side the
+                // traffic keeps to
+                Right => lanes_to_be_fix
+                    .iter_mut()
+                    .for_each(|lane| lane.direction_rule -= TurnRule::LEFT),
+                Left => lanes_to_be_fix
+                    .iter_mut()
+                    .for_each(|lane| lane.direction_rule -= TurnRule::RIGHT),
             }
         }
     }
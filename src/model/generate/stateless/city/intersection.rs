@@ -18,22 +18,27 @@ pub fn generate_intersections(
     for index in board.intersections.indices() {
         let context = board.context_of_intersection(index);
         if context.road_number() != 0 {
-            board.intersections[index] = Some(generate_with_context(&context, settings));
+            board.intersections[index] = Some(generate_with_context(
+                &context,
+                settings.intersection_max_speed,
+                settings.time_out,
+            ));
         }
     }
 }
 
-fn generate_with_context(
+/// Intersection fitting the roads around it, with signals switching every
+/// `time_out` seconds at T-junctions and crossroads.
+pub fn generate_with_context(
     context: &IntersectionContext,
-    settings: &StatelessModelGenerationSettings,
+    max_speed: f64,
+    time_out: f64,
 ) -> Intersection {
     match context.road_number() {
-        1 => Intersection::End {
-            max_speed: settings.intersection_max_speed,
-        },
-        2 => generate_with_2_road(context, settings),
-        3 => generate_with_3_road(context, settings),
-        4 => generate_with_4_road(settings),
+        1 => Intersection::End { max_speed },
+        2 => generate_with_2_road(context, max_speed),
+        3 => generate_with_3_road(context, max_speed, time_out),
+        4 => generate_with_4_road(max_speed, time_out),
         _ => unreachable!(),
     }
 }
@@ -48,14 +53,9 @@ pub fn is_turn_intersection(context: &IntersectionContext) -> bool {
     }
 }
 
-fn generate_with_2_road(
-    context: &IntersectionContext,
-    settings: &StatelessModelGenerationSettings,
-) -> Intersection {
+fn generate_with_2_road(context: &IntersectionContext, max_speed: f64) -> Intersection {
     if is_turn_intersection(context) {
-        Intersection::Turn {
-            max_speed: settings.intersection_max_speed,
-        }
+        Intersection::Turn { max_speed }
     } else {
         Intersection::Straight
     }
@@ -63,7 +63,8 @@ fn generate_with_2_road(
 
 fn generate_with_3_road(
     context: &IntersectionContext,
-    settings: &StatelessModelGenerationSettings,
+    max_speed: f64,
+    time_out: f64,
 ) -> Intersection {
     let single = AbsoluteDirection::directions()
         .find(|&&direction| context.get(direction).is_none())
@@ -87,18 +88,19 @@ fn generate_with_3_road(
         },
     ];
     let switch_rule = SwitchRule::LoopTimeout {
-        times: vec![settings.time_out],
+        times: vec![time_out],
+        offset: 0.0,
     };
 
     Intersection::TJunction {
-        max_speed: settings.intersection_max_speed,
+        max_speed,
         single,
         rule_set,
         switch_rule,
     }
 }
 
-fn generate_with_4_road(settings: &StatelessModelGenerationSettings) -> Intersection {
+fn generate_with_4_road(max_speed: f64, time_out: f64) -> Intersection {
     let rules = vec![
         CrossroadRule {
             north: TurnRule::FRONT | TurnRule::RIGHT | TurnRule::BACK,
@@ -126,10 +128,11 @@ fn generate_with_4_road(settings: &StatelessModelGenerationSettings) -> Intersec
         },
     ];
     let switch_rule = SwitchRule::LoopTimeout {
-        times: vec![settings.time_out],
+        times: vec![time_out],
+        offset: 0.0,
     };
     Intersection::Crossroad {
-        max_speed: settings.intersection_max_speed,
+        max_speed,
         rules,
         switch_rule,
     }
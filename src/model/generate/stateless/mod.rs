@@ -1,5 +1,8 @@
-use crate::model::stateless::Model;
-
+use crate::model::{
+    common::HandedTraffic,
+    stateless::{car::VehicleClass, Model},
+};
+use rand::{rngs::StdRng, SeedableRng};
 use structopt::StructOpt;
 
 pub mod car;
@@ -32,12 +35,21 @@ pub struct StatelessModelGenerationSettings {
         long = "stateless-model-generation-max-road-length"
     )]
     pub max_road_length: f64,
+    /// Width of every lane in meters, at least the width of a car.
     #[structopt(
         name = "stateless-model-generation-lane-width",
         default_value = "3.5",
         long = "stateless-model-generation-lane-width"
     )]
     pub lane_width: f64,
+    /// Size of intersections relative to the widest roads they join, above
+    /// 1 for room to turn and below 1 for tighter junctions.
+    #[structopt(
+        name = "stateless-model-generation-intersection-size-factor",
+        default_value = "1.0",
+        long = "stateless-model-generation-intersection-size-factor"
+    )]
+    pub intersection_size_factor: f64,
     #[structopt(
         name = "stateless-model-generation-initial-car-number",
         default_value = "20",
@@ -56,12 +68,25 @@ pub struct StatelessModelGenerationSettings {
         long = "stateless-model-generation-max-max-velocity"
     )]
     pub max_max_velocity: f64,
+    /// Range of the desired speeds of drivers relative to the speed limit.
+    #[structopt(
+        name = "stateless-model-generation-min-desired-speed-factor",
+        default_value = "0.85",
+        long = "stateless-model-generation-min-desired-speed-factor"
+    )]
+    pub min_desired_speed_factor: f64,
+    #[structopt(
+        name = "stateless-model-generation-max-desired-speed-factor",
+        default_value = "1.15",
+        long = "stateless-model-generation-max-desired-speed-factor"
+    )]
+    pub max_desired_speed_factor: f64,
     #[structopt(
         name = "stateless-model-generation-min-max-acceleration",
         default_value = "3.0",
         long = "stateless-model-generation-min-max-acceleration"
     )]
-    min_max_acceleration: f64,
+    pub min_max_acceleration: f64,
     #[structopt(
         name = "stateless-model-generation-max-max-acceleration",
         default_value = "10.0",
@@ -177,11 +202,226 @@ pub struct StatelessModelGenerationSettings {
         long = "stateless-model-generation-car-out-min-distance"
     )]
     pub car_out_min_distance: f64,
+    /// "right" or "left", the side of the road traffic keeps to.
+    #[structopt(
+        name = "stateless-model-generation-handed-traffic",
+        default_value = "right",
+        long = "stateless-model-generation-handed-traffic"
+    )]
+    pub handed_traffic: HandedTraffic,
+    /// Seed of the random choices of the generation, the same seed and
+    /// settings generate the same model. Random without it.
+    #[structopt(
+        name = "stateless-model-generation-seed",
+        long = "stateless-model-generation-seed"
+    )]
+    pub seed: Option<u64>,
+}
+
+impl Default for StatelessModelGenerationSettings {
+    /// The defaults of the command line.
+    fn default() -> Self {
+        Self::from_iter(&["stateless-model-generation"])
+    }
+}
+
+impl StatelessModelGenerationSettings {
+    /// Check the settings generate a city with at least one road and cars
+    /// from non-empty ranges.
+    pub fn validate(&self) -> Result<(), String> {
+        let (rows, cols) = (self.board_shape_rows, self.board_shape_cols);
+        if rows == 0 || cols == 0 || rows * cols < 2 {
+            return Err(format!(
+                "board of {}x{} intersections has no room for a road",
+                rows, cols
+            ));
+        }
+        check_positive("lane width", self.lane_width)?;
+        // Vehicles on neighbouring lanes would overlap
+        let max_width = VehicleClass::max_width();
+        if self.lane_width < max_width {
+            return Err(format!(
+                "lane width {} is narrower than a vehicle of {}",
+                self.lane_width, max_width
+            ));
+        }
+        check_positive("intersection size factor", self.intersection_size_factor)?;
+        check_positive("lane max speed", self.lane_max_speed)?;
+        check_positive("intersection max speed", self.intersection_max_speed)?;
+        check_positive("signal time out", self.time_out)?;
+        check_positive("min road length", self.min_road_length)?;
+        check_range("road length", self.min_road_length, self.max_road_length)?;
+        check_range("max velocity", self.min_max_velocity, self.max_max_velocity)?;
+        check_positive("min desired speed factor", self.min_desired_speed_factor)?;
+        check_range(
+            "desired speed factor",
+            self.min_desired_speed_factor,
+            self.max_desired_speed_factor,
+        )?;
+        check_range(
+            "max acceleration",
+            self.min_max_acceleration,
+            self.max_max_acceleration,
+        )?;
+        check_range(
+            "max break acceleration",
+            self.min_max_break_acceleration,
+            self.max_max_break_acceleration,
+        )?;
+        check_range(
+            "lane change time",
+            self.min_lane_change_time,
+            self.max_lane_change_time,
+        )?;
+        check_range(
+            "cushion velocity factor",
+            self.min_cushion_velocity_factor,
+            self.max_cushion_velocity_factor,
+        )?;
+        check_non_negative("min cushion", self.min_cushion)?;
+        check_non_negative("prediction time", self.prediction_time)?;
+        check_non_negative("car out min distance", self.car_out_min_distance)?;
+        if self.default_lane_num == 0
+            || self.one_way_lane_num == 0
+            || self.straight_long_way_lane_num == 0
+        {
+            return Err("roads need at least one lane".to_string());
+        }
+        check_proportion(
+            "straight long way proportion",
+            self.straight_long_way_proportion,
+        )?;
+        check_proportion("one way proportion", self.one_way_proportion)?;
+        check_proportion("empty proportion", self.empty_proportion)?;
+        if self.empty_proportion >= 1.0 {
+            return Err(format!(
+                "empty proportion {} removes every road",
+                self.empty_proportion
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn check_positive(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{} {} is not positive", name, value))
+    }
+}
+
+fn check_non_negative(name: &str, value: f64) -> Result<(), String> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{} {} is negative or not finite", name, value))
+    }
+}
+
+/// Check `min..=max` is a non-empty range of finite non-negative numbers.
+fn check_range(name: &str, min: f64, max: f64) -> Result<(), String> {
+    if min.is_finite() && max.is_finite() && 0.0 <= min && min <= max {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} range {}..={} is empty, negative or not finite",
+            name, min, max
+        ))
+    }
+}
+
+fn check_proportion(name: &str, value: f64) -> Result<(), String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{} {} is not in [0, 1]", name, value))
+    }
 }
 
 pub fn generate_stateless_model(settings: StatelessModelGenerationSettings) -> Model {
+    let mut rng = match settings.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
     Model {
-        city: city::generate_city(&settings),
-        cars: car::generate_cars(&settings),
+        city: city::generate_city(&settings, &mut rng),
+        cars: car::generate_cars(&settings, &mut rng),
+        detectors: Vec::new(),
+        demand: None,
+        od_matrix: None,
+        zones: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_settings() {
+        assert_eq!(
+            StatelessModelGenerationSettings::default().validate(),
+            Ok(())
+        );
+        let invalid = [
+            StatelessModelGenerationSettings {
+                board_shape_rows: 1,
+                board_shape_cols: 1,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                lane_width: 0.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                lane_width: 1.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                lane_width: 2.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                intersection_size_factor: 0.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                min_max_velocity: 200.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                min_cushion: -1.0,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                prediction_time: f64::INFINITY,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                one_way_proportion: 1.5,
+                ..Default::default()
+            },
+            StatelessModelGenerationSettings {
+                empty_proportion: f64::NAN,
+                ..Default::default()
+            },
+        ];
+        for settings in invalid.iter() {
+            assert!(settings.validate().is_err(), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn seeded_generation_repeats() {
+        let generate = |seed| {
+            let model = generate_stateless_model(StatelessModelGenerationSettings {
+                seed: Some(seed),
+                ..Default::default()
+            });
+            format!("{:?}", model)
+        };
+        assert_eq!(generate(3), generate(3));
+        assert_ne!(generate(3), generate(4));
     }
 }
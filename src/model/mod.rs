@@ -1,6 +1,10 @@
 pub mod board;
 pub mod common;
+pub mod demand;
+pub mod file;
 pub mod generate;
+pub mod routing;
+pub mod signals;
 pub mod stateful;
 pub mod stateless;
 
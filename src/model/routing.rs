@@ -0,0 +1,443 @@
+//! Shortest paths between intersections over the road network.
+//!
+//! A route is the sequence of directed road segments a car drives through.
+//! Segment costs default to the distance between the centers of the two
+//! intersections a segment joins.
+
+use crate::{
+    model::{
+        board::{IntersectionIndex, RoadIndex},
+        common::{AbsoluteDirection, AxisDirection, InOutDirection, LaneDirection},
+        stateless,
+    },
+    util::matrix::Matrix,
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::BinaryHeap, str::FromStr};
+
+/// One direction of a road.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub road_direction: AxisDirection,
+    pub road_index: RoadIndex,
+    pub lane_direction: LaneDirection,
+}
+
+pub type Route = Vec<Segment>;
+
+impl Segment {
+    /// The intersection the segment leads to.
+    pub fn to_intersection(&self, city: &stateless::City) -> IntersectionIndex {
+        city.board.lane_to_intersection_index(
+            self.road_direction,
+            self.road_index,
+            self.lane_direction,
+        )
+    }
+
+    /// The intersection the segment leaves from.
+    pub fn from_intersection(&self, city: &stateless::City) -> IntersectionIndex {
+        city.board.lane_to_intersection_index(
+            self.road_direction,
+            self.road_index,
+            self.lane_direction.opposite(),
+        )
+    }
+
+    /// Heading of the cars driving on the segment.
+    pub fn heading(&self) -> AbsoluteDirection {
+        AbsoluteDirection::of_lane(self.road_direction, self.lane_direction)
+    }
+
+    pub fn length(&self, city: &stateless::City) -> f64 {
+        city.intersection_center(self.from_intersection(city))
+            .distance(city.intersection_center(self.to_intersection(city)))
+    }
+}
+
+/// Segments leaving an intersection which have at least one lane.
+pub fn out_segments(
+    city: &stateless::City,
+    intersection_index: IntersectionIndex,
+) -> impl Iterator<Item = Segment> + '_ {
+    let context = city.board.context_of_intersection(intersection_index);
+    AbsoluteDirection::directions().filter_map(move |direction| {
+        let road_index = (*context.get(*direction))?;
+        let road_direction = direction.axis_direction();
+        let lane_direction =
+            LaneDirection::absolute_in_out_to_lane(*direction, InOutDirection::Out);
+        let road = city.board.get_road(road_direction, road_index)?.as_ref()?;
+        if road.lanes_to_direction(lane_direction).is_empty() {
+            None
+        } else {
+            Some(Segment {
+                road_direction,
+                road_index,
+                lane_direction,
+            })
+        }
+    })
+}
+
+/// Strongly connected components of the intersections with roads, over
+/// the directed road segments.
+///
+/// Cars can drive between any two intersections of a component, routes
+/// between components fail in at least one direction. The intersections of
+/// a component are in row-major order, and the components are ordered by
+/// their first intersections.
+pub fn components(city: &stateless::City) -> Vec<Vec<IntersectionIndex>> {
+    let shape = city.board.shape();
+    let intersections = city
+        .board
+        .intersections
+        .indices()
+        .filter(|index| city.board.context_of_intersection(*index).road_number() > 0)
+        .collect::<Vec<_>>();
+    let mut in_neighbors: Matrix<Vec<IntersectionIndex>> = Matrix::with_shape(Vec::new(), shape);
+    for from in intersections.iter() {
+        for segment in out_segments(city, *from) {
+            in_neighbors[segment.to_intersection(city)].push(*from);
+        }
+    }
+    // Intersections in the order a depth-first search finishes them
+    let mut visited = Matrix::with_shape(false, shape);
+    let mut finished = Vec::new();
+    for start in intersections.iter() {
+        if visited[*start] {
+            continue;
+        }
+        visited[*start] = true;
+        let mut stack = vec![(*start, out_segments(city, *start).collect::<Vec<_>>())];
+        while let Some((index, segments)) = stack.last_mut() {
+            match segments.pop() {
+                Some(segment) => {
+                    let next = segment.to_intersection(city);
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push((next, out_segments(city, next).collect()));
+                    }
+                }
+                None => {
+                    finished.push(*index);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    // Each search backwards from the last finished intersection left covers
+    // one component
+    let mut assigned = Matrix::with_shape(false, shape);
+    let mut components = Vec::new();
+    for start in finished.iter().rev() {
+        if assigned[*start] {
+            continue;
+        }
+        assigned[*start] = true;
+        let mut component = Vec::new();
+        let mut stack = vec![*start];
+        while let Some(index) = stack.pop() {
+            component.push(index);
+            for previous in in_neighbors[index].iter() {
+                if !assigned[*previous] {
+                    assigned[*previous] = true;
+                    stack.push(*previous);
+                }
+            }
+        }
+        component.sort_unstable();
+        components.push(component);
+    }
+    components.sort_unstable();
+    components
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RoutingAlgorithm {
+    Dijkstra,
+    AStar,
+}
+
+impl RoutingAlgorithm {
+    pub fn route(
+        self,
+        city: &stateless::City,
+        from: IntersectionIndex,
+        to: IntersectionIndex,
+    ) -> Option<Route> {
+        match self {
+            RoutingAlgorithm::Dijkstra => dijkstra(city, from, to),
+            RoutingAlgorithm::AStar => a_star(city, from, to),
+        }
+    }
+
+    /// Route with custom segment weights.
+    ///
+    /// `min_cost_per_meter` must not exceed the weight of any segment divided
+    /// by its length, otherwise A* may miss the optimal route.
+    pub fn route_by<W>(
+        self,
+        city: &stateless::City,
+        from: IntersectionIndex,
+        to: IntersectionIndex,
+        weight: W,
+        min_cost_per_meter: f64,
+    ) -> Option<Route>
+    where
+        W: Fn(&Segment) -> f64,
+    {
+        match self {
+            RoutingAlgorithm::Dijkstra => search(city, from, to, weight, |_| 0.0).0,
+            RoutingAlgorithm::AStar => {
+                let heuristic = grid_distance_heuristic(city, to, min_cost_per_meter);
+                search(city, from, to, weight, heuristic).0
+            }
+        }
+    }
+}
+
+impl FromStr for RoutingAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dijkstra" => Ok(RoutingAlgorithm::Dijkstra),
+            "a-star" => Ok(RoutingAlgorithm::AStar),
+            _ => Err(format!("unknown routing algorithm: {}", s)),
+        }
+    }
+}
+
+pub fn dijkstra(
+    city: &stateless::City,
+    from: IntersectionIndex,
+    to: IntersectionIndex,
+) -> Option<Route> {
+    search(city, from, to, |segment| segment.length(city), |_| 0.0).0
+}
+
+/// A* search with the grid distance between intersection centers as the
+/// heuristic, which never overestimates the segment lengths.
+pub fn a_star(
+    city: &stateless::City,
+    from: IntersectionIndex,
+    to: IntersectionIndex,
+) -> Option<Route> {
+    search(
+        city,
+        from,
+        to,
+        |segment| segment.length(city),
+        grid_distance_heuristic(city, to, 1.0),
+    )
+    .0
+}
+
+/// Grid distance to `to`, scaled by the minimum cost of a meter.
+pub fn grid_distance_heuristic(
+    city: &stateless::City,
+    to: IntersectionIndex,
+    min_cost_per_meter: f64,
+) -> impl Fn(IntersectionIndex) -> f64 + '_ {
+    let target = city.intersection_center(to);
+    move |index| {
+        let center = city.intersection_center(index);
+        ((center.x - target.x).abs() + (center.y - target.y).abs()) * min_cost_per_meter
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Candidate {
+    priority: f64,
+    cost: f64,
+    index: IntersectionIndex,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed for a min heap
+        other
+            .priority
+            .partial_cmp(&self.priority)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Best first search with custom segment weights and heuristic. Segments of
+/// infinite weight are never driven through.
+///
+/// Return the route and the number of expanded intersections.
+pub fn search<W, H>(
+    city: &stateless::City,
+    from: IntersectionIndex,
+    to: IntersectionIndex,
+    weight: W,
+    heuristic: H,
+) -> (Option<Route>, usize)
+where
+    W: Fn(&Segment) -> f64,
+    H: Fn(IntersectionIndex) -> f64,
+{
+    let shape = city.board.shape();
+    let mut costs = Matrix::with_shape(f64::INFINITY, shape);
+    let mut previous: Matrix<Option<Segment>> = Matrix::with_shape(None, shape);
+    let mut closed = Matrix::with_shape(false, shape);
+    let mut heap = BinaryHeap::new();
+    let mut expanded = 0;
+    costs[from] = 0.0;
+    heap.push(Candidate {
+        priority: heuristic(from),
+        cost: 0.0,
+        index: from,
+    });
+    while let Some(Candidate { cost, index, .. }) = heap.pop() {
+        if closed[index] {
+            continue;
+        }
+        closed[index] = true;
+        expanded += 1;
+        if index == to {
+            break;
+        }
+        for segment in out_segments(city, index) {
+            let next = segment.to_intersection(city);
+            let next_cost = cost + weight(&segment);
+            if next_cost < costs[next] {
+                costs[next] = next_cost;
+                previous[next] = Some(segment);
+                heap.push(Candidate {
+                    priority: next_cost + heuristic(next),
+                    cost: next_cost,
+                    index: next,
+                });
+            }
+        }
+    }
+    if !closed[to] {
+        return (None, expanded);
+    }
+    let mut route = Vec::new();
+    let mut index = to;
+    while let Some(segment) = previous[index] {
+        route.push(segment);
+        index = segment.from_intersection(city);
+    }
+    route.reverse();
+    (Some(route), expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{board::Board, common::TurnRule};
+
+    fn grid_city(shape: (usize, usize)) -> stateless::City {
+        let lane = stateless::Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = stateless::Road {
+            lane_to_high: vec![lane.clone()],
+            lane_to_low: vec![lane],
+        };
+        let (m, n) = shape;
+        stateless::City {
+            board: Board::with_shape(None, Some(road), shape),
+            car_out_intersection: (0, 0),
+            car_out_min_distance: 8.0,
+            lane_width: 3.5,
+            horizontal_road_length: vec![100.0; n - 1],
+            vertical_road_length: vec![100.0; m - 1],
+            intersection_height: vec![20.0; m],
+            intersection_width: vec![20.0; n],
+            handed_traffic: Default::default(),
+        }
+    }
+
+    fn route_length(city: &stateless::City, route: &[Segment]) -> f64 {
+        route.iter().map(|segment| segment.length(city)).sum()
+    }
+
+    #[test]
+    fn a_star_is_optimal_and_expands_less() {
+        let city = grid_city((6, 6));
+        let (from, to) = ((0, 0), (5, 4));
+        let distance = |segment: &Segment| segment.length(&city);
+        let (dijkstra_route, dijkstra_expanded) = search(&city, from, to, distance, |_| 0.0);
+        let (a_star_route, a_star_expanded) = search(
+            &city,
+            from,
+            to,
+            distance,
+            grid_distance_heuristic(&city, to, 1.0),
+        );
+        let dijkstra_route = dijkstra_route.unwrap();
+        let a_star_route = a_star_route.unwrap();
+        assert_eq!(route_length(&city, &dijkstra_route), 1080.0);
+        assert_eq!(
+            route_length(&city, &dijkstra_route),
+            route_length(&city, &a_star_route)
+        );
+        assert!(a_star_expanded < dijkstra_expanded);
+        assert_eq!(a_star_route[0].from_intersection(&city), from);
+        assert_eq!(a_star_route.last().unwrap().to_intersection(&city), to);
+    }
+
+    #[test]
+    fn no_route_to_isolated_intersection() {
+        let mut city = grid_city((2, 2));
+        city.board.horizontal_roads[(1, 0)] = None;
+        city.board.vertical_roads[(0, 1)] = None;
+        assert_eq!(dijkstra(&city, (0, 0), (1, 1)), None);
+        assert_eq!(a_star(&city, (0, 0), (1, 1)), None);
+    }
+
+    #[test]
+    fn routes_avoid_infinite_segments() {
+        let city = grid_city((2, 2));
+        let closed = |segment: &Segment| {
+            if segment.road_direction == AxisDirection::Horizontal && segment.road_index == (0, 0) {
+                f64::INFINITY
+            } else {
+                segment.length(&city)
+            }
+        };
+        let (route, _) = search(&city, (0, 0), (0, 1), closed, |_| 0.0);
+        assert_eq!(route.unwrap().len(), 3);
+        let (route, _) = search(&city, (0, 0), (0, 1), |_| f64::INFINITY, |_| 0.0);
+        assert_eq!(route, None);
+    }
+
+    #[test]
+    fn components_of_one_way_roads() {
+        let mut city = grid_city((2, 2));
+        assert_eq!(
+            components(&city),
+            vec![vec![(0, 0), (0, 1), (1, 0), (1, 1)]]
+        );
+        // (0, 0) can only be left toward (0, 1)
+        city.board.vertical_roads[(0, 0)] = None;
+        city.board.horizontal_roads[(0, 0)]
+            .as_mut()
+            .unwrap()
+            .lane_to_low
+            .clear();
+        assert_eq!(
+            components(&city),
+            vec![vec![(0, 0)], vec![(0, 1), (1, 0), (1, 1)]]
+        );
+        // Intersections without roads are left out
+        city.board.horizontal_roads[(0, 0)] = None;
+        assert_eq!(components(&city), vec![vec![(0, 1), (1, 0), (1, 1)]]);
+    }
+}
@@ -0,0 +1,325 @@
+//! Coordination of signal plans.
+
+use crate::model::{
+    board::IntersectionIndex,
+    common::{AbsoluteDirection, AxisDirection, InOutDirection, LaneDirection, TurnRule},
+    stateful::{self, intersection::SwitchState},
+    stateless::{self, intersection::SwitchRule},
+    Model,
+};
+use std::str::FromStr;
+
+/// A row or a column of intersections, driven through toward `heading`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Corridor {
+    pub heading: AbsoluteDirection,
+    /// Row of a corridor heading east or west, column of one heading north or
+    /// south.
+    pub line: usize,
+}
+
+impl Corridor {
+    /// Intersections of the corridor in the order cars pass them, an error
+    /// if the line is outside the board.
+    pub fn intersections(&self, city: &stateless::City) -> Result<Vec<IntersectionIndex>, String> {
+        let (m, n) = city.board.shape();
+        let line = self.line;
+        let (lines, mut indices): (usize, Vec<IntersectionIndex>) =
+            match self.heading.axis_direction() {
+                AxisDirection::Horizontal => (m, (0..n).map(|j| (line, j)).collect()),
+                AxisDirection::Vertical => (n, (0..m).map(|i| (i, line)).collect()),
+            };
+        if line >= lines {
+            return Err(format!(
+                "line {} of corridor is outside the board of {} lines",
+                line, lines
+            ));
+        }
+        if let AbsoluteDirection::West | AbsoluteDirection::North = self.heading {
+            indices.reverse();
+        }
+        Ok(indices)
+    }
+}
+
+impl FromStr for Corridor {
+    type Err = String;
+
+    /// Parse `<heading>:<line>`, such as `east:1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let heading = match parts.next() {
+            Some("north") => AbsoluteDirection::North,
+            Some("south") => AbsoluteDirection::South,
+            Some("east") => AbsoluteDirection::East,
+            Some("west") => AbsoluteDirection::West,
+            _ => return Err(format!("unknown heading of corridor: {}", s)),
+        };
+        let line = parts
+            .next()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| format!("no line of corridor: {}", s))?;
+        Ok(Corridor { heading, line })
+    }
+}
+
+/// Offsets of the signals along `corridor` which let a car driving at
+/// `speed` meet the start of a green for going straight at every signal.
+///
+/// Intersections without a signal, or whose plan never lets the corridor go
+/// straight, are skipped. A corridor outside the board is an error.
+pub fn green_wave(
+    city: &stateless::City,
+    corridor: Corridor,
+    speed: f64,
+) -> Result<Vec<(IntersectionIndex, f64)>, String> {
+    let intersections = corridor.intersections(city)?;
+    let origin = match intersections.first() {
+        Some(index) => city.intersection_center(*index),
+        None => return Ok(Vec::new()),
+    };
+    let from_direction = corridor.heading.turn_back();
+    let offsets = intersections
+        .into_iter()
+        .filter_map(|index| {
+            let intersection = city.board.intersections[index].as_ref()?;
+            let SwitchRule::LoopTimeout { times, .. } = intersection.switch_rule()?;
+            let green_index = (0..intersection.rule_number()).find(|rule_index| {
+                intersection
+                    .rule(*rule_index)
+                    .unwrap()
+                    .get(from_direction)
+                    .contains(TurnRule::FRONT)
+            })?;
+            let green_start = (0..green_index)
+                .map(|phase| times[phase % times.len()])
+                .sum::<f64>();
+            let arrival = city.intersection_center(index).distance(origin) / speed;
+            Some((index, green_start - arrival))
+        })
+        .collect();
+    Ok(offsets)
+}
+
+/// Cars per hour a lane passes through an intersection while its way is
+/// green, the saturation flow.
+pub const SATURATION_FLOW: f64 = 1800.0;
+
+/// Theoretical cars per hour entering an intersection, the saturation flow
+/// of each incoming lane times the fraction of the plan of the signal in
+/// which the lane may go in at least one of its turns.
+///
+/// Lanes of intersections without a signal may always go, exit lanes never
+/// go.
+pub fn capacity(city: &stateless::City, intersection_index: IntersectionIndex) -> f64 {
+    let intersection = match city.board.intersections[intersection_index].as_ref() {
+        Some(intersection) => intersection,
+        None => return 0.0,
+    };
+    // Seconds of each rule of the signal over as many loops of the rules as
+    // there are times, which is a whole number of cycles
+    let phases = match intersection.switch_rule() {
+        Some(SwitchRule::LoopTimeout { times, .. }) if !times.is_empty() => {
+            let rule_number = intersection.rule_number();
+            (0..rule_number * times.len())
+                .map(|phase| {
+                    (
+                        intersection.rule(phase % rule_number).unwrap(),
+                        times[phase % times.len()],
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+        _ => Vec::new(),
+    };
+    let cycle = phases.iter().map(|(_, time)| time).sum::<f64>();
+    let context = city.board.context_of_intersection(intersection_index);
+    let mut capacity = 0.0;
+    for &direction in AbsoluteDirection::directions() {
+        let road = match context.get(direction).and_then(|road_index| {
+            city.board
+                .get_road(direction.axis_direction(), road_index)?
+                .as_ref()
+        }) {
+            Some(road) => road,
+            None => continue,
+        };
+        let lane_direction = LaneDirection::absolute_in_out_to_lane(direction, InOutDirection::In);
+        for lane in road.lanes_to_direction(lane_direction).iter() {
+            let green_ratio = if lane.direction_rule.is_empty() {
+                0.0
+            } else if cycle > 0.0 {
+                phases
+                    .iter()
+                    .filter(|(rule, _)| rule.get(direction).intersects(lane.direction_rule))
+                    .map(|(_, time)| time)
+                    .sum::<f64>()
+                    / cycle
+            } else {
+                1.0
+            };
+            capacity += SATURATION_FLOW * green_ratio;
+        }
+    }
+    capacity
+}
+
+/// Set the offsets of signals in the model and restart their plans.
+pub fn apply_offsets(model: &mut Model, offsets: &[(IntersectionIndex, f64)]) {
+    for (index, offset) in offsets.iter() {
+        let stateless_intersection = match model.stateless.city.board.intersections[*index].as_mut()
+        {
+            Some(intersection) => intersection,
+            None => continue,
+        };
+        match stateless_intersection.switch_rule_mut() {
+            Some(SwitchRule::LoopTimeout {
+                offset: rule_offset,
+                ..
+            }) => *rule_offset = *offset,
+            None => continue,
+        }
+        let stateless_intersection = &*stateless_intersection;
+        if let Some(stateful_intersection) =
+            model.stateful.city.board.intersections[*index].as_mut()
+        {
+            match stateful_intersection {
+                stateful::Intersection::Crossroad { switch_state, .. }
+                | stateful::Intersection::TJunction { switch_state, .. } => {
+                    *switch_state = SwitchState::start(
+                        stateless_intersection.switch_rule().unwrap(),
+                        stateless_intersection.rule_number(),
+                    );
+                }
+                _ => (),
+            }
+            stateful_intersection.update_current(stateless_intersection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{board::Board, common::Around};
+
+    fn crossroad() -> stateless::Intersection {
+        let go = TurnRule::FRONT | TurnRule::RIGHT;
+        let stop = TurnRule::empty();
+        stateless::Intersection::Crossroad {
+            max_speed: 10.0,
+            rules: vec![
+                Around {
+                    north: go,
+                    south: go,
+                    west: stop,
+                    east: stop,
+                },
+                Around {
+                    north: stop,
+                    south: stop,
+                    west: go,
+                    east: go,
+                },
+            ],
+            switch_rule: SwitchRule::LoopTimeout {
+                times: vec![10.0, 20.0],
+                offset: 0.0,
+            },
+        }
+    }
+
+    fn row_city() -> stateless::City {
+        let mut board = Board::with_shape(Some(crossroad()), None, (1, 4));
+        board.intersections[(0, 3)] = None;
+        stateless::City {
+            board,
+            car_out_intersection: (0, 0),
+            car_out_min_distance: 8.0,
+            lane_width: 3.5,
+            horizontal_road_length: vec![100.0; 3],
+            vertical_road_length: Vec::new(),
+            intersection_height: vec![20.0],
+            intersection_width: vec![20.0; 4],
+            handed_traffic: Default::default(),
+        }
+    }
+
+    #[test]
+    fn parse_corridor() {
+        assert_eq!(
+            "west:2".parse::<Corridor>(),
+            Ok(Corridor {
+                heading: AbsoluteDirection::West,
+                line: 2,
+            })
+        );
+        assert!("up:2".parse::<Corridor>().is_err());
+        assert!("east".parse::<Corridor>().is_err());
+    }
+
+    #[test]
+    fn capacity_of_green_ratios() {
+        use crate::model::{common::AxisDirection, stateless::CityBuilder};
+        let mut city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 2, 2)
+            .road(AxisDirection::Vertical, (1, 1), 2, 2)
+            .build()
+            .unwrap();
+        city.board.intersections[(1, 1)] = Some(crossroad());
+        let lanes_go = |city: &stateless::City, direction_rule| {
+            let mut city = city.clone();
+            for road in city.board.vertical_roads.iter_mut().flatten() {
+                for lane in road
+                    .lane_to_high
+                    .iter_mut()
+                    .chain(road.lane_to_low.iter_mut())
+                {
+                    lane.direction_rule = direction_rule;
+                }
+            }
+            city
+        };
+        // 4 lanes from the north and the south go for 10 of 30 seconds, the
+        // lanes from the east and the west for 20
+        let city = lanes_go(&city, TurnRule::FRONT);
+        let expected = SATURATION_FLOW * (4.0 * 10.0 + 2.0 * 20.0) / 30.0;
+        assert!((capacity(&city, (1, 1)) - expected).abs() < 1e-9);
+        // Lanes turning left only never go
+        let city = lanes_go(&city, TurnRule::LEFT);
+        let expected = SATURATION_FLOW * 2.0 * 20.0 / 30.0;
+        assert!((capacity(&city, (1, 1)) - expected).abs() < 1e-9);
+        assert_eq!(capacity(&city, (0, 0)), 0.0);
+    }
+
+    #[test]
+    fn offsets_of_green_wave() {
+        let city = row_city();
+        let corridor = Corridor {
+            heading: AbsoluteDirection::East,
+            line: 0,
+        };
+        // Green for the corridor starts 10 seconds into the plans, cars arrive
+        // every 12 seconds
+        assert_eq!(
+            green_wave(&city, corridor, 10.0),
+            Ok(vec![((0, 0), 10.0), ((0, 1), -2.0), ((0, 2), -14.0)])
+        );
+        let corridor = Corridor {
+            heading: AbsoluteDirection::West,
+            line: 0,
+        };
+        // The missing intersection is the origin of the corridor
+        assert_eq!(
+            green_wave(&city, corridor, 10.0),
+            Ok(vec![((0, 2), -2.0), ((0, 1), -14.0), ((0, 0), -26.0)])
+        );
+        // Rows and columns outside the board
+        for corridor in &["east:1", "south:4", "north:99"] {
+            let corridor = corridor.parse::<Corridor>().unwrap();
+            assert!(green_wave(&city, corridor, 10.0).is_err(), "{:?}", corridor);
+        }
+    }
+}
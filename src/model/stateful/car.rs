@@ -1,17 +1,46 @@
 use crate::model::{
     board::{IntersectionIndex, RoadIndex},
-    common::{AbsoluteDirection, AxisDirection, LaneDirection, LaneIndex, RelativeDirection},
+    common::{
+        AbsoluteDirection, AxisDirection, InOutDirection, LaneDirection, LaneIndex, Position,
+        RelativeDirection,
+    },
+    routing::Route,
+    stateless,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Car {
     pub location: Location,
     pub velocity: f64,
     pub acceleration: f64,
+    /// Recent perceptions of the objects in front of the car, oldest first.
+    ///
+    /// Only recorded when drivers react with a delay.
+    pub perception: VecDeque<Perception>,
+    /// Seconds until a crashed car is removed, the car stands still until
+    /// then.
+    #[serde(default)]
+    pub crashed: Option<f64>,
+    /// Identifier of the car in the run, never reused, unlike the index of
+    /// its slot. Taken from `Model::next_car_id` when the car is created.
+    #[serde(default)]
+    pub id: u64,
+    /// Intersection the car drives to, cars without one turn randomly.
+    pub destination: Option<IntersectionIndex>,
+    /// Segments to drive through after the current one.
+    pub route: Route,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Perception {
+    pub time: f64,
+    /// Distance and velocity of every object in front of the car.
+    pub objects: Vec<(f64, f64)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Location {
     OnLane {
         road_direction: AxisDirection,
@@ -28,7 +57,8 @@ pub enum Location {
         from_lane_index: LaneIndex,
         to_lane_index: LaneIndex,
         position: f64,
-        /// Position in lane changing.
+        /// Fraction of the duration of the lane change done, the car moves
+        /// sideways by `lane_change_easing` of it.
         lane_changed_proportion: f64,
     },
     InIntersection {
@@ -41,3 +71,119 @@ pub enum Location {
         position: f64,
     },
 }
+
+impl Location {
+    /// Absolute position of the car in the city
+    pub fn city_position(&self, city: &stateless::City) -> Option<Position> {
+        match *self {
+            Location::OnLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                lane_index,
+                position,
+                ..
+            } => {
+                let center =
+                    city.lane_center(road_direction, road_index, lane_direction, lane_index)?;
+                Some(along_lane(
+                    city,
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    center,
+                    position,
+                ))
+            }
+            Location::ChangingLane {
+                road_direction,
+                road_index,
+                lane_direction,
+                from_lane_index,
+                to_lane_index,
+                position,
+                lane_changed_proportion,
+            } => {
+                let from =
+                    city.lane_center(road_direction, road_index, lane_direction, from_lane_index)?;
+                let to =
+                    city.lane_center(road_direction, road_index, lane_direction, to_lane_index)?;
+                let lateral = lane_change_easing(lane_changed_proportion);
+                let center = Position {
+                    x: from.x + (to.x - from.x) * lateral,
+                    y: from.y + (to.y - from.y) * lateral,
+                };
+                Some(along_lane(
+                    city,
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    center,
+                    position,
+                ))
+            }
+            Location::InIntersection {
+                intersection_index,
+                from_direction,
+                from_lane_index,
+                to_direction,
+                to_lane_index,
+                total_length,
+                position,
+            } => {
+                let from = city.intersection_road_join_position(
+                    intersection_index,
+                    from_direction,
+                    InOutDirection::In,
+                    from_lane_index,
+                )?;
+                let to = city.intersection_road_join_position(
+                    intersection_index,
+                    to_direction,
+                    InOutDirection::Out,
+                    to_lane_index,
+                )?;
+                let center = city.intersection_center(intersection_index);
+                let proportion = position / total_length;
+                Some(Position {
+                    x: center.x + from.x + (to.x - from.x) * proportion,
+                    y: center.y + from.y + (to.y - from.y) * proportion,
+                })
+            }
+        }
+    }
+}
+
+/// The point `position` meters along a lane of a road from its start, the
+/// center of the lane at the middle of the road being `center`.
+fn along_lane(
+    city: &stateless::City,
+    road_direction: AxisDirection,
+    road_index: RoadIndex,
+    lane_direction: LaneDirection,
+    center: Position,
+    position: f64,
+) -> Position {
+    let x = -city.road_length(road_direction, road_index) / 2.0 + position;
+    let offset = match lane_direction {
+        LaneDirection::LowToHigh => x,
+        LaneDirection::HighToLow => -x,
+    };
+    match road_direction {
+        AxisDirection::Horizontal => Position {
+            x: center.x + offset,
+            y: center.y,
+        },
+        AxisDirection::Vertical => Position {
+            x: center.x,
+            y: center.y + offset,
+        },
+    }
+}
+
+/// Fraction of the sideways move of a lane change done after `proportion`
+/// of its duration, easing in and out of the maneuver.
+pub fn lane_change_easing(proportion: f64) -> f64 {
+    let t = proportion.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
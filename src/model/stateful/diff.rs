@@ -0,0 +1,399 @@
+//! Differences between two states of a simulation, to find where runs
+//! diverge.
+
+use crate::model::{
+    board::IntersectionIndex,
+    common::CarIndex,
+    stateful::{car::Location, Car, Intersection, Model},
+};
+use std::fmt;
+
+/// Largest differences of values which still count as equal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    /// Meters of positions along lanes and intersections.
+    pub position: f64,
+    /// Meters per second.
+    pub velocity: f64,
+    /// Seconds remaining in signal phases.
+    pub time: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            position: 1e-6,
+            velocity: 1e-6,
+            time: 1e-6,
+        }
+    }
+}
+
+/// A difference between a base state and another state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// The states have different numbers of car slots.
+    CarNumber {
+        base: usize,
+        other: usize,
+    },
+    /// The car is in the city in only one of the states.
+    CarPresence {
+        car_index: CarIndex,
+        in_base: bool,
+    },
+    /// The car is on different lanes or in different intersections, its
+    /// positions are left out.
+    CarPlace {
+        car_index: CarIndex,
+        base: Location,
+        other: Location,
+    },
+    /// Meters the car of the other state is ahead of the car of the base
+    /// state at the same place.
+    CarPosition {
+        car_index: CarIndex,
+        delta: f64,
+    },
+    CarVelocity {
+        car_index: CarIndex,
+        delta: f64,
+    },
+    /// The intersection has a signal in only one of the states.
+    Signal {
+        intersection_index: IntersectionIndex,
+        in_base: bool,
+    },
+    /// The signal is in different phases.
+    SignalPhase {
+        intersection_index: IntersectionIndex,
+        base: usize,
+        other: usize,
+    },
+    /// Seconds more remaining in the phase of the other state.
+    SignalTime {
+        intersection_index: IntersectionIndex,
+        delta: f64,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = |in_base: bool| if in_base { "base" } else { "other" };
+        match self {
+            Difference::CarNumber { base, other } => {
+                write!(f, "{} car slots in base, {} in other", base, other)
+            }
+            Difference::CarPresence { car_index, in_base } => {
+                write!(f, "car {} only in {}", car_index, state(*in_base))
+            }
+            Difference::CarPlace {
+                car_index,
+                base,
+                other,
+            } => write!(
+                f,
+                "car {} at {:?} in base, at {:?} in other",
+                car_index, base, other
+            ),
+            Difference::CarPosition { car_index, delta } => {
+                write!(f, "car {} position differs by {:+.6} m", car_index, delta)
+            }
+            Difference::CarVelocity { car_index, delta } => {
+                write!(f, "car {} velocity differs by {:+.6} m/s", car_index, delta)
+            }
+            Difference::Signal {
+                intersection_index,
+                in_base,
+            } => write!(
+                f,
+                "signal of intersection {:?} only in {}",
+                intersection_index,
+                state(*in_base)
+            ),
+            Difference::SignalPhase {
+                intersection_index,
+                base,
+                other,
+            } => write!(
+                f,
+                "signal of intersection {:?} in phase {} in base, {} in other",
+                intersection_index, base, other
+            ),
+            Difference::SignalTime {
+                intersection_index,
+                delta,
+            } => write!(
+                f,
+                "signal of intersection {:?} remaining time differs by {:+.6} s",
+                intersection_index, delta
+            ),
+        }
+    }
+}
+
+/// Differences of cars by index, then of signals by intersection.
+///
+/// The report of `Display` lists the first difference on its own line, so
+/// the first divergence is easy to find in long reports.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub fn first(&self) -> Option<&Difference> {
+        self.differences.first()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.first() {
+            None => writeln!(f, "no differences"),
+            Some(first) => {
+                writeln!(
+                    f,
+                    "{} differences, first: {}",
+                    self.differences.len(),
+                    first
+                )?;
+                for difference in self.differences.iter() {
+                    writeln!(f, "  {}", difference)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Model {
+    /// Differences of `other` from this state beyond `tolerance`.
+    ///
+    /// Progress of lane changes is not compared.
+    pub fn diff(&self, other: &Model, tolerance: Tolerance) -> StateDiff {
+        let mut differences = Vec::new();
+        if self.cars.len() != other.cars.len() {
+            differences.push(Difference::CarNumber {
+                base: self.cars.len(),
+                other: other.cars.len(),
+            });
+        }
+        for (car_index, (base, other)) in self.cars.iter().zip(other.cars.iter()).enumerate() {
+            match (base, other) {
+                (Some(base), Some(other)) => {
+                    diff_car(car_index, base, other, tolerance, &mut differences)
+                }
+                (Some(_), None) | (None, Some(_)) => differences.push(Difference::CarPresence {
+                    car_index,
+                    in_base: base.is_some(),
+                }),
+                (None, None) => (),
+            }
+        }
+        for ((intersection_index, base), other) in self
+            .city
+            .board
+            .intersections
+            .enumerate()
+            .zip(other.city.board.intersections.iter())
+        {
+            let base = base.as_ref().and_then(Intersection::switch_state);
+            let other = other.as_ref().and_then(Intersection::switch_state);
+            match (base, other) {
+                (Some(base), Some(other)) => {
+                    let (base_phase, other_phase) = (base.rule_index(), other.rule_index());
+                    if base_phase != other_phase {
+                        differences.push(Difference::SignalPhase {
+                            intersection_index,
+                            base: base_phase,
+                            other: other_phase,
+                        });
+                        continue;
+                    }
+                    let delta = other.remain_time() - base.remain_time();
+                    if beyond(delta, tolerance.time) {
+                        differences.push(Difference::SignalTime {
+                            intersection_index,
+                            delta,
+                        });
+                    }
+                }
+                (Some(_), None) | (None, Some(_)) => differences.push(Difference::Signal {
+                    intersection_index,
+                    in_base: base.is_some(),
+                }),
+                (None, None) => (),
+            }
+        }
+        StateDiff { differences }
+    }
+}
+
+fn diff_car(
+    car_index: CarIndex,
+    base: &Car,
+    other: &Car,
+    tolerance: Tolerance,
+    differences: &mut Vec<Difference>,
+) {
+    let (base_place, base_position) = place_and_position(&base.location);
+    let (other_place, other_position) = place_and_position(&other.location);
+    if base_place != other_place {
+        differences.push(Difference::CarPlace {
+            car_index,
+            base: base_place,
+            other: other_place,
+        });
+    } else {
+        let delta = other_position - base_position;
+        if beyond(delta, tolerance.position) {
+            differences.push(Difference::CarPosition { car_index, delta });
+        }
+    }
+    let delta = other.velocity - base.velocity;
+    if beyond(delta, tolerance.velocity) {
+        differences.push(Difference::CarVelocity { car_index, delta });
+    }
+}
+
+/// Whether a difference is beyond a tolerance, differences with NaN are.
+fn beyond(delta: f64, tolerance: f64) -> bool {
+    delta.is_nan() || delta.abs() > tolerance
+}
+
+/// The location without its continuous parts, and the position.
+fn place_and_position(location: &Location) -> (Location, f64) {
+    let mut place = location.clone();
+    let position = match &mut place {
+        Location::OnLane { position, .. } | Location::InIntersection { position, .. } => {
+            std::mem::take(position)
+        }
+        Location::ChangingLane {
+            position,
+            lane_changed_proportion,
+            ..
+        } => {
+            *lane_changed_proportion = 0.0;
+            std::mem::take(position)
+        }
+    };
+    (place, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        board::Board,
+        common::{Around, TurnRule},
+        stateful::{
+            intersection::SwitchState,
+            testing::{car_at, on_lane},
+            City,
+        },
+    };
+
+    fn signal(rule_index: usize, remain_time: f64) -> Option<Intersection> {
+        Some(Intersection::Crossroad {
+            current: Around {
+                north: TurnRule::all(),
+                south: TurnRule::all(),
+                east: TurnRule::empty(),
+                west: TurnRule::empty(),
+            },
+            switch_state: SwitchState::LoopTimeout {
+                remain_time,
+                time_index: rule_index,
+                rule_index,
+            },
+            occupancy: Vec::new(),
+        })
+    }
+
+    fn model(cars: Vec<Option<Car>>, signal_state: (usize, f64)) -> Model {
+        let mut board = Board::with_shape(None, (), (1, 2));
+        board.intersections[(0, 1)] = signal(signal_state.0, signal_state.1);
+        Model {
+            city: City {
+                board,
+                signals_failed: false,
+            },
+            cars,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn diff_states() {
+        let base = model(
+            vec![
+                car_at(on_lane(0, 10.0), 5.0),
+                None,
+                car_at(on_lane(0, 30.0), 5.0),
+            ],
+            (0, 4.0),
+        );
+        assert!(base.diff(&base, Tolerance::default()).is_empty());
+
+        let other = model(
+            vec![
+                car_at(on_lane(0, 10.5), 5.0),
+                car_at(on_lane(0, 0.0), 0.0),
+                car_at(on_lane(1, 30.0), 5.0),
+            ],
+            (0, 3.5),
+        );
+        let diff = base.diff(&other, Tolerance::default());
+        assert_eq!(
+            diff.differences,
+            vec![
+                Difference::CarPosition {
+                    car_index: 0,
+                    delta: 0.5,
+                },
+                Difference::CarPresence {
+                    car_index: 1,
+                    in_base: false,
+                },
+                Difference::CarPlace {
+                    car_index: 2,
+                    base: place_and_position(&base.cars[2].as_ref().unwrap().location).0,
+                    other: place_and_position(&other.cars[2].as_ref().unwrap().location).0,
+                },
+                Difference::SignalTime {
+                    intersection_index: (0, 1),
+                    delta: -0.5,
+                },
+            ]
+        );
+        assert!(diff
+            .to_string()
+            .starts_with("4 differences, first: car 0 position differs by +0.500000 m\n"));
+
+        let tolerance = Tolerance {
+            position: 1.0,
+            ..Default::default()
+        };
+        let other = model(vec![car_at(on_lane(0, 10.5), 6.0), None], (1, 4.0));
+        assert_eq!(
+            base.diff(&other, tolerance).differences,
+            vec![
+                Difference::CarNumber { base: 3, other: 2 },
+                Difference::CarVelocity {
+                    car_index: 0,
+                    delta: 1.0,
+                },
+                Difference::SignalPhase {
+                    intersection_index: (0, 1),
+                    base: 0,
+                    other: 1,
+                },
+            ]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::model::{
     common::{Around, TurnRule},
-    stateless,
+    stateless::{self, Movement},
 };
 use serde::{Deserialize, Serialize};
 
@@ -9,14 +9,22 @@ pub enum Intersection {
     Crossroad {
         current: Around<TurnRule>,
         switch_state: SwitchState,
+        occupancy: Vec<Movement>,
     },
     TJunction {
         current: Around<TurnRule>,
         switch_state: SwitchState,
+        occupancy: Vec<Movement>,
+    },
+    Turn {
+        occupancy: Vec<Movement>,
+    },
+    Straight {
+        occupancy: Vec<Movement>,
+    },
+    End {
+        occupancy: Vec<Movement>,
     },
-    Turn,
-    Straight,
-    End,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -28,37 +36,191 @@ pub enum SwitchState {
     },
 }
 
-impl Intersection {
-    pub fn update_current(&mut self, stateless: &stateless::Intersection) {
-        match (self, stateless) {
+impl SwitchState {
+    /// Index of the current rule.
+    pub fn rule_index(&self) -> usize {
+        match self {
+            SwitchState::LoopTimeout { rule_index, .. } => *rule_index,
+        }
+    }
+
+    /// Seconds until the current rule switches.
+    pub fn remain_time(&self) -> f64 {
+        match self {
+            SwitchState::LoopTimeout { remain_time, .. } => *remain_time,
+        }
+    }
+
+    /// Seconds since the current rule started under `switch_rule`.
+    pub fn elapsed(&self, switch_rule: &stateless::intersection::SwitchRule) -> f64 {
+        match (self, switch_rule) {
             (
-                Intersection::Crossroad {
-                    current,
-                    switch_state: SwitchState::LoopTimeout { rule_index, .. },
+                SwitchState::LoopTimeout {
+                    remain_time,
+                    time_index,
+                    ..
                 },
-                stateless::Intersection::Crossroad { rules, .. },
-            ) => {
-                *current = rules[*rule_index];
+                stateless::intersection::SwitchRule::LoopTimeout { times, .. },
+            ) => times[*time_index] - remain_time,
+        }
+    }
+
+    /// State of a plan with `rule_number` rules after its offset.
+    pub fn start(switch_rule: &stateless::intersection::SwitchRule, rule_number: usize) -> Self {
+        match switch_rule {
+            stateless::intersection::SwitchRule::LoopTimeout { times, offset } => {
+                let mut state = SwitchState::LoopTimeout {
+                    remain_time: times[0],
+                    time_index: 0,
+                    rule_index: 0,
+                };
+                let cycle = times.iter().sum::<f64>();
+                if cycle > 0.0 {
+                    state.advance(offset.rem_euclid(cycle), switch_rule, rule_number);
+                }
+                state
             }
+        }
+    }
+
+    /// Advance the plan by `dt` seconds, switching rules on timeouts.
+    pub fn advance(
+        &mut self,
+        dt: f64,
+        switch_rule: &stateless::intersection::SwitchRule,
+        rule_number: usize,
+    ) {
+        match (self, switch_rule) {
             (
-                Intersection::TJunction {
-                    current,
-                    switch_state: SwitchState::LoopTimeout { rule_index, .. },
-                },
-                stateless::Intersection::TJunction {
-                    single, rule_set, ..
+                SwitchState::LoopTimeout {
+                    remain_time,
+                    time_index,
+                    rule_index,
                 },
+                stateless::intersection::SwitchRule::LoopTimeout { times, .. },
             ) => {
-                let rule = &rule_set[*rule_index];
-                *current.get_mut(*single) = rule.for_single;
-                let driver_direction = single.turn_back();
-                *current.get_mut(driver_direction.turn_left()) = rule.for_left;
-                *current.get_mut(driver_direction.turn_right()) = rule.for_right;
-                *current.get_mut(single.turn_back()) = TurnRule::empty();
+                *remain_time -= dt;
+                while *remain_time < 0.0 {
+                    // Timeout
+                    *time_index += 1;
+                    *time_index %= times.len();
+                    *rule_index += 1;
+                    *rule_index %= rule_number;
+                    *remain_time += times[*time_index]; // Set new timeout
+                }
+            }
+        }
+    }
+}
+
+impl Intersection {
+    /// State of the signal, if any.
+    pub fn switch_state(&self) -> Option<&SwitchState> {
+        match self {
+            Intersection::Crossroad { switch_state, .. }
+            | Intersection::TJunction { switch_state, .. } => Some(switch_state),
+            _ => None,
+        }
+    }
+
+    /// Turns allowed by the signal from each approach, if any.
+    pub fn current(&self) -> Option<&Around<TurnRule>> {
+        match self {
+            Intersection::Crossroad { current, .. } | Intersection::TJunction { current, .. } => {
+                Some(current)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Around<TurnRule>> {
+        match self {
+            Intersection::Crossroad { current, .. } | Intersection::TJunction { current, .. } => {
+                Some(current)
+            }
+            _ => None,
+        }
+    }
+
+    /// Movements of the cars currently in the intersection.
+    pub fn occupancy(&self) -> &Vec<Movement> {
+        match self {
+            Intersection::Crossroad { occupancy, .. } => occupancy,
+            Intersection::TJunction { occupancy, .. } => occupancy,
+            Intersection::Turn { occupancy } => occupancy,
+            Intersection::Straight { occupancy } => occupancy,
+            Intersection::End { occupancy } => occupancy,
+        }
+    }
+
+    pub fn occupancy_mut(&mut self) -> &mut Vec<Movement> {
+        match self {
+            Intersection::Crossroad { occupancy, .. } => occupancy,
+            Intersection::TJunction { occupancy, .. } => occupancy,
+            Intersection::Turn { occupancy } => occupancy,
+            Intersection::Straight { occupancy } => occupancy,
+            Intersection::End { occupancy } => occupancy,
+        }
+    }
+
+    pub fn update_current(&mut self, stateless: &stateless::Intersection) {
+        match self {
+            Intersection::Crossroad {
+                current,
+                switch_state: SwitchState::LoopTimeout { rule_index, .. },
+                ..
+            }
+            | Intersection::TJunction {
+                current,
+                switch_state: SwitchState::LoopTimeout { rule_index, .. },
+                ..
+            } => {
+                *current = stateless
+                    .rule(*rule_index)
+                    .expect("signal of intersection without rules");
             }
-            (Intersection::Crossroad { .. }, _) => unreachable!(),
-            (Intersection::TJunction { .. }, _) => unreachable!(),
             _ => (), // no need to update current
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stateless::intersection::SwitchRule;
+
+    fn state_after_offset(offset: f64) -> (f64, usize, usize) {
+        let switch_rule = SwitchRule::LoopTimeout {
+            times: vec![10.0, 20.0],
+            offset,
+        };
+        match SwitchState::start(&switch_rule, 2) {
+            SwitchState::LoopTimeout {
+                remain_time,
+                time_index,
+                rule_index,
+            } => (remain_time, time_index, rule_index),
+        }
+    }
+
+    #[test]
+    fn start_after_offset() {
+        assert_eq!(state_after_offset(0.0), (10.0, 0, 0));
+        assert_eq!(state_after_offset(15.0), (15.0, 1, 1));
+        assert_eq!(state_after_offset(35.0), (5.0, 0, 0));
+        assert_eq!(state_after_offset(-5.0), (5.0, 1, 1));
+    }
+
+    #[test]
+    fn elapsed_in_phase() {
+        let switch_rule = SwitchRule::LoopTimeout {
+            times: vec![10.0, 20.0],
+            offset: 15.0,
+        };
+        let mut state = SwitchState::start(&switch_rule, 2);
+        assert_eq!(state.elapsed(&switch_rule), 5.0);
+        state.advance(16.0, &switch_rule, 2);
+        assert_eq!(state.rule_index(), 0);
+        assert_eq!(state.elapsed(&switch_rule), 1.0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Cars on each lane in driving order, kept up to date as cars move.
+
+use crate::model::{
+    board::RoadIndex,
+    common::{AxisDirection, CarIndex, LaneDirection, LaneIndex},
+    stateful::{car::Location, Car},
+};
+use std::{cmp::Ordering, collections::BTreeMap};
+
+pub type LaneKey = (AxisDirection, RoadIndex, LaneDirection, LaneIndex);
+
+/// Positions and indices of the cars on each lane, sorted by position, then
+/// by index.
+///
+/// Cars changing lane are on both lanes, cars in intersections are on none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LaneCars {
+    lanes: BTreeMap<LaneKey, Vec<(f64, CarIndex)>>,
+}
+
+impl LaneCars {
+    pub fn from_cars(cars: &[Option<Car>]) -> Self {
+        let mut lane_cars = Self::default();
+        for (car_index, car) in cars.iter().enumerate() {
+            for (key, position) in places(car) {
+                lane_cars
+                    .lanes
+                    .entry(key)
+                    .or_default()
+                    .push((position, car_index));
+            }
+        }
+        for cars in lane_cars.lanes.values_mut() {
+            cars.sort_by(driving_order);
+        }
+        lane_cars
+    }
+
+    /// Lanes with cars and their cars from the back to the front.
+    pub fn iter(&self) -> impl Iterator<Item = (LaneKey, &[(f64, CarIndex)])> {
+        self.lanes.iter().map(|(key, cars)| (*key, cars.as_slice()))
+    }
+
+    /// Cars on the lane from the back to the front.
+    pub fn cars(&self, key: LaneKey) -> &[(f64, CarIndex)] {
+        self.lanes.get(&key).map_or(&[], Vec::as_slice)
+    }
+
+    /// The car right in front of the car at `position` on the lane.
+    pub fn leader(&self, key: LaneKey, position: f64, car_index: CarIndex) -> Option<CarIndex> {
+        let cars = self.cars(key);
+        let index = cars
+            .binary_search_by(|entry| driving_order(entry, &(position, car_index)))
+            .ok()?;
+        cars.get(index + 1).map(|(_, leader)| *leader)
+    }
+
+    /// The cars right behind and right in front of `position` on the lane,
+    /// leaving out the car at `car_index`, which does not need to be on it.
+    pub fn neighbours(
+        &self,
+        key: LaneKey,
+        position: f64,
+        car_index: CarIndex,
+    ) -> (Option<CarIndex>, Option<CarIndex>) {
+        let cars = self.cars(key);
+        let index = cars.partition_point(|entry| {
+            driving_order(entry, &(position, car_index)) == Ordering::Less
+        });
+        let follower = cars[..index].last().map(|(_, follower)| *follower);
+        let leader = cars[index..]
+            .iter()
+            .map(|(_, leader)| *leader)
+            .find(|leader| *leader != car_index);
+        (follower, leader)
+    }
+
+    /// Move the cars from their places in `before` to their places in
+    /// `after`.
+    ///
+    /// Return false and leave the lists partly updated if they did not
+    /// match `before`.
+    pub fn update(&mut self, before: &[Option<Car>], after: &[Option<Car>]) -> bool {
+        for car_index in 0..before.len().max(after.len()) {
+            let old = places(before.get(car_index).unwrap_or(&None));
+            let new = places(after.get(car_index).unwrap_or(&None));
+            if old == new {
+                continue;
+            }
+            for (key, position) in old {
+                let cars = match self.lanes.get_mut(&key) {
+                    Some(cars) => cars,
+                    None => return false,
+                };
+                match cars.binary_search_by(|entry| driving_order(entry, &(position, car_index))) {
+                    Ok(index) => {
+                        cars.remove(index);
+                    }
+                    Err(_) => return false,
+                }
+                if cars.is_empty() {
+                    self.lanes.remove(&key);
+                }
+            }
+            for (key, position) in new {
+                let cars = self.lanes.entry(key).or_default();
+                let entry = (position, car_index);
+                let index = cars
+                    .binary_search_by(|other| driving_order(other, &entry))
+                    .unwrap_or_else(|index| index);
+                cars.insert(index, entry);
+            }
+        }
+        true
+    }
+}
+
+/// Order of cars on a lane, NaN positions go to the front.
+fn driving_order((p1, i1): &(f64, CarIndex), (p2, i2): &(f64, CarIndex)) -> Ordering {
+    p1.total_cmp(p2).then(i1.cmp(i2))
+}
+
+/// Lanes of a car and its position on them.
+fn places(car: &Option<Car>) -> Vec<(LaneKey, f64)> {
+    match car.as_ref().map(|car| &car.location) {
+        Some(Location::OnLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            lane_index,
+            position,
+            ..
+        }) => vec![(
+            (*road_direction, *road_index, *lane_direction, *lane_index),
+            *position,
+        )],
+        Some(Location::ChangingLane {
+            road_direction,
+            road_index,
+            lane_direction,
+            from_lane_index,
+            to_lane_index,
+            position,
+            ..
+        }) => [from_lane_index, to_lane_index]
+            .iter()
+            .map(|lane_index| {
+                (
+                    (*road_direction, *road_index, *lane_direction, **lane_index),
+                    *position,
+                )
+            })
+            .collect(),
+        Some(Location::InIntersection { .. }) | None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stateful::testing::{car_at, on_lane};
+
+    fn lane(lane_index: LaneIndex) -> LaneKey {
+        (
+            AxisDirection::Horizontal,
+            (0, 0),
+            LaneDirection::LowToHigh,
+            lane_index,
+        )
+    }
+
+    #[test]
+    fn order_by_position_then_index() {
+        let cars = vec![
+            car_at(on_lane(0, 5.0), 10.0),
+            car_at(on_lane(0, 1.0), 10.0),
+            car_at(on_lane(1, 3.0), 10.0),
+            car_at(on_lane(0, 5.0), 10.0),
+            None,
+        ];
+        let lane_cars = LaneCars::from_cars(&cars);
+        assert_eq!(lane_cars.cars(lane(0)), &[(1.0, 1), (5.0, 0), (5.0, 3)]);
+        assert_eq!(lane_cars.cars(lane(1)), &[(3.0, 2)]);
+        assert_eq!(lane_cars.cars(lane(2)), &[]);
+        assert_eq!(lane_cars.leader(lane(0), 1.0, 1), Some(0));
+        assert_eq!(lane_cars.leader(lane(0), 5.0, 0), Some(3));
+        assert_eq!(lane_cars.leader(lane(0), 5.0, 3), None);
+        assert_eq!(lane_cars.leader(lane(1), 2.0, 2), None);
+        assert_eq!(lane_cars.neighbours(lane(0), 5.0, 0), (Some(1), Some(3)));
+        assert_eq!(lane_cars.neighbours(lane(0), 3.0, 2), (Some(1), Some(0)));
+        assert_eq!(lane_cars.neighbours(lane(1), 4.0, 0), (Some(2), None));
+        assert_eq!(lane_cars.neighbours(lane(2), 4.0, 0), (None, None));
+    }
+
+    #[test]
+    fn update_incrementally() {
+        let before = vec![
+            car_at(on_lane(0, 5.0), 10.0),
+            car_at(on_lane(0, 1.0), 10.0),
+            car_at(on_lane(1, 3.0), 10.0),
+            None,
+        ];
+        let after = vec![
+            car_at(on_lane(0, 6.0), 10.0),
+            car_at(on_lane(0, 7.0), 10.0),
+            None,
+            car_at(on_lane(1, 0.0), 10.0),
+        ];
+        let mut lane_cars = LaneCars::from_cars(&before);
+        assert!(lane_cars.update(&before, &after));
+        assert_eq!(lane_cars, LaneCars::from_cars(&after));
+        // Lists of other cars do not match
+        assert!(!LaneCars::default().update(&before, &after));
+    }
+}
@@ -1,21 +1,615 @@
 //! Module `stateful` is the dynamic part of the simulation
 
-use crate::model::board::Board;
+use crate::model::{
+    board::Board,
+    common::{AxisDirection, CarIndex, LaneDirection, Position},
+    stateless,
+};
+use car::Location;
+use intersection::SwitchState;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 
 pub mod car;
+pub mod diff;
 pub mod intersection;
+pub mod lanes;
+#[cfg(test)]
+pub mod testing;
 
 pub use car::Car;
+pub use diff::{StateDiff, Tolerance};
 pub use intersection::Intersection;
+pub use lanes::{LaneCars, LaneKey};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct City {
     pub board: Board<Option<Intersection>, ()>,
+    /// Whether all signals have failed, and signalized intersections work as
+    /// all-way stops.
+    #[serde(default)]
+    pub signals_failed: bool,
 }
 
+/// Cars are stored in slots which are never compacted.
+///
+/// A car keeps the index of its slot for its whole life. A removed car
+/// leaves its slot empty, and a new car may fill the slot later. Indices are
+/// only valid within an update, code keeping cars across updates keeps
+/// `CarHandle`s instead, which stop resolving once their cars are removed.
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Model {
     pub city: City,
     pub cars: Vec<Option<Car>>,
+    /// Number of cars which have filled each slot, missing entries are 0.
+    #[serde(default)]
+    pub generations: Vec<u32>,
+    /// Identifier of the next car to enter.
+    #[serde(default)]
+    pub next_car_id: u64,
+    /// Cars on each lane, kept by `replace_cars`. Set `cars` through
+    /// `replace_cars` to keep them in sync.
+    #[serde(skip)]
+    pub lanes: LaneCars,
+    /// Recent locations of each car before its current one, oldest first,
+    /// kept by `record_trails` for the view.
+    #[serde(skip)]
+    pub trails: Vec<VecDeque<Location>>,
+}
+
+/// A car which stays valid across updates, the index of its slot and the
+/// generation of the slot when it was taken.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct CarHandle {
+    pub index: CarIndex,
+    pub generation: u32,
+}
+
+impl Model {
+    pub fn generation(&self, index: CarIndex) -> u32 {
+        self.generations.get(index).copied().unwrap_or(0)
+    }
+
+    /// Handle of the car in slot `index`, if any.
+    pub fn handle(&self, index: CarIndex) -> Option<CarHandle> {
+        self.cars.get(index)?.as_ref()?;
+        Some(CarHandle {
+            index,
+            generation: self.generation(index),
+        })
+    }
+
+    /// The car of `handle`, unless it has been removed.
+    pub fn car(&self, handle: CarHandle) -> Option<&Car> {
+        if self.generation(handle.index) != handle.generation {
+            return None;
+        }
+        self.cars.get(handle.index)?.as_ref()
+    }
+
+    /// Give each car sharing its id with an earlier car a new id, and move
+    /// `next_car_id` past every id, as for a state saved before cars had
+    /// ids.
+    pub fn fix_car_ids(&mut self) {
+        let mut next_car_id = self
+            .cars
+            .iter()
+            .flatten()
+            .map(|car| car.id + 1)
+            .fold(self.next_car_id, u64::max);
+        let mut ids = HashSet::new();
+        for car in self.cars.iter_mut().flatten() {
+            if !ids.insert(car.id) {
+                car.id = next_car_id;
+                next_car_id += 1;
+            }
+        }
+        self.next_car_id = next_car_id;
+    }
+
+    /// Replace the cars with their updated states, advancing the generations
+    /// of slots filled by new cars.
+    ///
+    /// New cars keep the ids given to them when they were created, from
+    /// `next_car_id`. New cars without one, or with one already taken, get
+    /// the next ids in the order of their slots.
+    pub fn replace_cars(&mut self, mut cars: Vec<Option<Car>>) {
+        if self.generations.len() < cars.len() {
+            self.generations.resize(cars.len(), 0);
+        }
+        for (index, car) in cars.iter_mut().enumerate() {
+            let was_empty = !matches!(self.cars.get(index), Some(Some(_)));
+            if let (Some(car), true) = (car.as_mut(), was_empty) {
+                self.generations[index] = self.generations[index].wrapping_add(1);
+                if car.id < self.next_car_id {
+                    car.id = self.next_car_id;
+                }
+                self.next_car_id = car.id + 1;
+            }
+            if car.is_none() || was_empty {
+                if let Some(trail) = self.trails.get_mut(index) {
+                    trail.clear();
+                }
+            }
+        }
+        if !self.lanes.update(&self.cars, &cars) {
+            self.lanes = LaneCars::from_cars(&cars);
+        }
+        self.cars = cars;
+        self.debug_check_lanes();
+    }
+
+    /// Add the current location of each car to its trail, keeping the
+    /// `length` most recent locations.
+    ///
+    /// Call it before `replace_cars`, trails of removed and new cars are
+    /// cleared there.
+    pub fn record_trails(&mut self, length: usize) {
+        self.trails.resize_with(self.cars.len(), Default::default);
+        for (trail, car) in self.trails.iter_mut().zip(self.cars.iter()) {
+            if let Some(car) = car {
+                trail.push_back(car.location.clone());
+                while trail.len() > length {
+                    trail.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Cars whose positions in `city` are in the rectangle from `min` to
+    /// `max`, by index.
+    ///
+    /// Cars on lanes are looked up in the range of positions of each lane
+    /// within the rectangle, only the cars in intersections are checked one
+    /// by one.
+    pub fn cars_in_region(
+        &self,
+        city: &stateless::City,
+        min: Position,
+        max: Position,
+    ) -> Vec<CarIndex> {
+        let inside = |car_index: CarIndex| {
+            self.cars[car_index]
+                .as_ref()
+                .and_then(|car| car.location.city_position(city))
+                .map_or(false, |p| {
+                    min.x <= p.x && p.x <= max.x && min.y <= p.y && p.y <= max.y
+                })
+        };
+        let mut found = Vec::new();
+        for (key, cars) in self.lanes.iter() {
+            let (road_direction, road_index, lane_direction, lane_index) = key;
+            let center =
+                match city.lane_center(road_direction, road_index, lane_direction, lane_index) {
+                    Some(center) => center,
+                    None => continue,
+                };
+            let (along, across, (low, high), (low_across, high_across)) = match road_direction {
+                AxisDirection::Horizontal => (center.x, center.y, (min.x, max.x), (min.y, max.y)),
+                AxisDirection::Vertical => (center.y, center.x, (min.y, max.y), (min.x, max.x)),
+            };
+            // Cars changing lane are up to a lane away from its center
+            if across < low_across - city.lane_width || across > high_across + city.lane_width {
+                continue;
+            }
+            let half_length = city.road_length(road_direction, road_index) / 2.0;
+            let (first, last) = match lane_direction {
+                LaneDirection::LowToHigh => (low - along + half_length, high - along + half_length),
+                LaneDirection::HighToLow => (along - high + half_length, along - low + half_length),
+            };
+            let start = cars.partition_point(|(position, _)| *position < first);
+            found.extend(
+                cars[start..]
+                    .iter()
+                    .take_while(|(position, _)| *position <= last)
+                    .map(|(_, car_index)| *car_index)
+                    .filter(|car_index| inside(*car_index)),
+            );
+        }
+        found.extend(
+            self.cars
+                .iter()
+                .enumerate()
+                .filter(|(_, car)| {
+                    matches!(
+                        car,
+                        Some(Car {
+                            location: Location::InIntersection { .. },
+                            ..
+                        })
+                    )
+                })
+                .map(|(car_index, _)| car_index)
+                .filter(|car_index| inside(*car_index)),
+        );
+        // Cars changing lane are on two lanes
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// Check the lane lists match the cars in debug builds.
+    pub fn debug_check_lanes(&self) {
+        debug_assert!(
+            self.lanes == LaneCars::from_cars(&self.cars),
+            "lane lists out of sync with the cars"
+        );
+    }
+
+    /// Hash of the car locations, car velocities and intersection phases.
+    ///
+    /// The hash only depends on the values in the model, so identical runs
+    /// have identical fingerprints, whatever the number of processes.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write_usize(self.cars.len());
+        for car in self.cars.iter() {
+            let car = match car {
+                Some(car) => car,
+                None => {
+                    hash.write_u8(0);
+                    continue;
+                }
+            };
+            hash.write_u8(1);
+            match car.location {
+                Location::OnLane {
+                    road_direction,
+                    road_index: (i, j),
+                    lane_direction,
+                    lane_index,
+                    about_to_turn,
+                    position,
+                } => {
+                    hash.write_u8(0);
+                    hash.write_u8(road_direction as u8);
+                    hash.write_usize(i);
+                    hash.write_usize(j);
+                    hash.write_u8(lane_direction as u8);
+                    hash.write_usize(lane_index);
+                    hash.write_u8(about_to_turn as u8);
+                    hash.write_f64(position);
+                }
+                Location::ChangingLane {
+                    road_direction,
+                    road_index: (i, j),
+                    lane_direction,
+                    from_lane_index,
+                    to_lane_index,
+                    position,
+                    lane_changed_proportion,
+                } => {
+                    hash.write_u8(1);
+                    hash.write_u8(road_direction as u8);
+                    hash.write_usize(i);
+                    hash.write_usize(j);
+                    hash.write_u8(lane_direction as u8);
+                    hash.write_usize(from_lane_index);
+                    hash.write_usize(to_lane_index);
+                    hash.write_f64(position);
+                    hash.write_f64(lane_changed_proportion);
+                }
+                Location::InIntersection {
+                    intersection_index: (i, j),
+                    from_direction,
+                    from_lane_index,
+                    to_direction,
+                    to_lane_index,
+                    total_length,
+                    position,
+                } => {
+                    hash.write_u8(2);
+                    hash.write_usize(i);
+                    hash.write_usize(j);
+                    hash.write_u8(from_direction as u8);
+                    hash.write_usize(from_lane_index);
+                    hash.write_u8(to_direction as u8);
+                    hash.write_usize(to_lane_index);
+                    hash.write_f64(total_length);
+                    hash.write_f64(position);
+                }
+            }
+            hash.write_f64(car.velocity);
+            hash.write_f64(car.acceleration);
+        }
+        for intersection in self.city.board.intersections.iter() {
+            match intersection {
+                Some(Intersection::Crossroad {
+                    current,
+                    switch_state,
+                    ..
+                })
+                | Some(Intersection::TJunction {
+                    current,
+                    switch_state,
+                    ..
+                }) => {
+                    hash.write_u8(1);
+                    for turn_rule in
+                        [current.north, current.west, current.south, current.east].iter()
+                    {
+                        hash.write_u8(turn_rule.bits());
+                    }
+                    let SwitchState::LoopTimeout {
+                        remain_time,
+                        time_index,
+                        rule_index,
+                    } = switch_state;
+                    hash.write_f64(*remain_time);
+                    hash.write_usize(*time_index);
+                    hash.write_usize(*rule_index);
+                }
+                Some(_) => hash.write_u8(2),
+                None => hash.write_u8(0),
+            }
+        }
+        hash.finish()
+    }
+}
+
+/// 64 bit FNV-1a hash, integers are hashed in little endian.
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u8(&mut self, value: u8) {
+        self.write(&[value]);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn write_f64(&mut self, value: f64) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{car_at, on_lane};
+
+    #[test]
+    fn fingerprint_follows_state() {
+        let model = Model {
+            cars: vec![
+                car_at(on_lane(0, 1.0), 10.0),
+                None,
+                car_at(on_lane(0, 2.0), 10.0),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(model.fingerprint(), model.clone().fingerprint());
+
+        let mut moved = model.clone();
+        moved.cars[2] = car_at(on_lane(0, 2.0 + 1e-9), 10.0);
+        assert_ne!(model.fingerprint(), moved.fingerprint());
+
+        let mut swapped = model.clone();
+        swapped.cars.swap(0, 2);
+        assert_ne!(model.fingerprint(), swapped.fingerprint());
+
+        let mut removed = model.clone();
+        removed.cars[0] = None;
+        assert_ne!(model.fingerprint(), removed.fingerprint());
+    }
+
+    #[test]
+    fn handles_expire_with_their_cars() {
+        let mut model = Model {
+            cars: vec![car_at(on_lane(0, 1.0), 10.0), None],
+            ..Default::default()
+        };
+        let first = model.handle(0).unwrap();
+        assert_eq!(model.handle(1), None);
+        model.replace_cars(vec![
+            car_at(on_lane(0, 2.0), 10.0),
+            car_at(on_lane(0, 0.0), 10.0),
+        ]);
+        assert_eq!(model.car(first).map(|car| car.velocity), Some(10.0));
+        let second = model.handle(1).unwrap();
+        // The first car leaves and a new car fills its slot
+        model.replace_cars(vec![None, car_at(on_lane(0, 1.0), 10.0)]);
+        assert!(model.car(first).is_none());
+        model.replace_cars(vec![
+            car_at(on_lane(0, 0.0), 10.0),
+            car_at(on_lane(0, 2.0), 10.0),
+        ]);
+        assert!(model.car(first).is_none());
+        assert_ne!(model.handle(0), Some(first));
+        assert!(model.car(second).is_some());
+    }
+
+    #[test]
+    fn ids_are_never_reused() {
+        let mut model = Model::default();
+        model.replace_cars(vec![
+            car_at(on_lane(0, 1.0), 10.0),
+            car_at(on_lane(0, 5.0), 10.0),
+        ]);
+        let ids = |model: &Model| {
+            model
+                .cars
+                .iter()
+                .map(|car| car.as_ref().map(|car| car.id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&model), vec![Some(0), Some(1)]);
+        // Updated cars keep their ids
+        let mut cars = model.cars.clone();
+        cars[1].as_mut().unwrap().location = car_at(on_lane(0, 6.0), 10.0).unwrap().location;
+        model.replace_cars(cars);
+        assert_eq!(ids(&model), vec![Some(0), Some(1)]);
+        // A new car in the slot of a removed one gets a new id
+        model.replace_cars(vec![None, model.cars[1].clone()]);
+        model.replace_cars(vec![
+            car_at(on_lane(0, 0.0), 10.0),
+            model.cars[1].clone(),
+            car_at(on_lane(0, 9.0), 10.0),
+        ]);
+        assert_eq!(ids(&model), vec![Some(2), Some(1), Some(3)]);
+        assert_eq!(model.next_car_id, 4);
+        // A car created with the next id keeps it
+        let mut cars = model.cars.clone();
+        cars.push(car_at(on_lane(0, 3.0), 10.0));
+        cars[3].as_mut().unwrap().id = model.next_car_id;
+        model.replace_cars(cars);
+        assert_eq!(model.cars[3].as_ref().unwrap().id, 4);
+        assert_eq!(model.next_car_id, 5);
+    }
+
+    #[test]
+    fn fix_ids_of_old_states() {
+        // Every car of a state saved before cars had ids has id 0
+        let mut model = Model {
+            cars: vec![
+                car_at(on_lane(0, 1.0), 10.0),
+                None,
+                car_at(on_lane(0, 5.0), 10.0),
+                car_at(on_lane(0, 9.0), 10.0),
+            ],
+            ..Default::default()
+        };
+        model.fix_car_ids();
+        let ids = model
+            .cars
+            .iter()
+            .map(|car| car.as_ref().map(|car| car.id))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(0), None, Some(1), Some(2)]);
+        assert_eq!(model.next_car_id, 3);
+        model.replace_cars(vec![None, car_at(on_lane(0, 0.0), 10.0), None, None]);
+        assert_eq!(model.cars[1].as_ref().unwrap().id, 3);
+    }
+
+    #[test]
+    fn trails_keep_recent_locations() {
+        let mut model = Model::default();
+        model.replace_cars(vec![
+            car_at(on_lane(0, 1.0), 10.0),
+            car_at(on_lane(0, 5.0), 10.0),
+        ]);
+        for position in 2..=4 {
+            model.record_trails(2);
+            model.replace_cars(vec![
+                car_at(on_lane(0, position as f64), 10.0),
+                car_at(on_lane(0, 5.0), 10.0),
+            ]);
+        }
+        let positions = |trail: &VecDeque<Location>| {
+            trail
+                .iter()
+                .map(|location| match location {
+                    Location::OnLane { position, .. } => *position,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&model.trails[0]), vec![2.0, 3.0]);
+        assert_eq!(positions(&model.trails[1]), vec![5.0, 5.0]);
+        // A new car in the slot starts without a trail
+        model.replace_cars(vec![None, car_at(on_lane(0, 5.0), 10.0)]);
+        model.replace_cars(vec![
+            car_at(on_lane(0, 0.0), 10.0),
+            car_at(on_lane(0, 5.0), 10.0),
+        ]);
+        assert!(model.trails[0].is_empty());
+        assert_eq!(model.trails[1].len(), 2);
+    }
+
+    #[test]
+    fn find_cars_in_a_region() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road_length(AxisDirection::Horizontal, 0, 100.0)
+            .build()
+            .unwrap();
+        let mut model = Model::default();
+        let mut oncoming = car_at(on_lane(0, 30.0), 10.0).unwrap();
+        if let Location::OnLane {
+            ref mut lane_direction,
+            ..
+        } = oncoming.location
+        {
+            *lane_direction = LaneDirection::HighToLow;
+        }
+        model.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 10.0),
+            car_at(on_lane(0, 50.0), 10.0),
+            Some(oncoming),
+            None,
+        ]);
+        let position = |car_index: CarIndex| {
+            model.cars[car_index]
+                .as_ref()
+                .unwrap()
+                .location
+                .city_position(&city)
+                .unwrap()
+        };
+        let around = |car_index: CarIndex, margin: f64| {
+            let p = position(car_index);
+            (
+                Position {
+                    x: p.x - margin,
+                    y: p.y - margin,
+                },
+                Position {
+                    x: p.x + margin,
+                    y: p.y + margin,
+                },
+            )
+        };
+        for car_index in 0..3 {
+            let (min, max) = around(car_index, 0.5);
+            assert_eq!(model.cars_in_region(&city, min, max), vec![car_index]);
+        }
+        // The oncoming car is 30 m before the end of the road, beyond the
+        // car in the middle
+        let geometry = city.geometry();
+        let (min, _) = around(0, 1.0);
+        let (_, max) = around(2, 1.0);
+        let across = |min: Position, max: Position| {
+            model.cars_in_region(
+                &city,
+                Position { x: min.x, y: 0.0 },
+                Position {
+                    x: max.x,
+                    y: geometry.height,
+                },
+            )
+        };
+        assert_eq!(across(min, max), vec![0, 1, 2]);
+        assert_eq!(across(min, around(1, 1.0).1), vec![0, 1]);
+        assert!(model
+            .cars_in_region(
+                &city,
+                Position {
+                    x: geometry.width,
+                    y: 0.0
+                },
+                Position {
+                    x: geometry.width + 10.0,
+                    y: geometry.height
+                },
+            )
+            .is_empty());
+    }
 }
@@ -0,0 +1,50 @@
+//! Cars for the tests of the modules working on the state.
+
+use crate::model::{
+    board::RoadIndex,
+    common::{AxisDirection, LaneDirection, LaneIndex, RelativeDirection},
+    stateful::{car::Location, Car},
+};
+
+/// A car at `location` driving at `velocity`, without a destination.
+pub fn car_at(location: Location, velocity: f64) -> Option<Car> {
+    Some(Car {
+        location,
+        velocity,
+        acceleration: 0.0,
+        perception: Default::default(),
+        crashed: None,
+        id: 0,
+        destination: None,
+        route: Vec::new(),
+    })
+}
+
+/// Location on a lane of the horizontal road (0, 0) driving east.
+pub fn on_lane(lane_index: LaneIndex, position: f64) -> Location {
+    on_road(
+        AxisDirection::Horizontal,
+        (0, 0),
+        LaneDirection::LowToHigh,
+        lane_index,
+        position,
+    )
+}
+
+/// Location on a lane of any road, going straight at its end.
+pub fn on_road(
+    road_direction: AxisDirection,
+    road_index: RoadIndex,
+    lane_direction: LaneDirection,
+    lane_index: LaneIndex,
+    position: f64,
+) -> Location {
+    Location::OnLane {
+        road_direction,
+        road_index,
+        lane_direction,
+        lane_index,
+        about_to_turn: RelativeDirection::Front,
+        position,
+    }
+}
@@ -0,0 +1,410 @@
+//! Build a city from explicit roads, for tests and hand-made networks.
+//!
+//! ```ignore
+//! let city = CityBuilder::new((1, 2))
+//!     .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+//!     .road_length(AxisDirection::Horizontal, 0, 200.0)
+//!     .build()?;
+//! ```
+
+use crate::model::{
+    board::{Board, IntersectionIndex, RoadIndex},
+    common::{AxisDirection, HandedTraffic},
+    generate::stateless::city::{
+        calculate_intersection_geometry, fix::fix_lane_direction_rule,
+        intersection::generate_with_context, road::basic_lane,
+    },
+    stateless::{City, Intersection, Road},
+};
+use quick_error::quick_error;
+
+quick_error! {
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum BuildError {
+        RoadOutOfBoard(direction: AxisDirection, index: RoadIndex) {
+            display("{:?} road {:?} is out of the board", direction, index)
+        }
+        RoadWithoutLane(direction: AxisDirection, index: RoadIndex) {
+            display("{:?} road {:?} has no lane", direction, index)
+        }
+        IntersectionOutOfBoard(index: IntersectionIndex) {
+            display("intersection {:?} is out of the board", index)
+        }
+        IntersectionWithoutRoad(index: IntersectionIndex) {
+            display("intersection {:?} has no road", index)
+        }
+        EmptyCity {
+            display("the city has no road")
+        }
+        InvalidIntersectionSizeFactor(factor: f64) {
+            display("intersection size factor {} is not positive", factor)
+        }
+        InvalidCity(message: String) {
+            display("invalid city: {}", message)
+        }
+    }
+}
+
+/// Builder of a `City` on a board of intersections.
+///
+/// Roads are 100 meters long unless set. Intersections joining roads are
+/// generated as the random generator does unless set, and turn rules of lanes
+/// are restricted to the turns the roads allow.
+#[derive(Clone, Debug)]
+pub struct CityBuilder {
+    board: Board<Option<Intersection>, Option<Road>>,
+    horizontal_road_length: Vec<f64>,
+    vertical_road_length: Vec<f64>,
+    lane_width: f64,
+    intersection_size_factor: f64,
+    lane_max_speed: f64,
+    intersection_max_speed: f64,
+    signal_time: f64,
+    car_out_intersection: Option<IntersectionIndex>,
+    car_out_min_distance: f64,
+    handed_traffic: HandedTraffic,
+    errors: Vec<BuildError>,
+}
+
+impl CityBuilder {
+    /// Start with `rows` by `columns` intersections and no road.
+    pub fn new((rows, columns): (usize, usize)) -> Self {
+        Self {
+            board: Board::with_shape(None, None, (rows, columns)),
+            horizontal_road_length: vec![100.0; columns.saturating_sub(1)],
+            vertical_road_length: vec![100.0; rows.saturating_sub(1)],
+            lane_width: 3.5,
+            intersection_size_factor: 1.0,
+            lane_max_speed: 40.0,
+            intersection_max_speed: 10.0,
+            signal_time: 10.0,
+            car_out_intersection: None,
+            car_out_min_distance: 8.0,
+            handed_traffic: HandedTraffic::Right,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn lane_width(mut self, lane_width: f64) -> Self {
+        self.lane_width = lane_width;
+        self
+    }
+
+    /// Size of intersections relative to the widest roads they join, 1 by
+    /// default.
+    pub fn intersection_size_factor(mut self, factor: f64) -> Self {
+        self.intersection_size_factor = factor;
+        self
+    }
+
+    /// Max speed of the lanes added after this call.
+    pub fn lane_max_speed(mut self, max_speed: f64) -> Self {
+        self.lane_max_speed = max_speed;
+        self
+    }
+
+    /// Max speed of the generated intersections.
+    pub fn intersection_max_speed(mut self, max_speed: f64) -> Self {
+        self.intersection_max_speed = max_speed;
+        self
+    }
+
+    /// Seconds of a phase of the generated signals.
+    pub fn signal_time(mut self, time: f64) -> Self {
+        self.signal_time = time;
+        self
+    }
+
+    /// Side of the road traffic keeps to, the right by default.
+    pub fn handed_traffic(mut self, handed_traffic: HandedTraffic) -> Self {
+        self.handed_traffic = handed_traffic;
+        self
+    }
+
+    /// Set the length of the roads between the `k`-th and the next column of
+    /// intersections for horizontal roads, or rows for vertical roads.
+    pub fn road_length(mut self, direction: AxisDirection, k: usize, length: f64) -> Self {
+        let lengths = match direction {
+            AxisDirection::Horizontal => &mut self.horizontal_road_length,
+            AxisDirection::Vertical => &mut self.vertical_road_length,
+        };
+        match lengths.get_mut(k) {
+            Some(road_length) => *road_length = length,
+            None => {
+                let index = match direction {
+                    AxisDirection::Horizontal => (0, k),
+                    AxisDirection::Vertical => (k, 0),
+                };
+                self.errors
+                    .push(BuildError::RoadOutOfBoard(direction, index));
+            }
+        }
+        self
+    }
+
+    /// Add a road with lanes toward the intersection of the higher index and
+    /// lanes toward the lower one.
+    pub fn road(
+        mut self,
+        direction: AxisDirection,
+        index: RoadIndex,
+        lanes_to_high: usize,
+        lanes_to_low: usize,
+    ) -> Self {
+        let lane = basic_lane(self.lane_max_speed);
+        match self.board.get_road_mut(direction, index) {
+            Some(road) => {
+                *road = Some(Road {
+                    lane_to_high: vec![lane.clone(); lanes_to_high],
+                    lane_to_low: vec![lane; lanes_to_low],
+                });
+                if lanes_to_high + lanes_to_low == 0 {
+                    self.errors
+                        .push(BuildError::RoadWithoutLane(direction, index));
+                }
+            }
+            None => self
+                .errors
+                .push(BuildError::RoadOutOfBoard(direction, index)),
+        }
+        self
+    }
+
+    /// Use `intersection` instead of the generated one.
+    pub fn intersection(mut self, index: IntersectionIndex, intersection: Intersection) -> Self {
+        match self.board.intersections.get(index) {
+            Some(_) => self.board.intersections[index] = Some(intersection),
+            None => self.errors.push(BuildError::IntersectionOutOfBoard(index)),
+        }
+        self
+    }
+
+    /// Where new cars enter the city, the first intersection by default.
+    pub fn car_out(mut self, index: IntersectionIndex, min_distance: f64) -> Self {
+        self.car_out_intersection = Some(index);
+        self.car_out_min_distance = min_distance;
+        self
+    }
+
+    /// Check the roads and intersections and build the city.
+    pub fn build(self) -> Result<City, BuildError> {
+        let CityBuilder {
+            mut board,
+            horizontal_road_length,
+            vertical_road_length,
+            lane_width,
+            intersection_size_factor,
+            intersection_max_speed,
+            signal_time,
+            car_out_intersection,
+            car_out_min_distance,
+            handed_traffic,
+            mut errors,
+            ..
+        } = self;
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+        if !(intersection_size_factor.is_finite() && intersection_size_factor > 0.0) {
+            return Err(BuildError::InvalidIntersectionSizeFactor(
+                intersection_size_factor,
+            ));
+        }
+        for index in board.intersections.indices() {
+            let context = board.context_of_intersection(index);
+            let intersection = &mut board.intersections[index];
+            match (context.road_number(), intersection.is_some()) {
+                (0, true) => return Err(BuildError::IntersectionWithoutRoad(index)),
+                (0, false) => (),
+                (_, true) => (),
+                (_, false) => {
+                    *intersection = Some(generate_with_context(
+                        &context,
+                        intersection_max_speed,
+                        signal_time,
+                    ))
+                }
+            }
+        }
+        fix_lane_direction_rule(&mut board, handed_traffic);
+        let car_out_intersection = match car_out_intersection {
+            Some(index) => match board.intersections.get(index) {
+                Some(Some(_)) => index,
+                Some(None) => return Err(BuildError::IntersectionWithoutRoad(index)),
+                None => return Err(BuildError::IntersectionOutOfBoard(index)),
+            },
+            None => board
+                .intersections
+                .enumerate()
+                .find(|(_, intersection)| intersection.is_some())
+                .map(|(index, _)| index)
+                .ok_or(BuildError::EmptyCity)?,
+        };
+        let (intersection_height, intersection_width) =
+            calculate_intersection_geometry(&board, lane_width, intersection_size_factor);
+        let city = City {
+            board,
+            car_out_intersection,
+            car_out_min_distance,
+            lane_width,
+            horizontal_road_length,
+            vertical_road_length,
+            intersection_height,
+            intersection_width,
+            handed_traffic,
+        };
+        city.validate().map_err(BuildError::InvalidCity)?;
+        Ok(city)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::common::{LaneDirection, TurnRule};
+
+    #[test]
+    fn single_road() {
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 2, 1)
+            .road_length(AxisDirection::Horizontal, 0, 200.0)
+            .build()
+            .unwrap();
+        assert_eq!(city.horizontal_road_length, vec![200.0]);
+        assert_eq!(city.intersection_height, vec![10.5]);
+        assert_eq!(city.car_out_intersection, (0, 0));
+        assert!(city
+            .board
+            .intersections
+            .iter()
+            .all(|intersection| matches!(intersection, Some(Intersection::End { .. }))));
+        // Cars can only turn back at the ends, from the first lane
+        let road = city.board.horizontal_roads[(0, 0)].as_ref().unwrap();
+        let rules = road
+            .lanes_to_direction(LaneDirection::LowToHigh)
+            .iter()
+            .map(|lane| lane.direction_rule)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, vec![TurnRule::BACK, TurnRule::empty()]);
+    }
+
+    #[test]
+    fn intersection_size_factor_scales_geometry() {
+        use crate::model::common::{AbsoluteDirection, InOutDirection};
+        let build = |factor| {
+            CityBuilder::new((3, 3))
+                .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+                .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+                .road(AxisDirection::Vertical, (0, 1), 1, 1)
+                .road(AxisDirection::Vertical, (1, 1), 1, 1)
+                .intersection_size_factor(factor)
+                .build()
+                .unwrap()
+        };
+        let (city, large) = (build(1.0), build(2.0));
+        assert_eq!(city.intersection_height[1], 7.0);
+        assert_eq!(large.intersection_height[1], 14.0);
+        assert_eq!(large.intersection_width[1], 14.0);
+        // Stop lines move out with the sides of the intersection and the
+        // paths through it grow
+        let join = large
+            .intersection_road_join_position((1, 1), AbsoluteDirection::West, InOutDirection::In, 0)
+            .unwrap();
+        assert_eq!(join.x, -7.0);
+        let path_length = |city: &City| {
+            city.intersection_path_total_length(
+                (1, 1),
+                AbsoluteDirection::West,
+                0,
+                AbsoluteDirection::North,
+                0,
+            )
+            .unwrap()
+        };
+        assert!(path_length(&large) > path_length(&city));
+    }
+
+    #[test]
+    fn single_crossroad() {
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 1), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .road(AxisDirection::Vertical, (1, 1), 1, 1)
+            .car_out((1, 1), 8.0)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            city.board.intersections[(1, 1)],
+            Some(Intersection::Crossroad { .. })
+        ));
+        assert!(city.board.intersections[(0, 0)].is_none());
+        assert_eq!(city.car_out_intersection, (1, 1));
+    }
+
+    #[test]
+    fn left_hand_traffic() {
+        let city = CityBuilder::new((3, 3))
+            .road(AxisDirection::Horizontal, (1, 0), 2, 2)
+            .road(AxisDirection::Horizontal, (1, 1), 2, 2)
+            .road(AxisDirection::Vertical, (0, 1), 2, 2)
+            .road(AxisDirection::Vertical, (1, 1), 2, 2)
+            .handed_traffic(HandedTraffic::Left)
+            .build()
+            .unwrap();
+        assert_eq!(city.handed_traffic, HandedTraffic::Left);
+        // The innermost lane turns right across the opposite traffic, the
+        // outermost one turns left
+        let road = city.board.horizontal_roads[(1, 0)].as_ref().unwrap();
+        let rules = road
+            .lanes_to_direction(LaneDirection::LowToHigh)
+            .iter()
+            .map(|lane| lane.direction_rule)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                TurnRule::FRONT | TurnRule::RIGHT | TurnRule::BACK,
+                TurnRule::FRONT | TurnRule::LEFT,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_city() {
+        let error = CityBuilder::new((1, 2))
+            .road(AxisDirection::Vertical, (0, 0), 1, 1)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            error,
+            BuildError::RoadOutOfBoard(AxisDirection::Vertical, (0, 0))
+        );
+        let error = CityBuilder::new((1, 2))
+            .intersection((0, 1), Intersection::Straight)
+            .build()
+            .unwrap_err();
+        assert_eq!(error, BuildError::IntersectionWithoutRoad((0, 1)));
+        assert_eq!(
+            CityBuilder::new((1, 2)).build().unwrap_err(),
+            BuildError::EmptyCity
+        );
+        let road = || CityBuilder::new((1, 2)).road(AxisDirection::Horizontal, (0, 0), 1, 1);
+        assert_eq!(
+            road().intersection_size_factor(0.0).build().unwrap_err(),
+            BuildError::InvalidIntersectionSizeFactor(0.0)
+        );
+        let invalid = vec![
+            road().lane_width(2.0),
+            road().lane_width(f64::NAN),
+            road().road_length(AxisDirection::Horizontal, 0, 0.0),
+            road().road_length(AxisDirection::Horizontal, 0, -10.0),
+        ];
+        for builder in invalid.into_iter() {
+            match builder.build() {
+                Err(BuildError::InvalidCity(_)) => (),
+                result => panic!("{:?}", result),
+            }
+        }
+    }
+}
@@ -2,13 +2,37 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Car {
+    #[serde(default)]
+    pub class: VehicleClass,
+    /// Meters from the front to the rear bumper, drawn as simulated.
+    #[serde(default = "default_car_length")]
+    pub length: f64,
+    /// Meters from side to side, drawn as simulated.
+    #[serde(default = "default_car_width")]
+    pub width: f64,
     pub max_velocity: f64,
+    /// Speed the driver aims for relative to the speed limit, 1 for drivers
+    /// keeping the limit.
+    #[serde(default = "default_desired_speed_factor")]
+    pub desired_speed_factor: f64,
     pub max_acceleration: f64,
     pub max_break_acceleration: f64,
     pub lane_change_time: f64,
     pub driving_model: DrivingModel,
 }
 
+fn default_car_length() -> f64 {
+    VehicleClass::Car.length()
+}
+
+fn default_car_width() -> f64 {
+    VehicleClass::Car.width()
+}
+
+fn default_desired_speed_factor() -> f64 {
+    1.0
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum DrivingModel {
     Normal {
@@ -20,3 +44,41 @@ pub enum DrivingModel {
         prediction_time: f64,
     },
 }
+
+/// Kind of a vehicle, which decides how the vehicle looks in the view.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum VehicleClass {
+    #[default]
+    Car,
+    Bus,
+    Truck,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 3] = [VehicleClass::Car, VehicleClass::Bus, VehicleClass::Truck];
+
+    /// Width of the widest class in meters, the narrowest a lane may be.
+    pub fn max_width() -> f64 {
+        Self::ALL
+            .iter()
+            .map(|class| class.width())
+            .fold(0.0, f64::max)
+    }
+
+    /// Typical length of the class in meters.
+    pub fn length(self) -> f64 {
+        match self {
+            VehicleClass::Car => 4.5,
+            VehicleClass::Bus => 12.0,
+            VehicleClass::Truck => 10.0,
+        }
+    }
+
+    /// Typical width of the class in meters.
+    pub fn width(self) -> f64 {
+        match self {
+            VehicleClass::Car => 1.7,
+            VehicleClass::Bus | VehicleClass::Truck => 2.5,
+        }
+    }
+}
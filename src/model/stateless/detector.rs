@@ -0,0 +1,56 @@
+use crate::model::{
+    board::RoadIndex,
+    common::{AxisDirection, LaneDirection, LaneIndex},
+    stateless::City,
+};
+use serde::{Deserialize, Serialize};
+
+/// Virtual induction loop covering `length` meters of a lane from `position`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Detector {
+    pub road_direction: AxisDirection,
+    pub road_index: RoadIndex,
+    pub lane_direction: LaneDirection,
+    pub lane_index: LaneIndex,
+    pub position: f64,
+    #[serde(default = "default_length")]
+    pub length: f64,
+}
+
+fn default_length() -> f64 {
+    2.0
+}
+
+impl Detector {
+    pub fn covers(&self, position: f64) -> bool {
+        self.position <= position && position <= self.position + self.length
+    }
+
+    /// Check the detector is on a lane of `city` and within its length.
+    pub fn validate(&self, city: &City) -> Result<(), String> {
+        let road = city
+            .board
+            .get_road(self.road_direction, self.road_index)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| format!("no {:?} road at {:?}", self.road_direction, self.road_index))?;
+        if self.lane_index >= road.lanes_to_direction(self.lane_direction).len() {
+            return Err(format!(
+                "no lane {} to {:?} on the road",
+                self.lane_index, self.lane_direction
+            ));
+        }
+        let road_length = city.road_length(self.road_direction, self.road_index);
+        let inside = self.position.is_finite()
+            && self.length.is_finite()
+            && self.position >= 0.0
+            && self.length >= 0.0
+            && self.position + self.length <= road_length;
+        if !inside {
+            return Err(format!(
+                "{} meters from {} is not within the lane of {} meters",
+                self.length, self.position, road_length
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::model::common::{AbsoluteDirection, Around, TurnRule};
+use crate::model::common::{AbsoluteDirection, Around, LaneIndex, TurnRule};
 
 use serde::{Deserialize, Serialize};
 
@@ -24,6 +24,68 @@ pub enum Intersection {
     }, // only one road connected in and out
 }
 
+impl Intersection {
+    /// Number of rules the signal switches between, 0 without a signal.
+    pub fn rule_number(&self) -> usize {
+        match self {
+            Intersection::Crossroad { rules, .. } => rules.len(),
+            Intersection::TJunction { rule_set, .. } => rule_set.len(),
+            _ => 0,
+        }
+    }
+
+    /// Turn rules of the cars from each direction under the `rule_index`-th
+    /// rule of the signal.
+    pub fn rule(&self, rule_index: usize) -> Option<Around<TurnRule>> {
+        match self {
+            Intersection::Crossroad { rules, .. } => Some(rules[rule_index]),
+            Intersection::TJunction {
+                single, rule_set, ..
+            } => {
+                let rule = &rule_set[rule_index];
+                let mut current = Around::default();
+                *current.get_mut(*single) = rule.for_single;
+                let driver_direction = single.turn_back();
+                *current.get_mut(driver_direction.turn_left()) = rule.for_left;
+                *current.get_mut(driver_direction.turn_right()) = rule.for_right;
+                *current.get_mut(single.turn_back()) = TurnRule::empty();
+                Some(current)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check that no rule of the signal protects a turn across an oncoming
+    /// traffic which has a green straight ahead.
+    pub fn validate_rules(&self) -> Result<(), String> {
+        for rule_index in 0..self.rule_number() {
+            if let Some(direction) = self.rule(rule_index).unwrap().protected_against_green() {
+                return Err(format!(
+                    "rule {} protects the turn from {:?} against a green straight ahead",
+                    rule_index, direction
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn switch_rule(&self) -> Option<&SwitchRule> {
+        match self {
+            Intersection::Crossroad { switch_rule, .. } => Some(switch_rule),
+            Intersection::TJunction { switch_rule, .. } => Some(switch_rule),
+            _ => None,
+        }
+    }
+
+    pub fn switch_rule_mut(&mut self) -> Option<&mut SwitchRule> {
+        match self {
+            Intersection::Crossroad { switch_rule, .. } => Some(switch_rule),
+            Intersection::TJunction { switch_rule, .. } => Some(switch_rule),
+            _ => None,
+        }
+    }
+}
+
 pub type CrossroadRule = Around<TurnRule>;
 
 /// T-junction intersection has 3 arms denoted with "left", "right" and "single"
@@ -38,7 +100,42 @@ pub struct TJunctionRule {
     pub for_right: TurnRule,
 }
 
+/// Signal plan of an intersection.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum SwitchRule {
-    LoopTimeout { times: Vec<f64> },
+    /// Switch to the next rule after `times[i]` seconds of the `i`-th phase,
+    /// looping over the rules and the times.
+    ///
+    /// The plan starts `offset` seconds into its loop, so neighboring
+    /// intersections can be coordinated.
+    LoopTimeout {
+        times: Vec<f64>,
+        #[serde(default)]
+        offset: f64,
+    },
+}
+
+impl SwitchRule {
+    /// Check that the plan switches, which needs phases of positive times.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            SwitchRule::LoopTimeout { times, .. } => {
+                if times.is_empty() {
+                    return Err("the signal plan has no phases".to_string());
+                }
+                match times.iter().find(|time| !time.is_finite() || **time <= 0.0) {
+                    Some(time) => Err(format!("phase time {} is not positive", time)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// A path through an intersection, from an incoming lane to an outgoing road.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Movement {
+    pub from_direction: AbsoluteDirection,
+    pub from_lane_index: LaneIndex,
+    pub to_direction: AbsoluteDirection,
 }
@@ -1,19 +1,26 @@
 //! Module `stateless` is the static part of the simulation
 
+pub mod builder;
 pub mod car;
+pub mod detector;
 pub mod intersection;
 pub mod road;
+pub mod zone;
 
 use crate::model::{
     board::{Board, IntersectionIndex, RoadIndex},
     common::{
-        AbsoluteDirection, AxisDirection, Geometry, InOutDirection, LaneDirection, LaneIndex,
-        Position,
+        AbsoluteDirection, Around, AxisDirection, Geometry, HandedTraffic, InOutDirection,
+        LaneDirection, LaneIndex, Position, TurnRule,
     },
+    demand::{DemandProfile, OdMatrix},
 };
+pub use builder::CityBuilder;
 pub use car::Car;
-pub use intersection::Intersection;
+pub use detector::Detector;
+pub use intersection::{Intersection, Movement};
 pub use road::{Lane, Road};
+pub use zone::Zone;
 
 use serde::{Deserialize, Serialize};
 
@@ -22,20 +29,67 @@ pub struct City {
     pub board: Board<Option<Intersection>, Option<Road>>,
     pub car_out_intersection: IntersectionIndex,
     pub car_out_min_distance: f64,
+    /// Width of every lane in meters.
+    ///
+    /// The only width of lanes, it spaces the lanes across roads for the
+    /// positions of cars, including their paths while changing lanes, and
+    /// for drawing.
     pub lane_width: f64,
     pub horizontal_road_length: Vec<f64>,
     pub vertical_road_length: Vec<f64>,
     pub intersection_height: Vec<f64>,
     pub intersection_width: Vec<f64>,
+    #[serde(default)]
+    pub handed_traffic: HandedTraffic,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Model {
     pub city: City,
     pub cars: Vec<Car>,
+    #[serde(default)]
+    pub detectors: Vec<Detector>,
+    /// Rate of cars entering the city at the car out intersection, cars
+    /// enter whenever they can without it.
+    #[serde(default)]
+    pub demand: Option<DemandProfile>,
+    /// Weights of the destinations of the cars entering the city, cars get
+    /// destinations only when routed without it.
+    #[serde(default)]
+    pub od_matrix: Option<OdMatrix>,
+    /// Areas of the city drawn behind the roads, in the order they are
+    /// drawn.
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+}
+
+impl Model {
+    /// Check the city, the cars fit on its lanes and the detectors, such as
+    /// the ones of a model file.
+    pub fn validate(&self) -> Result<(), String> {
+        self.city.validate()?;
+        for (index, car) in self.cars.iter().enumerate() {
+            if car.width.is_nan() || car.width > self.city.lane_width {
+                return Err(format!(
+                    "car {} of width {} is wider than the lanes of {}",
+                    index, car.width, self.city.lane_width
+                ));
+            }
+        }
+        for (index, detector) in self.detectors.iter().enumerate() {
+            detector
+                .validate(&self.city)
+                .map_err(|e| format!("detector {}: {}", index, e))?;
+        }
+        Ok(())
+    }
 }
 
 impl City {
+    /// Size of the whole city
+    ///
+    /// All positions of the city are in meters, with the origin at the
+    /// top left corner, x growing to the east and y growing to the south.
     pub fn geometry(&self) -> Geometry {
         let width = self
             .horizontal_road_length
@@ -50,6 +104,75 @@ impl City {
         Geometry { width, height }
     }
 
+    /// Check the sizes of the roads and intersections fit the board, the
+    /// lanes are as wide as every class of vehicle, the rules of the lanes
+    /// and the signals and the signal plans of the intersections, such as
+    /// the ones of a model file.
+    pub fn validate(&self) -> Result<(), String> {
+        let (rows, columns) = self.board.shape();
+        check_sizes(
+            "horizontal road length",
+            &self.horizontal_road_length,
+            columns.saturating_sub(1),
+            false,
+        )?;
+        check_sizes(
+            "vertical road length",
+            &self.vertical_road_length,
+            rows.saturating_sub(1),
+            false,
+        )?;
+        // Intersections of rows or columns without roads have no size
+        check_sizes("intersection height", &self.intersection_height, rows, true)?;
+        check_sizes(
+            "intersection width",
+            &self.intersection_width,
+            columns,
+            true,
+        )?;
+        let max_width = car::VehicleClass::max_width();
+        if !(self.lane_width.is_finite() && self.lane_width >= max_width) {
+            return Err(format!(
+                "lane width {} is narrower than a vehicle of {}",
+                self.lane_width, max_width
+            ));
+        }
+        for (index, (direction, road)) in self.board.enumerate_roads() {
+            let road = match road {
+                Some(road) => road,
+                None => continue,
+            };
+            let protected = road
+                .lane_to_high
+                .iter()
+                .chain(road.lane_to_low.iter())
+                .any(|lane| lane.direction_rule.contains(TurnRule::PROTECTED));
+            if protected {
+                return Err(format!(
+                    "{:?} road {:?}: a lane rule cannot be protected",
+                    direction, index
+                ));
+            }
+        }
+        for (index, intersection) in self.board.intersections.enumerate() {
+            if let Some(intersection) = intersection {
+                intersection
+                    .validate_rules()
+                    .map_err(|e| format!("intersection {:?}: {}", index, e))?;
+            }
+            let switch_rule = match intersection {
+                Some(Intersection::Crossroad { switch_rule, .. })
+                | Some(Intersection::TJunction { switch_rule, .. }) => switch_rule,
+                _ => continue,
+            };
+            switch_rule
+                .validate()
+                .map_err(|e| format!("intersection {:?}: {}", index, e))?;
+        }
+        Ok(())
+    }
+
+    /// Absolute position of the center of an intersection
     pub fn intersection_center(&self, (i, j): IntersectionIndex) -> Position {
         let x = self.intersection_width.iter().take(j).sum::<f64>()
             + self.horizontal_road_length.iter().take(j).sum::<f64>()
@@ -60,6 +183,7 @@ impl City {
         Position { x, y }
     }
 
+    /// Absolute position of the center of a horizontal road
     pub fn horizontal_road_center(&self, (i, j): RoadIndex) -> Position {
         let x = self.intersection_width.iter().take(j + 1).sum::<f64>()
             + self.horizontal_road_length.iter().take(j).sum::<f64>()
@@ -70,6 +194,7 @@ impl City {
         Position { x, y }
     }
 
+    /// Absolute position of the center of a vertical road
     pub fn vertical_road_center(&self, (i, j): RoadIndex) -> Position {
         let x = self.intersection_width.iter().take(j).sum::<f64>()
             + self.horizontal_road_length.iter().take(j).sum::<f64>()
@@ -80,6 +205,7 @@ impl City {
         Position { x, y }
     }
 
+    /// Absolute position of the center of a road
     pub fn road_center(&self, direction: AxisDirection, index: RoadIndex) -> Position {
         use AxisDirection::*;
         match direction {
@@ -88,6 +214,7 @@ impl City {
         }
     }
 
+    /// Length of a road between the edges of its two intersections
     pub fn road_length(&self, direction: AxisDirection, (i, j): RoadIndex) -> f64 {
         use AxisDirection::*;
         match direction {
@@ -96,6 +223,7 @@ impl City {
         }
     }
 
+    /// Size of an intersection
     pub fn intersection_geometry(&self, (i, j): IntersectionIndex) -> Geometry {
         Geometry {
             width: self.intersection_width[j],
@@ -103,6 +231,32 @@ impl City {
         }
     }
 
+    /// Whether cars can enter an intersection from each direction
+    pub fn intersection_approaches(&self, intersection_index: IntersectionIndex) -> Around<bool> {
+        let context = self.board.context_of_intersection(intersection_index);
+        let approach = |direction: AbsoluteDirection| {
+            context
+                .get(direction)
+                .and_then(|road_index| {
+                    self.board
+                        .get_road(direction.axis_direction(), road_index)?
+                        .as_ref()
+                })
+                .map(|road| {
+                    let lane_direction =
+                        LaneDirection::absolute_in_out_to_lane(direction, InOutDirection::In);
+                    !road.lanes_to_direction(lane_direction).is_empty()
+                })
+                .unwrap_or(false)
+        };
+        Around {
+            north: approach(AbsoluteDirection::North),
+            west: approach(AbsoluteDirection::West),
+            south: approach(AbsoluteDirection::South),
+            east: approach(AbsoluteDirection::East),
+        }
+    }
+
     /// Return the join point relative to intersection center
     pub fn intersection_road_join_position(
         &self,
@@ -164,19 +318,155 @@ impl City {
         Some(from_position.distance(to_position))
     }
 
+    /// Return the straight path of a movement relative to intersection center
+    ///
+    /// The path ends at the middle of the outgoing lanes.
+    pub fn movement_path(
+        &self,
+        intersection_index: IntersectionIndex,
+        movement: &Movement,
+    ) -> Option<(Position, Position)> {
+        let from = self.intersection_road_join_position(
+            intersection_index,
+            movement.from_direction,
+            InOutDirection::In,
+            movement.from_lane_index,
+        )?;
+        let context = self.board.context_of_intersection(intersection_index);
+        let road_index = (*context.get(movement.to_direction))?;
+        let road = self
+            .board
+            .get_road(movement.to_direction.axis_direction(), road_index)?
+            .as_ref()?;
+        let lane_direction =
+            LaneDirection::absolute_in_out_to_lane(movement.to_direction, InOutDirection::Out);
+        // A road without lanes leaving the intersection can not be entered
+        let last_lane_index = road
+            .lanes_to_direction(lane_direction)
+            .len()
+            .checked_sub(1)?;
+        let first = self.intersection_road_join_position(
+            intersection_index,
+            movement.to_direction,
+            InOutDirection::Out,
+            0,
+        )?;
+        let last = self.intersection_road_join_position(
+            intersection_index,
+            movement.to_direction,
+            InOutDirection::Out,
+            last_lane_index,
+        )?;
+        let to = Position {
+            x: (first.x + last.x) / 2.0,
+            y: (first.y + last.y) / 2.0,
+        };
+        Some((from, to))
+    }
+
+    /// Whether two movements can not be in the intersection at the same time
+    ///
+    /// Movements from the same lane follow each other and never conflict,
+    /// movements merging into the same road or crossing each other do.
+    pub fn movements_conflict(
+        &self,
+        intersection_index: IntersectionIndex,
+        a: &Movement,
+        b: &Movement,
+    ) -> bool {
+        if a.from_direction == b.from_direction && a.from_lane_index == b.from_lane_index {
+            return false;
+        }
+        if a.to_direction == b.to_direction {
+            return true;
+        }
+        match (
+            self.movement_path(intersection_index, a),
+            self.movement_path(intersection_index, b),
+        ) {
+            (Some(a), Some(b)) => segments_cross(a, b),
+            _ => false,
+        }
+    }
+
+    /// Offset of the center line of a lane from the center line of its road
+    ///
+    /// The offset is across the road, toward the south of a horizontal road
+    /// and toward the west of a vertical road. Left-hand traffic mirrors the
+    /// lanes of right-hand traffic across the center line.
     pub fn lane_center_offset(
         &self,
         road: &Road,
         direction: LaneDirection,
         lane_index: usize,
     ) -> f64 {
-        let lane_number = road.lane_number();
-        let top = -self.lane_width * (lane_number - 1) as f64 / 2.0;
+        // In floats, a direction of a one-way road has no lanes to count from
+        let lane_number = road.lane_number() as f64;
+        let lanes_to_low = road.lane_to_low.len() as f64;
+        let top = -self.lane_width * (lane_number - 1.0) / 2.0;
         let lane_offset = match direction {
-            LaneDirection::HighToLow => road.lane_to_low.len() - 1 - lane_index,
-            LaneDirection::LowToHigh => road.lane_to_low.len() + lane_index,
+            LaneDirection::HighToLow => lanes_to_low - 1.0 - lane_index as f64,
+            LaneDirection::LowToHigh => lanes_to_low + lane_index as f64,
         };
-        top + lane_offset as f64 * self.lane_width
+        (top + lane_offset * self.lane_width) * self.handed_traffic.sign()
+    }
+
+    /// Absolute position of the center of a lane, at the middle of its road
+    ///
+    /// Return `None` if the road or the lane does not exist.
+    pub fn lane_center(
+        &self,
+        road_direction: AxisDirection,
+        road_index: RoadIndex,
+        lane_direction: LaneDirection,
+        lane_index: LaneIndex,
+    ) -> Option<Position> {
+        let road = self.board.get_road(road_direction, road_index)?.as_ref()?;
+        if lane_index >= road.lanes_to_direction(lane_direction).len() {
+            return None;
+        }
+        let offset = self.lane_center_offset(road, lane_direction, lane_index);
+        let center = self.road_center(road_direction, road_index);
+        let position = match road_direction {
+            AxisDirection::Horizontal => Position {
+                x: center.x,
+                y: center.y + offset,
+            },
+            AxisDirection::Vertical => Position {
+                x: center.x - offset,
+                y: center.y,
+            },
+        };
+        Some(position)
+    }
+}
+
+fn segments_cross((a1, a2): (Position, Position), (b1, b2): (Position, Position)) -> bool {
+    fn cross(o: Position, a: Position, b: Position) -> f64 {
+        (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+    }
+    let d1 = cross(b1, b2, a1);
+    let d2 = cross(b1, b2, a2);
+    let d3 = cross(a1, a2, b1);
+    let d4 = cross(a1, a2, b2);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Check there are `number` sizes, one for each row or column of the board,
+/// which are positive, or 0 too if `may_be_empty`.
+fn check_sizes(name: &str, sizes: &[f64], number: usize, may_be_empty: bool) -> Result<(), String> {
+    if sizes.len() != number {
+        return Err(format!(
+            "{} values of {} for {} on the board",
+            sizes.len(),
+            name,
+            number
+        ));
+    }
+    let valid = |size: f64| size.is_finite() && (size > 0.0 || (may_be_empty && size == 0.0));
+    match sizes.iter().find(|size| !valid(**size)) {
+        Some(size) => Err(format!("{} {} is not positive", name, size)),
+        None => Ok(()),
     }
 }
 
@@ -194,6 +484,7 @@ mod tests {
             vertical_road_length: vec![500.0, 500.0],
             intersection_height: vec![20.0, 20.0, 20.0],
             intersection_width: vec![20.0, 20.0, 20.0],
+            handed_traffic: Default::default(),
         }
     }
 
@@ -246,4 +537,452 @@ mod tests {
             assert_eq!((x, y), answer[i][j], "position: {:?}", (i, j));
         }
     }
+
+    #[test]
+    fn lane_center() {
+        use crate::model::common::TurnRule;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = Road {
+            lane_to_high: vec![lane.clone()],
+            lane_to_low: vec![lane],
+        };
+        let city = City {
+            board: Board::with_shape(None, Some(road), (3, 3)),
+            ..example_city()
+        };
+        use AxisDirection::*;
+        use LaneDirection::*;
+        let cases = vec![
+            ((Horizontal, (1, 0), HighToLow), (270.0, 528.25)),
+            ((Horizontal, (1, 0), LowToHigh), (270.0, 531.75)),
+            ((Vertical, (0, 1), HighToLow), (531.75, 270.0)),
+            ((Vertical, (0, 1), LowToHigh), (528.25, 270.0)),
+        ];
+        for ((direction, index, lane_direction), answer) in cases.into_iter() {
+            let Position { x, y } = city
+                .lane_center(direction, index, lane_direction, 0)
+                .unwrap();
+            assert_eq!(
+                (x, y),
+                answer,
+                "lane: {:?}",
+                (direction, index, lane_direction)
+            );
+        }
+        assert!(city.lane_center(Horizontal, (1, 0), LowToHigh, 1).is_none());
+        // Left-hand traffic mirrors the lanes across the center line
+        let city = City {
+            handed_traffic: HandedTraffic::Left,
+            ..city
+        };
+        let Position { x, y } = city.lane_center(Horizontal, (1, 0), HighToLow, 0).unwrap();
+        assert_eq!((x, y), (270.0, 531.75));
+    }
+
+    #[test]
+    fn lane_center_offsets_evenly_spaced() {
+        use crate::model::common::TurnRule;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let city = example_city();
+        let width = city.lane_width;
+        // One way, symmetric and asymmetric roads, as lanes to high and lanes
+        // to low
+        for &(to_high, to_low) in [(2, 0), (0, 3), (1, 1), (2, 2), (3, 1), (1, 2)].iter() {
+            let road = Road {
+                lane_to_high: vec![lane.clone(); to_high],
+                lane_to_low: vec![lane.clone(); to_low],
+            };
+            // Across the road from the lanes to low, outermost first, to the
+            // lanes to high, outermost last
+            let offsets = (0..to_low)
+                .rev()
+                .map(|i| city.lane_center_offset(&road, LaneDirection::HighToLow, i))
+                .chain(
+                    (0..to_high)
+                        .map(|i| city.lane_center_offset(&road, LaneDirection::LowToHigh, i)),
+                )
+                .collect::<Vec<_>>();
+            let lanes = (to_high, to_low);
+            for pair in offsets.windows(2) {
+                assert!(
+                    (pair[1] - pair[0] - width).abs() < 1e-9,
+                    "lanes: {:?}",
+                    lanes
+                );
+            }
+            // The lanes fill the road around its center line
+            let n = (to_high + to_low) as f64;
+            assert!(
+                (offsets[0] + width * (n - 1.0) / 2.0).abs() < 1e-9,
+                "lanes: {:?}",
+                lanes
+            );
+            assert!(
+                (offsets.iter().sum::<f64>()).abs() < 1e-9,
+                "lanes: {:?}",
+                lanes
+            );
+            // Left-hand traffic mirrors every lane
+            let left = City {
+                handed_traffic: HandedTraffic::Left,
+                ..city.clone()
+            };
+            for &direction in [LaneDirection::HighToLow, LaneDirection::LowToHigh].iter() {
+                for i in 0..road.lanes_to_direction(direction).len() {
+                    assert_eq!(
+                        left.lane_center_offset(&road, direction, i),
+                        -city.lane_center_offset(&road, direction, i)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lane_centers_scale_with_lane_width() {
+        use crate::model::common::TurnRule;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = Road {
+            lane_to_high: vec![lane.clone(); 2],
+            lane_to_low: vec![lane; 1],
+        };
+        let city = example_city();
+        let wide = City {
+            lane_width: city.lane_width * 2.0,
+            ..city.clone()
+        };
+        for &direction in [LaneDirection::HighToLow, LaneDirection::LowToHigh].iter() {
+            for i in 0..road.lanes_to_direction(direction).len() {
+                let offset = city.lane_center_offset(&road, direction, i);
+                assert!(
+                    (wide.lane_center_offset(&road, direction, i) - 2.0 * offset).abs() < 1e-9,
+                    "lane {} to {:?}",
+                    i,
+                    direction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn intersection_approaches() {
+        use crate::model::common::TurnRule;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = Road {
+            lane_to_high: vec![lane.clone()],
+            lane_to_low: vec![lane.clone()],
+        };
+        let mut city = City {
+            board: Board::with_shape(None, Some(road), (3, 3)),
+            ..example_city()
+        };
+        // One way road leaving the intersection to the east
+        city.board.horizontal_roads[(1, 1)] = Some(Road {
+            lane_to_high: vec![lane],
+            lane_to_low: Vec::new(),
+        });
+        city.board.vertical_roads[(1, 1)] = None;
+        assert_eq!(
+            city.intersection_approaches((1, 1)),
+            Around {
+                north: true,
+                west: true,
+                south: false,
+                east: false,
+            }
+        );
+        assert_eq!(
+            city.intersection_approaches((0, 0)),
+            Around {
+                north: false,
+                west: false,
+                south: true,
+                east: true,
+            }
+        );
+    }
+
+    #[test]
+    fn movement_onto_road_without_lanes() {
+        use crate::model::common::TurnRule;
+        use AbsoluteDirection::*;
+        // Every road is one way to the east or to the south
+        let road = Road {
+            lane_to_high: vec![Lane {
+                max_speed: 10.0,
+                speed_limit: None,
+                allowed_classes: None,
+                direction_rule: TurnRule::ALL,
+            }],
+            lane_to_low: Vec::new(),
+        };
+        let city = City {
+            board: Board::with_shape(None, Some(road), (3, 3)),
+            ..example_city()
+        };
+        let movement = |to_direction| Movement {
+            from_direction: West,
+            from_lane_index: 0,
+            to_direction,
+        };
+        assert!(city.movement_path((1, 1), &movement(South)).is_some());
+        assert!(city.movement_path((1, 1), &movement(North)).is_none());
+    }
+
+    #[test]
+    fn movements_conflict() {
+        use crate::model::common::TurnRule;
+        use AbsoluteDirection::*;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = Road {
+            lane_to_high: vec![lane.clone()],
+            lane_to_low: vec![lane],
+        };
+        let city = City {
+            board: Board::with_shape(None, Some(road), (3, 3)),
+            ..example_city()
+        };
+        let movement = |from_direction, to_direction| Movement {
+            from_direction,
+            from_lane_index: 0,
+            to_direction,
+        };
+        let cases = vec![
+            ((North, South), (South, North), false),
+            ((North, South), (East, West), true),
+            ((North, South), (West, South), true),
+            ((North, South), (North, East), false),
+            ((North, West), (South, East), false),
+        ];
+        for ((a_from, a_to), (b_from, b_to), conflict) in cases.into_iter() {
+            let a = movement(a_from, a_to);
+            let b = movement(b_from, b_to);
+            assert_eq!(
+                city.movements_conflict((1, 1), &a, &b),
+                conflict,
+                "{:?} {:?}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn signal_plans_switch() {
+        let city_with_times = |times| {
+            let mut city = example_city();
+            city.board.intersections[(1, 1)] = Some(Intersection::Crossroad {
+                max_speed: 10.0,
+                rules: vec![Default::default()],
+                switch_rule: intersection::SwitchRule::LoopTimeout { times, offset: 0.0 },
+            });
+            city
+        };
+        assert_eq!(city_with_times(vec![10.0, 5.0]).validate(), Ok(()));
+        for times in [vec![], vec![0.0, 0.0], vec![10.0, -1.0], vec![f64::NAN]].iter() {
+            assert!(
+                city_with_times(times.clone()).validate().is_err(),
+                "{:?}",
+                times
+            );
+        }
+    }
+
+    #[test]
+    fn protected_turns_only_in_signals() {
+        let city_with_rule = |rule| {
+            let mut city = example_city();
+            city.board.intersections[(1, 1)] = Some(Intersection::Crossroad {
+                max_speed: 10.0,
+                rules: vec![rule],
+                switch_rule: intersection::SwitchRule::LoopTimeout {
+                    times: vec![10.0],
+                    offset: 0.0,
+                },
+            });
+            city
+        };
+        let arrow = Around {
+            north: TurnRule::RIGHT,
+            west: TurnRule::empty(),
+            south: TurnRule::ALL | TurnRule::PROTECTED,
+            east: TurnRule::empty(),
+        };
+        assert!(!TurnRule::all().contains(TurnRule::PROTECTED));
+        assert_eq!(city_with_rule(arrow).validate(), Ok(()));
+        let against_oncoming = Around {
+            north: TurnRule::FRONT,
+            ..arrow
+        };
+        assert!(city_with_rule(against_oncoming).validate().is_err());
+
+        let mut city = example_city();
+        city.board.get_roads_mut(AxisDirection::Horizontal)[(0, 0)] = Some(Road {
+            lane_to_high: vec![Lane {
+                max_speed: 0.0,
+                speed_limit: None,
+                allowed_classes: None,
+                direction_rule: TurnRule::FRONT | TurnRule::PROTECTED,
+            }],
+            lane_to_low: vec![],
+        });
+        assert!(city.validate().is_err());
+    }
+
+    #[test]
+    fn sizes_fit_board() {
+        let invalid = [
+            City {
+                horizontal_road_length: vec![500.0],
+                ..example_city()
+            },
+            City {
+                vertical_road_length: vec![500.0, 0.0],
+                ..example_city()
+            },
+            City {
+                intersection_width: vec![20.0, -1.0, 20.0],
+                ..example_city()
+            },
+            City {
+                intersection_height: vec![20.0, 20.0, 20.0, 20.0],
+                ..example_city()
+            },
+        ];
+        for city in invalid.iter() {
+            assert!(city.validate().is_err(), "{:?}", city);
+        }
+        let city = City {
+            intersection_width: vec![20.0, 0.0, 20.0],
+            ..example_city()
+        };
+        assert_eq!(city.validate(), Ok(()));
+    }
+
+    #[test]
+    fn lanes_fit_vehicles() {
+        assert_eq!(example_city().validate(), Ok(()));
+        for &lane_width in [1.7, 2.0, f64::NAN, f64::INFINITY].iter() {
+            let city = City {
+                lane_width,
+                ..example_city()
+            };
+            assert!(city.validate().is_err(), "{}", lane_width);
+        }
+        let car = |width| Car {
+            class: Default::default(),
+            length: 4.5,
+            width,
+            max_velocity: 30.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 15.0,
+            driving_model: car::DrivingModel::Normal {
+                min_cushion: 10.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 5.0,
+            },
+        };
+        let model = |width| Model {
+            city: example_city(),
+            cars: vec![car(width)],
+            ..Default::default()
+        };
+        assert_eq!(model(2.5).validate(), Ok(()));
+        assert!(model(4.0).validate().is_err());
+    }
+
+    #[test]
+    fn detectors_on_lanes() {
+        use crate::model::common::TurnRule;
+        let lane = Lane {
+            max_speed: 10.0,
+            speed_limit: None,
+            allowed_classes: None,
+            direction_rule: TurnRule::ALL,
+        };
+        let road = Road {
+            lane_to_high: vec![lane],
+            lane_to_low: vec![],
+        };
+        let mut city = example_city();
+        city.board.horizontal_roads[(0, 0)] = Some(road);
+        let model_with = |detector| Model {
+            city: city.clone(),
+            detectors: vec![detector],
+            ..Default::default()
+        };
+        let detector = Detector {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+            lane_index: 0,
+            position: 100.0,
+            length: 2.0,
+        };
+        assert_eq!(model_with(detector.clone()).validate(), Ok(()));
+        let invalid = vec![
+            Detector {
+                road_index: (0, 1),
+                ..detector.clone()
+            },
+            Detector {
+                road_index: (5, 0),
+                ..detector.clone()
+            },
+            Detector {
+                lane_direction: LaneDirection::HighToLow,
+                ..detector.clone()
+            },
+            Detector {
+                lane_index: 1,
+                ..detector.clone()
+            },
+            Detector {
+                position: -1.0,
+                ..detector.clone()
+            },
+            Detector {
+                position: 499.0,
+                ..detector.clone()
+            },
+            Detector {
+                length: f64::NAN,
+                ..detector.clone()
+            },
+        ];
+        for detector in invalid.into_iter() {
+            assert!(
+                model_with(detector.clone()).validate().is_err(),
+                "{:?}",
+                detector
+            );
+        }
+    }
 }
@@ -1,4 +1,7 @@
-use crate::model::common::{LaneDirection, TurnRule};
+use crate::model::{
+    common::{LaneDirection, TurnRule},
+    stateless::car::VehicleClass,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -23,6 +26,13 @@ impl Road {
         }
     }
 
+    /// Whether a lane to `lane_direction` allows vehicles of `class`.
+    pub fn allows(&self, lane_direction: LaneDirection, class: VehicleClass) -> bool {
+        self.lanes_to_direction(lane_direction)
+            .iter()
+            .any(|lane| lane.allows(class))
+    }
+
     pub fn lanes_to_direction_mut(&mut self, lane_direction: LaneDirection) -> &mut Vec<Lane> {
         match lane_direction {
             LaneDirection::HighToLow => &mut self.lane_to_low,
@@ -33,6 +43,32 @@ impl Road {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Lane {
+    /// Meters per second, 0 if the lane has no limit of its own and the
+    /// default speed limit of the simulation applies.
+    #[serde(default)]
     pub max_speed: f64,
+    /// Meters per second, overrides `max_speed` for this lane only, as for a
+    /// turn pocket or a slower rightmost lane of a road.
+    #[serde(default)]
+    pub speed_limit: Option<f64>,
+    /// Classes of the vehicles which may drive on the lane, as buses on a
+    /// bus lane, every class if not set.
+    #[serde(default)]
+    pub allowed_classes: Option<Vec<VehicleClass>>,
     pub direction_rule: TurnRule,
 }
+
+impl Lane {
+    /// Whether vehicles of `class` may drive on the lane.
+    pub fn allows(&self, class: VehicleClass) -> bool {
+        match &self.allowed_classes {
+            Some(classes) => classes.contains(&class),
+            None => true,
+        }
+    }
+
+    /// Whether some classes may not drive on the lane.
+    pub fn is_restricted(&self) -> bool {
+        self.allowed_classes.is_some()
+    }
+}
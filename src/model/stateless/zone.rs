@@ -0,0 +1,30 @@
+use crate::model::common::Position;
+use serde::{Deserialize, Serialize};
+
+/// Rectangle of the city colored behind the roads for context, such as a
+/// residential or a commercial area.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Zone {
+    /// Kind of the zone, such as `residential`.
+    #[serde(default)]
+    pub name: String,
+    /// Corner of the rectangle with the smallest coordinates, in meters.
+    pub min: Position,
+    /// Corner of the rectangle with the largest coordinates, in meters.
+    pub max: Position,
+    /// Fill of the rectangle as red, green, blue and alpha from 0 to 1.
+    pub color: [f32; 4],
+}
+
+impl Zone {
+    /// The rectangle of the zone as x, y, width and height, empty if the
+    /// corners are swapped.
+    pub fn rectangle(&self) -> [f64; 4] {
+        [
+            self.min.x,
+            self.min.y,
+            (self.max.x - self.min.x).max(0.0),
+            (self.max.y - self.min.y).max(0.0),
+        ]
+    }
+}
@@ -1,2 +1,3 @@
 pub mod dump;
 pub mod matrix;
+pub mod pace;
@@ -0,0 +1,102 @@
+//! Pacing updates to the wall clock.
+
+use log::warn;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Seconds the simulation may fall behind its pace before the pace restarts
+/// from the current time, instead of hurrying to catch up.
+const MAX_LAG: f64 = 0.5;
+
+/// Keeps the simulated time at a fixed ratio of the wall clock time by
+/// sleeping after updates which are ahead of it.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    /// Simulated seconds of a wall clock second.
+    factor: f64,
+    start: Instant,
+    /// Wall clock seconds from `start` when the current pace started.
+    origin: f64,
+    /// Simulated seconds since the current pace started.
+    simulated: f64,
+}
+
+/// Where an update is relative to the pace.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pace {
+    /// Ahead of the pace, which is met after waiting this long.
+    Ahead(Duration),
+    OnTime,
+    /// Behind the pace by these seconds, the pace restarted.
+    Behind(f64),
+}
+
+impl Pacer {
+    pub fn new(factor: f64) -> Result<Self, String> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return Err(format!("realtime factor {} is not positive", factor));
+        }
+        Ok(Self {
+            factor,
+            start: Instant::now(),
+            origin: 0.0,
+            simulated: 0.0,
+        })
+    }
+
+    /// Add an update of `dt` simulated seconds, `elapsed` wall clock seconds
+    /// after the pacer was created.
+    pub fn pace(&mut self, dt: f64, elapsed: f64) -> Pace {
+        self.simulated += dt;
+        let target = self.origin + self.simulated / self.factor;
+        if target > elapsed {
+            Pace::Ahead(Duration::from_secs_f64(target - elapsed))
+        } else if elapsed - target > MAX_LAG {
+            self.origin = elapsed;
+            self.simulated = 0.0;
+            Pace::Behind(elapsed - target)
+        } else {
+            Pace::OnTime
+        }
+    }
+
+    /// Add an update of `dt` simulated seconds and sleep until the pace is
+    /// met, warning when it cannot be kept.
+    pub fn wait(&mut self, dt: f64) {
+        match self.pace(dt, self.start.elapsed().as_secs_f64()) {
+            Pace::Ahead(delay) => thread::sleep(delay),
+            Pace::OnTime => (),
+            Pace::Behind(lag) => warn!(
+                "{:.2} s behind the realtime factor {}, the updates are too slow",
+                lag, self.factor
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pace_simulated_time_to_wall_clock() {
+        assert!(Pacer::new(0.0).is_err());
+        assert!(Pacer::new(f64::NAN).is_err());
+        let ahead = |pace| match pace {
+            Pace::Ahead(delay) => Duration::as_secs_f64(&delay),
+            pace => panic!("not ahead: {:?}", pace),
+        };
+        // Two simulated seconds a second
+        let mut pacer = Pacer::new(2.0).unwrap();
+        assert!((ahead(pacer.pace(1.0, 0.1)) - 0.4).abs() < 1e-9);
+        assert_eq!(pacer.pace(1.0, 1.2), Pace::OnTime);
+        // Too far behind, the pace restarts from there
+        match pacer.pace(1.0, 3.0) {
+            Pace::Behind(lag) => assert!((lag - 1.5).abs() < 1e-9, "{}", lag),
+            pace => panic!("not behind: {:?}", pace),
+        }
+        assert!((ahead(pacer.pace(1.0, 3.0)) - 0.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,179 @@
+//! Transform between model coordinates and window coordinates.
+
+use crate::model::common::{Geometry, Position};
+use piston_window::{math, types::Matrix2d, Transformed};
+
+/// Range of the zoom of `zoom_at`, so the view neither vanishes nor flips.
+const MIN_ZOOM: f64 = 0.05;
+const MAX_ZOOM: f64 = 100.0;
+
+/// A translation after a zoom, from the coordinates of the model to the
+/// logical coordinates of the window.
+///
+/// The camera of the window panned and zoomed by the user applies on top of
+/// the camera which fits the city in the window, see `compose`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Camera {
+    /// The camera which leaves coordinates unchanged.
+    pub fn new() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+        }
+    }
+
+    /// The camera which shows a model of `geometry` as large as possible
+    /// in the center of a window of `window_size`, `padding` away from its
+    /// sides.
+    pub fn fit(geometry: Geometry, window_size: [f64; 2], padding: f64) -> Self {
+        // Model logical width and model height
+        let Geometry {
+            width: mw,
+            height: mh,
+        } = geometry;
+        // Window width and window height
+        let [ww, wh] = window_size;
+        // Model container width and model container height
+        let (cw, ch) = (ww - 2.0 * padding, wh - 2.0 * padding);
+        let (cx, cy) = (padding, padding);
+        // An empty city or a window smaller than the padding has nothing to
+        // fit, keep the transform finite
+        if !(mw > 0.0 && mh > 0.0 && cw > 0.0 && ch > 0.0) {
+            return Self {
+                x: cx,
+                y: cy,
+                zoom: 1.0,
+            };
+        }
+
+        let model_ratio = mw / mh;
+        let container_ratio = cw / ch;
+        let zoom = if model_ratio > container_ratio {
+            cw / mw
+        } else {
+            ch / mh
+        };
+        let (zw, zh) = (mw * zoom, mh * zoom);
+        let (x, y) = if model_ratio > container_ratio {
+            (cx, cy + (ch - zh) / 2.0)
+        } else {
+            (cx + (cw - zw) / 2.0, cy)
+        };
+        Self { x, y, zoom }
+    }
+
+    /// The camera applying `inner` first, then this camera.
+    pub fn compose(&self, inner: Camera) -> Self {
+        Self {
+            x: self.x + self.zoom * inner.x,
+            y: self.y + self.zoom * inner.y,
+            zoom: self.zoom * inner.zoom,
+        }
+    }
+
+    /// The camera as a transform to append to the transform of a context.
+    pub fn transform(&self) -> Matrix2d {
+        math::identity().trans(self.x, self.y).zoom(self.zoom)
+    }
+
+    pub fn world_to_screen(&self, position: Position) -> [f64; 2] {
+        [
+            self.x + self.zoom * position.x,
+            self.y + self.zoom * position.y,
+        ]
+    }
+
+    pub fn screen_to_world(&self, [sx, sy]: [f64; 2]) -> Position {
+        Position {
+            x: (sx - self.x) / self.zoom,
+            y: (sy - self.y) / self.zoom,
+        }
+    }
+
+    /// Move what is shown by `dx` and `dy` in window coordinates.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    /// Change the zoom to `zoom`, clamped to a positive range, keeping what
+    /// is shown at `screen` in place.
+    pub fn zoom_at(&mut self, screen: [f64; 2], zoom: f64) {
+        if zoom.is_nan() {
+            return;
+        }
+        let position = self.screen_to_world(screen);
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let [sx, sy] = self.world_to_screen(position);
+        self.pan(screen[0] - sx, screen[1] - sy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_transforms() {
+        let fit = Camera::fit(
+            Geometry {
+                width: 400.0,
+                height: 100.0,
+            },
+            [800.0, 600.0],
+            10.0,
+        );
+        // The city is as wide as the window without the padding, centered
+        // vertically
+        assert_eq!(
+            fit,
+            Camera {
+                x: 10.0,
+                y: 202.5,
+                zoom: 1.95
+            }
+        );
+        let mut camera = Camera {
+            x: -30.0,
+            y: 12.0,
+            zoom: 1.5,
+        }
+        .compose(fit);
+        let position = Position { x: 120.0, y: 40.0 };
+        let screen = camera.world_to_screen(position);
+        assert_eq!(
+            math::transform_pos(camera.transform(), [position.x, position.y]),
+            screen
+        );
+        let back = camera.screen_to_world(screen);
+        assert!((back.x - position.x).abs() < 1e-9 && (back.y - position.y).abs() < 1e-9);
+
+        camera.zoom_at(screen, 4.0);
+        let [x, y] = camera.world_to_screen(position);
+        assert!((x - screen[0]).abs() < 1e-9 && (y - screen[1]).abs() < 1e-9);
+        camera.pan(5.0, -5.0);
+        assert_eq!(camera.world_to_screen(position), [x + 5.0, y - 5.0]);
+
+        // Zooming out past the floor keeps the view upright and in place
+        let screen = camera.world_to_screen(position);
+        camera.zoom_at(screen, -2.0);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        let [x, y] = camera.world_to_screen(position);
+        assert!((x - screen[0]).abs() < 1e-9 && (y - screen[1]).abs() < 1e-9);
+        camera.zoom_at(screen, f64::NAN);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+}
@@ -0,0 +1,157 @@
+//! Backends of the drawing primitives used by the view.
+//!
+//! The window draws with piston, `Svg` records the same shapes as an SVG
+//! document for figures.
+
+use piston_window::{
+    math,
+    types::{Color, Matrix2d},
+    G2d,
+};
+use std::fmt::Write;
+
+/// Filled shapes in coordinates mapped by a transform, as piston draws them.
+pub trait Canvas {
+    /// Draw the rectangle `[x, y, width, height]`.
+    fn rectangle(&mut self, color: Color, rectangle: [f64; 4], transform: Matrix2d);
+    fn polygon(&mut self, color: Color, points: &[[f64; 2]], transform: Matrix2d);
+    /// Draw the line `[x1, y1, x2, y2]` with half of its width as `radius`.
+    fn line(&mut self, color: Color, radius: f64, line: [f64; 4], transform: Matrix2d);
+}
+
+impl<'a> Canvas for G2d<'a> {
+    fn rectangle(&mut self, color: Color, rectangle: [f64; 4], transform: Matrix2d) {
+        piston_window::rectangle(color, rectangle, transform, self);
+    }
+
+    fn polygon(&mut self, color: Color, points: &[[f64; 2]], transform: Matrix2d) {
+        piston_window::polygon(color, points, transform, self);
+    }
+
+    fn line(&mut self, color: Color, radius: f64, line: [f64; 4], transform: Matrix2d) {
+        piston_window::line(color, radius, line, transform, self);
+    }
+}
+
+/// An SVG document of `width` by `height` pixels.
+#[derive(Clone, Debug)]
+pub struct Svg {
+    width: f64,
+    height: f64,
+    body: String,
+}
+
+impl Svg {
+    pub fn new(width: f64, height: f64, background: Color) -> Self {
+        let mut svg = Self {
+            width,
+            height,
+            body: String::new(),
+        };
+        svg.rectangle(background, [0.0, 0.0, width, height], math::identity());
+        svg
+    }
+
+    /// The complete document.
+    pub fn finish(self) -> String {
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\">\n{}</svg>\n",
+            self.body,
+            w = self.width,
+            h = self.height
+        )
+    }
+}
+
+impl Canvas for Svg {
+    fn rectangle(&mut self, color: Color, rectangle: [f64; 4], transform: Matrix2d) {
+        let [x, y, w, h] = rectangle;
+        self.polygon(
+            color,
+            &[[x, y], [x + w, y], [x + w, y + h], [x, y + h]],
+            transform,
+        );
+    }
+
+    fn polygon(&mut self, color: Color, points: &[[f64; 2]], transform: Matrix2d) {
+        let points = points
+            .iter()
+            .map(|point| {
+                let [x, y] = math::transform_pos(transform, *point);
+                format!("{:.3},{:.3}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            self.body,
+            "<polygon points=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+            points,
+            hex(color),
+            color[3]
+        )
+        .unwrap();
+    }
+
+    fn line(&mut self, color: Color, radius: f64, line: [f64; 4], transform: Matrix2d) {
+        let [x1, y1] = math::transform_pos(transform, [line[0], line[1]]);
+        let [x2, y2] = math::transform_pos(transform, [line[2], line[3]]);
+        // Lengths are scaled by the square root of the determinant
+        let scale = (transform[0][0] * transform[1][1] - transform[0][1] * transform[1][0])
+            .abs()
+            .sqrt();
+        writeln!(
+            self.body,
+            "<line x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\" stroke=\"{}\" \
+             stroke-opacity=\"{}\" stroke-width=\"{:.3}\"/>",
+            x1,
+            y1,
+            x2,
+            y2,
+            hex(color),
+            color[3],
+            2.0 * radius * scale
+        )
+        .unwrap();
+    }
+}
+
+/// `#rrggbb` of a color, without the alpha.
+fn hex(color: Color) -> String {
+    let component = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        component(color[0]),
+        component(color[1]),
+        component(color[2])
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston_window::Transformed;
+
+    #[test]
+    fn svg_shapes_are_transformed() {
+        let mut svg = Svg::new(10.0, 20.0, [0.0, 0.0, 0.0, 1.0]);
+        let transform = math::identity().trans(1.0, 2.0).zoom(2.0);
+        svg.rectangle([1.0, 0.0, 0.0, 0.5], [0.0, 0.0, 1.0, 1.0], transform);
+        svg.line([1.0, 1.0, 1.0, 1.0], 0.5, [0.0, 0.0, 3.0, 0.0], transform);
+        let document = svg.finish();
+        let lines = document.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("<svg "));
+        assert_eq!(
+            lines[2],
+            "<polygon points=\"1.000,2.000 3.000,2.000 3.000,4.000 1.000,4.000\" \
+             fill=\"#ff0000\" fill-opacity=\"0.5\"/>"
+        );
+        assert_eq!(
+            lines[3],
+            "<line x1=\"1.000\" y1=\"2.000\" x2=\"7.000\" y2=\"2.000\" stroke=\"#ffffff\" \
+             stroke-opacity=\"1\" stroke-width=\"2.000\"/>"
+        );
+        assert_eq!(lines[4], "</svg>");
+    }
+}
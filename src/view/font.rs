@@ -0,0 +1,119 @@
+//! A tiny bitmap font for labels, which needs no font files.
+//!
+//! Glyphs are 3 pixels wide and 5 pixels high, letters are upper case only.
+
+use crate::view::canvas::Canvas;
+use piston_window::types::{Color, Matrix2d};
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+/// Pixels between two glyphs.
+pub const GLYPH_SPACING: usize = 1;
+
+/// Rows of a glyph from top to bottom, the highest of the 3 bits is the left
+/// pixel. Unknown characters are blank.
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+/// Width of a text drawn with pixels of `pixel_size`.
+pub fn text_width(text: &str, pixel_size: f64) -> f64 {
+    let n = text.chars().count();
+    if n == 0 {
+        0.0
+    } else {
+        (n * (GLYPH_WIDTH + GLYPH_SPACING) - GLYPH_SPACING) as f64 * pixel_size
+    }
+}
+
+/// Draw a text with its top left corner at the origin.
+pub fn draw_text<C: Canvas>(
+    text: &str,
+    color: Color,
+    pixel_size: f64,
+    transform: Matrix2d,
+    canvas: &mut C,
+) {
+    for (n, c) in text.chars().enumerate() {
+        let left = (n * (GLYPH_WIDTH + GLYPH_SPACING)) as f64 * pixel_size;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    canvas.rectangle(
+                        color,
+                        [
+                            left + column as f64 * pixel_size,
+                            row as f64 * pixel_size,
+                            pixel_size,
+                            pixel_size,
+                        ],
+                        transform,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_fit_and_width() {
+        for c in ('A'..='Z').chain('0'..='9') {
+            let glyph = glyph(c);
+            assert!(glyph.iter().all(|row| *row < 1 << GLYPH_WIDTH), "{}", c);
+            assert!(glyph.iter().any(|row| *row != 0), "{}", c);
+        }
+        assert_eq!(glyph('n'), glyph('N'));
+        assert_eq!(glyph('?'), [0; GLYPH_HEIGHT]);
+        assert_eq!(text_width("", 2.0), 0.0);
+        assert_eq!(text_width("GO", 2.0), 14.0);
+    }
+}
@@ -1,19 +1,40 @@
+pub mod camera;
+pub mod canvas;
+pub mod font;
+pub mod overlay;
+pub mod split;
+
 use crate::{
+    controller::{history::History, params::SpeedUnit},
     info::Info,
     model::{
         board::{IntersectionIndex, RoadIndex},
         common::{
-            AbsoluteDirection, AxisDirection, Geometry, InOutDirection, LaneDirection, LaneIndex,
-            Position, RelativeDirection, TurnRule,
+            AbsoluteDirection, Around, AxisDirection, CarIndex, Geometry, HandedTraffic,
+            InOutDirection, LaneDirection, LaneIndex, Position, RelativeDirection, TurnRule,
         },
-        stateful, stateless,
+        routing::Segment,
+        stateful,
+        stateless::{self, car::VehicleClass},
     },
+    Error,
 };
+pub use camera::Camera;
+use canvas::{Canvas, Svg};
+use overlay::Overlay;
 use piston_window::{
+    color,
     context::Context,
-    polygon, rectangle,
+    line, math, polygon, rectangle,
     types::{Color, Matrix2d},
-    G2d, Transformed,
+    Button, ButtonArgs, ButtonState, G2d, Input, Key, MouseButton, Transformed, Viewport,
+};
+use split::Split;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
 };
 use structopt::StructOpt;
 
@@ -40,6 +61,14 @@ pub struct ViewSettings {
         parse(from_str = piston_window::color::hex)
     )]
     pub road_color: Color,
+    /// Color of lanes only some vehicle classes may drive on, as bus lanes.
+    #[structopt(
+        name = "view-restricted-lane-color",
+        long = "view-restricted-lane-color",
+        default_value = "994d4d",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub restricted_lane_color: Color,
     #[structopt(
         name = "view-road-sign-color",
         long = "view-road-sign-color",
@@ -80,6 +109,21 @@ pub struct ViewSettings {
         parse(from_str = piston_window::color::hex)
     )]
     pub intersection_sign_color: Color,
+    /// Color of the flashing signs of failed signals.
+    #[structopt(
+        name = "view-failed-signal-color",
+        long = "view-failed-signal-color",
+        default_value = "ff3333",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub failed_signal_color: Color,
+    /// Seconds of a flash of failed signals, lit for the first half.
+    #[structopt(
+        name = "view-failed-signal-period",
+        long = "view-failed-signal-period",
+        default_value = "1.0"
+    )]
+    pub failed_signal_period: f64,
     #[structopt(
         name = "view-car-color",
         long = "view-car-color",
@@ -87,18 +131,393 @@ pub struct ViewSettings {
         parse(from_str = piston_window::color::hex)
     )]
     pub car_color: Color,
+    /// Draw faded copies of the cars at their recent locations.
+    #[structopt(name = "view-show-trails", long = "view-show-trails")]
+    pub show_trails: bool,
+    /// Number of recent locations of a car in its trail.
+    #[structopt(
+        name = "view-trail-length",
+        long = "view-trail-length",
+        default_value = "8"
+    )]
+    pub trail_length: usize,
+    /// Opacity of the newest copy of a car in its trail.
+    #[structopt(
+        name = "view-trail-opacity",
+        long = "view-trail-opacity",
+        default_value = "0.5"
+    )]
+    pub trail_opacity: f64,
+    /// Draw translucent cars where the next update puts them while paused,
+    /// toggled with the 9 key.
+    #[structopt(name = "view-show-ghosts", long = "view-show-ghosts")]
+    pub show_ghosts: bool,
+    /// Opacity of the cars drawn where the next update puts them.
+    #[structopt(
+        name = "view-ghost-opacity",
+        long = "view-ghost-opacity",
+        default_value = "0.4"
+    )]
+    pub ghost_opacity: f64,
+    /// Scale of drawn cars over their length and width in the model.
+    #[structopt(name = "view-car-scale", long = "view-car-scale", default_value = "1")]
+    pub car_scale: f64,
+    /// Shape of cars of the car class, one of "rectangle", "rounded" and
+    /// "triangle".
+    #[structopt(
+        name = "view-car-shape",
+        long = "view-car-shape",
+        default_value = "rectangle"
+    )]
+    pub car_shape: CarShape,
+    #[structopt(
+        name = "view-bus-shape",
+        long = "view-bus-shape",
+        default_value = "rounded"
+    )]
+    pub bus_shape: CarShape,
+    #[structopt(
+        name = "view-truck-shape",
+        long = "view-truck-shape",
+        default_value = "triangle"
+    )]
+    pub truck_shape: CarShape,
+    /// How cars are colored, one of "fixed" for the car color,
+    /// "destination" for a hue per destination intersection and
+    /// "aggressiveness" for a hue by the desired speed factor of the driver.
+    #[structopt(
+        name = "view-car-coloring",
+        long = "view-car-coloring",
+        default_value = "fixed"
+    )]
+    pub car_coloring: CarColoring,
+    /// Desired speed factor of the calmest drivers, blue in the
+    /// "aggressiveness" coloring.
+    #[structopt(
+        name = "view-aggressiveness-min",
+        long = "view-aggressiveness-min",
+        default_value = "0.85"
+    )]
+    pub aggressiveness_min: f64,
+    /// Desired speed factor of the most aggressive drivers, red in the
+    /// "aggressiveness" coloring.
+    #[structopt(
+        name = "view-aggressiveness-max",
+        long = "view-aggressiveness-max",
+        default_value = "1.15"
+    )]
+    pub aggressiveness_max: f64,
+    #[structopt(
+        name = "view-selected-car-color",
+        long = "view-selected-car-color",
+        default_value = "ffff00",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub selected_car_color: Color,
+    /// Color of the remaining route of the selected car.
+    #[structopt(
+        name = "view-route-color",
+        long = "view-route-color",
+        default_value = "33ff9980",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub route_color: Color,
+    /// Show the graph of the average speed, toggled with the 1 key.
+    #[structopt(name = "view-show-graph", long = "view-show-graph")]
+    pub show_graph: bool,
+    #[structopt(
+        name = "view-graph-width",
+        long = "view-graph-width",
+        default_value = "240.0"
+    )]
+    pub graph_width: f64,
+    #[structopt(
+        name = "view-graph-height",
+        long = "view-graph-height",
+        default_value = "100.0"
+    )]
+    pub graph_height: f64,
     #[structopt(
-        name = "view-car-length",
-        long = "view-car-length",
-        default_value = "4.5"
+        name = "view-graph-color",
+        long = "view-graph-color",
+        default_value = "33ccff",
+        parse(from_str = piston_window::color::hex)
     )]
-    pub car_length: f64,
+    pub graph_color: Color,
     #[structopt(
-        name = "view-car-width",
-        long = "view-car-width",
-        default_value = "1.7"
+        name = "view-graph-axis-color",
+        long = "view-graph-axis-color",
+        default_value = "ffffff",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub graph_axis_color: Color,
+    /// Show the legend of directions and signs, toggled with the 2 or the L
+    /// key.
+    #[structopt(name = "view-show-legend", long = "view-show-legend")]
+    pub show_legend: bool,
+    #[structopt(
+        name = "view-legend-color",
+        long = "view-legend-color",
+        default_value = "ffffff",
+        parse(from_str = piston_window::color::hex)
     )]
-    pub car_width: f64,
+    pub legend_color: Color,
+    #[structopt(
+        name = "view-legend-background-color",
+        long = "view-legend-background-color",
+        default_value = "262626",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub legend_background_color: Color,
+    /// Hide the turn rules of lanes, toggled with the 3 key.
+    #[structopt(name = "view-hide-lane-signs", long = "view-hide-lane-signs")]
+    pub hide_lane_signs: bool,
+    /// Hide the turn rules of signals, toggled with the 4 key.
+    #[structopt(name = "view-hide-signals", long = "view-hide-signals")]
+    pub hide_signals: bool,
+    /// Show the seconds remaining in the current phases of signals, toggled
+    /// with the 5 key.
+    #[structopt(name = "view-show-phase-timer", long = "view-show-phase-timer")]
+    pub show_phase_timer: bool,
+    /// Seconds over which signs of signals fade from the previous rule to
+    /// the current one, 0 to switch at once.
+    #[structopt(
+        name = "view-signal-transition",
+        long = "view-signal-transition",
+        default_value = "0.3"
+    )]
+    pub signal_transition: f64,
+    /// Seconds an intersection stays highlighted after going to it with
+    /// the G key.
+    #[structopt(
+        name = "view-highlight-time",
+        long = "view-highlight-time",
+        default_value = "2.0"
+    )]
+    pub highlight_time: f64,
+    /// Show a grid through the center lines of the roads behind them,
+    /// toggled with the 6 key.
+    #[structopt(name = "view-show-grid", long = "view-show-grid")]
+    pub show_grid: bool,
+    #[structopt(
+        name = "view-grid-color",
+        long = "view-grid-color",
+        default_value = "ffffff1a",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub grid_color: Color,
+    /// Meters of the width of grid lines.
+    #[structopt(
+        name = "view-grid-width",
+        long = "view-grid-width",
+        default_value = "0.5"
+    )]
+    pub grid_width: f64,
+    /// Hide the zones of the model behind the roads, toggled with the 0
+    /// key.
+    #[structopt(name = "view-hide-zones", long = "view-hide-zones")]
+    pub hide_zones: bool,
+    /// Show a minimap of the whole city and the part in the window in the
+    /// bottom right corner, toggled with the 7 key. Clicking the minimap
+    /// centers the window there.
+    #[structopt(name = "view-show-minimap", long = "view-show-minimap")]
+    pub show_minimap: bool,
+    /// Pixels of the longer side of the minimap.
+    #[structopt(
+        name = "view-minimap-size",
+        long = "view-minimap-size",
+        default_value = "200.0"
+    )]
+    pub minimap_size: f64,
+    #[structopt(
+        name = "view-minimap-color",
+        long = "view-minimap-color",
+        default_value = "ffffff",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub minimap_color: Color,
+    /// Hide the stop lines at the ends of lanes, toggled with the 8 key.
+    #[structopt(name = "view-hide-stop-lines", long = "view-hide-stop-lines")]
+    pub hide_stop_lines: bool,
+    #[structopt(
+        name = "view-stop-line-color",
+        long = "view-stop-line-color",
+        default_value = "ffffff",
+        parse(from_str = piston_window::color::hex)
+    )]
+    pub stop_line_color: Color,
+    /// Meters of the width of stop lines along their lanes.
+    #[structopt(
+        name = "view-stop-line-width",
+        long = "view-stop-line-width",
+        default_value = "0.4"
+    )]
+    pub stop_line_width: f64,
+    /// Show the key bindings of overlays, toggled with the ? key.
+    #[structopt(name = "view-show-help", long = "view-show-help")]
+    pub show_help: bool,
+    /// Write the current frame to this SVG file on the P key.
+    #[structopt(name = "view-svg-output", long = "view-svg-output", parse(from_os_str))]
+    pub svg_output: Option<PathBuf>,
+    /// Pixels of a meter in SVG files.
+    #[structopt(
+        name = "view-svg-scale",
+        long = "view-svg-scale",
+        default_value = "2.0"
+    )]
+    pub svg_scale: f64,
+    /// How the window is divided when comparing two runs, one of
+    /// "left-right" and "top-bottom".
+    #[structopt(name = "view-split", long = "view-split", default_value = "left-right")]
+    pub split: Split,
+}
+
+/// Outline of a car in the view.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum CarShape {
+    #[default]
+    Rectangle,
+    Rounded,
+    /// Pointing to the heading of the car.
+    Triangle,
+}
+
+impl FromStr for CarShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rectangle" => Ok(CarShape::Rectangle),
+            "rounded" => Ok(CarShape::Rounded),
+            "triangle" => Ok(CarShape::Triangle),
+            _ => Err(format!("unknown car shape: {}", s)),
+        }
+    }
+}
+
+/// Coloring mode of cars in the view, only one mode applies at a time.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum CarColoring {
+    /// All cars in the car color.
+    #[default]
+    Fixed,
+    /// Cars sharing a destination share a hue, cars without a destination
+    /// are in the car color.
+    Destination,
+    /// Cars of calm drivers are blue, of aggressive ones red, by their
+    /// desired speed factor.
+    Aggressiveness,
+}
+
+impl FromStr for CarColoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(CarColoring::Fixed),
+            "destination" => Ok(CarColoring::Destination),
+            "aggressiveness" => Ok(CarColoring::Aggressiveness),
+            _ => Err(format!("unknown car coloring: {}", s)),
+        }
+    }
+}
+
+/// A hue of its own for each intersection, the same in every run.
+///
+/// Hues of the intersections in row-major order are spaced by the golden
+/// ratio, so near intersections get distinct colors.
+pub fn destination_color((i, j): IntersectionIndex) -> Color {
+    let n = (i * 31 + j) as f64;
+    hue_color((n * 0.618_033_988_749_895).fract() * 6.0)
+}
+
+/// Color of a desired speed factor, from blue at `min` through green to red
+/// at `max`. Factors outside of the range have the color of its nearest end.
+pub fn aggressiveness_color(factor: f64, min: f64, max: f64) -> Color {
+    let t = if max > min {
+        ((factor - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    hue_color(4.0 * (1.0 - t))
+}
+
+/// A saturated color of `hue`, in sixths of the color wheel from red.
+fn hue_color(hue: f64) -> Color {
+    let (saturation, value) = (0.8, 1.0);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [(r + m) as f32, (g + m) as f32, (b + m) as f32, 1.0]
+}
+
+impl View {
+    pub fn input(&mut self, input: &Input) {
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            ..
+        }) = input
+        {
+            if let Some(overlay) = overlay::binding(*key) {
+                self.settings.toggle_overlay(overlay);
+            }
+        }
+    }
+
+    /// The SVG file to write the current frame to, if `input` asks for it.
+    pub fn svg_export(&self, input: &Input) -> Option<&Path> {
+        match input {
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::P),
+                ..
+            }) => self.settings.svg_output.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Write roads, intersections and cars to an SVG file, with the city
+    /// fitted as in a window of its size.
+    pub fn render_to_svg<P: AsRef<Path>>(
+        &self,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        path: P,
+    ) -> Result<(), Error> {
+        fs::write(path, self.svg_document(stateless_model, stateful_model))?;
+        Ok(())
+    }
+
+    /// The SVG document `render_to_svg` writes.
+    pub fn svg_document(
+        &self,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+    ) -> String {
+        let Geometry { width, height } = stateless_model.city.geometry();
+        let size = [
+            width * self.settings.svg_scale + 2.0 * self.settings.padding,
+            height * self.settings.svg_scale + 2.0 * self.settings.padding,
+        ];
+        let mut svg = Svg::new(size[0], size[1], color::BLACK);
+        self.draw_model(
+            &Info::new(),
+            stateless_model,
+            stateful_model,
+            self.fit_model(&stateless_model.city, size).transform(),
+            &mut svg,
+        );
+        svg.finish()
+    }
 }
 
 impl View {
@@ -110,61 +529,111 @@ impl View {
         context: Context,
         g2d: &mut G2d,
     ) {
-        let context = context.trans(info.x, info.y).zoom(info.zoom);
-
-        // Model logical width and model height
-        let Geometry {
-            width: mw,
-            height: mh,
-        } = stateless_model.city.geometry();
-        // Window width and window height
-        let [ww, wh] = context
+        let window_size = context
             .viewport
             .expect("no viewport info provided")
             .window_size;
-        // Model container width and model container height
-        let (cw, ch) = (
-            ww - 2.0 * self.settings.padding,
-            wh - 2.0 * self.settings.padding,
-        );
-        let (cx, cy) = (self.settings.padding, self.settings.padding);
-
-        let model_context = {
-            let model_ratio = mw / mh;
-            let container_ratio = cw / ch;
-            let zoom = if model_ratio > container_ratio {
-                cw / mw
-            } else {
-                ch / mh
-            };
-            let (zw, zh) = (mw * zoom, mh * zoom);
-            let (x, y) = if model_ratio > container_ratio {
-                (cx, cy + (ch - zh) / 2.0)
-            } else {
-                (cx + (cw - zw) / 2.0, cy)
-            };
-            // Transform from model coordinates to model container coordinates
-            context.trans(x, y).zoom(zoom)
-        };
+        let transform = context.transform.append_transform(self.model_transform(
+            info,
+            &stateless_model.city,
+            window_size,
+        ));
+        self.draw_model(info, stateless_model, stateful_model, transform, g2d);
+    }
+
+    /// Transform from model coordinates to window coordinates.
+    pub fn model_transform(
+        &self,
+        info: &Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+    ) -> Matrix2d {
+        info.camera
+            .compose(self.fit_model(city, window_size))
+            .transform()
+    }
+
+    /// Transforms from the coordinates of each car to window coordinates, as
+    /// the cars are drawn.
+    ///
+    /// A car is centered at the origin of its coordinates and heads to the
+    /// north. Append the transforms to the transform of a context to draw in
+    /// its coordinates.
+    pub fn car_transforms<'a>(
+        &'a self,
+        stateless_model: &'a stateless::Model,
+        stateful_model: &'a stateful::Model,
+        info: &Info,
+        window_size: [f64; 2],
+    ) -> impl Iterator<Item = (CarIndex, Matrix2d)> + 'a {
+        let transform = self.model_transform(info, &stateless_model.city, window_size);
+        stateful_model
+            .cars
+            .iter()
+            .enumerate()
+            .filter_map(move |(car_index, car)| {
+                let car = car.as_ref()?;
+                Some((
+                    car_index,
+                    self.car_transform(car, &stateless_model.city, transform),
+                ))
+            })
+    }
+
+    /// Model coordinates under a cursor position in logical window
+    /// coordinates, the inverse of `model_transform`.
+    pub fn screen_to_model(
+        &self,
+        info: &Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+        screen: [f64; 2],
+    ) -> Position {
+        info.camera
+            .compose(self.fit_model(city, window_size))
+            .screen_to_world(screen)
+    }
+
+    /// Record the device scale of the viewport, so cursor positions in device
+    /// pixels can be mapped to the logical coordinates the view draws in.
+    pub fn update_device_scale(&self, info: &mut Info, context: Context) {
+        info.device_scale = device_scale(context.viewport.expect("no viewport info provided"));
+    }
+
+    /// Draw roads, intersections and cars with `transform` from model
+    /// coordinates.
+    pub fn draw_model<C: Canvas>(
+        &self,
+        info: &Info,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        self.draw_zones(&stateless_model.zones, transform, canvas);
+        self.draw_background_grid(&stateless_model.city, transform, canvas);
         // Draw horizontal roads
-        let lane_width = stateless_model.city.lane_width;
         for ((i, j), (direction, road)) in stateless_model.city.board.enumerate_roads() {
             if let Some(road) = road.as_ref() {
                 let length = stateless_model.city.road_length(direction, (i, j));
                 self.draw_road(
-                    lane_width,
+                    &stateless_model.city,
                     length,
                     road,
                     self.transform_to_road_center(
-                        model_context.transform,
+                        transform,
                         &stateless_model.city,
                         direction,
                         (i, j),
                     ),
-                    g2d,
+                    canvas,
                 );
             }
         }
+        let failed_signals_lit = info.signals_failed.map(|since| {
+            let period = self.settings.failed_signal_period;
+            period <= 0.0 || since.elapsed().as_secs_f64() % period < period / 2.0
+        });
         for (((i, j), intersection), state) in stateless_model
             .city
             .board
@@ -177,17 +646,19 @@ impl View {
                 self.draw_intersection(
                     geometry,
                     intersection,
+                    stateless_model.city.intersection_approaches((i, j)),
                     state.as_ref().unwrap(),
-                    self.transform_to_intersection_center(
-                        model_context.transform,
-                        &stateless_model.city,
-                        (i, j),
-                    ),
-                    g2d,
+                    stateless_model.city.handed_traffic,
+                    failed_signals_lit,
+                    self.transform_to_intersection_center(transform, &stateless_model.city, (i, j)),
+                    canvas,
                 );
             }
         }
 
+        if self.settings.show_trails {
+            self.draw_trails(stateless_model, stateful_model, transform, canvas);
+        }
         for (stateless_car, stateful_car) in
             stateless_model.cars.iter().zip(stateful_model.cars.iter())
         {
@@ -196,50 +667,854 @@ impl View {
                     stateless_car,
                     stateful_car,
                     &stateless_model.city,
-                    model_context.transform,
-                    g2d,
+                    transform,
+                    canvas,
+                );
+            }
+        }
+        let selected_car = info
+            .selected_car
+            .and_then(|handle| stateful_model.car(handle));
+        if let Some(car) = selected_car {
+            for pair in route_points(car, &stateless_model.city).windows(2) {
+                canvas.line(
+                    self.settings.route_color,
+                    0.5,
+                    [pair[0].x, pair[0].y, pair[1].x, pair[1].y],
+                    transform,
+                );
+            }
+        }
+        if let (Some(position), Some(handle)) = (
+            selected_car.and_then(|car| car.location.city_position(&stateless_model.city)),
+            info.selected_car,
+        ) {
+            let length = stateless_model.cars[handle.index].length;
+            self.draw_car_box(position, length, 0.3, transform, canvas);
+        }
+        for handle in info.selected_cars.iter() {
+            if let Some(position) = stateful_model
+                .car(*handle)
+                .and_then(|car| car.location.city_position(&stateless_model.city))
+            {
+                let length = stateless_model.cars[handle.index].length;
+                self.draw_car_box(position, length, 0.15, transform, canvas);
+            }
+        }
+        if let Some((intersection_index, since)) = info.highlighted_intersection {
+            let age = since.elapsed().as_secs_f64();
+            if age < self.settings.highlight_time {
+                let center = stateless_model.city.intersection_center(intersection_index);
+                let geometry = stateless_model
+                    .city
+                    .intersection_geometry(intersection_index);
+                let mut color = self.settings.selected_car_color;
+                color[3] *= (1.0 - age / self.settings.highlight_time) as f32;
+                canvas.rectangle(
+                    color,
+                    [
+                        center.x - geometry.width / 2.0,
+                        center.y - geometry.height / 2.0,
+                        geometry.width,
+                        geometry.height,
+                    ],
+                    transform,
+                );
+            }
+        }
+        if let Some(intersection_index) = info.selected_intersection {
+            let center = stateless_model.city.intersection_center(intersection_index);
+            let geometry = stateless_model
+                .city
+                .intersection_geometry(intersection_index);
+            let (left, right) = (
+                center.x - geometry.width / 2.0,
+                center.x + geometry.width / 2.0,
+            );
+            let (top, bottom) = (
+                center.y - geometry.height / 2.0,
+                center.y + geometry.height / 2.0,
+            );
+            let edges = Around {
+                north: [left, top, right, top],
+                west: [left, bottom, left, top],
+                south: [right, bottom, left, bottom],
+                east: [right, top, right, bottom],
+            };
+            for &direction in AbsoluteDirection::directions() {
+                let radius = if info.selected_approach == Some(direction) {
+                    0.8
+                } else {
+                    0.3
+                };
+                canvas.line(
+                    self.settings.selected_car_color,
+                    radius,
+                    *edges.get(direction),
+                    transform,
                 );
             }
         }
     }
 
-    /// Draw a horizontal road.
-    pub fn draw_road(
+    /// Draw a square around a car of `length` at `position`, in lines of
+    /// `radius`.
+    fn draw_car_box<C: Canvas>(
         &self,
-        lane_width: f64,
+        position: Position,
         length: f64,
-        road: &stateless::Road,
+        radius: f64,
         transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        let half_size = length * self.settings.car_scale * 1.5;
+        let (left, right) = (position.x - half_size, position.x + half_size);
+        let (top, bottom) = (position.y - half_size, position.y + half_size);
+        for segment in [
+            [left, top, right, top],
+            [right, top, right, bottom],
+            [right, bottom, left, bottom],
+            [left, bottom, left, top],
+        ]
+        .iter()
+        {
+            canvas.line(
+                self.settings.selected_car_color,
+                radius,
+                *segment,
+                transform,
+            );
+        }
+    }
+
+    /// The camera which fits the city in the window.
+    fn fit_model(&self, city: &stateless::City, window_size: [f64; 2]) -> Camera {
+        Camera::fit(city.geometry(), window_size, self.settings.padding)
+    }
+
+    /// Move the view to keep the selected car in the center of the window.
+    pub fn follow_selected(
+        &self,
+        info: &mut Info,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        context: Context,
+    ) {
+        if !info.follow_selected {
+            return;
+        }
+        let position = match info
+            .selected_car
+            .and_then(|handle| stateful_model.car(handle))
+            .and_then(|car| car.location.city_position(&stateless_model.city))
+        {
+            Some(position) => position,
+            None => return,
+        };
+        let [ww, wh] = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        self.center_on(info, &stateless_model.city, [ww, wh], position);
+    }
+
+    /// Move the view to put `position` of the model in the center of the
+    /// window.
+    pub fn center_on(
+        &self,
+        info: &mut Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+        position: Position,
+    ) {
+        let [ww, wh] = window_size;
+        let [x, y] = info
+            .camera
+            .compose(self.fit_model(city, window_size))
+            .world_to_screen(position);
+        info.camera.pan(ww / 2.0 - x, wh / 2.0 - y);
+    }
+
+    /// Rectangle of the minimap in window coordinates and the transform from
+    /// model coordinates to window coordinates in the minimap, unless the
+    /// city has no area.
+    pub fn minimap_transform(
+        &self,
+        city: &stateless::City,
+        window_size: [f64; 2],
+    ) -> Option<([f64; 4], Matrix2d)> {
+        let Geometry { width, height } = city.geometry();
+        if !(width > 0.0 && height > 0.0) {
+            return None;
+        }
+        let scale = self.settings.minimap_size / width.max(height);
+        let (w, h) = (width * scale, height * scale);
+        let [ww, wh] = window_size;
+        let (x, y) = (
+            ww - self.settings.padding - w,
+            wh - self.settings.padding - h,
+        );
+        Some(([x, y, w, h], math::identity().trans(x, y).zoom(scale)))
+    }
+
+    /// Center the window on the point of the minimap under the cursor when
+    /// `input` clicks the minimap, and return whether it did.
+    pub fn minimap_click(
+        &self,
+        info: &mut Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+        input: &Input,
+    ) -> bool {
+        if !self.settings.overlay_shown(Overlay::Minimap) {
+            return false;
+        }
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Mouse(MouseButton::Left),
+            ..
+        }) = input
+        {
+            let ([x, y, w, h], transform) = match self.minimap_transform(city, window_size) {
+                Some(minimap) => minimap,
+                None => return false,
+            };
+            let [cx, cy] = info.cursor;
+            if !(x <= cx && cx <= x + w && y <= cy && cy <= y + h) {
+                return false;
+            }
+            let scale = transform[0][0];
+            let position = Position {
+                x: (cx - x) / scale,
+                y: (cy - y) / scale,
+            };
+            info.follow_selected = false;
+            self.center_on(info, city, window_size, position);
+            return true;
+        }
+        false
+    }
+
+    /// Select the intersection under the cursor for editing its signal when
+    /// `input` presses the I key, or clear the selection if there is none,
+    /// and return whether it did.
+    pub fn select_intersection(
+        &self,
+        info: &mut Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+        input: &Input,
+    ) -> bool {
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(Key::I),
+            ..
+        }) = input
+        {
+            let position = self.screen_to_model(info, city, window_size, info.cursor);
+            info.selected_intersection = city
+                .board
+                .intersections
+                .enumerate()
+                .filter(|(_, intersection)| intersection.is_some())
+                .map(|(index, _)| index)
+                .find(|index| {
+                    let center = city.intersection_center(*index);
+                    let geometry = city.intersection_geometry(*index);
+                    (position.x - center.x).abs() <= geometry.width / 2.0
+                        && (position.y - center.y).abs() <= geometry.height / 2.0
+                });
+            info.selected_approach = None;
+            return true;
+        }
+        false
+    }
+
+    /// Select the cars in the rectangle of a finished selection drag, see
+    /// `Controller::selection_start`, and return whether it did.
+    pub fn select_cars(
+        &self,
+        info: &mut Info,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        window_size: [f64; 2],
+    ) -> bool {
+        let (start, end) = match info.selection_box.take() {
+            Some(corners) => corners,
+            None => return false,
+        };
+        let city = &stateless_model.city;
+        let a = self.screen_to_model(info, city, window_size, start);
+        let b = self.screen_to_model(info, city, window_size, end);
+        let min = Position {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+        };
+        let max = Position {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+        };
+        info.selected_cars = stateful_model
+            .cars_in_region(city, min, max)
+            .into_iter()
+            .filter_map(|car_index| stateful_model.handle(car_index))
+            .collect();
+        true
+    }
+
+    /// Draw the rectangle of a drag selecting cars, from `start` to the
+    /// cursor.
+    pub fn draw_selection_box(
+        &self,
+        info: &Info,
+        start: Option<[f64; 2]>,
+        context: Context,
         g2d: &mut G2d,
     ) {
-        let lane_number = road.lane_number();
-        let center_distance = (lane_number - 1) as f64 * lane_width;
-        let mut center_y = -center_distance / 2.0;
+        let [x1, y1] = match start {
+            Some(start) => start,
+            None => return,
+        };
+        let [x2, y2] = info.cursor;
+        for segment in [
+            [x1, y1, x2, y1],
+            [x2, y1, x2, y2],
+            [x2, y2, x1, y2],
+            [x1, y2, x1, y1],
+        ]
+        .iter()
+        {
+            line(
+                self.settings.selected_car_color,
+                0.5,
+                *segment,
+                context.transform,
+                g2d,
+            );
+        }
+    }
+
+    /// Center the window on the intersection typed after the G key, see
+    /// `Controller::goto_input`, highlight it and return whether it did.
+    pub fn goto_intersection(
+        &self,
+        info: &mut Info,
+        city: &stateless::City,
+        window_size: [f64; 2],
+    ) -> bool {
+        let intersection_index = match info.goto_intersection.take() {
+            Some(intersection_index) => intersection_index,
+            None => return false,
+        };
+        info.follow_selected = false;
+        let center = city.intersection_center(intersection_index);
+        self.center_on(info, city, window_size, center);
+        info.highlighted_intersection = Some((intersection_index, Instant::now()));
+        true
+    }
+
+    /// Draw the coordinates being typed after the G key in the bottom left
+    /// corner of the window.
+    pub fn draw_goto_prompt(&self, info: &Info, context: Context, g2d: &mut G2d) {
+        let text = match info.goto_input.as_ref() {
+            Some(text) => format!("GO TO: {}", text),
+            None => return,
+        };
+        let pixel_size = 2.0;
+        let margin = 4.0;
+        let width = font::text_width(&text, pixel_size) + 2.0 * margin;
+        let height = font::GLYPH_HEIGHT as f64 * pixel_size + 2.0 * margin;
+        let [_, wh] = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let transform = context
+            .transform
+            .trans(self.settings.padding, wh - self.settings.padding - height);
+        rectangle(
+            self.settings.legend_background_color,
+            [0.0, 0.0, width, height],
+            transform,
+            g2d,
+        );
+        font::draw_text(
+            &text,
+            self.settings.legend_color,
+            pixel_size,
+            transform.trans(margin, margin),
+            g2d,
+        );
+    }
+
+    /// Draw the roads of the whole city and the outline of the part in the
+    /// window in the bottom right corner of the window.
+    pub fn draw_minimap(
+        &self,
+        info: &Info,
+        city: &stateless::City,
+        context: Context,
+        g2d: &mut G2d,
+    ) {
+        if !self.settings.overlay_shown(Overlay::Minimap) {
+            return;
+        }
+        let window_size = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let ([x, y, w, h], transform) = match self.minimap_transform(city, window_size) {
+            Some(minimap) => minimap,
+            None => return,
+        };
+        rectangle(
+            self.settings.legend_background_color,
+            [x, y, w, h],
+            context.transform,
+            g2d,
+        );
+        let to_minimap =
+            |position: Position| math::transform_pos(transform, [position.x, position.y]);
+        for (index, (direction, road)) in city.board.enumerate_roads() {
+            if road.is_none() {
+                continue;
+            }
+            let segment = Segment {
+                road_direction: direction,
+                road_index: index,
+                lane_direction: LaneDirection::LowToHigh,
+            };
+            let from = to_minimap(city.intersection_center(segment.from_intersection(city)));
+            let to = to_minimap(city.intersection_center(segment.to_intersection(city)));
+            line(
+                self.settings.minimap_color,
+                0.5,
+                [from[0], from[1], to[0], to[1]],
+                context.transform,
+                g2d,
+            );
+        }
+        // The part of the city in the window, clipped to the minimap
+        let [ww, wh] = window_size;
+        let clip = |[px, py]: [f64; 2]| [px.max(x).min(x + w), py.max(y).min(y + h)];
+        let [left, top] = clip(to_minimap(self.screen_to_model(
+            info,
+            city,
+            window_size,
+            [0.0, 0.0],
+        )));
+        let [right, bottom] = clip(to_minimap(self.screen_to_model(
+            info,
+            city,
+            window_size,
+            [ww, wh],
+        )));
+        for segment in [
+            [left, top, right, top],
+            [right, top, right, bottom],
+            [right, bottom, left, bottom],
+            [left, bottom, left, top],
+        ]
+        .iter()
+        {
+            line(
+                self.settings.minimap_color,
+                1.0,
+                *segment,
+                context.transform,
+                g2d,
+            );
+        }
+    }
+
+    /// Draw average speed over time in the bottom left corner of the window.
+    ///
+    /// Time axis spans the history window, speed axis is scaled to the
+    /// highest sample in the window, which is labeled in `unit`.
+    pub fn draw_graph(&self, history: &History, unit: SpeedUnit, context: Context, g2d: &mut G2d) {
+        if !self.settings.overlay_shown(Overlay::Graph) {
+            return;
+        }
+        let latest = match history.latest() {
+            Some(latest) => latest,
+            None => return,
+        };
+        let [_, wh] = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let (gw, gh) = (self.settings.graph_width, self.settings.graph_height);
+        let transform = context
+            .transform
+            .trans(self.settings.padding, wh - self.settings.padding);
+        let axis_radius = 0.5;
+        line(
+            self.settings.graph_axis_color,
+            axis_radius,
+            [0.0, 0.0, gw, 0.0],
+            transform,
+            g2d,
+        );
+        line(
+            self.settings.graph_axis_color,
+            axis_radius,
+            [0.0, 0.0, 0.0, -gh],
+            transform,
+            g2d,
+        );
+
+        let start_time = latest.time - history.window;
+        let max_speed = history
+            .samples()
+            .map(|sample| sample.average_speed)
+            .fold(0.0, f64::max);
+        let x_scale = gw / history.window;
+        let y_scale = if max_speed > 0.0 { gh / max_speed } else { 0.0 };
+        let points = history.samples().map(|sample| {
+            (
+                (sample.time - start_time) * x_scale,
+                -sample.average_speed * y_scale,
+            )
+        });
+        for ((x1, y1), (x2, y2)) in points.clone().zip(points.skip(1)) {
+            line(
+                self.settings.graph_color,
+                1.0,
+                [x1, y1, x2, y2],
+                transform,
+                g2d,
+            );
+        }
+        let pixel_size = 2.0;
+        font::draw_text(
+            &format!("{:.1} {}", unit.from_si(max_speed), unit.symbol()),
+            self.settings.graph_axis_color,
+            pixel_size,
+            transform.trans(4.0, -gh - (font::GLYPH_HEIGHT as f64 + 2.0) * pixel_size),
+            g2d,
+        );
+    }
+
+    /// Draw the legend in the top right corner of the window.
+    ///
+    /// The legend shows the north, the turn rules drawn as signs and the
+    /// colors of the signal and the lane signs.
+    pub fn draw_legend(&self, context: Context, g2d: &mut G2d) {
+        if !self.settings.overlay_shown(Overlay::Legend) {
+            return;
+        }
+        let [ww, _] = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let pixel_size = 2.0;
+        let row_height = 24.0;
+        let icon_size = 16.0;
+        let half_icon_size = icon_size / 2.0;
+        let label_x = row_height + 4.0;
+        let label_y = (row_height - font::GLYPH_HEIGHT as f64 * pixel_size) / 2.0;
+        let signs = [
+            (TurnRule::FRONT, "FRONT"),
+            (TurnRule::LEFT, "LEFT"),
+            (TurnRule::RIGHT, "RIGHT"),
+            (TurnRule::BACK, "BACK"),
+        ];
+        let colors = [
+            (self.settings.intersection_sign_color, "GO"),
+            (self.settings.intersection_color, "STOP"),
+            (self.settings.road_sign_color, "LANE"),
+        ];
+        let labels = signs
+            .iter()
+            .map(|(_, label)| *label)
+            .chain(colors.iter().map(|(_, label)| *label));
+        let width = label_x
+            + labels
+                .map(|label| font::text_width(label, pixel_size))
+                .fold(0.0, f64::max)
+            + 4.0;
+        let height = row_height * (1 + signs.len() + colors.len()) as f64;
+        let transform = context
+            .transform
+            .trans(ww - self.settings.padding - width, self.settings.padding);
+        rectangle(
+            self.settings.legend_background_color,
+            [0.0, 0.0, width, height],
+            transform,
+            g2d,
+        );
+
+        let center = row_height / 2.0;
+        let row_transform = |row: usize| transform.trans(0.0, row as f64 * row_height);
+        // North arrow
+        let north = row_transform(0);
+        line(
+            self.settings.legend_color,
+            1.0,
+            [
+                center,
+                center + half_icon_size,
+                center,
+                center - half_icon_size,
+            ],
+            north,
+            g2d,
+        );
+        polygon(
+            self.settings.legend_color,
+            &[
+                [center, center - half_icon_size],
+                [center - 4.0, center - half_icon_size + 6.0],
+                [center + 4.0, center - half_icon_size + 6.0],
+            ],
+            north,
+            g2d,
+        );
+        font::draw_text(
+            "N",
+            self.settings.legend_color,
+            pixel_size,
+            north.trans(label_x, label_y),
+            g2d,
+        );
+
+        for (row, (turn_rule, label)) in signs.iter().enumerate() {
+            let transform = row_transform(1 + row);
+            self.draw_turn_rule_as_sign(
+                *turn_rule,
+                self.settings.intersection_sign_color,
+                transform.trans(center, center).zoom(half_icon_size),
+                g2d,
+            );
+            font::draw_text(
+                label,
+                self.settings.legend_color,
+                pixel_size,
+                transform.trans(label_x, label_y),
+                g2d,
+            );
+        }
+        for (row, (color, label)) in colors.iter().enumerate() {
+            let transform = row_transform(1 + signs.len() + row);
+            rectangle(
+                *color,
+                [
+                    center - half_icon_size,
+                    center - half_icon_size,
+                    icon_size,
+                    icon_size,
+                ],
+                transform,
+                g2d,
+            );
+            font::draw_text(
+                label,
+                self.settings.legend_color,
+                pixel_size,
+                transform.trans(label_x, label_y),
+                g2d,
+            );
+        }
+    }
+
+    /// Draw the line between the halves of a window comparing two runs and
+    /// the names of the runs on the top of their halves.
+    pub fn draw_comparison(&self, names: [&str; 2], context: Context, g2d: &mut G2d) {
+        let window_size = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let split = self.settings.split;
+        line(
+            self.settings.legend_color,
+            1.0,
+            split.divider(window_size),
+            context.transform,
+            g2d,
+        );
+        let pixel_size = 2.0;
+        let margin = 4.0;
+        let height = font::GLYPH_HEIGHT as f64 * pixel_size + 2.0 * margin;
+        for (half, name) in names.iter().enumerate() {
+            let ([x, y], [w, _]) = split.half(window_size, half);
+            let width = font::text_width(name, pixel_size) + 2.0 * margin;
+            // Centered on the top, clear of the help and the legend in the
+            // corners
+            let transform = context
+                .transform
+                .trans(x + (w - width) / 2.0, y + self.settings.padding);
+            rectangle(
+                self.settings.legend_background_color,
+                [0.0, 0.0, width, height],
+                transform,
+                g2d,
+            );
+            font::draw_text(
+                name,
+                self.settings.legend_color,
+                pixel_size,
+                transform.trans(margin, margin),
+                g2d,
+            );
+        }
+    }
+
+    /// Draw the keys toggling overlays in the top left corner of the window.
+    pub fn draw_help(&self, context: Context, g2d: &mut G2d) {
+        if !self.settings.overlay_shown(Overlay::Help) {
+            return;
+        }
+        let pixel_size = 2.0;
+        let row_height = 16.0;
+        let margin = 4.0;
+        let label_width = overlay::BINDINGS
+            .iter()
+            .map(|binding| font::text_width(binding.label, pixel_size))
+            .fold(0.0, f64::max);
+        let name_x = margin + label_width + 3.0 * pixel_size * font::GLYPH_WIDTH as f64;
+        let rows = overlay::BINDINGS
+            .iter()
+            .map(|binding| {
+                let state = if self.settings.overlay_shown(binding.overlay) {
+                    "ON"
+                } else {
+                    "OFF"
+                };
+                (
+                    binding.label,
+                    format!("{} {}", binding.overlay.name(), state),
+                )
+            })
+            .collect::<Vec<_>>();
+        let width = name_x
+            + rows
+                .iter()
+                .map(|(_, text)| font::text_width(text, pixel_size))
+                .fold(font::text_width("KEYS", pixel_size), f64::max)
+            + margin;
+        let height = row_height * (1 + rows.len()) as f64;
+        let transform = context
+            .transform
+            .trans(self.settings.padding, self.settings.padding);
+        rectangle(
+            self.settings.legend_background_color,
+            [0.0, 0.0, width, height],
+            transform,
+            g2d,
+        );
+        let text_y = (row_height - font::GLYPH_HEIGHT as f64 * pixel_size) / 2.0;
+        font::draw_text(
+            "KEYS",
+            self.settings.legend_color,
+            pixel_size,
+            transform.trans(margin, text_y),
+            g2d,
+        );
+        for (row, (label, text)) in rows.iter().enumerate() {
+            let transform = transform.trans(0.0, (1 + row) as f64 * row_height + text_y);
+            font::draw_text(
+                label,
+                self.settings.legend_color,
+                pixel_size,
+                transform.trans(margin, 0.0),
+                g2d,
+            );
+            font::draw_text(
+                text,
+                self.settings.legend_color,
+                pixel_size,
+                transform.trans(name_x, 0.0),
+                g2d,
+            );
+        }
+    }
+
+    /// Draw a line along each row and each column of intersections of
+    /// `city`, across the whole city.
+    ///
+    /// The lines follow the center lines of the roads, also where roads are
+    /// missing.
+    pub fn draw_background_grid<C: Canvas>(
+        &self,
+        city: &stateless::City,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        if !self.settings.overlay_shown(Overlay::Grid)
+            || city.intersection_width.is_empty()
+            || city.intersection_height.is_empty()
+        {
+            return;
+        }
+        let Geometry { width, height } = city.geometry();
+        let radius = self.settings.grid_width / 2.0;
+        for i in 0..city.intersection_height.len() {
+            let y = city.intersection_center((i, 0)).y;
+            canvas.line(
+                self.settings.grid_color,
+                radius,
+                [0.0, y, width, y],
+                transform,
+            );
+        }
+        for j in 0..city.intersection_width.len() {
+            let x = city.intersection_center((0, j)).x;
+            canvas.line(
+                self.settings.grid_color,
+                radius,
+                [x, 0.0, x, height],
+                transform,
+            );
+        }
+    }
+
+    /// Draw the rectangles of `zones` in their colors, in model coordinates
+    /// like the roads.
+    pub fn draw_zones<C: Canvas>(
+        &self,
+        zones: &[stateless::Zone],
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        if !self.settings.overlay_shown(Overlay::Zones) {
+            return;
+        }
+        for zone in zones {
+            canvas.rectangle(zone.color, zone.rectangle(), transform);
+        }
+    }
+
+    /// Draw a horizontal road of `city`.
+    pub fn draw_road<C: Canvas>(
+        &self,
+        city: &stateless::City,
+        length: f64,
+        road: &stateless::Road,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
         let half_length = length / 2.0;
-        let middle = center_y + road.lane_to_high.len() as f64 * lane_width - lane_width / 2.0;
         for direction in [LaneDirection::HighToLow, LaneDirection::LowToHigh].iter() {
-            let iter = road.lanes_to_direction(*direction).iter();
-            let iter: Box<dyn Iterator<Item = &stateless::Lane>> = match direction {
-                LaneDirection::HighToLow => Box::new(iter.rev()),
-                LaneDirection::LowToHigh => Box::new(iter),
-            };
-            for lane in iter {
+            for (lane_index, lane) in road.lanes_to_direction(*direction).iter().enumerate() {
+                let offset = city.lane_center_offset(road, *direction, lane_index);
                 self.draw_lane(
                     lane,
                     length,
-                    lane_width,
-                    transform.trans(0.0, center_y).rot_deg(match direction {
+                    city.lane_width,
+                    transform.trans(0.0, offset).rot_deg(match direction {
                         LaneDirection::HighToLow => 180.0,
                         LaneDirection::LowToHigh => 0.0,
                     }),
-                    g2d,
+                    canvas,
                 );
-                center_y += lane_width;
             }
         }
         if !road.is_one_way() {
+            // between the innermost lanes of both directions
+            let middle = (city.lane_center_offset(road, LaneDirection::HighToLow, 0)
+                + city.lane_center_offset(road, LaneDirection::LowToHigh, 0))
+                / 2.0;
             // draw middle sperator line
-            rectangle(
+            canvas.rectangle(
                 self.settings.road_middle_separator_color,
                 [
                     -half_length,
@@ -248,27 +1523,44 @@ impl View {
                     self.settings.road_middle_separator_width,
                 ],
                 transform,
-                g2d,
             );
         }
     }
 
-    pub fn draw_lane(
+    pub fn draw_lane<C: Canvas>(
         &self,
         lane: &stateless::Lane,
         length: f64,
         width: f64,
         transform: Matrix2d,
-        g2d: &mut G2d,
+        canvas: &mut C,
     ) {
-        let half_length = length / 2.0;
-        let half_width = width / 2.0;
-        rectangle(
-            self.settings.road_color,
-            [-half_length, -half_width, length, width],
-            transform,
-            g2d,
-        );
+        let half_length = length / 2.0;
+        let half_width = width / 2.0;
+        let color = if lane.is_restricted() {
+            self.settings.restricted_lane_color
+        } else {
+            self.settings.road_color
+        };
+        canvas.rectangle(color, [-half_length, -half_width, length, width], transform);
+        if self.settings.overlay_shown(Overlay::StopLines) {
+            // Cars wait with their position at the end of the lane, the bar
+            // ends there
+            let stop_line_width = self.settings.stop_line_width;
+            canvas.rectangle(
+                self.settings.stop_line_color,
+                [
+                    half_length - stop_line_width,
+                    -half_width,
+                    stop_line_width,
+                    width,
+                ],
+                transform,
+            );
+        }
+        if !self.settings.overlay_shown(Overlay::LaneSigns) {
+            return;
+        }
         let sign_half_size = (width - self.settings.lane_sign_padding) / 2.0;
         self.draw_turn_rule_as_sign(
             lane.direction_rule,
@@ -277,25 +1569,33 @@ impl View {
                 .trans(half_length - half_width, 0.0)
                 .rot_deg(90.0)
                 .zoom(sign_half_size),
-            g2d,
+            canvas,
         );
     }
 
-    pub fn draw_intersection(
+    /// Draw an intersection and its signal, `failed_signals_lit` is set while
+    /// the signals have failed to whether their flashing signs are lit.
+    ///
+    /// Turns across the oncoming traffic on a permissive green have a ball
+    /// at the head of their arrow, protected turns keep the arrow head.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_intersection<C: Canvas>(
         &self,
         g: Geometry,
-        _intersection: &stateless::Intersection,
+        intersection: &stateless::Intersection,
+        approaches: Around<bool>,
         state: &stateful::Intersection,
+        handed_traffic: HandedTraffic,
+        failed_signals_lit: Option<bool>,
         transform: Matrix2d,
-        g2d: &mut G2d,
+        canvas: &mut C,
     ) {
         let half_width = g.width / 2.0;
         let half_height = g.height / 2.0;
-        rectangle(
+        canvas.rectangle(
             self.settings.intersection_color,
             [-half_width, -half_height, g.width, g.height],
             transform,
-            g2d,
         );
         let sign_size = if half_height < half_width {
             half_height
@@ -305,35 +1605,117 @@ impl View {
         let half_sign_size = sign_size / 2.0;
         let sign_x = half_width - half_sign_size;
         let sign_y = half_height - half_sign_size;
-        let draws = [
-            (AbsoluteDirection::North, -sign_x, -sign_y, 180.0),
-            (AbsoluteDirection::East, sign_x, -sign_y, 270.0),
-            (AbsoluteDirection::South, sign_x, sign_y, 0.0),
-            (AbsoluteDirection::West, -sign_x, sign_y, 90.0),
-        ];
+        let placements = Around {
+            north: (-sign_x, -sign_y, 180.0),
+            east: (sign_x, -sign_y, 270.0),
+            south: (sign_x, sign_y, 0.0),
+            west: (-sign_x, sign_y, 90.0),
+        };
         if let Some(current) = match state {
             stateful::Intersection::Crossroad { current, .. } => Some(current),
             stateful::Intersection::TJunction { current, .. } => Some(current),
             _ => None,
-        } {
-            for &(d, x, y, rot) in draws.iter() {
-                self.draw_turn_rule_as_sign(
-                    *current.get(d),
-                    self.settings.intersection_sign_color,
-                    transform.trans(x, y).zoom(half_sign_size).rot_deg(rot),
-                    g2d,
-                );
+        }
+        .filter(|_| self.settings.overlay_shown(Overlay::Signals))
+        {
+            if let Some(lit) = failed_signals_lit {
+                // Every approach stops and phases mean nothing, the whole
+                // sign flashes instead
+                for direction in AbsoluteDirection::directions() {
+                    if !lit || !approaches.get(*direction) {
+                        continue;
+                    }
+                    let &(x, y, rot) = placements.get(*direction);
+                    canvas.rectangle(
+                        self.settings.failed_signal_color,
+                        [-1.0, -1.0, 2.0, 2.0],
+                        transform.trans(x, y).zoom(half_sign_size).rot_deg(rot),
+                    );
+                }
+                return;
             }
+            let transition = self.signal_transition(intersection, state);
+            let signs = placements.zip_ref(current);
+            for direction in AbsoluteDirection::directions() {
+                if !approaches.get(*direction) {
+                    continue;
+                }
+                let (&(x, y, rot), &turn_rule) = *signs.get(*direction);
+                let transform = transform.trans(x, y).zoom(half_sign_size).rot_deg(rot);
+                let color = self.settings.intersection_sign_color;
+                match transition {
+                    Some((previous, progress)) if *previous.get(*direction) != turn_rule => {
+                        self.draw_turn_rule_as_sign(
+                            *previous.get(*direction),
+                            faded(color, 1.0 - progress),
+                            transform,
+                            canvas,
+                        );
+                        self.draw_turn_rule_as_sign(
+                            turn_rule,
+                            faded(color, progress),
+                            transform,
+                            canvas,
+                        );
+                    }
+                    _ => self.draw_turn_rule_as_sign(turn_rule, color, transform, canvas),
+                }
+                if handed_traffic.permissive_crossing(current, *direction) {
+                    self.draw_permissive_turn(
+                        handed_traffic.crossing_turn(),
+                        color,
+                        transform,
+                        canvas,
+                    );
+                }
+            }
+        }
+        if let Some(switch_state) = state
+            .switch_state()
+            .filter(|_| self.settings.overlay_shown(Overlay::PhaseTimer))
+        {
+            // Countdown of whole seconds in the center, half a sign high
+            let countdown = format!("{}", switch_state.remain_time().ceil() as u64);
+            let pixel_size = half_sign_size / font::GLYPH_HEIGHT as f64;
+            let text_width = font::text_width(&countdown, pixel_size);
+            let text_height = font::GLYPH_HEIGHT as f64 * pixel_size;
+            font::draw_text(
+                &countdown,
+                self.settings.intersection_sign_color,
+                pixel_size,
+                transform.trans(-text_width / 2.0, -text_height / 2.0),
+                canvas,
+            );
+        }
+    }
+
+    /// Turn rules of the previous rule of the signal of an intersection and
+    /// the fraction of the transition to the current rule done, while the
+    /// transition lasts.
+    pub fn signal_transition(
+        &self,
+        intersection: &stateless::Intersection,
+        state: &stateful::Intersection,
+    ) -> Option<(Around<TurnRule>, f64)> {
+        let switch_state = state.switch_state()?;
+        let rule_number = intersection.rule_number();
+        let progress =
+            switch_state.elapsed(intersection.switch_rule()?) / self.settings.signal_transition;
+        // A transition of no time or beyond its end is not drawn
+        if rule_number < 2 || !(0.0..1.0).contains(&progress) {
+            return None;
         }
+        let previous = (switch_state.rule_index() + rule_number - 1) % rule_number;
+        Some((intersection.rule(previous)?, progress))
     }
 
     /// Draw turn rule in (-1.0, -1.0) to (1.0, 1.0) or top left to down right
-    pub fn draw_turn_rule_as_sign(
+    pub fn draw_turn_rule_as_sign<C: Canvas>(
         &self,
         turn_rule: TurnRule,
         color: Color,
         transform: Matrix2d,
-        g2d: &mut G2d,
+        canvas: &mut C,
     ) {
         if turn_rule != TurnRule::empty() {
             let size = 2.0;
@@ -344,7 +1726,7 @@ impl View {
             let arrow_height = 0.3;
             let arrow_arm_length = half_size - arrow_height;
             let back_arrow_location = -0.5;
-            rectangle(
+            canvas.rectangle(
                 color,
                 [
                     -half_center_size,
@@ -353,9 +1735,8 @@ impl View {
                     center_size,
                 ],
                 transform,
-                g2d,
             );
-            rectangle(
+            canvas.rectangle(
                 color,
                 [
                     -half_center_size,
@@ -364,10 +1745,9 @@ impl View {
                     half_center_size + half_size,
                 ],
                 transform,
-                g2d,
             );
             if turn_rule.intersects(TurnRule::FRONT) {
-                rectangle(
+                canvas.rectangle(
                     color,
                     [
                         -half_center_size,
@@ -376,9 +1756,8 @@ impl View {
                         arrow_arm_length,
                     ],
                     transform,
-                    g2d,
                 );
-                polygon(
+                canvas.polygon(
                     color,
                     &[
                         [0.0, -half_size],
@@ -386,11 +1765,10 @@ impl View {
                         [-arrow_half_width, -arrow_arm_length],
                     ],
                     transform,
-                    g2d,
                 );
             }
             if turn_rule.intersects(TurnRule::LEFT) {
-                rectangle(
+                canvas.rectangle(
                     color,
                     [
                         -arrow_arm_length,
@@ -399,9 +1777,8 @@ impl View {
                         center_size,
                     ],
                     transform,
-                    g2d,
                 );
-                polygon(
+                canvas.polygon(
                     color,
                     &[
                         [-half_size, 0.0],
@@ -409,17 +1786,15 @@ impl View {
                         [-arrow_arm_length, -arrow_half_width],
                     ],
                     transform,
-                    g2d,
                 );
             }
             if turn_rule.intersects(TurnRule::RIGHT) {
-                rectangle(
+                canvas.rectangle(
                     color,
                     [0.0, -half_center_size, arrow_arm_length, center_size],
                     transform,
-                    g2d,
                 );
-                polygon(
+                canvas.polygon(
                     color,
                     &[
                         [half_size, 0.0],
@@ -427,11 +1802,10 @@ impl View {
                         [arrow_arm_length, -arrow_half_width],
                     ],
                     transform,
-                    g2d,
                 );
             }
             if turn_rule.intersects(TurnRule::BACK) {
-                rectangle(
+                canvas.rectangle(
                     color,
                     [
                         back_arrow_location - half_center_size,
@@ -440,9 +1814,8 @@ impl View {
                         center_size,
                     ],
                     transform,
-                    g2d,
                 );
-                rectangle(
+                canvas.rectangle(
                     color,
                     [
                         back_arrow_location - half_center_size,
@@ -451,9 +1824,8 @@ impl View {
                         arrow_arm_length,
                     ],
                     transform,
-                    g2d,
                 );
-                polygon(
+                canvas.polygon(
                     color,
                     &[
                         [back_arrow_location, half_size],
@@ -461,22 +1833,172 @@ impl View {
                         [back_arrow_location + arrow_half_width, arrow_arm_length],
                     ],
                     transform,
-                    g2d,
                 );
             }
         }
     }
 
-    pub fn draw_car(
+    /// Draw a ball over the head of the arrow of `turn`, left or right, in a
+    /// sign drawn by `draw_turn_rule_as_sign`.
+    pub fn draw_permissive_turn<C: Canvas>(
+        &self,
+        turn: TurnRule,
+        color: Color,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        let x = if turn.contains(TurnRule::LEFT) {
+            -0.75
+        } else {
+            0.75
+        };
+        let radius = 0.3;
+        let ball = (0..8)
+            .map(|k| {
+                let angle = k as f64 * std::f64::consts::PI / 4.0;
+                [x + radius * angle.cos(), radius * angle.sin()]
+            })
+            .collect::<Vec<_>>();
+        canvas.polygon(color, &ball, transform);
+    }
+
+    pub fn draw_car<C: Canvas>(
         &self,
-        _stateless: &stateless::Car,
+        stateless: &stateless::Car,
         stateful: &stateful::Car,
         city: &stateless::City,
         transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        let shape = self.car_shape(stateless.class);
+        self.draw_car_only(
+            shape,
+            stateless.length,
+            stateless.width,
+            self.car_color(stateless, stateful),
+            self.car_transform(stateful, city, transform),
+            canvas,
+        );
+    }
+
+    /// Draw faded copies of the cars at their recent locations, older ones
+    /// more faded.
+    pub fn draw_trails<C: Canvas>(
+        &self,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        for (car_index, trail) in stateful_model.trails.iter().enumerate() {
+            let car = match stateful_model.cars.get(car_index) {
+                Some(Some(car)) => car,
+                _ => continue,
+            };
+            let stateless_car = &stateless_model.cars[car_index];
+            let shape = self.car_shape(stateless_car.class);
+            let color = self.car_color(stateless_car, car);
+            for (k, location) in trail.iter().enumerate() {
+                let opacity =
+                    self.settings.trail_opacity * (k + 1) as f64 / (trail.len() + 1) as f64;
+                self.draw_car_only(
+                    shape,
+                    stateless_car.length,
+                    stateless_car.width,
+                    faded(color, opacity),
+                    self.location_transform(location, &stateless_model.city, transform),
+                    canvas,
+                );
+            }
+        }
+    }
+
+    /// Draw translucent copies of the cars at the locations of `ghosts`, as
+    /// previewed for the next update.
+    pub fn draw_ghosts(
+        &self,
+        info: &Info,
+        stateless_model: &stateless::Model,
+        stateful_model: &stateful::Model,
+        ghosts: &[(CarIndex, stateful::car::Location)],
+        context: Context,
         g2d: &mut G2d,
     ) {
+        if !self.settings.overlay_shown(Overlay::Ghosts) {
+            return;
+        }
+        let window_size = context
+            .viewport
+            .expect("no viewport info provided")
+            .window_size;
+        let transform = context.transform.append_transform(self.model_transform(
+            info,
+            &stateless_model.city,
+            window_size,
+        ));
+        for (car_index, location) in ghosts.iter() {
+            let car = match stateful_model.cars.get(*car_index) {
+                Some(Some(car)) => car,
+                _ => continue,
+            };
+            let stateless_car = &stateless_model.cars[*car_index];
+            self.draw_car_only(
+                self.car_shape(stateless_car.class),
+                stateless_car.length,
+                stateless_car.width,
+                faded(
+                    self.car_color(stateless_car, car),
+                    self.settings.ghost_opacity,
+                ),
+                self.location_transform(location, &stateless_model.city, transform),
+                g2d,
+            );
+        }
+    }
+
+    /// Shape of the cars of `class`.
+    pub fn car_shape(&self, class: VehicleClass) -> CarShape {
+        match class {
+            VehicleClass::Car => self.settings.car_shape,
+            VehicleClass::Bus => self.settings.bus_shape,
+            VehicleClass::Truck => self.settings.truck_shape,
+        }
+    }
+
+    /// Color of a car by the coloring mode.
+    pub fn car_color(&self, stateless: &stateless::Car, stateful: &stateful::Car) -> Color {
+        match (self.settings.car_coloring, stateful.destination) {
+            (CarColoring::Destination, Some(destination)) => destination_color(destination),
+            (CarColoring::Aggressiveness, _) => aggressiveness_color(
+                stateless.desired_speed_factor,
+                self.settings.aggressiveness_min,
+                self.settings.aggressiveness_max,
+            ),
+            _ => self.settings.car_color,
+        }
+    }
+
+    /// Transform from the coordinates of a car, heading to the north, to the
+    /// coordinates of `transform`, which are model coordinates.
+    pub fn car_transform(
+        &self,
+        stateful: &stateful::Car,
+        city: &stateless::City,
+        transform: Matrix2d,
+    ) -> Matrix2d {
+        self.location_transform(&stateful.location, city, transform)
+    }
+
+    /// Transform from the coordinates of a car at `location` to the
+    /// coordinates of `transform`, as `car_transform`.
+    pub fn location_transform(
+        &self,
+        location: &stateful::car::Location,
+        city: &stateless::City,
+        transform: Matrix2d,
+    ) -> Matrix2d {
         use LaneDirection::*;
-        match stateful.location {
+        match *location {
             stateful::car::Location::OnLane {
                 road_direction,
                 road_index,
@@ -487,28 +2009,25 @@ impl View {
             } => {
                 let length = city.road_length(road_direction, road_index);
                 let x = -length / 2.0 + position;
-                self.draw_car_only(
-                    self.transform_to_lane_center(
-                        transform,
-                        city,
-                        road_direction,
-                        road_index,
-                        lane_direction,
-                        lane_index,
-                    )
-                    .trans(
-                        match lane_direction {
-                            LowToHigh => x,
-                            HighToLow => -x,
-                        },
-                        0.0,
-                    )
-                    .rot_deg(match lane_direction {
-                        LowToHigh => 90.0,
-                        HighToLow => 270.0,
-                    }),
-                    g2d,
-                );
+                self.transform_to_lane_center(
+                    transform,
+                    city,
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    lane_index,
+                )
+                .trans(
+                    match lane_direction {
+                        LowToHigh => x,
+                        HighToLow => -x,
+                    },
+                    0.0,
+                )
+                .rot_deg(match lane_direction {
+                    LowToHigh => 90.0,
+                    HighToLow => 270.0,
+                })
             }
             stateful::car::Location::ChangingLane {
                 road_direction,
@@ -521,35 +2040,35 @@ impl View {
             } => {
                 let length = city.road_length(road_direction, road_index);
                 let x = -length / 2.0 + position;
-                let lane_changed_offset = lane_changed_proportion
-                    * city.lane_width
-                    * (to_lane_index - from_lane_index) as f64
-                    * match lane_direction {
-                        LaneDirection::HighToLow => 1.0,
-                        LaneDirection::LowToHigh => -1.0,
-                    };
-                self.draw_car_only(
-                    self.transform_to_lane_center(
-                        transform,
-                        city,
-                        road_direction,
-                        road_index,
-                        lane_direction,
-                        from_lane_index,
-                    )
-                    .trans(
-                        match lane_direction {
-                            LowToHigh => x,
-                            HighToLow => -x,
-                        },
-                        lane_changed_offset,
-                    )
-                    .rot_deg(match lane_direction {
-                        LowToHigh => 90.0,
-                        HighToLow => 270.0,
-                    }),
-                    g2d,
-                );
+                let road = city
+                    .board
+                    .get_road(road_direction, road_index)
+                    .unwrap()
+                    .as_ref()
+                    .unwrap();
+                let lane_changed_offset =
+                    stateful::car::lane_change_easing(lane_changed_proportion)
+                        * (city.lane_center_offset(road, lane_direction, to_lane_index)
+                            - city.lane_center_offset(road, lane_direction, from_lane_index));
+                self.transform_to_lane_center(
+                    transform,
+                    city,
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    from_lane_index,
+                )
+                .trans(
+                    match lane_direction {
+                        LowToHigh => x,
+                        HighToLow => -x,
+                    },
+                    lane_changed_offset,
+                )
+                .rot_deg(match lane_direction {
+                    LowToHigh => 90.0,
+                    HighToLow => 270.0,
+                })
             }
             stateful::car::Location::InIntersection {
                 intersection_index,
@@ -593,30 +2112,64 @@ impl View {
                 );
                 let turn_heading = self.car_heading_offset_deb_to_turn(turn_direction);
                 let heading = origin_heading + turn_heading * proportion;
-                self.draw_car_only(
-                    self.transform_to_intersection_center(transform, city, intersection_index)
-                        .trans(x, y)
-                        .rot_deg(heading),
-                    g2d,
-                );
+                self.transform_to_intersection_center(transform, city, intersection_index)
+                    .trans(x, y)
+                    .rot_deg(heading)
             }
         }
     }
 
-    /// Draw a car under centralized coordinate system.
+    /// Draw a car of `length` and `width` meters under centralized
+    /// coordinate system.
     ///
     /// The car is heading to north.
-    pub fn draw_car_only(&self, transform: Matrix2d, g2d: &mut G2d) {
-        let height = self.settings.car_length;
-        let width = self.settings.car_width;
+    pub fn draw_car_only<C: Canvas>(
+        &self,
+        shape: CarShape,
+        length: f64,
+        width: f64,
+        color: Color,
+        transform: Matrix2d,
+        canvas: &mut C,
+    ) {
+        let height = length * self.settings.car_scale;
+        let width = width * self.settings.car_scale;
         let half_height = height / 2.0;
         let half_width = width / 2.0;
-        rectangle(
-            self.settings.car_color,
-            [-half_width, -half_height, width, height],
-            transform,
-            g2d,
-        );
+        match shape {
+            CarShape::Rectangle => {
+                canvas.rectangle(color, [-half_width, -half_height, width, height], transform)
+            }
+            CarShape::Rounded => {
+                let radius = half_width / 2.0;
+                let segments = 4;
+                let corners = [
+                    (half_width - radius, -half_height + radius, -90.0),
+                    (half_width - radius, half_height - radius, 0.0),
+                    (-half_width + radius, half_height - radius, 90.0),
+                    (-half_width + radius, -half_height + radius, 180.0),
+                ];
+                let points = corners
+                    .iter()
+                    .flat_map(|&(x, y, start): &(f64, f64, f64)| {
+                        (0..=segments).map(move |k| {
+                            let angle = (start + 90.0 * k as f64 / segments as f64).to_radians();
+                            [x + radius * angle.cos(), y + radius * angle.sin()]
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                canvas.polygon(color, &points, transform);
+            }
+            CarShape::Triangle => canvas.polygon(
+                color,
+                &[
+                    [0.0, -half_height],
+                    [half_width, half_height],
+                    [-half_width, half_height],
+                ],
+                transform,
+            ),
+        }
     }
 
     fn transform_to_road_center(
@@ -691,3 +2244,775 @@ impl View {
         }
     }
 }
+
+/// `color` with its alpha scaled by `factor`.
+fn faded([r, g, b, a]: Color, factor: f64) -> Color {
+    [r, g, b, a * factor as f32]
+}
+
+/// Device pixels per logical pixel of `viewport` on each axis, 1 for an
+/// empty window.
+pub fn device_scale(viewport: Viewport) -> [f64; 2] {
+    let scale = |draw: u32, window: f64| {
+        if draw > 0 && window > 0.0 {
+            f64::from(draw) / window
+        } else {
+            1.0
+        }
+    };
+    [
+        scale(viewport.draw_size[0], viewport.window_size[0]),
+        scale(viewport.draw_size[1], viewport.window_size[1]),
+    ]
+}
+
+/// Points of the remaining route of a car in model coordinates, from the car
+/// through the intersections and road centers of its route.
+pub fn route_points(car: &stateful::Car, city: &stateless::City) -> Vec<Position> {
+    let mut points = match car.location.city_position(city) {
+        Some(position) => vec![position],
+        None => return Vec::new(),
+    };
+    for segment in car.route.iter() {
+        points.push(city.intersection_center(segment.from_intersection(city)));
+        points.push(city.road_center(segment.road_direction, segment.road_index));
+    }
+    if let Some(segment) = car.route.last() {
+        points.push(city.intersection_center(segment.to_intersection(city)));
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stateful::testing::{car_at, on_lane, on_road};
+
+    #[test]
+    fn parse_car_shape() {
+        assert_eq!("rounded".parse::<CarShape>(), Ok(CarShape::Rounded));
+        assert_eq!("triangle".parse::<CarShape>(), Ok(CarShape::Triangle));
+        assert!("circle".parse::<CarShape>().is_err());
+    }
+
+    #[test]
+    fn color_cars_by_destination() {
+        assert_eq!(
+            "destination".parse::<CarColoring>(),
+            Ok(CarColoring::Destination)
+        );
+        assert!("speed".parse::<CarColoring>().is_err());
+        assert_eq!(destination_color((2, 3)), destination_color((2, 3)));
+        let colors = (0..3)
+            .flat_map(|i| (0..3).map(move |j| destination_color((i, j))))
+            .collect::<Vec<_>>();
+        for (k, a) in colors.iter().enumerate() {
+            assert!(a.iter().all(|c| (0.0..=1.0).contains(c)), "{:?}", a);
+            for b in colors[k + 1..].iter() {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn color_cars_by_aggressiveness() {
+        assert_eq!(
+            "aggressiveness".parse::<CarColoring>(),
+            Ok(CarColoring::Aggressiveness)
+        );
+        let brightest = |color: Color| {
+            (0..3)
+                .max_by(|&a, &b| color[a].partial_cmp(&color[b]).unwrap())
+                .unwrap()
+        };
+        assert_eq!(brightest(aggressiveness_color(0.85, 0.85, 1.15)), 2);
+        assert_eq!(brightest(aggressiveness_color(1.0, 0.85, 1.15)), 1);
+        assert_eq!(brightest(aggressiveness_color(1.15, 0.85, 1.15)), 0);
+        assert_eq!(
+            aggressiveness_color(2.0, 0.85, 1.15),
+            aggressiveness_color(1.15, 0.85, 1.15)
+        );
+        assert_eq!(
+            aggressiveness_color(0.5, 0.85, 1.15),
+            aggressiveness_color(0.85, 0.85, 1.15)
+        );
+    }
+
+    #[test]
+    fn select_cars_in_dragged_rectangle() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 0)
+            .road_length(AxisDirection::Horizontal, 0, 100.0)
+            .build()
+            .unwrap();
+        let stateless_model = stateless::Model {
+            city,
+            ..Default::default()
+        };
+        let mut stateful_model = stateful::Model::default();
+        stateful_model.replace_cars(vec![
+            car_at(on_lane(0, 10.0), 0.0),
+            car_at(on_lane(0, 20.0), 0.0),
+            car_at(on_lane(0, 90.0), 0.0),
+        ]);
+        let view = View::new(ViewSettings {
+            padding: 10.0,
+            ..Default::default()
+        });
+        let window_size = [1000.0, 500.0];
+        let mut info = Info::new();
+        // A rectangle around the first two cars, dragged from its bottom
+        // right corner
+        let corner = |car_index: CarIndex, dx: f64, dy: f64| {
+            let position = stateful_model.cars[car_index]
+                .as_ref()
+                .unwrap()
+                .location
+                .city_position(&stateless_model.city)
+                .unwrap();
+            let transform = view.model_transform(&info, &stateless_model.city, window_size);
+            math::transform_pos(transform, [position.x + dx, position.y + dy])
+        };
+        let (start, end) = (corner(1, 1.0, 5.0), corner(0, -1.0, -5.0));
+        info.selection_box = Some((start, end));
+        assert!(view.select_cars(&mut info, &stateless_model, &stateful_model, window_size));
+        let selected = info
+            .selected_cars
+            .iter()
+            .map(|handle| handle.index)
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(selected.into_iter().collect::<Vec<_>>(), vec![0, 1]);
+        assert!(info.selection_box.is_none());
+        assert!(!view.select_cars(&mut info, &stateless_model, &stateful_model, window_size));
+    }
+
+    #[test]
+    fn fit_empty_city() {
+        let view = View::new(ViewSettings {
+            padding: 10.0,
+            ..Default::default()
+        });
+        let fit = view.fit_model(&Default::default(), [800.0, 600.0]);
+        assert_eq!(
+            fit,
+            Camera {
+                x: 10.0,
+                y: 10.0,
+                zoom: 1.0
+            }
+        );
+        let transform = view.model_transform(&Info::new(), &Default::default(), [0.0, 0.0]);
+        assert!(transform.iter().flatten().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn screen_to_model_inverts_model_transform() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let view = View::new(ViewSettings::default());
+        let info = Info {
+            camera: Camera {
+                x: -30.0,
+                y: 12.0,
+                zoom: 1.5,
+            },
+            device_scale: [2.0, 2.0],
+            ..Info::new()
+        };
+        let window_size = [800.0, 600.0];
+        let transform = view.model_transform(&info, &city, window_size);
+        // A cursor reported in device pixels lands on the drawn point
+        let cursor = info.logical_position([300.0, 500.0]);
+        let position = view.screen_to_model(&info, &city, window_size, cursor);
+        let drawn = math::transform_pos(transform, [position.x, position.y]);
+        assert!((drawn[0] - 150.0).abs() < 1e-9 && (drawn[1] - 250.0).abs() < 1e-9);
+
+        let viewport = Viewport {
+            rect: [0, 0, 1600, 1200],
+            draw_size: [1600, 1200],
+            window_size,
+        };
+        assert_eq!(device_scale(viewport), [2.0, 2.0]);
+        let viewport = Viewport {
+            draw_size: [0, 0],
+            ..viewport
+        };
+        assert_eq!(device_scale(viewport), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn minimap_click_centers_window() {
+        use crate::model::stateless::CityBuilder;
+
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let mut view = View::new(ViewSettings {
+            minimap_size: 200.0,
+            ..Default::default()
+        });
+        let window_size = [800.0, 600.0];
+        let ([x, y, w, h], _) = view.minimap_transform(&city, window_size).unwrap();
+        assert!((w.max(h) - 200.0).abs() < 1e-9);
+        let press = Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Mouse(MouseButton::Left),
+            scancode: None,
+        });
+        let mut info = Info {
+            camera: Camera {
+                zoom: 3.0,
+                ..Camera::new()
+            },
+            cursor: [x + w / 4.0, y + h / 2.0],
+            ..Info::new()
+        };
+        assert!(!view.minimap_click(&mut info, &city, window_size, &press));
+        view.settings.toggle_overlay(Overlay::Minimap);
+        assert!(view.minimap_click(&mut info, &city, window_size, &press));
+        // The point a quarter along the city is in the center of the window
+        let Geometry { width, height } = city.geometry();
+        let transform = view.model_transform(&info, &city, window_size);
+        let center = math::transform_pos(transform, [width / 4.0, height / 2.0]);
+        assert!((center[0] - 400.0).abs() < 1e-9 && (center[1] - 300.0).abs() < 1e-9);
+        info.cursor = [x - 1.0, y];
+        assert!(!view.minimap_click(&mut info, &city, window_size, &press));
+    }
+
+    #[test]
+    fn signal_fades_after_switch() {
+        use crate::model::stateless::intersection::SwitchRule;
+
+        let north_south = Around {
+            north: TurnRule::FRONT,
+            south: TurnRule::FRONT,
+            ..Default::default()
+        };
+        let west_east = Around {
+            west: TurnRule::FRONT,
+            east: TurnRule::FRONT,
+            ..Default::default()
+        };
+        let switch_rule = SwitchRule::LoopTimeout {
+            times: vec![10.0, 10.0],
+            offset: 0.0,
+        };
+        let intersection = stateless::Intersection::Crossroad {
+            max_speed: 10.0,
+            rules: vec![north_south, west_east],
+            switch_rule: switch_rule.clone(),
+        };
+        let mut switch_state = stateful::intersection::SwitchState::start(&switch_rule, 2);
+        switch_state.advance(10.1, &switch_rule, 2);
+        let state = stateful::Intersection::Crossroad {
+            current: west_east,
+            switch_state,
+            occupancy: Vec::new(),
+        };
+        let mut view = View::new(ViewSettings::default());
+        assert!(view.signal_transition(&intersection, &state).is_none());
+        view.settings.signal_transition = 0.4;
+        let (previous, progress) = view.signal_transition(&intersection, &state).unwrap();
+        assert_eq!(previous, north_south);
+        assert!((progress - 0.25).abs() < 1e-9);
+        view.settings.signal_transition = 0.05;
+        assert!(view.signal_transition(&intersection, &state).is_none());
+    }
+
+    #[test]
+    fn car_transforms_follow_cars() {
+        use crate::model::stateless::CityBuilder;
+        let city = CityBuilder::new((2, 2))
+            .road(AxisDirection::Horizontal, (1, 0), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .build()
+            .unwrap();
+        let car = |road_direction, road_index, lane_direction, position| {
+            car_at(
+                on_road(road_direction, road_index, lane_direction, 0, position),
+                0.0,
+            )
+        };
+        let stateful_model = stateful::Model {
+            cars: vec![
+                car(
+                    AxisDirection::Horizontal,
+                    (1, 0),
+                    LaneDirection::HighToLow,
+                    30.0,
+                ),
+                None,
+                car(
+                    AxisDirection::Vertical,
+                    (0, 1),
+                    LaneDirection::LowToHigh,
+                    60.0,
+                ),
+            ],
+            ..Default::default()
+        };
+        let stateless_model = stateless::Model {
+            city,
+            ..Default::default()
+        };
+        let view = View::new(ViewSettings::default());
+        let info = Info {
+            camera: Camera {
+                x: 5.0,
+                zoom: 2.0,
+                ..Camera::new()
+            },
+            ..Info::new()
+        };
+        let window_size = [800.0, 600.0];
+        let model_transform = view.model_transform(&info, &stateless_model.city, window_size);
+        let transforms = view
+            .car_transforms(&stateless_model, &stateful_model, &info, window_size)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transforms
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        for (car_index, transform) in transforms {
+            let position = stateful_model.cars[car_index]
+                .as_ref()
+                .unwrap()
+                .location
+                .city_position(&stateless_model.city)
+                .unwrap();
+            let expected = math::transform_pos(model_transform, [position.x, position.y]);
+            let actual = math::transform_pos(transform, [0.0, 0.0]);
+            for (a, b) in actual.iter().zip(expected.iter()) {
+                assert!((a - b).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn zones_behind_roads() {
+        let zones = vec![
+            stateless::Zone {
+                name: "residential".to_string(),
+                min: Position { x: 10.0, y: 20.0 },
+                max: Position { x: 40.0, y: 60.0 },
+                color: [1.0, 0.0, 0.0, 0.5],
+            },
+            stateless::Zone {
+                name: String::new(),
+                min: Position { x: 40.0, y: 60.0 },
+                max: Position { x: 10.0, y: 20.0 },
+                color: [0.0, 0.0, 1.0, 0.5],
+            },
+        ];
+        assert_eq!(zones[0].rectangle(), [10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(zones[1].rectangle()[2..], [0.0, 0.0]);
+        let rectangles = |view: &View| {
+            let mut svg = Svg::new(100.0, 100.0, color::BLACK);
+            view.draw_zones(&zones, math::identity(), &mut svg);
+            svg.finish()
+                .lines()
+                .filter(|line| line.starts_with("<polygon "))
+                .count()
+        };
+        // Shown by default, besides the background
+        let mut view = View::new(Default::default());
+        assert_eq!(rectangles(&view), 1 + 2);
+        view.settings.toggle_overlay(Overlay::Zones);
+        assert_eq!(rectangles(&view), 1);
+    }
+
+    #[test]
+    fn grid_through_road_center_lines() {
+        use crate::model::stateless::CityBuilder;
+        let city = CityBuilder::new((2, 3))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road(AxisDirection::Vertical, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let grid_lines = |view: &View| {
+            let mut svg = Svg::new(100.0, 100.0, color::BLACK);
+            view.draw_background_grid(&city, math::identity(), &mut svg);
+            svg.finish()
+                .lines()
+                .filter(|line| line.starts_with("<line "))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let mut view = View::new(Default::default());
+        assert!(grid_lines(&view).is_empty());
+        view.settings.toggle_overlay(Overlay::Grid);
+        let lines = grid_lines(&view);
+        assert_eq!(lines.len(), 2 + 3);
+        let Position { x, y } = city.horizontal_road_center((0, 0));
+        assert!(
+            lines[0].contains(&format!("y1=\"{:.3}\"", y)),
+            "{}",
+            lines[0]
+        );
+        let Position { x: x2, .. } = city.vertical_road_center((0, 0));
+        assert!(
+            lines[2].contains(&format!("x1=\"{:.3}\"", x2)),
+            "{}",
+            lines[2]
+        );
+        assert!(x > x2);
+        // Nothing to draw for an empty city
+        let mut svg = Svg::new(100.0, 100.0, color::BLACK);
+        view.draw_background_grid(&Default::default(), math::identity(), &mut svg);
+        assert!(!svg.finish().contains("<line "));
+    }
+
+    #[test]
+    fn one_way_road_without_middle_separator() {
+        use crate::model::{
+            common::RelativeDirection, stateful::car::Location, stateless::CityBuilder,
+        };
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 3, 0)
+            .build()
+            .unwrap();
+        let road = city
+            .board
+            .get_road(AxisDirection::Horizontal, (0, 0))
+            .unwrap()
+            .as_ref()
+            .unwrap();
+        assert!(road.is_one_way());
+        assert_eq!(road.lane_number(), 3);
+        assert!(road.lanes_to_direction(LaneDirection::HighToLow).is_empty());
+        let length = city.road_length(AxisDirection::Horizontal, (0, 0));
+        let view = View::new(ViewSettings {
+            road_middle_separator_color: [0.0, 0.0, 1.0, 1.0],
+            road_middle_separator_width: 0.4,
+            ..Default::default()
+        });
+        let separators = |road: &stateless::Road| {
+            let mut svg = Svg::new(100.0, 100.0, color::BLACK);
+            view.draw_road(&city, length, road, math::identity(), &mut svg);
+            svg.finish()
+                .lines()
+                .filter(|line| line.contains("fill=\"#0000ff\""))
+                .count()
+        };
+        assert_eq!(separators(road), 0);
+        let two_way = stateless::Road {
+            lane_to_low: road.lane_to_high[..1].to_vec(),
+            ..road.clone()
+        };
+        assert_eq!(separators(&two_way), 1);
+        // Cars on the lanes of the one-way road stand a lane apart, across
+        // the whole road
+        let center = city.horizontal_road_center((0, 0));
+        let ys = (0..3)
+            .map(|lane_index| {
+                Location::OnLane {
+                    road_direction: AxisDirection::Horizontal,
+                    road_index: (0, 0),
+                    lane_direction: LaneDirection::LowToHigh,
+                    lane_index,
+                    about_to_turn: RelativeDirection::Front,
+                    position: length / 2.0,
+                }
+                .city_position(&city)
+                .unwrap()
+                .y
+            })
+            .collect::<Vec<_>>();
+        assert!((ys[1] - center.y).abs() < 1e-9);
+        assert!(((ys[0] - ys[2]).abs() - 2.0 * city.lane_width).abs() < 1e-9);
+        assert_eq!(
+            city.lane_center(
+                AxisDirection::Horizontal,
+                (0, 0),
+                LaneDirection::HighToLow,
+                0
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn stop_lines_where_cars_wait() {
+        use crate::model::{
+            common::RelativeDirection, stateful::car::Location, stateless::CityBuilder,
+        };
+        let city = CityBuilder::new((1, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .build()
+            .unwrap();
+        let road = city
+            .board
+            .get_road(AxisDirection::Horizontal, (0, 0))
+            .unwrap()
+            .as_ref()
+            .unwrap();
+        let length = city.road_length(AxisDirection::Horizontal, (0, 0));
+        let stop_lines = |view: &View| {
+            let mut svg = Svg::new(100.0, 100.0, color::BLACK);
+            view.draw_road(
+                &city,
+                length,
+                road,
+                view.transform_to_road_center(
+                    math::identity(),
+                    &city,
+                    AxisDirection::Horizontal,
+                    (0, 0),
+                ),
+                &mut svg,
+            );
+            svg.finish()
+                .lines()
+                .filter(|line| line.contains("fill=\"#ff0000\""))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let mut view = View::new(ViewSettings {
+            stop_line_color: [1.0, 0.0, 0.0, 1.0],
+            stop_line_width: 0.5,
+            ..Default::default()
+        });
+        let lines = stop_lines(&view);
+        assert_eq!(lines.len(), 2);
+        // A car waiting at the end of the lane toward the east stands on the
+        // east edge of its stop line
+        let waiting = Location::OnLane {
+            road_direction: AxisDirection::Horizontal,
+            road_index: (0, 0),
+            lane_direction: LaneDirection::LowToHigh,
+            lane_index: 0,
+            about_to_turn: RelativeDirection::Front,
+            position: length,
+        }
+        .city_position(&city)
+        .unwrap();
+        let half_width = city.lane_width / 2.0;
+        let edge = format!(
+            "{:.3},{:.3} {:.3},{:.3}",
+            waiting.x,
+            waiting.y - half_width,
+            waiting.x,
+            waiting.y + half_width
+        );
+        assert!(
+            lines.iter().any(|line| line.contains(&edge)),
+            "{:?} has no edge {}",
+            lines,
+            edge
+        );
+        view.settings.toggle_overlay(Overlay::StopLines);
+        assert!(stop_lines(&view).is_empty());
+    }
+
+    #[test]
+    fn route_points_through_road_centers() {
+        use crate::model::{routing::Segment, stateless::CityBuilder};
+        let city = CityBuilder::new((1, 3))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (0, 1), 1, 1)
+            .build()
+            .unwrap();
+        let mut car = stateful::Car {
+            destination: Some((0, 2)),
+            route: vec![Segment {
+                road_direction: AxisDirection::Horizontal,
+                road_index: (0, 1),
+                lane_direction: LaneDirection::LowToHigh,
+            }],
+            ..car_at(on_lane(0, 10.0), 0.0).unwrap()
+        };
+        let position = car.location.city_position(&city).unwrap();
+        assert_eq!(
+            route_points(&car, &city),
+            vec![
+                position,
+                city.intersection_center((0, 1)),
+                city.horizontal_road_center((0, 1)),
+                city.intersection_center((0, 2)),
+            ]
+        );
+        car.route.clear();
+        assert_eq!(route_points(&car, &city), vec![position]);
+    }
+
+    /// Differences between two SVG documents beyond `tolerance` in any
+    /// number, such as a coordinate in pixels. The text between the numbers
+    /// has to be the same.
+    fn svg_differences(expected: &str, actual: &str, tolerance: f64) -> Vec<String> {
+        // Numbers start after a separator, the digits of colors and names
+        // are text
+        fn tokens(document: &str) -> Vec<Result<f64, String>> {
+            let mut tokens = Vec::new();
+            let mut rest = document;
+            while !rest.is_empty() {
+                let number = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+                    .unwrap_or(rest.len());
+                match rest[..number].parse::<f64>() {
+                    Ok(value) if number > 0 => {
+                        tokens.push(Ok(value));
+                        rest = &rest[number..];
+                    }
+                    _ => {
+                        let text = rest
+                            .char_indices()
+                            .skip(1)
+                            .find(|&(i, c)| {
+                                let before = rest[..i].chars().last().unwrap();
+                                (c.is_ascii_digit() || c == '-')
+                                    && !(before.is_ascii_alphanumeric() || before == '#')
+                            })
+                            .map_or(rest.len(), |(i, _)| i);
+                        tokens.push(Err(rest[..text].to_string()));
+                        rest = &rest[text..];
+                    }
+                }
+            }
+            tokens
+        }
+        let (expected, actual) = (tokens(expected), tokens(actual));
+        let mut differences = Vec::new();
+        if expected.len() != actual.len() {
+            differences.push(format!(
+                "{} tokens instead of {}",
+                actual.len(),
+                expected.len()
+            ));
+        }
+        for (n, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            let different = match (expected, actual) {
+                (Ok(a), Ok(b)) => (a - b).abs() > tolerance,
+                (a, b) => a != b,
+            };
+            if different {
+                differences.push(format!(
+                    "token {}: {:?} instead of {:?}",
+                    n, actual, expected
+                ));
+            }
+        }
+        differences
+    }
+
+    /// Render a small fixed model and compare it with the reference in
+    /// `tests/snapshots`, written instead when it is missing or
+    /// `MPI_TRAFFIC_BLESS` is set.
+    #[test]
+    fn render_matches_snapshot() {
+        use crate::model::{
+            generate::stateful::generate_from_stateless,
+            stateless::{car::DrivingModel, CityBuilder},
+        };
+        let city = CityBuilder::new((2, 2))
+            .road(AxisDirection::Horizontal, (0, 0), 1, 1)
+            .road(AxisDirection::Horizontal, (1, 0), 2, 0)
+            .road(AxisDirection::Vertical, (0, 0), 1, 1)
+            .road(AxisDirection::Vertical, (0, 1), 1, 1)
+            .build()
+            .unwrap();
+        let stateless_car = |class: VehicleClass| stateless::Car {
+            class,
+            length: class.length(),
+            width: class.width(),
+            max_velocity: 15.0,
+            desired_speed_factor: 1.0,
+            max_acceleration: 3.0,
+            max_break_acceleration: 8.0,
+            lane_change_time: 2.0,
+            driving_model: DrivingModel::Normal {
+                min_cushion: 2.0,
+                cushion_velocity_factor: 2.0,
+                prediction_time: 1.0,
+            },
+        };
+        let stateless_model = stateless::Model {
+            city,
+            cars: vec![
+                stateless_car(VehicleClass::Car),
+                stateless_car(VehicleClass::Bus),
+                stateless_car(VehicleClass::Truck),
+            ],
+            ..Default::default()
+        };
+        let car = |road_direction, road_index, lane_direction, lane_index, position| {
+            car_at(
+                on_road(
+                    road_direction,
+                    road_index,
+                    lane_direction,
+                    lane_index,
+                    position,
+                ),
+                5.0,
+            )
+        };
+        let mut stateful_model = generate_from_stateless(&stateless_model);
+        stateful_model.replace_cars(vec![
+            car(
+                AxisDirection::Horizontal,
+                (0, 0),
+                LaneDirection::LowToHigh,
+                0,
+                20.0,
+            ),
+            car(
+                AxisDirection::Horizontal,
+                (1, 0),
+                LaneDirection::LowToHigh,
+                1,
+                40.0,
+            ),
+            car(
+                AxisDirection::Vertical,
+                (0, 1),
+                LaneDirection::HighToLow,
+                0,
+                30.0,
+            ),
+        ]);
+        // The settings of the command line, not the empty ones of `Default`
+        let view = View::new(ViewSettings::from_iter_safe(&["view"]).unwrap());
+        let actual = view.svg_document(&stateless_model, &stateful_model);
+
+        // Moving a shape by a pixel is a difference, rounding is not
+        let moved = actual.replacen(".000,", ".900,", 1);
+        assert!(!svg_differences(&actual, &moved, 0.01).is_empty());
+        let rounded = actual.replacen(".000,", ".001,", 1);
+        assert_eq!(
+            svg_differences(&actual, &rounded, 0.01),
+            Vec::<String>::new()
+        );
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/small_city.svg");
+        // Writing the reference is explicit, a missing one is a failure
+        if std::env::var_os("MPI_TRAFFIC_BLESS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!(
+                "no reference rendering at {:?} ({}), write it with MPI_TRAFFIC_BLESS=1",
+                path, err
+            )
+        });
+        let differences = svg_differences(&expected, &actual, 0.01);
+        assert!(
+            differences.is_empty(),
+            "rendering differs from {:?} in {} places, first {}, bless with MPI_TRAFFIC_BLESS=1 if \
+             intended",
+            path,
+            differences.len(),
+            differences[0]
+        );
+    }
+}
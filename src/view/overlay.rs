@@ -0,0 +1,193 @@
+//! Overlays of the view and the keys toggling them.
+//!
+//! To add an overlay, add a variant, its flag in `ViewSettings` and a row of
+//! `BINDINGS`, the help overlay lists the new binding by itself.
+
+use crate::view::ViewSettings;
+use piston_window::Key;
+
+/// Part of the view which can be shown or hidden.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Overlay {
+    /// Graph of the average speed.
+    Graph,
+    /// Legend of directions and signs.
+    Legend,
+    /// Turn rules of lanes.
+    LaneSigns,
+    /// Turn rules of signals in intersections.
+    Signals,
+    /// Seconds remaining in the current phases of signals.
+    PhaseTimer,
+    /// Grid through the center lines of the roads, behind the roads.
+    Grid,
+    /// Zones of the model, behind the roads.
+    Zones,
+    /// Minimap of the whole city and the part in the window.
+    Minimap,
+    /// Bars across the ends of lanes where cars wait to enter intersections.
+    StopLines,
+    /// Translucent cars where the next update puts them, while paused.
+    Ghosts,
+    /// List of these bindings.
+    Help,
+}
+
+impl Overlay {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overlay::Graph => "GRAPH",
+            Overlay::Legend => "LEGEND",
+            Overlay::LaneSigns => "LANE SIGNS",
+            Overlay::Signals => "SIGNALS",
+            Overlay::PhaseTimer => "PHASE TIMER",
+            Overlay::Grid => "GRID",
+            Overlay::Zones => "ZONES",
+            Overlay::Minimap => "MINIMAP",
+            Overlay::StopLines => "STOP LINES",
+            Overlay::Ghosts => "GHOSTS",
+            Overlay::Help => "HELP",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Binding {
+    pub key: Key,
+    /// Name of the key in the help overlay.
+    pub label: &'static str,
+    pub overlay: Overlay,
+}
+
+/// Keys toggling overlays, in the order of the help overlay.
+///
+/// The help overlay is bound to the key of `?`, which is read as a slash.
+pub const BINDINGS: &[Binding] = &[
+    Binding {
+        key: Key::D1,
+        label: "1",
+        overlay: Overlay::Graph,
+    },
+    Binding {
+        key: Key::D2,
+        label: "2",
+        overlay: Overlay::Legend,
+    },
+    Binding {
+        key: Key::L,
+        label: "L",
+        overlay: Overlay::Legend,
+    },
+    Binding {
+        key: Key::D3,
+        label: "3",
+        overlay: Overlay::LaneSigns,
+    },
+    Binding {
+        key: Key::D4,
+        label: "4",
+        overlay: Overlay::Signals,
+    },
+    Binding {
+        key: Key::D5,
+        label: "5",
+        overlay: Overlay::PhaseTimer,
+    },
+    Binding {
+        key: Key::D6,
+        label: "6",
+        overlay: Overlay::Grid,
+    },
+    Binding {
+        key: Key::D7,
+        label: "7",
+        overlay: Overlay::Minimap,
+    },
+    Binding {
+        key: Key::D8,
+        label: "8",
+        overlay: Overlay::StopLines,
+    },
+    Binding {
+        key: Key::D9,
+        label: "9",
+        overlay: Overlay::Ghosts,
+    },
+    Binding {
+        key: Key::D0,
+        label: "0",
+        overlay: Overlay::Zones,
+    },
+    Binding {
+        key: Key::Slash,
+        label: "/",
+        overlay: Overlay::Help,
+    },
+];
+
+/// The overlay toggled by `key`.
+pub fn binding(key: Key) -> Option<Overlay> {
+    BINDINGS
+        .iter()
+        .find(|binding| binding.key == key)
+        .map(|binding| binding.overlay)
+}
+
+impl ViewSettings {
+    pub fn overlay_shown(&self, overlay: Overlay) -> bool {
+        match overlay {
+            Overlay::Graph => self.show_graph,
+            Overlay::Legend => self.show_legend,
+            Overlay::LaneSigns => !self.hide_lane_signs,
+            Overlay::Signals => !self.hide_signals,
+            Overlay::PhaseTimer => self.show_phase_timer,
+            Overlay::Grid => self.show_grid,
+            Overlay::Zones => !self.hide_zones,
+            Overlay::Minimap => self.show_minimap,
+            Overlay::StopLines => !self.hide_stop_lines,
+            Overlay::Ghosts => self.show_ghosts,
+            Overlay::Help => self.show_help,
+        }
+    }
+
+    pub fn toggle_overlay(&mut self, overlay: Overlay) {
+        let flag = match overlay {
+            Overlay::Graph => &mut self.show_graph,
+            Overlay::Legend => &mut self.show_legend,
+            Overlay::LaneSigns => &mut self.hide_lane_signs,
+            Overlay::Signals => &mut self.hide_signals,
+            Overlay::PhaseTimer => &mut self.show_phase_timer,
+            Overlay::Grid => &mut self.show_grid,
+            Overlay::Zones => &mut self.hide_zones,
+            Overlay::Minimap => &mut self.show_minimap,
+            Overlay::StopLines => &mut self.hide_stop_lines,
+            Overlay::Ghosts => &mut self.show_ghosts,
+            Overlay::Help => &mut self.show_help,
+        };
+        *flag = !*flag;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle_bound_overlays() {
+        let mut settings = ViewSettings::default();
+        assert!(settings.overlay_shown(Overlay::LaneSigns));
+        assert!(!settings.overlay_shown(Overlay::Help));
+        for key in [Key::D3, Key::Slash, Key::L].iter() {
+            settings.toggle_overlay(binding(*key).unwrap());
+        }
+        assert!(!settings.overlay_shown(Overlay::LaneSigns));
+        assert!(settings.overlay_shown(Overlay::Help));
+        assert!(settings.overlay_shown(Overlay::Legend));
+        assert_eq!(binding(Key::D5), Some(Overlay::PhaseTimer));
+        assert_eq!(binding(Key::D6), Some(Overlay::Grid));
+        assert_eq!(binding(Key::D8), Some(Overlay::StopLines));
+        assert_eq!(binding(Key::D9), Some(Overlay::Ghosts));
+        assert_eq!(binding(Key::D0), Some(Overlay::Zones));
+        assert_eq!(binding(Key::Q), None);
+    }
+}
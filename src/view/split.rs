@@ -0,0 +1,120 @@
+//! Halves of a window showing two runs side by side, each drawn as into a
+//! window of its own.
+
+use super::device_scale;
+use piston_window::{context::Context, Transformed, Viewport};
+use std::str::FromStr;
+
+/// How the window is divided between two runs.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Split {
+    /// The first run on the left, the second on the right.
+    #[default]
+    LeftRight,
+    /// The first run on the top, the second on the bottom.
+    TopBottom,
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left-right" => Ok(Split::LeftRight),
+            "top-bottom" => Ok(Split::TopBottom),
+            _ => Err(format!("unknown split: {}", s)),
+        }
+    }
+}
+
+impl Split {
+    /// Origin and size of the first (0) or second (1) half of a window of
+    /// `window_size`, in logical window coordinates.
+    pub fn half(self, window_size: [f64; 2], half: usize) -> ([f64; 2], [f64; 2]) {
+        let [w, h] = window_size;
+        let n = half.min(1) as f64;
+        match self {
+            Split::LeftRight => ([n * w / 2.0, 0.0], [w / 2.0, h]),
+            Split::TopBottom => ([0.0, n * h / 2.0], [w, h / 2.0]),
+        }
+    }
+
+    /// Line between the halves of a window of `window_size`.
+    pub fn divider(self, window_size: [f64; 2]) -> [f64; 4] {
+        let [w, h] = window_size;
+        match self {
+            Split::LeftRight => [w / 2.0, 0.0, w / 2.0, h],
+            Split::TopBottom => [0.0, h / 2.0, w, h / 2.0],
+        }
+    }
+
+    /// `context` drawing into a half of its window, with the origin at the
+    /// corner of the half and the size of the half as the window size.
+    ///
+    /// Drawing outside the half is cut off.
+    pub fn context(self, context: Context, half: usize) -> Context {
+        let viewport = context.viewport.expect("no viewport info provided");
+        let ([x, y], [w, h]) = self.half(viewport.window_size, half);
+        let [sx, sy] = device_scale(viewport);
+        let pixels = [
+            (x * sx) as u32,
+            (y * sy) as u32,
+            (w * sx) as u32,
+            (h * sy) as u32,
+        ];
+        Context {
+            viewport: Some(Viewport {
+                rect: [
+                    viewport.rect[0] + pixels[0] as i32,
+                    viewport.rect[1] + pixels[1] as i32,
+                    pixels[2] as i32,
+                    pixels[3] as i32,
+                ],
+                draw_size: [pixels[2], pixels[3]],
+                window_size: [w, h],
+            }),
+            transform: context.transform.trans(x, y),
+            draw_state: context.draw_state.scissor(pixels),
+            ..context
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston_window::math;
+
+    #[test]
+    fn halves_of_the_window() {
+        assert_eq!("top-bottom".parse(), Ok(Split::TopBottom));
+        assert!("diagonal".parse::<Split>().is_err());
+        let size = [1000.0, 500.0];
+        assert_eq!(
+            Split::LeftRight.half(size, 1),
+            ([500.0, 0.0], [500.0, 500.0])
+        );
+        assert_eq!(
+            Split::TopBottom.half(size, 0),
+            ([0.0, 0.0], [1000.0, 250.0])
+        );
+        assert_eq!(Split::LeftRight.divider(size), [500.0, 0.0, 500.0, 500.0]);
+
+        // A window with two device pixels per logical pixel
+        let context = Context::new_viewport(Viewport {
+            rect: [0, 0, 2000, 1000],
+            draw_size: [2000, 1000],
+            window_size: size,
+        });
+        let right = Split::LeftRight.context(context, 1);
+        let viewport = right.viewport.unwrap();
+        assert_eq!(viewport.window_size, [500.0, 500.0]);
+        assert_eq!(viewport.draw_size, [1000, 1000]);
+        assert_eq!(right.draw_state.scissor, Some([1000, 0, 1000, 1000]));
+        // The origin of the half is the middle of the window
+        let origin = math::transform_pos(right.transform, [0.0, 0.0]);
+        let middle = math::transform_pos(context.transform, [500.0, 0.0]);
+        assert!((origin[0] - middle[0]).abs() < 1e-9);
+        assert!((origin[1] - middle[1]).abs() < 1e-9);
+    }
+}